@@ -0,0 +1,66 @@
+// Times renders of growing size on the calling thread and through the
+// thread pool, to check where RenderSettings::sequential_samples should
+// sit. Timing depends on the machine, so run it on the one that matters,
+// with several cores:
+//
+//     cargo bench --bench sequential_crossover
+//
+// RAYON_NUM_THREADS sets the size of the pool.
+
+use rt::image::Image;
+use rt::scene::{RenderSettings, Scene};
+use std::time::{Duration, Instant};
+
+// Renders of each size per path; the fastest counts, as the one least
+// disturbed by the rest of the machine
+const RUNS: u32 = 20;
+
+fn render(scene: &Scene, size: u32, sequential_samples: u64) -> (Image, Duration) {
+    let settings = RenderSettings { sequential_samples, ..RenderSettings::default() };
+    let mut fastest = Duration::MAX;
+    let mut image = Image::new(size, size);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        scene.render_into(&mut image, &settings);
+        fastest = fastest.min(start.elapsed());
+    }
+    (image, fastest)
+}
+
+fn main() {
+    let scene: Scene = r#"{
+        "camera": { "position": [0, 1, 3], "look_at": [0, 0, 0] },
+        "lights": [{ "position": [2, 3, 1] }],
+        "objects": [
+            { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] },
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": { "reflectivity": 0.3 } },
+            { "type": "cube", "center": [1.5, 0, -1], "size": 1 }
+        ]
+    }"#.parse().unwrap();
+    
+    let threads = rayon::current_num_threads();
+    println!("{} threads; default sequential_samples {}", threads, RenderSettings::default().sequential_samples);
+    if threads == 1 {
+        println!("a single thread always renders sequentially, so there is nothing to compare");
+        return;
+    }
+    // Starts the pool, so its startup is not counted against the first size
+    render(&scene, 8, 0);
+    
+    let mut crossover = None;
+    println!("{:>6} {:>8} {:>12} {:>12} {:>12}", "size", "samples", "sequential", "pool", "difference");
+    for size in [8, 16, 24, 32, 48, 64, 96, 128, 192, 256] {
+        let (sequential_image, sequential) = render(&scene, size, u64::MAX);
+        let (pool_image, pool) = render(&scene, size, 0);
+        assert_eq!(sequential_image.pixels, pool_image.pixels, "the two paths rendered {}x{} differently", size, size);
+        let difference = pool.as_secs_f64() - sequential.as_secs_f64();
+        println!("{:>6} {:>8} {:>12.3?} {:>12.3?} {:>+11.1}µs", size, size * size, sequential, pool, difference * 1e6);
+        if pool < sequential && crossover.is_none() {
+            crossover = Some(size);
+        }
+    }
+    match crossover {
+        Some(size) => println!("the pool is faster from {}x{} ({} samples)", size, size, size * size),
+        None => println!("the pool was never faster"),
+    }
+}
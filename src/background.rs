@@ -0,0 +1,77 @@
+use crate::image::color::Color;
+use crate::ray::Ray;
+use crate::camera::Camera;
+use crate::sky::SkyModel;
+use crate::texture::{ImageTexture, TextureFilter, TextureWrap};
+
+// What rays that miss every object see, including reflected and refracted
+// rays, so mirrors pick up the same sky as the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    Solid(Color),
+    // Blend from `bottom` (looking straight down) to `top` (straight up)
+    Gradient { bottom: Color, top: Color },
+    // Daylight lit by a sun, which the scene needs as a light as well; see
+    // `SkyModel::sun_light`
+    Sky(SkyModel),
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let t = 0.5 * (ray.direction.y + 1.0);
+                bottom.lerp(top, t)
+            }
+            Background::Sky(sky) => sky.radiance(ray.direction),
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid(Color::new(0.7, 0.8, 1.0)) // Light sky blue
+    }
+}
+
+// A photo behind the scene, seen only by camera rays that miss everything;
+// reflections and refractions still see the background. The photo is
+// pinned to the frame and scaled to fill it, cropping whichever sides
+// overflow.
+#[derive(Clone)]
+pub struct Backdrop {
+    texture: ImageTexture,
+}
+
+impl Backdrop {
+    // The photo's sRGB colors are made linear on loading, like textures
+    pub fn load(path: &str) -> Result<Self, String> {
+        let texture = ImageTexture::load_ppm(path)?;
+        Ok(Backdrop::new(texture))
+    }
+    
+    pub fn new(texture: ImageTexture) -> Self {
+        Backdrop { texture: texture.with_filter(TextureFilter::Bilinear).with_wrap(TextureWrap::Clamp) }
+    }
+    
+    // The photo where `ray` crosses the camera's image plane
+    pub fn color(&self, ray: &Ray, camera: &Camera) -> Color {
+        let along = -ray.direction.dot(&camera.w);
+        if along <= 0.0 {
+            return Color::BLACK;
+        }
+        let on_plane = camera.position + ray.direction / along - camera.lower_left_corner;
+        let s = on_plane.dot(&camera.horizontal) / camera.horizontal.length_squared();
+        let t = on_plane.dot(&camera.vertical) / camera.vertical.length_squared();
+        
+        let frame_aspect = camera.horizontal.length() / camera.vertical.length();
+        let photo_aspect = self.texture.width() as f64 / self.texture.height() as f64;
+        let (u, v) = if photo_aspect > frame_aspect {
+            (0.5 + (s - 0.5) * frame_aspect / photo_aspect, t)
+        } else {
+            (s, 0.5 + (t - 0.5) * photo_aspect / frame_aspect)
+        };
+        self.texture.sample(u, v, 0.0)
+    }
+}
@@ -0,0 +1,126 @@
+use crate::vector::Vec3;
+
+// Little-endian encoding for the binary cache files. Floats are stored as
+// their exact bit patterns so data reads back unchanged.
+
+pub fn put_u8(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+pub fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_f64(out: &mut Vec<u8>, value: f64) {
+    put_u64(out, value.to_bits());
+}
+
+pub fn put_vec3(out: &mut Vec<u8>, v: Vec3) {
+    put_f64(out, v.x);
+    put_f64(out, v.y);
+    put_f64(out, v.z);
+}
+
+// Length-prefixed byte string
+pub fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+/// Reads values back in the order they were put; every read fails cleanly
+/// on truncated data.
+///
+/// ```
+/// use rt::binary::{self, Reader};
+/// use rt::vector::Vec3;
+///
+/// let mut out = Vec::new();
+/// binary::put_u32(&mut out, 7);
+/// binary::put_vec3(&mut out, Vec3::new(0.1, -2.0, f64::MIN_POSITIVE));
+/// binary::put_bytes(&mut out, b"mesh.obj");
+///
+/// let mut reader = Reader::new(&out);
+/// assert_eq!(reader.u32(), Ok(7));
+/// assert_eq!(reader.vec3(), Ok(Vec3::new(0.1, -2.0, f64::MIN_POSITIVE)));
+/// assert_eq!(reader.bytes(), Ok(&b"mesh.obj"[..]));
+/// assert!(reader.is_at_end());
+///
+/// // Cut anywhere, the data cannot be read back
+/// for length in 0..out.len() {
+///     let mut reader = Reader::new(&out[..length]);
+///     assert!(reader.u32().and_then(|_| reader.vec3()).and_then(|_| reader.bytes()).is_err());
+/// }
+/// // Nor can a count larger than what is left
+/// assert!(Reader::new(&[2, 0, 0, 0, 1, 2, 3]).count(4).is_err());
+/// assert_eq!(Reader::new(&[1, 0, 0, 0, 1, 2, 3, 4]).count(4), Ok(1));
+/// ```
+pub struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data, position: 0 }
+    }
+    
+    pub fn is_at_end(&self) -> bool {
+        self.position == self.data.len()
+    }
+    
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.position < count {
+            return Err("unexpected end of data".to_string());
+        }
+        let bytes = &self.data[self.position..self.position + count];
+        self.position += count;
+        Ok(bytes)
+    }
+    
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+    
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    
+    pub fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+    
+    pub fn vec3(&mut self) -> Result<Vec3, String> {
+        Ok(Vec3::new(self.f64()?, self.f64()?, self.f64()?))
+    }
+    
+    pub fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+    
+    // Element count for an array of `element_size`-byte items, checked
+    // against the remaining data so a corrupt count cannot cause a huge
+    // allocation
+    pub fn count(&mut self, element_size: usize) -> Result<usize, String> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(element_size) > self.data.len() - self.position {
+            return Err("unexpected end of data".to_string());
+        }
+        Ok(count)
+    }
+}
+
+// 64-bit FNV-1a hash, used to tell whether a cache's source has changed
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
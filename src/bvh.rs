@@ -0,0 +1,464 @@
+use crate::vector::Vec3;
+use crate::ray::{Ray, RayPacket4, HitRecord};
+use crate::objects::Object;
+use crate::binary::{self, Reader};
+use std::mem;
+
+// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+    
+    // Box around a center with the given half extents
+    pub fn around(center: Vec3, half_extents: Vec3) -> Self {
+        Aabb::new(center - half_extents, center + half_extents)
+    }
+    
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            Vec3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            Vec3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        )
+    }
+    
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+    
+    // Slab test; `inv_dir` is the reciprocal of the ray direction
+    fn hit(&self, origin: Vec3, inv_dir: Vec3, t_min: f64, t_max: f64) -> bool {
+        let (mut lo, mut hi) = (t_min, t_max);
+        for (min, max, o, inv) in [
+            (self.min.x, self.max.x, origin.x, inv_dir.x),
+            (self.min.y, self.max.y, origin.y, inv_dir.y),
+            (self.min.z, self.max.z, origin.z, inv_dir.z),
+        ] {
+            if inv.is_infinite() {
+                // Parallel to this slab: inside it everywhere or nowhere.
+                // Testing directly avoids 0 * inf when the ray lies in a face.
+                if o < min || o > max {
+                    return false;
+                }
+                continue;
+            }
+            let t0 = (min - o) * inv;
+            let t1 = (max - o) * inv;
+            lo = lo.max(t0.min(t1));
+            hi = hi.min(t0.max(t1));
+        }
+        lo <= hi
+    }
+}
+
+const MAX_LEAF_SIZE: usize = 4;
+
+// Subtrees over fewer items are built on the current thread; above this the
+// two halves of a split are built in parallel
+const PARALLEL_BUILD_SIZE: usize = 4096;
+
+enum NodeKind {
+    // Objects `start..start + count` of `Bvh::order`
+    Leaf { start: usize, count: usize },
+    Interior { left: usize, right: usize },
+}
+
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+// Nodes a traversal keeps on the stack without allocating; built trees
+// are far shallower, and only a deeper one read from a file spills over
+const INLINE_STACK: usize = 64;
+
+// Nodes still to visit, last in first out
+struct TraversalStack {
+    inline: [usize; INLINE_STACK],
+    len: usize,
+    spill: Vec<usize>,
+}
+
+impl TraversalStack {
+    fn new(root: usize) -> Self {
+        let mut inline = [0; INLINE_STACK];
+        inline[0] = root;
+        TraversalStack { inline, len: 1, spill: Vec::new() }
+    }
+    
+    fn push(&mut self, node: usize) {
+        if self.len < INLINE_STACK {
+            self.inline[self.len] = node;
+            self.len += 1;
+        } else {
+            self.spill.push(node);
+        }
+    }
+    
+    fn pop(&mut self) -> Option<usize> {
+        if let Some(node) = self.spill.pop() {
+            return Some(node);
+        }
+        self.len = self.len.checked_sub(1)?;
+        Some(self.inline[self.len])
+    }
+}
+
+// Bounding volume hierarchy over a list of objects, referring to them by
+// index. Objects without a bounding box (infinite planes) are kept aside
+// and tested against every ray.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    order: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(objects: &[Box<dyn Object>]) -> Self {
+        Bvh::from_bounds(objects.iter().map(|object| object.bounding_box()))
+    }
+    
+    /// Hierarchy over items given by their bounding boxes, None for
+    /// unbounded items. Large hierarchies are built in parallel, into the
+    /// same tree whatever the number of threads.
+    ///
+    /// ```
+    /// use rt::bvh::{Aabb, Bvh};
+    /// use rt::vector::Vec3;
+    ///
+    /// let bounds: Vec<_> = (0..20_000)
+    ///     .map(|i| Some(Aabb::around(Vec3::new((i % 37) as f64, (i % 101) as f64 * 0.5, (i / 37) as f64 * 0.25), Vec3::new(0.1, 0.2, 0.3))))
+    ///     .collect();
+    /// let build = |threads| {
+    ///     let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    ///     let mut bytes = Vec::new();
+    ///     pool.install(|| Bvh::from_bounds(bounds.clone())).write(&mut bytes);
+    ///     bytes
+    /// };
+    /// assert_eq!(build(1), build(4));
+    /// ```
+    pub fn from_bounds(bounds: impl IntoIterator<Item = Option<Aabb>>) -> Self {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (index, bounds) in bounds.into_iter().enumerate() {
+            match bounds {
+                Some(bounds) => bounded.push((index, bounds)),
+                None => unbounded.push(index),
+            }
+        }
+        
+        let mut bvh = Bvh { nodes: Vec::new(), order: Vec::with_capacity(bounded.len()), unbounded };
+        if !bounded.is_empty() {
+            bvh.build_node(&mut bounded);
+        }
+        bvh
+    }
+    
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+    
+    pub fn unbounded_count(&self) -> usize {
+        self.unbounded.len()
+    }
+    
+    // Most bytes a hierarchy over `count` items takes, before it is built.
+    // Median splits of more than `MAX_LEAF_SIZE` items leave at least two
+    // on each side, so there is at most a leaf for every two items, and
+    // the interior nodes joining them.
+    pub fn memory_for(count: usize) -> usize {
+        let leaves = count.div_ceil(2);
+        mem::size_of::<Self>() + (2 * leaves).saturating_sub(1) * mem::size_of::<Node>() + count * mem::size_of::<usize>()
+    }
+    
+    // Bytes held by the hierarchy, including its own size
+    pub fn memory_estimate(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.nodes.capacity() * mem::size_of::<Node>()
+            + (self.order.capacity() + self.unbounded.capacity()) * mem::size_of::<usize>()
+    }
+    
+    // Nodes on the longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        fn depth_below(nodes: &[Node], index: usize) -> usize {
+            match nodes[index].kind {
+                NodeKind::Leaf { .. } => 1,
+                NodeKind::Interior { left, right } => 1 + depth_below(nodes, left).max(depth_below(nodes, right)),
+            }
+        }
+        if self.nodes.is_empty() { 0 } else { depth_below(&self.nodes, 0) }
+    }
+    
+    // Bounds of everything in the hierarchy, None if it is empty or holds
+    // unbounded items
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self.nodes.first() {
+            Some(root) if self.unbounded.is_empty() => Some(root.bounds),
+            _ => None,
+        }
+    }
+    
+    // Splits at the median centroid along the longest axis of the
+    // centroids' extent; returns the new node's index
+    fn build_node(&mut self, items: &mut [(usize, Aabb)]) -> usize {
+        let bounds = items.iter().skip(1).fold(items[0].1, |acc, (_, b)| acc.union(b));
+        let index = self.nodes.len();
+        
+        if items.len() <= MAX_LEAF_SIZE {
+            let start = self.order.len();
+            self.order.extend(items.iter().map(|(i, _)| *i));
+            self.nodes.push(Node { bounds, kind: NodeKind::Leaf { start, count: items.len() } });
+            return index;
+        }
+        
+        let first = items[0].1.centroid();
+        let centroids = items.iter().fold(Aabb::new(first, first), |acc, (_, b)| {
+            let c = b.centroid();
+            acc.union(&Aabb::new(c, c))
+        });
+        let extent = centroids.max - centroids.min;
+        let axis_value: fn(Vec3) -> f64 = if extent.x >= extent.y && extent.x >= extent.z {
+            |v| v.x
+        } else if extent.y >= extent.z {
+            |v| v.y
+        } else {
+            |v| v.z
+        };
+        
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+            axis_value(a.centroid()).total_cmp(&axis_value(b.centroid()))
+        });
+        
+        // Reserve this node's slot before building the children
+        self.nodes.push(Node { bounds, kind: NodeKind::Leaf { start: 0, count: 0 } });
+        let parallel = items.len() >= PARALLEL_BUILD_SIZE;
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let (left, right) = if parallel {
+            let (left, right) = rayon::join(|| Bvh::subtree(left_items), || Bvh::subtree(right_items));
+            (self.append(left), self.append(right))
+        } else {
+            (self.build_node(left_items), self.build_node(right_items))
+        };
+        self.nodes[index].kind = NodeKind::Interior { left, right };
+        index
+    }
+    
+    // A hierarchy of its own over `items`, to be appended to another
+    fn subtree(items: &mut [(usize, Aabb)]) -> Bvh {
+        let mut bvh = Bvh { nodes: Vec::new(), order: Vec::with_capacity(items.len()), unbounded: Vec::new() };
+        bvh.build_node(items);
+        bvh
+    }
+    
+    // Appends a subtree's nodes and order after this one's, in the same
+    // places building it here would have put them; returns its root's index
+    fn append(&mut self, subtree: Bvh) -> usize {
+        let (node_offset, order_offset) = (self.nodes.len(), self.order.len());
+        self.nodes.extend(subtree.nodes.into_iter().map(|node| Node {
+            bounds: node.bounds,
+            kind: match node.kind {
+                NodeKind::Leaf { start, count } => NodeKind::Leaf { start: start + order_offset, count },
+                NodeKind::Interior { left, right } => NodeKind::Interior { left: left + node_offset, right: right + node_offset },
+            },
+        }));
+        self.order.extend(subtree.order);
+        node_offset
+    }
+    
+    pub fn hit<'a>(
+        &self,
+        objects: &'a [Box<dyn Object>],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(HitRecord, &'a dyn Object)> {
+        self.hit_index(objects, ray, t_min, t_max)
+            .map(|(hit_record, index)| (hit_record, objects[index].as_ref()))
+    }
+    
+    // Like `hit`, but gives the index of the object that was hit
+    pub fn hit_index(
+        &self,
+        objects: &[Box<dyn Object>],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(HitRecord, usize)> {
+        self.closest_hit(ray, t_min, t_max, |index, closest_t| {
+            objects[index].hit(ray, t_min, closest_t)
+        })
+    }
+    
+    // Nearest hit among the items, found by calling `test(index, closest_t)`
+    // for each item whose box the ray reaches before the closest hit so far
+    pub fn closest_hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut test: impl FnMut(usize, f64) -> Option<HitRecord>,
+    ) -> Option<(HitRecord, usize)> {
+        let mut closest_hit: Option<(HitRecord, usize)> = None;
+        let mut closest_t = t_max;
+        
+        let mut test = |index: usize, closest_t: &mut f64| {
+            if let Some(hit_record) = test(index, *closest_t) {
+                *closest_t = hit_record.t;
+                closest_hit = Some((hit_record, index));
+            }
+        };
+        
+        for &index in &self.unbounded {
+            test(index, &mut closest_t);
+        }
+        
+        if !self.nodes.is_empty() {
+            let inv_dir = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+            let mut stack = TraversalStack::new(0);
+            while let Some(node_index) = stack.pop() {
+                let node = &self.nodes[node_index];
+                if !node.bounds.hit(ray.origin, inv_dir, t_min, closest_t) {
+                    continue;
+                }
+                match node.kind {
+                    NodeKind::Leaf { start, count } => {
+                        for &index in &self.order[start..start + count] {
+                            test(index, &mut closest_t);
+                        }
+                    }
+                    NodeKind::Interior { left, right } => {
+                        stack.push(right);
+                        stack.push(left);
+                    }
+                }
+            }
+        }
+        
+        closest_hit
+    }
+    
+    // Packet traversal: a node is entered if any lane's ray reaches its box
+    pub fn hit4<'a>(
+        &self,
+        objects: &'a [Box<dyn Object>],
+        packet: &RayPacket4,
+        t_min: [f64; 4],
+        t_max: [f64; 4],
+    ) -> [Option<(HitRecord, &'a dyn Object)>; 4] {
+        let mut closest_hits: [Option<(HitRecord, &dyn Object)>; 4] = [None; 4];
+        let mut closest_t = t_max;
+        
+        let mut test = |index: usize, closest_t: &mut [f64; 4]| {
+            let object = objects[index].as_ref();
+            let hits = object.hit4(packet, t_min, *closest_t);
+            for (lane, hit) in hits.into_iter().enumerate() {
+                if let Some(hit_record) = hit {
+                    closest_t[lane] = hit_record.t;
+                    closest_hits[lane] = Some((hit_record, object));
+                }
+            }
+        };
+        
+        for &index in &self.unbounded {
+            test(index, &mut closest_t);
+        }
+        
+        if !self.nodes.is_empty() {
+            let inv_dirs = packet.rays.map(|ray| {
+                Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z)
+            });
+            let mut stack = TraversalStack::new(0);
+            while let Some(node_index) = stack.pop() {
+                let node = &self.nodes[node_index];
+                let any_lane = (0..4).any(|lane| {
+                    node.bounds.hit(packet.rays[lane].origin, inv_dirs[lane], t_min[lane], closest_t[lane])
+                });
+                if !any_lane {
+                    continue;
+                }
+                match node.kind {
+                    NodeKind::Leaf { start, count } => {
+                        for &index in &self.order[start..start + count] {
+                            test(index, &mut closest_t);
+                        }
+                    }
+                    NodeKind::Interior { left, right } => {
+                        stack.push(right);
+                        stack.push(left);
+                    }
+                }
+            }
+        }
+        
+        closest_hits
+    }
+    
+    pub fn write(&self, out: &mut Vec<u8>) {
+        binary::put_u32(out, self.nodes.len() as u32);
+        for node in &self.nodes {
+            binary::put_vec3(out, node.bounds.min);
+            binary::put_vec3(out, node.bounds.max);
+            let (tag, a, b) = match node.kind {
+                NodeKind::Leaf { start, count } => (0, start, count),
+                NodeKind::Interior { left, right } => (1, left, right),
+            };
+            binary::put_u8(out, tag);
+            binary::put_u32(out, a as u32);
+            binary::put_u32(out, b as u32);
+        }
+        for indices in [&self.order, &self.unbounded] {
+            binary::put_u32(out, indices.len() as u32);
+            for &index in indices {
+                binary::put_u32(out, index as u32);
+            }
+        }
+    }
+    
+    // Reads a hierarchy written by `write` over `item_count` items,
+    // rejecting anything that would index out of range or loop
+    pub fn read(reader: &mut Reader, item_count: usize) -> Result<Bvh, String> {
+        let node_count = reader.count(57)?;
+        let mut nodes = Vec::with_capacity(node_count);
+        for _ in 0..node_count {
+            let bounds = Aabb::new(reader.vec3()?, reader.vec3()?);
+            let (tag, a, b) = (reader.u8()?, reader.u32()? as usize, reader.u32()? as usize);
+            let kind = match tag {
+                0 => NodeKind::Leaf { start: a, count: b },
+                1 => NodeKind::Interior { left: a, right: b },
+                _ => return Err(format!("bad BVH node tag {}", tag)),
+            };
+            nodes.push(Node { bounds, kind });
+        }
+        let mut indices = || -> Result<Vec<usize>, String> {
+            let count = reader.count(4)?;
+            let indices = (0..count).map(|_| reader.u32().map(|i| i as usize)).collect::<Result<Vec<_>, _>>()?;
+            if indices.iter().any(|&index| index >= item_count) {
+                return Err("BVH refers to a missing item".to_string());
+            }
+            Ok(indices)
+        };
+        let order = indices()?;
+        let unbounded = indices()?;
+        
+        for (index, node) in nodes.iter().enumerate() {
+            let valid = match node.kind {
+                NodeKind::Leaf { start, count } => start.checked_add(count).is_some_and(|end| end <= order.len()),
+                // Children always come after their parent
+                NodeKind::Interior { left, right } => {
+                    left > index && right > index && left < nodes.len() && right < nodes.len()
+                }
+            };
+            if !valid {
+                return Err(format!("BVH node {} is out of range", index));
+            }
+        }
+        Ok(Bvh { nodes, order, unbounded })
+    }
+}
@@ -1,12 +1,78 @@
 use crate::vector::Vec3;
 use crate::ray::Ray;
+use crate::rng::Rng;
+use crate::sampling::polygon_point;
+use std::f64::consts::TAU;
 
+// Same offset that keeps secondary rays from hitting their own surface
+pub const DEFAULT_NEAR: f64 = 0.001;
+
+// Range of vertical fields of view, in degrees, that perspective cameras
+// accept
+pub const MIN_FOV: f64 = 0.1;
+pub const MAX_FOV: f64 = 179.0;
+
+fn check_fov(fov: f64) -> Result<(), String> {
+    if !(MIN_FOV..=MAX_FOV).contains(&fov) {
+        return Err(format!("fov must be between {} and {} degrees (got {})", MIN_FOV, MAX_FOV, fov));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    // Rays spread from the position over the vertical field of view
+    Perspective,
+    // Parallel rays along the view direction, from a rectangle this tall
+    // centered on the position, so sizes do not shrink with distance
+    Orthographic { height: f64 },
+}
+
+// A thin lens in front of a perspective camera: rays leave from points
+// across an aperture this wide and meet again at the focus distance along
+// the view direction, blurring everything nearer or further. Apertures with
+// 3 or more blades are regular polygons turned `rotation` radians from the
+// camera's right, and round otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lens {
+    pub aperture: f64,
+    pub focus_distance: f64,
+    pub blades: u32,
+    pub rotation: f64,
+}
+
+impl Lens {
+    pub fn new(aperture: f64, focus_distance: f64) -> Result<Lens, String> {
+        if !(aperture >= 0.0 && aperture.is_finite()) {
+            return Err(format!("aperture must not be negative (got {})", aperture));
+        }
+        if !(focus_distance > 0.0 && focus_distance.is_finite()) {
+            return Err(format!("focus distance must be positive (got {})", focus_distance));
+        }
+        Ok(Lens { aperture, focus_distance, blades: 0, rotation: 0.0 })
+    }
+    
+    pub fn with_blades(self, blades: u32, rotation: f64) -> Lens {
+        Lens { blades, rotation, ..self }
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub position: Vec3,
     pub look_at: Vec3,
     pub up: Vec3,
     pub fov: f64,
+    // Width over height of the image in pixels, and of each pixel; the
+    // view is as wide as their product
     pub aspect_ratio: f64,
+    pub pixel_aspect: f64,
+    // Clipping planes for camera rays, as distances along the view
+    // direction; only surfaces between them are seen directly
+    pub near: f64,
+    pub far: f64,
+    pub projection: Projection,
+    pub lens: Option<Lens>,
     
     // Computed values
     pub u: Vec3,
@@ -18,10 +84,119 @@ pub struct Camera {
 }
 
 impl Camera {
-    pub fn new(position: Vec3, look_at: Vec3, up: Vec3, fov: f64, aspect_ratio: f64) -> Self {
-        let theta = fov.to_radians();
-        let viewport_height = 2.0 * (theta / 2.0).tan();
-        let viewport_width = aspect_ratio * viewport_height;
+    /// A perspective camera at `position` looking towards `look_at`, whose
+    /// view spans `fov` degrees from the bottom of the image to the top and
+    /// `aspect_ratio` times as much across. Fields of view outside
+    /// `MIN_FOV..=MAX_FOV` degrees are refused: towards 0 the view shrinks
+    /// to a point, towards 180 it grows without bound, and below 0 it
+    /// turns upside down.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::vector::Vec3;
+    ///
+    /// let camera = |fov| Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), fov, 1.0);
+    ///
+    /// // A telephoto view half a degree tall still has its top ray above
+    /// // its bottom one
+    /// let narrow = camera(0.5).unwrap();
+    /// let (top, bottom) = (narrow.get_ray(0.5, 1.0).direction, narrow.get_ray(0.5, 0.0).direction);
+    /// let angle = top.normalize().dot(&bottom.normalize()).acos().to_degrees();
+    /// assert!((angle - 0.5).abs() < 1e-9);
+    ///
+    /// // At 179 degrees the edges of the view look almost straight up and
+    /// // down, but still a little forward
+    /// let wide = camera(179.0).unwrap();
+    /// let top = wide.get_ray(0.5, 1.0).direction.normalize();
+    /// assert!(top.y > 0.9999 && top.z < 0.0);
+    ///
+    /// for fov in [0.0, 0.05, -60.0, 179.5, 180.0, f64::NAN] {
+    ///     assert!(camera(fov).is_err(), "{}", fov);
+    /// }
+    /// ```
+    pub fn new(position: Vec3, look_at: Vec3, up: Vec3, fov: f64, aspect_ratio: f64) -> Result<Self, String> {
+        check_fov(fov)?;
+        Ok(Camera::build(position, look_at, up, fov, aspect_ratio, 1.0, Projection::Perspective))
+    }
+    
+    /// A perspective camera framed as a lens `focal_length` millimeters
+    /// long would frame it on a sensor `sensor_width` millimeters wide (36
+    /// for full frame), the way photographers describe a shot. The image
+    /// is `aspect_ratio` times as wide as it is tall, so the sensor is cut
+    /// to that shape.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::vector::Vec3;
+    ///
+    /// let (position, look_at, up) = (Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0));
+    ///
+    /// // A 50mm lens on a full-frame sensor sees 39.6 degrees across
+    /// let camera = Camera::with_focal_length(position, look_at, up, 50.0, 36.0, 1.5).unwrap();
+    /// let across = 2.0 * (camera.horizontal.length() / 2.0).atan().to_degrees();
+    /// assert!((across - 39.598).abs() < 1e-3);
+    /// // and 27 degrees from top to bottom of a 3:2 frame
+    /// assert!((camera.fov - 26.991).abs() < 1e-3);
+    ///
+    /// // The same as asking for that field of view directly
+    /// let same = Camera::new(position, look_at, up, camera.fov, 1.5).unwrap();
+    /// assert!((same.get_ray(1.0, 1.0).direction - camera.get_ray(1.0, 1.0).direction).length() < 1e-12);
+    ///
+    /// assert!(Camera::with_focal_length(position, look_at, up, 0.0, 36.0, 1.5).is_err());
+    /// assert!(Camera::with_focal_length(position, look_at, up, 50.0, -36.0, 1.5).is_err());
+    /// // A 20 meter lens narrows the view past the smallest field of view
+    /// assert!(Camera::with_focal_length(position, look_at, up, 20000.0, 36.0, 1.5).is_err());
+    /// ```
+    pub fn with_focal_length(
+        position: Vec3,
+        look_at: Vec3,
+        up: Vec3,
+        focal_length: f64,
+        sensor_width: f64,
+        aspect_ratio: f64,
+    ) -> Result<Self, String> {
+        if !(focal_length > 0.0 && sensor_width > 0.0) {
+            return Err(format!(
+                "focal length and sensor width must be positive (got {}mm and {}mm)",
+                focal_length, sensor_width,
+            ));
+        }
+        let sensor_height = sensor_width / aspect_ratio;
+        let fov = 2.0 * (sensor_height / (2.0 * focal_length)).atan().to_degrees();
+        Camera::new(position, look_at, up, fov, aspect_ratio)
+            .map_err(|e| format!("a {}mm lens on a {}mm sensor: {}", focal_length, sensor_width, e))
+    }
+    
+    /// A camera whose rays all run parallel from `position` towards
+    /// `look_at`, covering a view `height` units tall and `aspect_ratio`
+    /// times as wide, as for maps and technical drawings. `fov` has no
+    /// meaning for it and is left at 0.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::vector::Vec3;
+    ///
+    /// let camera = Camera::orthographic(Vec3::new(0.0, 10.0, 0.0), Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), 4.0, 2.0);
+    /// let (corner, middle) = (camera.get_ray(0.0, 1.0), camera.get_ray(0.5, 0.5));
+    /// assert_eq!(corner.direction, middle.direction);
+    /// assert!((corner.origin - Vec3::new(-4.0, 10.0, -2.0)).length() < 1e-12);
+    /// assert!((middle.direction - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-12);
+    ///
+    /// // Points project straight down, whatever their distance
+    /// let (s, t, depth) = camera.project(Vec3::new(2.0, -5.0, 1.0)).unwrap();
+    /// assert!((s - 0.75).abs() < 1e-12 && (t - 0.25).abs() < 1e-12 && (depth - 15.0).abs() < 1e-12);
+    /// assert!(camera.project(Vec3::new(0.0, 11.0, 0.0)).is_none());
+    /// ```
+    pub fn orthographic(position: Vec3, look_at: Vec3, up: Vec3, height: f64, aspect_ratio: f64) -> Self {
+        Camera::build(position, look_at, up, 0.0, aspect_ratio, 1.0, Projection::Orthographic { height })
+    }
+    
+    fn build(position: Vec3, look_at: Vec3, up: Vec3, fov: f64, aspect_ratio: f64, pixel_aspect: f64, projection: Projection) -> Self {
+        let viewport_height = match projection {
+            Projection::Perspective => 2.0 * (fov.to_radians() / 2.0).tan(),
+            Projection::Orthographic { height } => height,
+        };
+        let viewport_width = aspect_ratio * pixel_aspect * viewport_height;
         
         let w = (position - look_at).normalize();
         let u = up.cross(&w).normalize();
@@ -29,7 +204,12 @@ impl Camera {
         
         let horizontal = u * viewport_width;
         let vertical = v * viewport_height;
-        let lower_left_corner = position - horizontal / 2.0 - vertical / 2.0 - w;
+        // The image plane is one unit ahead for perspective, and through
+        // the position for parallel rays
+        let lower_left_corner = match projection {
+            Projection::Perspective => position - horizontal / 2.0 - vertical / 2.0 - w,
+            Projection::Orthographic { .. } => position - horizontal / 2.0 - vertical / 2.0,
+        };
         
         Camera {
             position,
@@ -37,6 +217,11 @@ impl Camera {
             up,
             fov,
             aspect_ratio,
+            pixel_aspect,
+            near: DEFAULT_NEAR,
+            far: f64::INFINITY,
+            projection,
+            lens: None,
             u,
             v,
             w,
@@ -46,8 +231,299 @@ impl Camera {
         }
     }
     
+    /// The derived basis and viewport are always rebuilt from the stored
+    /// parameters, so adjusted cameras stay consistent with `new`.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::vector::Vec3;
+    ///
+    /// // Looking down at an angle, with an up vector that is not square to
+    /// // the view
+    /// let camera = Camera::new(Vec3::new(1.0, 4.0, 3.0), Vec3::new(-2.0, 0.0, -1.0), Vec3::new(0.2, 1.0, 0.1), 50.0, 1.5).unwrap();
+    /// let orthonormal = |camera: &Camera| {
+    ///     let (u, v, w) = (camera.u, camera.v, camera.w);
+    ///     [u.length(), v.length(), w.length()].iter().all(|length| (length - 1.0).abs() < 1e-12)
+    ///         && [u.dot(&v), v.dot(&w), w.dot(&u)].iter().all(|dot| dot.abs() < 1e-12)
+    ///         && (u.cross(&v) - w).length() < 1e-12
+    /// };
+    /// assert!(orthonormal(&camera));
+    ///
+    /// for fov in [0.1, 20.0, 90.0, 179.0] {
+    ///     let zoomed = camera.with_fov(fov).unwrap();
+    ///     assert!(orthonormal(&zoomed));
+    ///     assert!((zoomed.w - camera.w).length() < 1e-12 && (zoomed.u - camera.u).length() < 1e-12);
+    ///     assert!((zoomed.vertical.length() - 2.0 * (fov.to_radians() / 2.0).tan()).abs() < 1e-9);
+    ///     assert!((zoomed.horizontal.length() / zoomed.vertical.length() - 1.5).abs() < 1e-9);
+    /// }
+    /// assert!(camera.with_fov(180.0).is_err());
+    ///
+    /// // Moving keeps the view direction, so the basis does not change
+    /// let moved = camera.translated(Vec3::new(-7.0, 2.5, 30.0));
+    /// assert!(orthonormal(&moved));
+    /// assert!((moved.u - camera.u).length() < 1e-12 && (moved.v - camera.v).length() < 1e-12 && (moved.w - camera.w).length() < 1e-12);
+    /// assert!((moved.lower_left_corner - camera.lower_left_corner - Vec3::new(-7.0, 2.5, 30.0)).length() < 1e-9);
+    /// ```
+    pub fn with_fov(&self, fov: f64) -> Result<Camera, String> {
+        check_fov(fov)?;
+        let camera = Camera::build(self.position, self.look_at, self.up, fov, self.aspect_ratio, self.pixel_aspect, self.projection);
+        Ok(Camera { near: self.near, far: self.far, lens: self.lens, ..camera })
+    }
+    
+    pub fn translated(&self, delta: Vec3) -> Camera {
+        let (position, look_at) = (self.position + delta, self.look_at + delta);
+        let camera = Camera::build(position, look_at, self.up, self.fov, self.aspect_ratio, self.pixel_aspect, self.projection);
+        Camera { near: self.near, far: self.far, lens: self.lens, ..camera }
+    }
+    
+    /// Widens the view for pixels `pixel_aspect` times as wide as they are
+    /// tall, keeping the image's size in pixels. An image half as wide with
+    /// pixels twice as wide frames the same view as the square-pixel one,
+    /// and stretched back out matches it:
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::image::Image;
+    /// use rt::objects::Sphere;
+    /// use rt::material::Material;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// let render = |width: u32, camera: Camera| {
+    ///     let mut scene = Scene::new();
+    ///     scene.add_object(Box::new(Sphere::new(Vec3::new(0.3, 0.0, -3.0), 1.0, Material::default())));
+    ///     scene.set_camera(camera);
+    ///     let mut image = Image::new(width, 5);
+    ///     scene.render_into(&mut image, &RenderSettings::default());
+    ///     image
+    /// };
+    /// let camera = |aspect_ratio| Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 60.0, aspect_ratio).unwrap();
+    ///
+    /// // Pixel centers span the image from edge to edge, so the x-th of 4
+    /// // wide pixels sits where the 2x-th of 7 square pixels does
+    /// let square = render(7, camera(1.5));
+    /// let wide = render(4, camera(0.75).with_pixel_aspect(2.0));
+    /// for y in 0..5 {
+    ///     for x in 0..4 {
+    ///         assert_eq!(wide.get_pixel(x, y), square.get_pixel(2 * x, y));
+    ///     }
+    /// }
+    /// ```
+    pub fn with_pixel_aspect(&self, pixel_aspect: f64) -> Camera {
+        let camera = Camera::build(self.position, self.look_at, self.up, self.fov, self.aspect_ratio, pixel_aspect, self.projection);
+        Camera { near: self.near, far: self.far, lens: self.lens, ..camera }
+    }
+    
+    // Same camera with its position and the point it looks at moved by the
+    // shake's offsets `time` seconds into an animation
+    pub fn shaken(&self, shake: &Shake, seed: u64, time: f64) -> Camera {
+        let (position, look_at) = shake.offsets(seed, time);
+        let camera = Camera::build(
+            self.position + position,
+            self.look_at + look_at,
+            self.up,
+            self.fov,
+            self.aspect_ratio,
+            self.pixel_aspect,
+            self.projection,
+        );
+        Camera { near: self.near, far: self.far, lens: self.lens, ..camera }
+    }
+    
+    pub fn with_clip(self, near: f64, far: f64) -> Result<Camera, String> {
+        if !(near > 0.0 && far > near) {
+            return Err(format!("clipping planes need 0 < near < far (got near {}, far {})", near, far));
+        }
+        Ok(Camera { near, far, ..self })
+    }
+    
+    // Orthographic rays are all parallel, so they have no focus to blur
+    // around
+    pub fn with_lens(self, lens: Lens) -> Result<Camera, String> {
+        if let Projection::Orthographic { .. } = self.projection {
+            return Err("a lens needs a perspective camera".to_string());
+        }
+        Ok(Camera { lens: Some(lens), ..self })
+    }
+    
+    // A camera looking at its own position, or with `up` along the view
+    // direction, has no usable basis and would generate degenerate rays
+    pub fn validate(&self) -> Result<(), String> {
+        if self.w.length_squared() == 0.0 {
+            return Err("look_at must differ from position".to_string());
+        }
+        if self.u.length_squared() == 0.0 {
+            return Err("up must not be parallel to the view direction".to_string());
+        }
+        Ok(())
+    }
+    
+    // Range of ray parameters between the clipping planes for a camera
+    // ray. The planes are perpendicular to the view direction, so the
+    // range grows towards the edges of the image.
+    pub fn clip_range(&self, ray: &Ray) -> (f64, f64) {
+        let cos_angle = -ray.direction.dot(&self.w);
+        (self.near / cos_angle, self.far / cos_angle)
+    }
+    
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let direction = self.lower_left_corner + self.horizontal * s + self.vertical * t - self.position;
-        Ray::new(self.position, direction)
+        Ray::new(self.origin(s, t), self.direction(s, t))
+    }
+    
+    /// The ray through (s, t) from the point at (a, b) of the unit square
+    /// mapped onto the lens's aperture, or `get_ray` without a lens. Every
+    /// point of the aperture sees the same spot at the focus distance:
+    ///
+    /// ```
+    /// use rt::camera::{Camera, Lens};
+    /// use rt::vector::Vec3;
+    ///
+    /// let camera = Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 1.5).unwrap();
+    /// let lens = Lens::new(0.5, 3.0).unwrap().with_blades(6, 0.2);
+    /// let camera = camera.with_lens(lens).unwrap();
+    /// let focus = camera.position + camera.direction(0.3, 0.6) * 3.0;
+    /// for (a, b) in [(0.1, 0.9), (0.5, 0.5), (0.95, 0.2)] {
+    ///     let ray = camera.lens_ray(0.3, 0.6, a, b);
+    ///     assert!((ray.origin - camera.position).length() <= 0.25 + 1e-12);
+    ///     assert!(((focus - ray.origin).normalize() - ray.direction).length() < 1e-12);
+    /// }
+    /// ```
+    pub fn lens_ray(&self, s: f64, t: f64, a: f64, b: f64) -> Ray {
+        let lens = match self.lens {
+            Some(lens) => lens,
+            None => return self.get_ray(s, t),
+        };
+        let (x, y) = polygon_point(lens.blades, lens.rotation, a, b);
+        let radius = lens.aperture / 2.0;
+        let origin = self.position + self.u * (x * radius) + self.v * (y * radius);
+        let focus = self.position + self.direction(s, t) * lens.focus_distance;
+        Ray::new(origin, focus - origin)
+    }
+    
+    // Where the camera ray through (s, t) starts
+    pub fn origin(&self, s: f64, t: f64) -> Vec3 {
+        match self.projection {
+            Projection::Perspective => self.position,
+            Projection::Orthographic { .. } => self.lower_left_corner + self.horizontal * s + self.vertical * t,
+        }
+    }
+    
+    // Direction of the camera ray through (s, t), scaled to reach one unit
+    // along the view direction, so a point `d` along the view direction is
+    // at `origin + direction * d`
+    pub fn direction(&self, s: f64, t: f64) -> Vec3 {
+        match self.projection {
+            Projection::Perspective => self.lower_left_corner + self.horizontal * s + self.vertical * t - self.position,
+            Projection::Orthographic { .. } => -self.w,
+        }
+    }
+    
+    /// Inverse of `direction`: where `point` shows up, as the (s, t) that
+    /// `get_ray` takes, and its distance along the view direction. None for
+    /// points not in front of the camera.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::vector::Vec3;
+    ///
+    /// let camera = Camera::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 1.0, 0.0), 50.0, 1.5).unwrap();
+    /// let point = camera.position + camera.direction(0.2, 0.9) * 6.5;
+    /// let (s, t, depth) = camera.project(point).unwrap();
+    /// assert!((s - 0.2).abs() < 1e-12 && (t - 0.9).abs() < 1e-12 && (depth - 6.5).abs() < 1e-12);
+    /// assert!(camera.project(camera.position + camera.w).is_none());
+    /// ```
+    pub fn project(&self, point: Vec3) -> Option<(f64, f64, f64)> {
+        let offset = point - self.position;
+        let depth = -offset.dot(&self.w);
+        if depth <= 0.0 {
+            return None;
+        }
+        let on_plane = match self.projection {
+            Projection::Perspective => offset / depth,
+            Projection::Orthographic { .. } => offset,
+        };
+        let s = on_plane.dot(&self.horizontal) / self.horizontal.length_squared() + 0.5;
+        let t = on_plane.dot(&self.vertical) / self.vertical.length_squared() + 0.5;
+        Some((s, t, depth))
+    }
+}
+
+// Octaves of camera shake as (multiple of the frequency, weight). The
+// multiples are not whole numbers, so the sum never settles into a
+// visible loop.
+const SHAKE_OCTAVES: [(f64, f64); 3] = [(1.0, 1.0), (2.17, 0.5), (4.53, 0.25)];
+
+// First key of the shake's random streams, keeping them apart from those
+// of pixels and photons
+const SHAKE_STREAM: u64 = u64::MAX;
+
+/// Procedural shake for animated cameras: the position and the point looked
+/// at each wander around where the animation puts them, along every axis,
+/// as a sum of a few sine octaves with phases drawn from the render's seed.
+/// The sines average out, so a shaken camera keeps to its path on average,
+/// and the same seed shakes it the same way on every render.
+///
+/// ```
+/// use rt::camera::Shake;
+/// use rt::vector::Vec3;
+///
+/// let shake = Shake::new(0.05, 2.0).unwrap();
+/// let frames: Vec<(Vec3, Vec3)> = (0..2400).map(|frame| shake.offsets(7, frame as f64 / 24.0)).collect();
+///
+/// // Never further than the amplitude along any axis, and zero on average
+/// let mut sum = Vec3::zero();
+/// for &(position, look_at) in &frames {
+///     for offset in [position, look_at] {
+///         assert!(offset.x.abs().max(offset.y.abs()).max(offset.z.abs()) <= 0.05);
+///         sum = sum + offset;
+///     }
+/// }
+/// let mean = sum / (2 * frames.len()) as f64;
+/// assert!(mean.length() < 0.001, "{:?}", mean);
+/// // but it does move
+/// assert!(frames.iter().any(|(position, _)| position.x > 0.03));
+///
+/// // The same for the same seed only
+/// assert_eq!(shake.offsets(7, 1.5), frames[36]);
+/// assert_ne!(shake.offsets(8, 1.5), frames[36]);
+///
+/// assert!(Shake::new(-0.1, 2.0).is_err());
+/// assert!(Shake::new(0.05, 0.0).is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shake {
+    // Furthest the camera moves along each axis, in meters
+    pub amplitude: f64,
+    // Cycles per second of the slowest octave
+    pub frequency: f64,
+}
+
+impl Shake {
+    pub fn new(amplitude: f64, frequency: f64) -> Result<Shake, String> {
+        if !(amplitude >= 0.0 && amplitude.is_finite()) {
+            return Err(format!("amplitude must be zero or more (got {})", amplitude));
+        }
+        if !(frequency > 0.0 && frequency.is_finite()) {
+            return Err(format!("frequency must be positive (got {})", frequency));
+        }
+        Ok(Shake { amplitude, frequency })
+    }
+    
+    // How far the position and the point looked at are moved `time`
+    // seconds into an animation
+    pub fn offsets(&self, seed: u64, time: f64) -> (Vec3, Vec3) {
+        let total: f64 = SHAKE_OCTAVES.iter().map(|(_, weight)| weight).sum();
+        let wave = |stream: u64| {
+            let mut rng = Rng::keyed(seed, &[SHAKE_STREAM, stream]);
+            let sum: f64 = SHAKE_OCTAVES.iter()
+                .map(|&(multiple, weight)| {
+                    let phase = rng.next_f64() * TAU;
+                    weight * (TAU * self.frequency * multiple * time + phase).sin()
+                })
+                .sum();
+            self.amplitude * sum / total
+        };
+        let offset = |point: u64| Vec3::new(wave(3 * point), wave(3 * point + 1), wave(3 * point + 2));
+        (offset(0), offset(1))
     }
 }
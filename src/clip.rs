@@ -0,0 +1,120 @@
+use crate::vector::Vec3;
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::objects::Plane;
+
+/// Cuts away everything on the side of the plane that `normal` points to,
+/// for section views. Hits there are ignored, so rays see into the objects
+/// the plane passes through.
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::ray::Ray;
+/// use rt::scene::{RenderSettings, Scene};
+/// use rt::vector::Vec3;
+///
+/// // A ball cut through its middle, and a pebble wholly below the cut
+/// let scene = |clip: &str, camera: &str| -> Scene {
+///     format!(r#"{{
+///         "camera": {{ {} }},
+///         "materials": {{ "section": {{ "preset": "matte", "color": [0.9, 0.1, 0.1] }} }},
+///         "lights": [{{ "position": [0, 5, 5] }}],
+///         "objects": [
+///             {{ "type": "sphere", "name": "ball", "center": [-1.2, 0, 0], "radius": 1 }},
+///             {{ "type": "sphere", "name": "pebble", "center": [1.5, -1.5, 0], "radius": 0.5 }}
+///         ],
+///         "clip": [{}]
+///     }}"#, camera, clip).parse().unwrap()
+/// };
+/// let cut = r#"{ "point": [0, 0, 0], "normal": [0, 1, 0], "section": "section" }"#;
+/// let front = r#""position": [0, 0, 6], "look_at": [0, 0, 0]"#;
+/// let (clipped, whole) = (scene(cut, front), scene("", front));
+/// let ahead = |x: f64, y: f64| Ray::new(Vec3::new(x, y, 6.0), Vec3::new(0.0, 0.0, -1.0));
+///
+/// // Rays through the removed top half of the ball go straight past it
+/// for x in [-1.8, -1.2, -0.6] {
+///     assert!(whole.hit(&ahead(x, 0.5), 0.001, 100.0).is_some());
+///     assert!(clipped.hit(&ahead(x, 0.5), 0.001, 100.0).is_none());
+/// }
+///
+/// // Looking down, the ball is capped where the cut passes through it
+/// let down = Ray::new(Vec3::new(-1.2, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+/// let (hit, object) = clipped.hit(&down, 0.001, 100.0).unwrap();
+/// assert_eq!((hit.t, hit.geometric_normal), (5.0, Vec3::new(0.0, 1.0, 0.0)));
+/// assert_eq!(object.material().color.g, 0.1);
+///
+/// // and the cap shades with the section color
+/// let above = r#""position": [-1.2, 4, 3], "look_at": [-1.2, 0, 0]"#;
+/// let render = |scene: &Scene| {
+///     let mut image = Image::new(16, 16);
+///     scene.render_into(&mut image, &RenderSettings::default());
+///     image.get_pixel(8, 8)
+/// };
+/// let (capped, uncut) = (render(&scene(cut, above)), render(&scene("", above)));
+/// assert!(capped.r > 3.0 * capped.g && capped.g == capped.b);
+/// assert!(uncut.r == uncut.g && uncut.g == uncut.b);
+///
+/// // The pebble, wholly on the kept side, is hit just as it was
+/// for i in 0..25 {
+///     let ray = ahead(1.1 + (i % 5) as f64 * 0.2, -1.9 + (i / 5) as f64 * 0.2);
+///     let t = |scene: &Scene| scene.hit(&ray, 0.001, 100.0).map(|(hit, _)| hit.t);
+///     assert_eq!(t(&clipped), t(&whole));
+/// }
+/// ```
+pub struct ClipPlane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    // Flat cap drawn where the plane cuts through a closed object, if any
+    pub section: Option<Plane>,
+}
+
+impl ClipPlane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        ClipPlane {
+            point,
+            normal: normal.normalize(),
+            section: None,
+        }
+    }
+    
+    pub fn with_section(mut self, material: Material) -> Self {
+        self.section = Some(Plane::new(self.point, self.normal, material));
+        self
+    }
+}
+
+// The part of a ray's [t_min, t_max] that is left by the clipping planes.
+// Every plane keeps a half-space, so this is always a single interval.
+#[derive(Debug, Clone, Copy)]
+pub struct KeptRange {
+    pub t_min: f64,
+    pub t_max: f64,
+    // Plane the ray crosses into the kept part through, when it starts in
+    // a cut-away part
+    pub entry: Option<usize>,
+}
+
+pub fn kept_range(planes: &[ClipPlane], ray: &Ray, t_min: f64, t_max: f64) -> Option<KeptRange> {
+    let mut range = KeptRange { t_min, t_max, entry: None };
+    for (index, plane) in planes.iter().enumerate() {
+        // Positive distances are on the cut-away side
+        let distance = plane.normal.dot(&(ray.origin - plane.point));
+        let rate = plane.normal.dot(&ray.direction);
+        if rate == 0.0 {
+            if distance > 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = -distance / rate;
+        if rate < 0.0 {
+            if t > range.t_min {
+                range.t_min = t;
+                range.entry = Some(index);
+            }
+        } else {
+            range.t_max = range.t_max.min(t);
+        }
+    }
+    (range.t_min <= range.t_max).then_some(range)
+}
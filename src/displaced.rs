@@ -0,0 +1,267 @@
+use crate::vector::Vec3;
+use crate::ray::{Ray, HitRecord};
+use crate::material::Material;
+use crate::objects::Object;
+use crate::bvh::Aabb;
+use crate::rng::Rng;
+use std::any::Any;
+
+// Octaves of noise summed into the displacement, each at twice the
+// frequency and half the weight of the one before
+const OCTAVES: u32 = 4;
+
+// Bound on how fast one octave of `value_noise` changes per unit moved:
+// the steepest slope of the fade curve (15/8) across the widest step
+// between lattice values (2), along each of three axes
+const NOISE_SLOPE: f64 = 15.0 / 8.0 * 2.0 * 1.7320508075688772;
+
+// Steps along one ray before giving up, which only rays grazing the
+// surface for a long way reach; they are counted as misses
+const MAX_STEPS: u32 = 512;
+
+/// A sphere whose radius rises and falls with smooth noise over its
+/// surface, for rocky asteroids and moons without building a mesh. The
+/// surface lies within `amplitude` of `radius`: noise summed over a few
+/// octaves, ridged so bumps rise to sharp crests between rounded hollows,
+/// and fixed to the sphere's own directions so it moves with it.
+/// `frequency` is about how many hollows span one radius, and `seed` picks
+/// the pattern. A plain `Sphere` stays the fast choice for smooth ones.
+///
+/// Rays are clipped to the sphere of the largest radius, then stepped
+/// along by the distance to the surface divided by how steeply the surface
+/// can tilt, which can never step through it. Normals come from finite
+/// differences of the radius.
+///
+/// ```
+/// use rt::displaced::DisplacedSphere;
+/// use rt::material::Material;
+/// use rt::objects::Object;
+/// use rt::ray::Ray;
+/// use rt::vector::Vec3;
+///
+/// let sphere = DisplacedSphere::new(Vec3::zero(), 1.0, 0.1, 3.0, 7, Material::default()).unwrap();
+///
+/// // Straight at the center the hit is within the displacement band, on
+/// // the surface, facing back along the ray within the bumps' tilt
+/// let ray = Ray::new(Vec3::new(0.3, 0.2, 5.0), Vec3::new(-0.3, -0.2, -5.0));
+/// let hit = sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+/// let distance = hit.point.length();
+/// assert!((0.9..=1.1).contains(&distance));
+/// assert!((distance - sphere.surface_radius(hit.point)).abs() < 1e-6);
+/// assert!(hit.front_face && hit.geometric_normal.dot(&hit.point.normalize()) > 0.5);
+///
+/// // The surface is not round
+/// let radii: Vec<f64> = (0..100).map(|i| sphere.surface_radius(Vec3::new((i as f64 * 0.7).cos(), (i as f64 * 0.3).sin(), 0.5))).collect();
+/// assert!(radii.iter().cloned().fold(0.0, f64::max) - radii.iter().cloned().fold(2.0, f64::min) > 0.05);
+///
+/// // Rays just outside the band miss, and the box holds the whole band
+/// let miss = Ray::new(Vec3::new(1.101, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+/// assert!(sphere.hit(&miss, 0.001, f64::INFINITY).is_none());
+/// assert!(sphere.bounding_box().unwrap().max.x >= 1.1);
+///
+/// // From inside the band, on the inside of the surface, rays find the
+/// // way out as a back face
+/// let inside = Vec3::new(0.0, 0.0, 0.5);
+/// let out = sphere.hit(&Ray::new(inside, Vec3::new(0.0, 0.0, 1.0)), 0.0, f64::INFINITY).unwrap();
+/// assert!(!out.front_face && (out.point.length() - sphere.surface_radius(out.point)).abs() < 1e-6);
+/// // and a ray along the surface of the band stops at the first bump in
+/// // its way or leaves, without running out of steps
+/// let band = Ray::new(Vec3::new(0.0, 1.05, 0.0), Vec3::new(1.0, 0.0, 0.0));
+/// if let Some(hit) = sphere.hit(&band, 0.0, f64::INFINITY) {
+///     assert!((hit.point.length() - sphere.surface_radius(hit.point)).abs() < 1e-6);
+/// }
+///
+/// // Entering and leaving pair up into a span through the middle
+/// let through = sphere.hit_range(&Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), 0.0, f64::INFINITY);
+/// assert_eq!(through.len(), 1);
+/// assert!((through[0].1 - through[0].0 - 2.0).abs() < 0.2 + 1e-9);
+///
+/// assert!(DisplacedSphere::new(Vec3::zero(), 1.0, 1.0, 3.0, 0, Material::default()).is_err());
+/// assert!(DisplacedSphere::new(Vec3::zero(), 1.0, 0.1, 0.0, 0, Material::default()).is_err());
+/// ```
+pub struct DisplacedSphere {
+    pub center: Vec3,
+    pub radius: f64,
+    pub amplitude: f64,
+    pub frequency: f64,
+    pub seed: u64,
+    pub material: Material,
+    // Bound on how much the distance from the center less the surface's
+    // radius there changes per unit moved, anywhere in the band
+    steepness: f64,
+}
+
+impl DisplacedSphere {
+    pub fn new(center: Vec3, radius: f64, amplitude: f64, frequency: f64, seed: u64, material: Material) -> Result<Self, String> {
+        if radius <= 0.0 || !radius.is_finite() {
+            return Err("radius must be positive".to_string());
+        }
+        if !(0.0..radius).contains(&amplitude) {
+            return Err(format!("amplitude must be at least 0 and less than the radius {} (got {})", radius, amplitude));
+        }
+        if !(frequency > 0.0 && frequency.is_finite()) {
+            return Err(format!("frequency must be positive (got {})", frequency));
+        }
+        // The noise is fed directions scaled by the frequency, which turn
+        // one unit per radius sideways, least at the inside of the band.
+        // Folding an octave doubles its slope, and each octave's weight
+        // cancels its frequency.
+        let noise_slope = 2.0 * NOISE_SLOPE * OCTAVES as f64 / octave_weights();
+        let steepness = 1.0 + amplitude * frequency * noise_slope / (radius - amplitude);
+        Ok(DisplacedSphere { center, radius, amplitude, frequency, seed, material, steepness })
+    }
+    
+    // Distance from the center to the surface in the direction of `point`
+    pub fn surface_radius(&self, point: Vec3) -> f64 {
+        let direction = (point - self.center).normalize();
+        self.radius + self.amplitude * ridged_noise(direction * self.frequency, self.seed)
+    }
+    
+    // Negative inside the surface and positive outside, changing by at most
+    // `steepness` per unit moved
+    fn field(&self, point: Vec3) -> f64 {
+        (point - self.center).length() - self.surface_radius(point)
+    }
+    
+    fn normal(&self, point: Vec3) -> Vec3 {
+        let h = self.radius * 1e-5;
+        let axis = |offset: Vec3| self.field(point + offset) - self.field(point - offset);
+        Vec3::new(
+            axis(Vec3::new(h, 0.0, 0.0)),
+            axis(Vec3::new(0.0, h, 0.0)),
+            axis(Vec3::new(0.0, 0.0, h)),
+        ).normalize()
+    }
+    
+    // First t in [start, end] where the ray crosses the surface, from
+    // whichever side it starts on. The steps close in on the surface
+    // without reaching it, so once within a hair of it the ray is pushed
+    // across and the crossing narrowed down between the last two points;
+    // the t returned is just past the surface, so a search from there
+    // finds the next crossing rather than this one again.
+    fn crossing(&self, ray: &Ray, start: f64, end: f64) -> Option<f64> {
+        let outside = self.field(ray.at(start)) >= 0.0;
+        let gap = |t: f64| if outside { self.field(ray.at(t)) } else { -self.field(ray.at(t)) };
+        // Within a hair of the surface the steps would shrink towards
+        // nothing, so they are kept to at least this, short enough to
+        // step through nothing that shows
+        let min_step = self.radius * 1e-4;
+        // The sphere inside the band is wholly inside the surface, so from
+        // inside, rays jump across it
+        let core = match outside {
+            true => None,
+            false => sphere_span(ray, self.center, self.radius - self.amplitude),
+        };
+        let (mut before, mut t) = (start, start);
+        for _ in 0..MAX_STEPS {
+            if let Some((enter, leave)) = core {
+                if (enter..leave).contains(&t) {
+                    (before, t) = (leave, leave);
+                }
+            }
+            let distance = gap(t);
+            if distance < 0.0 {
+                let mut after = t;
+                for _ in 0..50 {
+                    let middle = 0.5 * (before + after);
+                    if gap(middle) < 0.0 {
+                        after = middle;
+                    } else {
+                        before = middle;
+                    }
+                }
+                return Some(after);
+            }
+            before = t;
+            t += (distance / self.steepness).max(min_step);
+            if t > end {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+// Where the ray (of unit direction) enters and leaves a sphere
+fn sphere_span(ray: &Ray, center: Vec3, radius: f64) -> Option<(f64, f64)> {
+    let oc = ray.origin - center;
+    let half_b = oc.dot(&ray.direction);
+    let discriminant = half_b * half_b - (oc.length_squared() - radius * radius);
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some((-half_b - root, -half_b + root))
+}
+
+// Sum of the octaves' weights, which scales their sum back into [-1, 1]
+fn octave_weights() -> f64 {
+    (0..OCTAVES).map(|octave| 0.5_f64.powi(octave as i32)).sum()
+}
+
+// Octaves of noise folded at zero and flipped, so each has sharp crests
+// where the noise crosses zero and rounded troughs between; in [-1, 1]
+fn ridged_noise(point: Vec3, seed: u64) -> f64 {
+    let mut total = 0.0;
+    for octave in 0..OCTAVES {
+        let scale = (1u32 << octave) as f64;
+        total += (1.0 - 2.0 * value_noise(point * scale, seed + octave as u64).abs()) / scale;
+    }
+    total / octave_weights()
+}
+
+// Random values in [-1, 1] at the integer lattice points, blended between
+// them with a curve that is flat at both ends, so the noise is smooth
+fn value_noise(point: Vec3, seed: u64) -> f64 {
+    let corner = Vec3::new(point.x.floor(), point.y.floor(), point.z.floor());
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (u, v, w) = (fade(point.x - corner.x), fade(point.y - corner.y), fade(point.z - corner.z));
+    let lattice = |dx: f64, dy: f64, dz: f64| {
+        let keys = [corner.x + dx, corner.y + dy, corner.z + dz].map(|c| c as i64 as u64);
+        2.0 * Rng::keyed(seed, &keys).next_f64() - 1.0
+    };
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    let face = |dz: f64| {
+        lerp(
+            lerp(lattice(0.0, 0.0, dz), lattice(1.0, 0.0, dz), u),
+            lerp(lattice(0.0, 1.0, dz), lattice(1.0, 1.0, dz), u),
+            v,
+        )
+    };
+    lerp(face(0.0), face(1.0), w)
+}
+
+impl Object for DisplacedSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // The band's outer sphere bounds where the surface can be
+        let (start, end) = sphere_span(ray, self.center, self.radius + self.amplitude)?;
+        let (start, end) = (start.max(t_min), end.min(t_max));
+        if start > end {
+            return None;
+        }
+        
+        let t = self.crossing(ray, start, end)?;
+        let point = ray.at(t);
+        Some(HitRecord::new(point, self.normal(point), t, ray))
+    }
+    
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "displaced_sphere"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, Vec3::splat(self.radius + self.amplitude)))
+    }
+}
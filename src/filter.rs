@@ -0,0 +1,101 @@
+// Reconstruction filter used to combine samples into pixels. Radii are in
+// pixels, measured from the pixel center along each axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box { radius: f64 },
+    Tent { radius: f64 },
+    Gaussian { radius: f64 },
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius } | Filter::Tent { radius } | Filter::Gaussian { radius } => radius,
+        }
+    }
+    
+    /// The same kind of filter with another radius. Tent and gaussian
+    /// filters weigh a sample by how close it lands to a pixel center, so
+    /// they need at least half a pixel to reach every pixel's own samples:
+    ///
+    /// ```
+    /// use rt::filter::Filter;
+    /// use rt::image::{color::Color, Image};
+    /// use rt::scene::{RenderSettings, Scene};
+    ///
+    /// let gaussian = Filter::Gaussian { radius: 1.5 };
+    /// assert_eq!(gaussian.with_radius(0.5), Ok(Filter::Gaussian { radius: 0.5 }));
+    /// for radius in [0.2, 0.0, -1.0, f64::NAN, f64::INFINITY] {
+    ///     assert!(gaussian.with_radius(radius).is_err());
+    ///     assert!(Filter::Tent { radius: 1.0 }.with_radius(radius).is_err());
+    /// }
+    /// // A box that narrow is a plain per-pixel average
+    /// assert!(Filter::Box { radius: 0.5 }.with_radius(0.2).unwrap().is_pixel_local());
+    ///
+    /// // Built directly, a narrower filter still leaves no pixel without
+    /// // weight: those its samples miss take their nearest sample
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+    ///     "background": { "bottom": [0.9, 0.9, 0.85], "top": [0.4, 0.55, 0.8] },
+    ///     "lights": [{ "type": "point", "position": [2, 3, 0] }],
+    ///     "objects": [{ "type": "sphere", "center": [0, 0, -3], "radius": 1 }]
+    /// }"#.parse().unwrap();
+    /// for filter in [Filter::Gaussian { radius: 0.2 }, Filter::Tent { radius: 0.2 }] {
+    ///     let settings = RenderSettings { filter, samples_per_pixel: 4, ..RenderSettings::default() };
+    ///     let mut image = Image::new(40, 30);
+    ///     scene.render_into(&mut image, &settings);
+    ///     assert!(image.pixels.iter().all(|&pixel| pixel != Color::BLACK));
+    /// }
+    /// ```
+    pub fn with_radius(&self, radius: f64) -> Result<Filter, String> {
+        if !radius.is_finite() || radius < 0.0 {
+            return Err(format!("the filter radius must be a finite number of pixels (got {})", radius));
+        }
+        match self {
+            Filter::Box { .. } => Ok(Filter::Box { radius }),
+            Filter::Tent { .. } | Filter::Gaussian { .. } if radius < 0.5 => {
+                Err(format!("a radius of {} px cannot cover a pixel; tent and gaussian filters need at least 0.5", radius))
+            }
+            Filter::Tent { .. } => Ok(Filter::Tent { radius }),
+            Filter::Gaussian { .. } => Ok(Filter::Gaussian { radius }),
+        }
+    }
+    
+    // A box no wider than the pixel only ever weights a sample into its own
+    // pixel, equally, so the result is a plain per-pixel average
+    pub fn is_pixel_local(&self) -> bool {
+        matches!(*self, Filter::Box { radius } if radius <= 0.5)
+    }
+    
+    // Weight of a sample `dx`, `dy` pixels away from a pixel center
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+    
+    fn weight_1d(&self, x: f64) -> f64 {
+        let radius = self.radius();
+        let x = x.abs();
+        if x > radius {
+            return 0.0;
+        }
+        
+        match self {
+            Filter::Box { .. } => 1.0,
+            // Also keeps a zero radius from dividing by zero
+            Filter::Tent { .. } if x >= radius => 0.0,
+            Filter::Tent { .. } => 1.0 - x / radius,
+            Filter::Gaussian { .. } => {
+                // Shifted down so the weight reaches zero at the radius
+                let sigma = radius / 2.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(radius)
+            }
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::vector::Vec3;
+
+// Aspect ratios a bit wider or narrower than this are refused as typos
+const MAX_ASPECT: u32 = 100;
+
+/// An aspect ratio written as WxH or W:H in whole numbers, such as 16x9
+/// or 4:5, kept as written so framings can be listed by their names.
+///
+/// ```
+/// use rt::framing::parse_aspect;
+///
+/// assert_eq!(parse_aspect("16x9"), Ok((16, 9)));
+/// assert_eq!(parse_aspect(" 4:5 "), Ok((4, 5)));
+/// assert_eq!(parse_aspect("1X1"), Ok((1, 1)));
+/// assert!(parse_aspect("16").is_err());
+/// assert!(parse_aspect("16x0").is_err());
+/// assert!(parse_aspect("1.5x1").is_err());
+/// ```
+pub fn parse_aspect(s: &str) -> Result<(u32, u32), String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let Some((width, height)) = lower.split_once(['x', ':']) else {
+        return Err(format!("expected an aspect ratio such as 16x9 or 4:5, got '{}'", s));
+    };
+    let side = |text: &str| match text.trim().parse::<u32>() {
+        Ok(side) if (1..=MAX_ASPECT).contains(&side) => Ok(side),
+        _ => Err(format!("expected an aspect ratio in whole numbers from 1 to {}, such as 16x9, got '{}'", MAX_ASPECT, s)),
+    };
+    Ok((side(width)?, side(height)?))
+}
+
+// Whether two aspect ratios are the same, however they are written
+pub fn same_aspect(a: (u32, u32), b: (u32, u32)) -> bool {
+    a.0 as u64 * b.1 as u64 == a.1 as u64 * b.0 as u64
+}
+
+// A view of the scene composed for images of one aspect ratio, such as
+// a square thumbnail or a 16:9 video frame, picked instead of the
+// camera's own view
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framing {
+    pub aspect: (u32, u32),
+    pub position: Vec3,
+    pub look_at: Vec3,
+    pub up: Vec3,
+    // Vertical, in degrees, for an image of exactly this aspect ratio
+    pub fov: f64,
+}
+
+impl Framing {
+    // Width over height
+    pub fn ratio(&self) -> f64 {
+        self.aspect.0 as f64 / self.aspect.1 as f64
+    }
+}
+
+// What to do when the image's aspect ratio differs from the framing's:
+// crop the framing to fill the image, or fit all of it in and leave bars
+// at the sides or top and bottom
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fit {
+    Crop,
+    Letterbox,
+}
+
+impl Fit {
+    /// The vertical field of view, in degrees, for a view `view_aspect`
+    /// wide (width over height) that crops or letterboxes a framing
+    /// `aspect` wide with vertical field of view `fov`, and the size of
+    /// the framing as fractions of the view's width and height, centered
+    /// in it. Cropping keeps one side of the framing and cuts the other,
+    /// which then measures more than 1; letterboxing keeps one side and
+    /// leaves room around the other, which measures less.
+    ///
+    /// ```
+    /// use rt::framing::Fit;
+    ///
+    /// let tan = |fov: f64| (fov.to_radians() / 2.0).tan();
+    /// let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+    /// let (square, wide, tall) = (1.0, 16.0 / 9.0, 4.0 / 5.0);
+    ///
+    /// // A square framing cropped for a wide image keeps its width and
+    /// // loses a little at the top and bottom
+    /// let (fov, frame) = Fit::Crop.view(60.0, square, wide);
+    /// assert!(close(tan(fov), tan(60.0) * 9.0 / 16.0) && close(frame.0, 1.0) && close(frame.1, 16.0 / 9.0));
+    /// // ... and for a tall one keeps its height and loses the sides
+    /// let (fov, frame) = Fit::Crop.view(60.0, square, tall);
+    /// assert!(close(fov, 60.0) && close(frame.0, 1.25) && close(frame.1, 1.0));
+    ///
+    /// // Letterboxed in a wide image, it keeps its height with bars at the
+    /// // sides
+    /// let (fov, frame) = Fit::Letterbox.view(60.0, square, wide);
+    /// assert!(close(fov, 60.0) && close(frame.0, 9.0 / 16.0) && close(frame.1, 1.0));
+    /// // ... and in a tall one keeps its width with bars above and below
+    /// let (fov, frame) = Fit::Letterbox.view(60.0, square, tall);
+    /// assert!(close(tan(fov), tan(60.0) * 1.25) && close(frame.0, 1.0) && close(frame.1, 0.8));
+    ///
+    /// // In every case the framing's width is its height times its aspect
+    /// for fit in [Fit::Crop, Fit::Letterbox] {
+    ///     for (aspect, view) in [(wide, tall), (tall, wide), (wide, square), (square, square)] {
+    ///         let (fov, frame) = fit.view(45.0, aspect, view);
+    ///         let view_height = tan(fov);
+    ///         assert!(close(frame.1 * view_height, tan(45.0)));
+    ///         assert!(close(frame.0 * view_height * view, tan(45.0) * aspect));
+    ///     }
+    ///     assert_eq!(fit.view(45.0, wide, wide), (45.0, (1.0, 1.0)));
+    /// }
+    /// ```
+    pub fn view(self, fov: f64, aspect: f64, view_aspect: f64) -> (f64, (f64, f64)) {
+        // How much taller than the framing the view is
+        let scale = match self {
+            Fit::Crop => (aspect / view_aspect).min(1.0),
+            Fit::Letterbox => (aspect / view_aspect).max(1.0),
+        };
+        let fov = if scale == 1.0 { fov } else { 2.0 * ((fov.to_radians() / 2.0).tan() * scale).atan().to_degrees() };
+        (fov, (aspect / (scale * view_aspect), 1.0 / scale))
+    }
+}
@@ -0,0 +1,188 @@
+use crate::vector::Vec3;
+use crate::image::color::Color;
+use crate::ray::{Ray, HitRecord};
+use crate::material::Material;
+use crate::objects::Object;
+use crate::bvh::{Aabb, Bvh};
+use crate::transform::Mat4;
+use crate::lod::Lod;
+use std::any::Any;
+use std::mem;
+
+/// Objects placed together through one transform, such as the top and legs
+/// of a table. Rays are carried into the group's own space once and tested
+/// against the children there. Groups can hold groups, each placed relative
+/// to its parent.
+///
+/// ```
+/// use rt::group::Group;
+/// use rt::image::color::Color;
+/// use rt::material::Material;
+/// use rt::objects::{Cube, Object, Sphere};
+/// use rt::ray::Ray;
+/// use rt::transform::Mat4;
+/// use rt::vector::Vec3;
+///
+/// let red = Material { color: Color::new(1.0, 0.0, 0.0), ..Material::default() };
+/// // A sphere one unit along x inside the inner group...
+/// let inner = Group::new(vec![
+///     Box::new(Cube::new(Vec3::new(0.0, 5.0, 0.0), 1.0, Material::default())),
+///     Box::new(Sphere::new(Vec3::zero(), 0.5, red)),
+/// ], Mat4::translation(Vec3::new(1.0, 0.0, 0.0))).unwrap();
+/// // ...turned a quarter around y and moved back by the outer group, which
+/// // puts its center at (0, 0, -6)
+/// let outer = Group::new(
+///     vec![Box::new(inner)],
+///     Mat4::translation(Vec3::new(0.0, 0.0, -5.0)) * Mat4::rotation_y(90.0),
+/// ).unwrap();
+///
+/// let ray = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+/// let hit = outer.hit(&ray, 0.001, f64::INFINITY).unwrap();
+/// assert!((hit.t - 5.5).abs() < 1e-9);
+/// assert!((hit.point - Vec3::new(0.0, 0.0, -5.5)).length() < 1e-9);
+/// assert!((hit.geometric_normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-9);
+/// assert_eq!(outer.material_at(&hit).color, red.color);
+/// ```
+pub struct Group {
+    children: Vec<Box<dyn Object>>,
+    // Group space to parent space, and back
+    transform: Mat4,
+    inverse: Mat4,
+    bvh: Bvh,
+    // Every object below the group that is not itself a group is a numbered
+    // part, so shading can find the material of the one that was hit. This
+    // holds the number of the first part under each child, then the total.
+    first_parts: Vec<u32>,
+}
+
+impl Group {
+    pub fn new(children: Vec<Box<dyn Object>>, transform: Mat4) -> Result<Self, String> {
+        if children.is_empty() {
+            return Err("a group needs at least one child".to_string());
+        }
+        let inverse = transform.inverse().ok_or("the group's transform flattens it to nothing")?;
+        
+        let mut first_parts = vec![0];
+        for child in &children {
+            first_parts.push(first_parts[first_parts.len() - 1] + part_count(child.as_ref()));
+        }
+        let bvh = Bvh::build(&children);
+        Ok(Group { children, transform, inverse, bvh, first_parts })
+    }
+    
+    pub fn children(&self) -> &[Box<dyn Object>] {
+        &self.children
+    }
+    
+    pub fn transform(&self) -> Mat4 {
+        self.transform
+    }
+    
+    // The child holding a part, and the hit as seen in that child's space
+    fn child_hit(&self, hit: &HitRecord) -> (&dyn Object, HitRecord) {
+        let index = self.first_parts[1..].partition_point(|&end| end <= hit.part);
+        let index = index.min(self.children.len() - 1);
+        let mut local = *hit;
+        local.point = self.inverse.transform_point(hit.point);
+        local.geometric_normal = self.transform.transform_normal(hit.geometric_normal).normalize();
+        local.shading_normal = self.transform.transform_normal(hit.shading_normal).normalize();
+        local.part -= self.first_parts[index];
+        (self.children[index].as_ref(), local)
+    }
+}
+
+// Number of simple objects an object is made of
+fn part_count(object: &dyn Object) -> u32 {
+    if let Some(lod) = object.as_any().downcast_ref::<Lod>() {
+        // Room for the level with the most parts, whichever is traced
+        return lod.levels().iter().map(|level| part_count(level.as_ref())).max().unwrap_or(1);
+    }
+    match object.as_any().downcast_ref::<Group>() {
+        Some(group) => group.first_parts[group.children.len()],
+        None => 1,
+    }
+}
+
+impl Object for Group {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // The direction is renormalized in group space, which stretches
+        // distances along the ray. The hierarchy's root box gives the early
+        // out for rays that miss the group.
+        let direction = self.inverse.transform_vector(ray.direction);
+        let stretch = direction.length();
+        let local_ray = Ray::new(self.inverse.transform_point(ray.origin), direction);
+        let (mut hit, index) = self.bvh.hit_index(&self.children, &local_ray, t_min * stretch, t_max * stretch)?;
+        
+        hit.point = self.transform.transform_point(hit.point);
+        hit.t /= stretch;
+        hit.geometric_normal = self.inverse.transform_normal(hit.geometric_normal).normalize();
+        hit.shading_normal = self.inverse.transform_normal(hit.shading_normal).normalize();
+        hit.part += self.first_parts[index];
+        Some(hit)
+    }
+    
+    // The first child's material; overrides by the group's name change only
+    // that child
+    fn material(&self) -> &Material {
+        self.children[0].material()
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        self.children[0].material_mut()
+    }
+    
+    fn material_at(&self, hit: &HitRecord) -> Material {
+        let (child, local) = self.child_hit(hit);
+        child.material_at(&local)
+    }
+    
+    fn albedo(&self, hit: &HitRecord, footprint: f64) -> Color {
+        let (child, local) = self.child_hit(hit);
+        child.albedo(&local, footprint)
+    }
+    
+    fn emission(&self, hit: &HitRecord, footprint: f64) -> Color {
+        let (child, local) = self.child_hit(hit);
+        child.emission(&local, footprint)
+    }
+    
+    fn kind(&self) -> &'static str {
+        "group"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn memory_estimate(&self) -> usize {
+        let children: usize = self.children.iter().map(|child| child.memory_estimate()).sum();
+        mem::size_of::<Self>() - mem::size_of::<Bvh>()
+            + self.children.capacity() * mem::size_of::<Box<dyn Object>>()
+            + children
+            + self.bvh.memory_estimate()
+            + self.first_parts.capacity() * mem::size_of::<u32>()
+    }
+    
+    fn shared_memory(&self) -> Vec<(usize, usize)> {
+        self.children.iter().flat_map(|child| child.shared_memory()).collect()
+    }
+    
+    // Box around the corners of the children's box, carried out of group
+    // space
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = self.bvh.bounds()?;
+        let corner = |i: usize| {
+            let pick = |bit: usize, min: f64, max: f64| if i & bit == 0 { min } else { max };
+            self.transform.transform_point(Vec3::new(
+                pick(1, local.min.x, local.max.x),
+                pick(2, local.min.y, local.max.y),
+                pick(4, local.min.z, local.max.z),
+            ))
+        };
+        let first = corner(0);
+        Some((1..8).fold(Aabb::new(first, first), |bounds, i| {
+            let point = corner(i);
+            bounds.union(&Aabb::new(point, point))
+        }))
+    }
+}
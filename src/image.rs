@@ -1,71 +1,716 @@
-use crate::vector::Vec3;
+use crate::font;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
 
+pub mod metrics;
+pub mod color;
+pub mod y4m;
+pub mod ppm;
+pub mod resolution;
+
+use color::{Color, Encoding};
+use ppm::PpmWriter;
+
+// False-color ramp for diagnostic passes: black -> blue -> green -> yellow
+// -> red -> white as `t` goes from 0 to 1. Values outside are clamped.
+pub fn heat_color(t: f64) -> Color {
+    const STOPS: [(f64, f64, f64); 6] = [
+        (0.0, 0.0, 0.0),
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (1.0, 0.0, 0.0),
+        (1.0, 1.0, 1.0),
+    ];
+    let scaled = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let index = (scaled as usize).min(STOPS.len() - 2);
+    let (r0, g0, b0) = STOPS[index];
+    let (r1, g1, b1) = STOPS[index + 1];
+    Color::new(r0, g0, b0).lerp(&Color::new(r1, g1, b1), scaled - index as f64)
+}
+
+// Perceptually even ramp for diagnostic passes, after matplotlib's
+// viridis: dark purple -> blue -> teal -> green -> yellow as `t` goes from
+// 0 to 1. The stops are display values, converted to linear so they come
+// out as listed once the image is encoded as sRGB.
+pub fn viridis_color(t: f64) -> Color {
+    const STOPS: [(f64, f64, f64); 5] = [
+        (68.0, 1.0, 84.0),
+        (59.0, 82.0, 139.0),
+        (33.0, 145.0, 140.0),
+        (94.0, 201.0, 98.0),
+        (253.0, 231.0, 37.0),
+    ];
+    let scaled = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let index = (scaled as usize).min(STOPS.len() - 2);
+    let (r0, g0, b0) = STOPS[index];
+    let (r1, g1, b1) = STOPS[index + 1];
+    let display = Color::new(r0, g0, b0).lerp(&Color::new(r1, g1, b1), scaled - index as f64) / 255.0;
+    Color::new(
+        color::srgb_to_linear(display.r),
+        color::srgb_to_linear(display.g),
+        color::srgb_to_linear(display.b),
+    )
+}
+
+/// Value below which `percent` percent of `sorted` lies, interpolating
+/// linearly between neighbouring entries. `sorted` must be ascending and
+/// not empty.
+///
+/// ```
+/// use rt::image::percentile;
+///
+/// // 0, 10, ..., 100: every 10 percent is one step
+/// let ramp: Vec<f64> = (0..=10).map(|i| i as f64 * 10.0).collect();
+/// assert_eq!(percentile(&ramp, 0.0), 0.0);
+/// assert_eq!(percentile(&ramp, 50.0), 50.0);
+/// assert_eq!(percentile(&ramp, 100.0), 100.0);
+/// // Between samples, and past either end
+/// assert!((percentile(&ramp, 25.0) - 25.0).abs() < 1e-12);
+/// assert!((percentile(&ramp, 99.0) - 99.0).abs() < 1e-12);
+/// assert_eq!((percentile(&ramp, -5.0), percentile(&ramp, 150.0)), (0.0, 100.0));
+///
+/// // Halfway between two samples is their mean
+/// assert_eq!(percentile(&[1.0, 3.0], 50.0), 2.0);
+/// assert_eq!(percentile(&[1.0, 2.0, 4.0], 75.0), 3.0);
+/// assert_eq!(percentile(&[7.0], 30.0), 7.0);
+/// ```
+pub fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let position = (percent / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = (lower + 1).min(sorted.len() - 1);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+// Summary of an image's luminance distribution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LuminanceStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p1: f64,
+    pub p50: f64,
+    pub p99: f64,
+    // Fraction of pixels with a channel above 1.0, which the output clamps
+    pub clipped: f64,
+}
+
+impl LuminanceStats {
+    // Exposure multiplier that brings the 99th percentile just under 1.0;
+    // 1.0 for an image that is black at the 99th percentile
+    pub fn auto_exposure(&self) -> f64 {
+        if self.p99 > 0.0 {
+            0.99 / self.p99
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Image {
     pub width: u32,
     pub height: u32,
-    pub pixels: Vec<Vec3>,
+    pub pixels: Vec<Color>,
+    // Width over height of each pixel, noted in saved files for viewers
+    // that display non-square pixels
+    pub pixel_aspect: f64,
 }
 
 impl Image {
+    /// Length of the pixel buffer for a frame. It is multiplied in usize,
+    /// as width * height overflows u32 for very large frames:
+    ///
+    /// ```
+    /// use rt::image::Image;
+    ///
+    /// assert_eq!(Image::pixel_count(1920, 1080), 2_073_600);
+    /// assert_eq!(Image::pixel_count(0, 1080), 0);
+    /// // 2^32 pixels, one more than u32 holds
+    /// assert_eq!(Image::pixel_count(65_536, 65_536), 1 << 32);
+    /// assert_eq!(Image::pixel_count(100_000, 100_000), 10_000_000_000);
+    /// assert_eq!(Image::pixel_count(u32::MAX, u32::MAX), (u32::MAX as usize).pow(2));
+    /// ```
+    ///
+    /// Frames far wider than tall, or the other way about, render every row
+    /// and match the same view rendered a band at a time:
+    ///
+    /// ```
+    /// use rt::image::{color::Color, Image};
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+    ///     "background": { "bottom": [0.9, 0.9, 0.85], "top": [0.4, 0.55, 0.8] },
+    ///     "lights": [{ "type": "point", "position": [2, 3, 0] }],
+    ///     "objects": [{ "type": "sphere", "center": [0, 0, -3], "radius": 1 }]
+    /// }"#.parse().unwrap();
+    /// let settings = RenderSettings::default();
+    ///
+    /// for (width, height) in [(8192, 2), (2, 8192)] {
+    ///     let unwritten = Color::new(-1.0, -1.0, -1.0);
+    ///     let mut image = Image::new(width, height);
+    ///     image.pixels.fill(unwritten);
+    ///     scene.render_into(&mut image, &settings);
+    ///     assert_eq!(image.pixels.len(), Image::pixel_count(width, height));
+    ///     for row in image.pixels.chunks(width as usize) {
+    ///         assert!(row.iter().all(|&pixel| pixel != unwritten));
+    ///     }
+    ///
+    ///     let mut banded = Vec::new();
+    ///     scene.render_bands(width, height, 1, &settings, |band| {
+    ///         banded.extend_from_slice(&band.pixels);
+    ///         Ok(())
+    ///     }).unwrap();
+    ///     assert_eq!(banded.len(), image.pixels.len());
+    ///     for (a, b) in banded.iter().zip(&image.pixels) {
+    ///         assert!(Vec3::from(*a - *b).length() < 1e-9);
+    ///     }
+    /// }
+    /// ```
+    pub fn pixel_count(width: u32, height: u32) -> usize {
+        width as usize * height as usize
+    }
+
     pub fn new(width: u32, height: u32) -> Self {
         Image {
             width,
             height,
-            pixels: vec![Vec3::zero(); (width * height) as usize],
+            pixels: vec![Color::BLACK; Image::pixel_count(width, height)],
+            pixel_aspect: 1.0,
         }
     }
     
-    pub fn set_pixel(&mut self, x: usize, y: usize, color: Vec3) {
-        if x < self.width as usize && y < self.height as usize {
-            let index = y * self.width as usize + x;
-            self.pixels[index] = color;
+    // Like `new`, but failing instead of aborting when there is not enough
+    // memory for the frame
+    pub fn try_new(width: u32, height: u32) -> Result<Self, String> {
+        let count = Image::pixel_count(width, height);
+        let mut pixels = Vec::new();
+        pixels.try_reserve_exact(count).map_err(|_| format!("not enough memory for a {}x{} image", width, height))?;
+        pixels.resize(count, Color::BLACK);
+        Ok(Image { width, height, pixels, pixel_aspect: 1.0 })
+    }
+    
+    pub fn load_ppm(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("cannot read '{}': {}", path, e))?;
+        parse_ppm(&data).map_err(|e| format!("bad image '{}': {}", path, e))
+    }
+    
+    /// Builds an image from the color of each pixel. Pixels are stored row
+    /// by row from the top left, which is the order `f` is called in, and
+    /// the order `pixels`, `rows` and `enumerate_pixels` give them back.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::image::color::Color;
+    ///
+    /// let image = Image::from_fn(3, 2, |x, y| Color::new(x as f64, y as f64, 0.0));
+    /// assert_eq!(image.pixels()[4], Color::new(1.0, 1.0, 0.0));
+    ///
+    /// let rows: Vec<(usize, Vec<f64>)> = image.rows().map(|(y, row)| (y, row.iter().map(|p| p.r).collect())).collect();
+    /// assert_eq!(rows, vec![(0, vec![0.0, 1.0, 2.0]), (1, vec![0.0, 1.0, 2.0])]);
+    ///
+    /// for (x, y, pixel) in image.enumerate_pixels() {
+    ///     assert_eq!(*pixel, Color::new(x as f64, y as f64, 0.0));
+    ///     assert_eq!(image.get_pixel(x, y), *pixel);
+    /// }
+    /// assert_eq!(image.enumerate_pixels().nth(3).map(|(x, y, _)| (x, y)), Some((0, 1)));
+    ///
+    /// let flipped = image.map(|x, _, pixel| pixel + Color::new(0.0, 0.0, x as f64));
+    /// assert_eq!(flipped.get_pixel(2, 1), Color::new(2.0, 1.0, 2.0));
+    ///
+    /// assert_eq!(image.try_get_pixel(2, 1), Some(Color::new(2.0, 1.0, 0.0)));
+    /// assert_eq!(image.try_get_pixel(3, 0), None);
+    /// let mut image = image;
+    /// assert!(image.try_set_pixel(0, 2, Color::BLACK).is_err());
+    /// ```
+    pub fn from_fn(width: u32, height: u32, mut f: impl FnMut(usize, usize) -> Color) -> Self {
+        let mut image = Image::new(width, height);
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            *pixel = f(x, y);
         }
+        image
     }
     
-    pub fn get_pixel(&self, x: usize, y: usize) -> Vec3 {
-        if x < self.width as usize && y < self.height as usize {
-            let index = y * self.width as usize + x;
-            self.pixels[index]
-        } else {
-            Vec3::zero()
-        }
-    }
-    
-    pub fn output_ppm(&self) {
-        println!("P3");
-        println!("{} {}", self.width, self.height);
-        println!("255");
-        
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let pixel = self.get_pixel(x as usize, y as usize);
-                let r = (pixel.x.clamp(0.0, 1.0) * 255.0) as u8;
-                let g = (pixel.y.clamp(0.0, 1.0) * 255.0) as u8;
-                let b = (pixel.z.clamp(0.0, 1.0) * 255.0) as u8;
-                println!("{} {} {}", r, g, b);
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+    
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+    
+    // Rows from the top, with their y
+    pub fn rows(&self) -> impl Iterator<Item = (usize, &[Color])> {
+        self.pixels.chunks(self.width.max(1) as usize).enumerate()
+    }
+    
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = (usize, &mut [Color])> {
+        self.pixels.chunks_mut(self.width.max(1) as usize).enumerate()
+    }
+    
+    // Every pixel with its x and y, in storage order
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (usize, usize, &Color)> {
+        self.rows().flat_map(|(y, row)| row.iter().enumerate().map(move |(x, pixel)| (x, y, pixel)))
+    }
+    
+    pub fn enumerate_pixels_mut(&mut self) -> impl Iterator<Item = (usize, usize, &mut Color)> {
+        self.rows_mut().flat_map(|(y, row)| row.iter_mut().enumerate().map(move |(x, pixel)| (x, y, pixel)))
+    }
+    
+    // A new image of the same size and pixel shape with each pixel replaced
+    pub fn map(&self, mut f: impl FnMut(usize, usize, Color) -> Color) -> Image {
+        let mut image = Image::from_fn(self.width, self.height, |x, y| f(x, y, self.pixels[y * self.width as usize + x]));
+        image.pixel_aspect = self.pixel_aspect;
+        image
+    }
+    
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        (x < self.width as usize && y < self.height as usize).then(|| y * self.width as usize + x)
+    }
+    
+    pub fn try_get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
+    
+    pub fn try_set_pixel(&mut self, x: usize, y: usize, color: Color) -> Result<(), String> {
+        let index = self.index(x, y).ok_or_else(|| {
+            format!("pixel ({}, {}) is outside the {}x{} image", x, y, self.width, self.height)
+        })?;
+        self.pixels[index] = color;
+        Ok(())
+    }
+    
+    // Out of range coordinates are a bug in the caller: they panic in debug
+    // builds, and are ignored (or read as black) in release builds
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        debug_assert!(self.index(x, y).is_some(), "set_pixel({}, {}) outside a {}x{} image", x, y, self.width, self.height);
+        let _ = self.try_set_pixel(x, y, color);
+    }
+    
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
+        debug_assert!(self.index(x, y).is_some(), "get_pixel({}, {}) outside a {}x{} image", x, y, self.width, self.height);
+        self.try_get_pixel(x, y).unwrap_or(Color::BLACK)
+    }
+    
+    // Replaces NaN and infinite pixels with `color`, returning their
+    // coordinates in row order
+    pub fn replace_non_finite(&mut self, color: Color) -> Vec<(u32, u32)> {
+        let mut replaced = Vec::new();
+        for (x, y, pixel) in self.enumerate_pixels_mut() {
+            if !pixel.is_finite() {
+                *pixel = color;
+                replaced.push((x as u32, y as u32));
             }
         }
+        replaced
+    }
+    
+    // Pixel luminances, ascending
+    pub fn sorted_luminances(&self) -> Vec<f64> {
+        let mut values: Vec<f64> = self.pixels.iter().map(|p| p.luminance()).collect();
+        values.sort_by(f64::total_cmp);
+        values
+    }
+    
+    // Pixel counts over `bins` equal luminance ranges covering 0..1; values
+    // outside that go into the first or last bin
+    pub fn luminance_histogram(&self, bins: usize) -> Vec<u32> {
+        let mut counts = vec![0; bins];
+        if bins == 0 {
+            return counts;
+        }
+        for &pixel in &self.pixels {
+            let bin = (pixel.luminance() * bins as f64).clamp(0.0, (bins - 1) as f64) as usize;
+            counts[bin] += 1;
+        }
+        counts
+    }
+    
+    // None for an empty image
+    pub fn luminance_stats(&self) -> Option<LuminanceStats> {
+        let sorted = self.sorted_luminances();
+        if sorted.is_empty() {
+            return None;
+        }
+        let clipped = self.pixels.iter().filter(|p| p.r.max(p.g).max(p.b) > 1.0).count();
+        Some(LuminanceStats {
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p1: percentile(&sorted, 1.0),
+            p50: percentile(&sorted, 50.0),
+            p99: percentile(&sorted, 99.0),
+            clipped: clipped as f64 / self.pixels.len() as f64,
+        })
     }
     
-    pub fn save_ppm(&self, filename: &str) -> std::io::Result<()> {
-        use std::fs::File;
-        use std::io::Write;
-        
-        let mut file = File::create(filename)?;
-        writeln!(file, "P3")?;
-        writeln!(file, "{} {}", self.width, self.height)?;
-        writeln!(file, "255")?;
-        
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let pixel = self.get_pixel(x as usize, y as usize);
-                let r = (pixel.x.clamp(0.0, 1.0) * 255.0) as u8;
-                let g = (pixel.y.clamp(0.0, 1.0) * 255.0) as u8;
-                let b = (pixel.z.clamp(0.0, 1.0) * 255.0) as u8;
-                writeln!(file, "{} {} {}", r, g, b)?;
+    pub fn apply_exposure(&mut self, multiplier: f64) {
+        for pixel in &mut self.pixels {
+            *pixel = *pixel * multiplier;
+        }
+    }
+    
+    // Fills a rectangle whose top-left corner is at `x`, `y`; the parts
+    // outside the image are clipped
+    pub fn draw_rect(&mut self, x: i64, y: i64, width: u32, height: u32, color: Color) {
+        let x0 = x.clamp(0, self.width as i64) as usize;
+        let y0 = y.clamp(0, self.height as i64) as usize;
+        let x1 = (x + width as i64).clamp(0, self.width as i64) as usize;
+        let y1 = (y + height as i64).clamp(0, self.height as i64) as usize;
+        for row in y0..y1 {
+            let start = row * self.width as usize;
+            self.pixels[start + x0..start + x1].fill(color);
+        }
+    }
+    
+    /// Draws `text` in the built-in 5x7 font with its top-left corner at `x`,
+    /// `y`, each font pixel becoming a `scale` x `scale` block. '\n' starts a
+    /// new line. Only the glyph pixels are drawn; see `draw_rect` for a
+    /// background.
+    ///
+    /// ```
+    /// use rt::image::color::Color;
+    /// use rt::image::Image;
+    ///
+    /// // Number of lit pixels and their bounding box, as (left, top, right, bottom)
+    /// let lit = |image: &Image| {
+    ///     let (mut count, mut bounds) = (0, (usize::MAX, usize::MAX, 0, 0));
+    ///     for y in 0..image.height as usize {
+    ///         for x in 0..image.width as usize {
+    ///             if image.get_pixel(x, y) != Color::BLACK {
+    ///                 count += 1;
+    ///                 bounds = (bounds.0.min(x), bounds.1.min(y), bounds.2.max(x), bounds.3.max(y));
+    ///             }
+    ///         }
+    ///     }
+    ///     (count, bounds)
+    /// };
+    ///
+    /// // H has two full columns and a bar of 3, I a column and 4 serifs.
+    /// // The I's outer columns are blank, so it ends 2 short of its cell.
+    /// let mut image = Image::new(32, 24);
+    /// image.draw_text(0, 0, "HI", Color::WHITE, 1);
+    /// assert_eq!(lit(&image), (28, (0, 0, 9, 6)));
+    ///
+    /// let mut image = Image::new(32, 24);
+    /// image.draw_text(3, 2, "HI", Color::WHITE, 2);
+    /// assert_eq!(lit(&image), (4 * 28, (3, 2, 22, 15)));
+    ///
+    /// // A second line starts 8 rows down
+    /// let mut image = Image::new(32, 24);
+    /// image.draw_text(0, 0, "I\nI", Color::WHITE, 1);
+    /// assert_eq!(lit(&image), (22, (1, 0, 3, 14)));
+    ///
+    /// // Clipped on the left, losing the H's first column and a bar pixel
+    /// let mut image = Image::new(32, 24);
+    /// image.draw_text(-2, 0, "HI", Color::WHITE, 1);
+    /// assert_eq!(lit(&image), (20, (0, 0, 7, 6)));
+    ///
+    /// // Clipped at the right and the bottom: the H's top four rows and the
+    /// // top of the I's first lit column are left
+    /// let mut image = Image::new(8, 4);
+    /// image.draw_text(0, 0, "HI", Color::WHITE, 1);
+    /// assert_eq!(lit(&image), (4 + 4 + 3 + 1, (0, 0, 7, 3)));
+    ///
+    /// // Rectangles are clipped the same way
+    /// let mut image = Image::new(8, 4);
+    /// image.draw_rect(-3, -3, 5, 5, Color::WHITE);
+    /// image.draw_rect(6, 3, 10, 10, Color::WHITE);
+    /// assert_eq!(lit(&image), (4 + 2, (0, 0, 7, 3)));
+    /// ```
+    pub fn draw_text(&mut self, x: i64, y: i64, text: &str, color: Color, scale: u32) {
+        let step = scale as i64;
+        for (line_index, line) in text.split('\n').enumerate() {
+            let top = y + line_index as i64 * font::LINE_HEIGHT as i64 * step;
+            for (char_index, c) in line.chars().enumerate() {
+                let left = x + char_index as i64 * font::ADVANCE as i64 * step;
+                for (column, bits) in font::glyph(c).iter().enumerate() {
+                    for row in 0..font::GLYPH_HEIGHT as i64 {
+                        if bits >> row & 1 == 1 {
+                            let px = left + column as i64 * step;
+                            self.draw_rect(px, top + row * step, scale, scale, color);
+                        }
+                    }
+                }
             }
         }
-        
+    }
+    
+    /// The image with every value multiplied by 2^`stops`, as a camera's
+    /// exposure would scale the light; the values stay linear, so each stop
+    /// exactly doubles or halves them before they are encoded.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::image::color::Encoding;
+    /// use rt::image::color::Color;
+    ///
+    /// let image = Image::from_fn(4, 3, |x, y| Color::new(x as f64 * 0.3, y as f64 * 0.17, 0.01));
+    /// let (normal, brighter) = (image.exposed(0.0), image.exposed(1.0));
+    /// assert_eq!(normal.pixels, image.pixels);
+    /// for (a, b) in normal.pixels().iter().zip(brighter.pixels()) {
+    ///     assert_eq!(*a * 2.0, *b);
+    /// }
+    /// assert_eq!(image.exposed(-2.0).get_pixel(1, 1), image.get_pixel(1, 1) * 0.25);
+    ///
+    /// // Writing goes through the same encoding whatever the exposure
+    /// let mut bytes = Vec::new();
+    /// brighter.write_ppm(&mut bytes, Encoding::Srgb).unwrap();
+    /// assert!(String::from_utf8(bytes).unwrap().starts_with("P3\n4 3\n255\n"));
+    /// ```
+    pub fn exposed(&self, stops: f64) -> Image {
+        let multiplier = stops.exp2();
+        let mut image = self.clone();
+        image.apply_exposure(multiplier);
+        image
+    }
+    
+    /// The image resampled to `width` x `height` by area averaging: each new
+    /// pixel is the mean of the part of this image it covers, with pixels
+    /// it only partly covers weighted by the covered fraction. Averaging
+    /// the linear values keeps the mean brightness, which resampling after
+    /// encoding would not. Pixels get wider or taller when the aspect ratio
+    /// changes, which `pixel_aspect` records.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::image::color::Color;
+    ///
+    /// let image = Image::from_fn(30, 20, |x, y| Color::new((x * y % 7) as f64 * 0.4, (x % 3) as f64, 0.1));
+    /// let mean = |image: &Image| image.luminance_stats().unwrap().mean;
+    /// for (width, height) in [(15, 10), (7, 5), (11, 20), (1, 1)] {
+    ///     let small = image.resized(width, height);
+    ///     assert_eq!((small.width, small.height), (width, height));
+    ///     assert!((mean(&small) - mean(&image)).abs() < 1e-9);
+    /// }
+    ///
+    /// // Halving averages each 2x2 block
+    /// let half = image.resized(15, 10);
+    /// let block = (0..4).fold(Color::BLACK, |sum, i| sum + image.get_pixel(2 + i % 2, 4 + i / 2)) / 4.0;
+    /// assert!((half.get_pixel(1, 2).r - block.r).abs() < 1e-12);
+    /// assert_eq!(image.resized(11, 20).pixel_aspect, 30.0 / 11.0);
+    /// ```
+    pub fn resized(&self, width: u32, height: u32) -> Image {
+        // For each new pixel along one axis, the old pixels under it and
+        // how much of each it covers, as a fraction of its own length
+        fn spans(from: u32, to: u32) -> Vec<Vec<(usize, f64)>> {
+            let scale = from as f64 / to as f64;
+            (0..to)
+                .map(|i| {
+                    let (start, end) = (i as f64 * scale, ((i + 1) as f64 * scale).min(from as f64));
+                    (start.floor() as usize..(end.ceil() as usize).min(from as usize))
+                        .map(|j| (j, (end.min((j + 1) as f64) - start.max(j as f64)) / scale))
+                        .filter(|&(_, weight)| weight > 0.0)
+                        .collect()
+                })
+                .collect()
+        }
+        let (columns, rows) = (spans(self.width, width), spans(self.height, height));
+        let mut image = Image::from_fn(width, height, |x, y| {
+            let mut sum = Color::BLACK;
+            for &(row, row_weight) in &rows[y] {
+                for &(column, column_weight) in &columns[x] {
+                    sum = sum + self.get_pixel(column, row) * (row_weight * column_weight);
+                }
+            }
+            sum
+        });
+        image.pixel_aspect = self.pixel_aspect * (self.width as f64 / width as f64) / (self.height as f64 / height as f64);
+        image
+    }
+    
+    // Encodes the image as a plain (P3) PPM file into `out`. Every writer,
+    // to a file, stdout or a band at a time, encodes pixels the same way.
+    pub fn write_ppm<W: Write>(&self, out: W, encoding: Encoding) -> io::Result<()> {
+        let mut writer = PpmWriter::new(out, self.width, self.height, self.pixel_aspect, encoding)?;
+        writer.write_rows(self)?;
+        writer.finish()?;
         Ok(())
     }
+    
+    pub fn output_ppm(&self, encoding: Encoding) -> io::Result<()> {
+        self.write_ppm(io::stdout().lock(), encoding)
+    }
+    
+    pub fn save_ppm(&self, filename: &str, encoding: Encoding) -> io::Result<()> {
+        self.write_ppm(BufWriter::new(fs::File::create(filename)?), encoding)
+    }
+    
+    /// Encodes the image as a binary (P6) PPM file with 16 bits per channel,
+    /// for data such as depth and height maps that 8 bits would band.
+    /// Values are written linearly, clamped to 0..1, and read back by
+    /// `parse_ppm`.
+    ///
+    /// ```
+    /// use rt::image::{parse_ppm, Image};
+    /// use rt::image::color::Color;
+    ///
+    /// let image = Image::from_fn(3, 2, |x, y| Color::new(x as f64 / 2.0, y as f64, 1.5));
+    /// let mut bytes = Vec::new();
+    /// image.write_ppm16(&mut bytes).unwrap();
+    /// assert!(bytes.starts_with(b"P6\n3 2\n65535\n"));
+    /// assert_eq!(bytes.len(), 13 + 3 * 2 * 6);
+    ///
+    /// let read = parse_ppm(&bytes).unwrap();
+    /// assert_eq!(read.get_pixel(1, 1), Color::new(32768.0 / 65535.0, 1.0, 1.0));
+    /// assert_eq!(read.get_pixel(2, 0), Color::new(1.0, 0.0, 1.0));
+    /// ```
+    pub fn write_ppm16<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n65535\n", self.width, self.height)?;
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 6);
+        for pixel in &self.pixels {
+            for value in [pixel.r, pixel.g, pixel.b] {
+                let sample = (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+                bytes.extend_from_slice(&sample.to_be_bytes());
+            }
+        }
+        out.write_all(&bytes)?;
+        out.flush()
+    }
+    
+    pub fn save_ppm16(&self, filename: &str) -> io::Result<()> {
+        self.write_ppm16(BufWriter::new(fs::File::create(filename)?))
+    }
+    
+    /// Encodes the image with a coverage per pixel as a PAM file (P7,
+    /// RGB_ALPHA, 8 bits per channel), the Netpbm format with transparency.
+    /// The colors are taken as already multiplied by `alpha`, the way a
+    /// render with holdouts comes out, and are stored divided by it as PAM
+    /// expects; alpha itself is stored linearly.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::image::color::{Color, Encoding};
+    ///
+    /// let image = Image::from_fn(2, 1, |x, _| Color::splat(x as f64 * 0.25));
+    /// let mut bytes = Vec::new();
+    /// image.write_pam(&mut bytes, &[0.0, 0.5], Encoding::Linear).unwrap();
+    /// let header = "P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+    /// assert!(bytes.starts_with(header.as_bytes()));
+    /// assert_eq!(bytes[header.len()..], [0, 0, 0, 0, 128, 128, 128, 128]);
+    /// assert!(image.write_pam(Vec::new(), &[1.0], Encoding::Linear).is_err());
+    /// ```
+    pub fn write_pam<W: Write>(&self, mut out: W, alpha: &[f64], encoding: Encoding) -> io::Result<()> {
+        if alpha.len() != self.pixels.len() {
+            let message = format!("{} alpha values for {} pixels", alpha.len(), self.pixels.len());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        write!(
+            out,
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            self.width, self.height,
+        )?;
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for (pixel, &alpha) in self.pixels.iter().zip(alpha) {
+            let alpha = alpha.clamp(0.0, 1.0);
+            let straight = if alpha > 0.0 { *pixel / alpha } else { Color::BLACK };
+            bytes.extend([straight.r, straight.g, straight.b].map(|value| encoding.to_byte(value)));
+            bytes.push((alpha * 255.0).round() as u8);
+        }
+        out.write_all(&bytes)?;
+        out.flush()
+    }
+    
+    pub fn save_pam(&self, filename: &str, alpha: &[f64], encoding: Encoding) -> io::Result<()> {
+        self.write_pam(BufWriter::new(fs::File::create(filename)?), alpha, encoding)
+    }
+}
+
+// Longest PPM header `read_ppm_size` looks through, comments included
+const PPM_HEADER_LIMIT: u64 = 64 * 1024;
+
+// Whitespace-separated tokens of a PPM file, skipping '#' comments
+struct PpmTokens<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl PpmTokens<'_> {
+    fn next_token(&mut self) -> Result<String, String> {
+        let data = self.data;
+        loop {
+            while self.pos < data.len() && data[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if self.pos < data.len() && data[self.pos] == b'#' {
+                while self.pos < data.len() && data[self.pos] != b'\n' {
+                    self.pos += 1;
+                }
+                continue;
+            }
+            break;
+        }
+        let start = self.pos;
+        while self.pos < data.len() && !data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err("unexpected end of file".to_string());
+        }
+        Ok(String::from_utf8_lossy(&data[start..self.pos]).into_owned())
+    }
+    
+    fn number(&mut self, what: &str) -> Result<usize, String> {
+        let token = self.next_token()?;
+        token.parse().map_err(|_| format!("invalid {} '{}'", what, token))
+    }
+}
+
+// Magic number, width, height and largest value of a PPM file, leaving
+// `tokens` just past the last of them
+fn parse_ppm_header(tokens: &mut PpmTokens) -> Result<(String, usize, usize, usize), String> {
+    let magic = tokens.next_token()?;
+    let width = tokens.number("width")?;
+    let height = tokens.number("height")?;
+    let max_value = tokens.number("max value")?;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 65535 {
+        return Err(format!("unsupported size {}x{} or max value {}", width, height, max_value));
+    }
+    Ok((magic, width, height, max_value))
+}
+
+// Width and height of the PPM file at `path`, from its header alone, so
+// a file's size can be known before its pixels are read
+pub fn read_ppm_size(path: &str) -> Result<(usize, usize), String> {
+    let mut header = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| file.take(PPM_HEADER_LIMIT).read_to_end(&mut header))
+        .map_err(|e| format!("cannot read '{}': {}", path, e))?;
+    let (_, width, height, _) = parse_ppm_header(&mut PpmTokens { data: &header, pos: 0 })
+        .map_err(|e| format!("bad image '{}': {}", path, e))?;
+    Ok((width, height))
+}
+
+// Reads a binary (P6) or plain (P3) PPM file with values up to 65535;
+// binary files above 255 take two bytes per value, most significant first
+pub fn parse_ppm(data: &[u8]) -> Result<Image, String> {
+    let mut tokens = PpmTokens { data, pos: 0 };
+    let (magic, width, height, max_value) = parse_ppm_header(&mut tokens)?;
+    let scale = 1.0 / max_value as f64;
+    
+    let values: Vec<usize> = match magic.as_str() {
+        "P6" => {
+            // Exactly one whitespace byte separates the header from the data
+            let start = tokens.pos + 1;
+            let bytes_per_value = if max_value > 255 { 2 } else { 1 };
+            let end = start + width * height * 3 * bytes_per_value;
+            if end > data.len() {
+                return Err("pixel data is truncated".to_string());
+            }
+            match bytes_per_value {
+                1 => data[start..end].iter().map(|&b| b as usize).collect(),
+                _ => data[start..end].chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize).collect(),
+            }
+        }
+        "P3" => (0..width * height * 3).map(|_| tokens.number("sample")).collect::<Result<_, _>>()?,
+        _ => return Err(format!("unsupported format '{}' (expected P3 or P6)", magic)),
+    };
+    
+    let pixels = values
+        .chunks(3)
+        .map(|rgb| Color::new(rgb[0] as f64, rgb[1] as f64, rgb[2] as f64) * scale)
+        .collect();
+    Ok(Image { width: width as u32, height: height as u32, pixels, pixel_aspect: 1.0 })
 }
@@ -0,0 +1,262 @@
+// Colors, and transfer functions between the renderer's linear values and
+// the encoded values stored in 8-bit image files
+
+use crate::vector::Vec3;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// Exact sRGB encoding: a linear segment near black, then a 2.4 power curve.
+///
+/// ```
+/// use rt::image::color::{linear_to_srgb, srgb_to_linear, Encoding};
+///
+/// assert_eq!(Encoding::Srgb.to_byte(0.0), 0);
+/// assert_eq!(Encoding::Srgb.to_byte(0.5), 188);
+/// assert_eq!(Encoding::Srgb.to_byte(1.0), 255);
+/// // Either side of where the linear segment meets the curve
+/// assert!((linear_to_srgb(0.0031308) - 0.04045).abs() < 1e-6);
+/// assert!((srgb_to_linear(0.04045) - 0.0031308).abs() < 1e-6);
+///
+/// // Every 8-bit level decodes to a value that encodes back to it
+/// for level in 0..=255u8 {
+///     let linear = srgb_to_linear(level as f64 / 255.0);
+///     assert_eq!(Encoding::Srgb.to_byte(linear), level);
+///     assert!((linear_to_srgb(linear) * 255.0 - level as f64).abs() < 1e-9);
+/// }
+/// ```
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// How linear values are encoded when an image is written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Srgb,
+    // Pure 1/2.2 power curve, the usual approximation of sRGB
+    Gamma22,
+    // Values written as they are
+    Linear,
+}
+
+impl Encoding {
+    pub fn encode(&self, value: f64) -> f64 {
+        match self {
+            Encoding::Srgb => linear_to_srgb(value),
+            Encoding::Gamma22 => value.powf(1.0 / 2.2),
+            Encoding::Linear => value,
+        }
+    }
+    
+    // Clamps to 0..1, encodes and rounds to the nearest 8-bit level
+    pub fn to_byte(&self, value: f64) -> u8 {
+        (self.encode(value.clamp(0.0, 1.0)) * 255.0).round() as u8
+    }
+}
+
+// CSS color names `Color::parse` knows, a common subset of the full list
+const NAMED_COLORS: [(&str, &str); 47] = [
+    ("black", "#000000"), ("white", "#ffffff"), ("red", "#ff0000"), ("lime", "#00ff00"),
+    ("green", "#008000"), ("blue", "#0000ff"), ("yellow", "#ffff00"), ("cyan", "#00ffff"),
+    ("aqua", "#00ffff"), ("magenta", "#ff00ff"), ("fuchsia", "#ff00ff"), ("gray", "#808080"),
+    ("grey", "#808080"), ("darkgray", "#a9a9a9"), ("darkgrey", "#a9a9a9"), ("lightgray", "#d3d3d3"),
+    ("lightgrey", "#d3d3d3"), ("silver", "#c0c0c0"), ("slategray", "#708090"),
+    ("slategrey", "#708090"), ("maroon", "#800000"), ("olive", "#808000"), ("teal", "#008080"),
+    ("navy", "#000080"), ("purple", "#800080"), ("orange", "#ffa500"), ("pink", "#ffc0cb"),
+    ("brown", "#a52a2a"), ("gold", "#ffd700"), ("skyblue", "#87ceeb"), ("steelblue", "#4682b4"),
+    ("forestgreen", "#228b22"), ("crimson", "#dc143c"), ("coral", "#ff7f50"), ("salmon", "#fa8072"),
+    ("tomato", "#ff6347"), ("indigo", "#4b0082"), ("violet", "#ee82ee"), ("orchid", "#da70d6"),
+    ("tan", "#d2b48c"), ("beige", "#f5f5dc"), ("ivory", "#fffff0"), ("khaki", "#f0e68c"),
+    ("lavender", "#e6e6fa"), ("turquoise", "#40e0d0"), ("chocolate", "#d2691e"),
+    ("sienna", "#a0522d"),
+];
+
+/// A linear RGB color: what materials, lights, backgrounds and image
+/// pixels hold, kept apart from `Vec3` so a color cannot be used as a
+/// position by mistake or the other way around. Conversions between the
+/// two are explicit, through `From`.
+///
+/// ```
+/// use rt::image::color::Color;
+/// use rt::vector::Vec3;
+///
+/// let red = Color::new(0.8, 0.1, 0.1);
+/// assert_eq!(red * Color::splat(0.5) + Color::BLACK, Color::new(0.4, 0.05, 0.05));
+/// assert!((Color::WHITE.luminance() - 1.0).abs() < 1e-12);
+/// assert_eq!(Vec3::from(red), Vec3::new(0.8, 0.1, 0.1));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Color {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+impl Color {
+    pub const BLACK: Color = Color { r: 0.0, g: 0.0, b: 0.0 };
+    pub const WHITE: Color = Color { r: 1.0, g: 1.0, b: 1.0 };
+    
+    pub fn new(r: f64, g: f64, b: f64) -> Self {
+        Color { r, g, b }
+    }
+    
+    // All three channels set to `value`
+    pub fn splat(value: f64) -> Self {
+        Color::new(value, value, value)
+    }
+    
+    /// A color written the way people write them: "#rrggbb", "#rgb" or a
+    /// CSS color name, all in sRGB as on the web and decoded to linear
+    /// values, so they look as expected once the image is encoded again.
+    ///
+    /// ```
+    /// use rt::image::color::Color;
+    ///
+    /// let pink = Color::parse("#cc3344").unwrap();
+    /// assert!(pink.r > 0.6 && pink.r < 0.8);
+    /// assert_eq!(Color::parse("#c34").unwrap(), pink);
+    /// assert_eq!(Color::parse("#FFF").unwrap(), Color::WHITE);
+    /// assert_eq!(Color::parse("Red").unwrap(), Color::new(1.0, 0.0, 0.0));
+    /// assert_eq!(Color::parse("slategrey").unwrap(), Color::parse("#708090").unwrap());
+    ///
+    /// // Back through linear space every 8-bit value comes out as it went in
+    /// for text in ["#cc3344", "#000000", "#ffffff", "#010203", "#7f8081"] {
+    ///     let [r, g, b] = Color::parse(text).unwrap().to_srgb8();
+    ///     assert_eq!(format!("#{:02x}{:02x}{:02x}", r, g, b), text);
+    /// }
+    /// assert_eq!(Color::parse("orange").unwrap().to_srgb8(), [0xff, 0xa5, 0x00]);
+    ///
+    /// for bad in ["", "#", "#cc33", "#cc33445", "#ggg", "cc3344", "reddish"] {
+    ///     assert!(Color::parse(bad).is_err(), "{}", bad);
+    /// }
+    /// ```
+    pub fn parse(text: &str) -> Result<Color, String> {
+        let text = text.trim();
+        if text.starts_with('#') {
+            return Color::from_hex(text);
+        }
+        let name = text.to_ascii_lowercase();
+        match NAMED_COLORS.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, hex)) => Color::from_hex(hex),
+            None => Err(format!("unknown color '{}' (expected #rrggbb, #rgb or a name such as red or slategray)", text)),
+        }
+    }
+    
+    // "#rrggbb" or "#rgb", in sRGB
+    pub fn from_hex(text: &str) -> Result<Color, String> {
+        let invalid = || format!("'{}' is not a hex color (expected #rrggbb or #rgb)", text);
+        let digits = text.strip_prefix('#').ok_or_else(invalid)?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let digits: String = match digits.len() {
+            6 => digits.to_string(),
+            3 => digits.chars().flat_map(|c| [c, c]).collect(),
+            _ => return Err(invalid()),
+        };
+        let channel = |i: usize| {
+            let value = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).unwrap_or_default();
+            srgb_to_linear(value as f64 / 255.0)
+        };
+        Ok(Color::new(channel(0), channel(1), channel(2)))
+    }
+    
+    // Clamped to 0..1 and encoded as 8-bit sRGB
+    pub fn to_srgb8(&self) -> [u8; 3] {
+        [self.r, self.g, self.b].map(|value| Encoding::Srgb.to_byte(value))
+    }
+    
+    // Rec. 709 luminance
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+    
+    pub fn max_component(&self) -> f64 {
+        self.r.max(self.g).max(self.b)
+    }
+    
+    pub fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+    
+    pub fn lerp(&self, other: &Color, t: f64) -> Color {
+        *self * (1.0 - t) + *other * t
+    }
+    
+    pub fn clamp(&self, min: f64, max: f64) -> Color {
+        Color::new(self.r.clamp(min, max), self.g.clamp(min, max), self.b.clamp(min, max))
+    }
+}
+
+impl From<Vec3> for Color {
+    fn from(v: Vec3) -> Color {
+        Color::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<Color> for Vec3 {
+    fn from(c: Color) -> Vec3 {
+        Vec3::new(c.r, c.g, c.b)
+    }
+}
+
+// "(r, g, b)", with any precision applied to each channel
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match f.precision() {
+            Some(p) => write!(f, "({:.*}, {:.*}, {:.*})", p, self.r, p, self.g, p, self.b),
+            None => write!(f, "({}, {}, {})", self.r, self.g, self.b),
+        }
+    }
+}
+
+impl Add for Color {
+    type Output = Color;
+    
+    fn add(self, other: Color) -> Color {
+        Color::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    }
+}
+
+impl Sub for Color {
+    type Output = Color;
+    
+    fn sub(self, other: Color) -> Color {
+        Color::new(self.r - other.r, self.g - other.g, self.b - other.b)
+    }
+}
+
+impl Mul<f64> for Color {
+    type Output = Color;
+    
+    fn mul(self, scalar: f64) -> Color {
+        Color::new(self.r * scalar, self.g * scalar, self.b * scalar)
+    }
+}
+
+impl Mul<Color> for Color {
+    type Output = Color;
+    
+    fn mul(self, other: Color) -> Color {
+        Color::new(self.r * other.r, self.g * other.g, self.b * other.b)
+    }
+}
+
+impl Div<f64> for Color {
+    type Output = Color;
+    
+    fn div(self, scalar: f64) -> Color {
+        Color::new(self.r / scalar, self.g / scalar, self.b / scalar)
+    }
+}
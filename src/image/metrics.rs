@@ -0,0 +1,160 @@
+use super::Image;
+
+// Image comparison on the luminance channel. Pixel values are clamped to
+// 0..1 first so images compare the way they are written out. PPM files
+// carry no gamma information, so loaded images are compared as they are,
+// in whatever encoding they were written with (sRGB by default).
+
+// Window the SSIM statistics are gathered over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SsimWindow {
+    // Uniform 8x8 window
+    Box8,
+    // 11x11 Gaussian window with a standard deviation of 1.5 pixels, as in
+    // the original SSIM paper
+    Gaussian,
+}
+
+impl SsimWindow {
+    // Normalized 1D weights; the 2D window is their outer product
+    fn weights(&self) -> Vec<f64> {
+        match self {
+            SsimWindow::Box8 => vec![1.0 / 8.0; 8],
+            SsimWindow::Gaussian => {
+                let weights: Vec<f64> = (-5..=5)
+                    .map(|i| (-(i * i) as f64 / (2.0 * 1.5 * 1.5)).exp())
+                    .collect();
+                let total: f64 = weights.iter().sum();
+                weights.iter().map(|w| w / total).collect()
+            }
+        }
+    }
+}
+
+fn luminances(image: &Image) -> Vec<f64> {
+    image.pixels().iter().map(|p| p.luminance().clamp(0.0, 1.0)).collect()
+}
+
+fn check_sizes(a: &Image, b: &Image) -> Result<(), String> {
+    if a.width != b.width || a.height != b.height {
+        return Err(format!(
+            "images differ in size ({}x{} and {}x{})",
+            a.width, a.height, b.width, b.height
+        ));
+    }
+    if a.pixels().is_empty() {
+        return Err("images are empty".to_string());
+    }
+    Ok(())
+}
+
+// Mean squared luminance difference
+pub fn mse(a: &Image, b: &Image) -> Result<f64, String> {
+    check_sizes(a, b)?;
+    let (la, lb) = (luminances(a), luminances(b));
+    let sum: f64 = la.iter().zip(&lb).map(|(x, y)| (x - y) * (x - y)).sum();
+    Ok(sum / la.len() as f64)
+}
+
+// Peak signal-to-noise ratio in decibels for a peak of 1.0; infinite for
+// identical images
+pub fn psnr(a: &Image, b: &Image) -> Result<f64, String> {
+    let mse = mse(a, b)?;
+    Ok(10.0 * (1.0 / mse).log10())
+}
+
+/// Mean structural similarity over every window position fully inside the
+/// image: 1.0 for identical images, lower as structure differs, negative
+/// when it is inverted. Windows shrink to fit images smaller than them.
+///
+/// ```
+/// use rt::image::color::Color;
+/// use rt::image::Image;
+/// use rt::image::metrics::{ssim, SsimWindow};
+///
+/// // A diagonal gradient with a bright square in it, and its negative
+/// let pattern = Image::from_fn(32, 24, |x, y| {
+///     let square = (8..16).contains(&x) && (8..16).contains(&y);
+///     Color::splat(if square { 0.9 } else { (x + y) as f64 / 60.0 })
+/// });
+/// let inverted = Image::from_fn(32, 24, |x, y| Color::splat(1.0) - pattern.get_pixel(x, y));
+///
+/// for window in [SsimWindow::Box8, SsimWindow::Gaussian] {
+///     assert!((ssim(&pattern, &pattern, window).unwrap() - 1.0).abs() < 1e-12);
+///     assert!(ssim(&pattern, &inverted, window).unwrap() < 0.0);
+/// }
+///
+/// // Light noise keeps most of the structure
+/// let noisy = Image::from_fn(32, 24, |x, y| pattern.get_pixel(x, y) + Color::splat(((x * 7 + y * 3) % 5) as f64 * 0.01));
+/// let score = ssim(&pattern, &noisy, SsimWindow::Gaussian).unwrap();
+/// assert!(score > 0.5 && score < 1.0);
+///
+/// // Smaller than the window, it shrinks to fit
+/// let tiny = Image::from_fn(3, 3, |x, y| Color::splat((x * y) as f64 / 4.0));
+/// assert!((ssim(&tiny, &tiny, SsimWindow::Gaussian).unwrap() - 1.0).abs() < 1e-12);
+///
+/// assert_eq!(ssim(&pattern, &tiny, SsimWindow::Box8), Err("images differ in size (32x24 and 3x3)".to_string()));
+/// assert!(ssim(&Image::new(0, 0), &Image::new(0, 0), SsimWindow::Box8).is_err());
+/// ```
+pub fn ssim(a: &Image, b: &Image, window: SsimWindow) -> Result<f64, String> {
+    check_sizes(a, b)?;
+    let (width, height) = (a.width as usize, a.height as usize);
+    
+    let mut weights = window.weights();
+    let size = weights.len().min(width).min(height);
+    if size < weights.len() {
+        // Keep the middle of the window
+        let start = (weights.len() - size) / 2;
+        weights = weights[start..start + size].to_vec();
+        let total: f64 = weights.iter().sum();
+        weights.iter_mut().for_each(|w| *w /= total);
+    }
+    
+    let (x, y) = (luminances(a), luminances(b));
+    let xx: Vec<f64> = x.iter().map(|v| v * v).collect();
+    let yy: Vec<f64> = y.iter().map(|v| v * v).collect();
+    let xy: Vec<f64> = x.iter().zip(&y).map(|(p, q)| p * q).collect();
+    
+    let filter = |values: &[f64]| filter_valid(values, width, height, &weights);
+    let (mean_x, mean_y) = (filter(&x), filter(&y));
+    let (mean_xx, mean_yy, mean_xy) = (filter(&xx), filter(&yy), filter(&xy));
+    
+    // Stabilizing constants for a dynamic range of 1.0
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    
+    let mut total = 0.0;
+    for i in 0..mean_x.len() {
+        let (mx, my) = (mean_x[i], mean_y[i]);
+        let var_x = mean_xx[i] - mx * mx;
+        let var_y = mean_yy[i] - my * my;
+        let covariance = mean_xy[i] - mx * my;
+        total += ((2.0 * mx * my + C1) * (2.0 * covariance + C2))
+            / ((mx * mx + my * my + C1) * (var_x + var_y + C2));
+    }
+    Ok(total / mean_x.len() as f64)
+}
+
+// Separable weighted sum over every position where the window fits
+// entirely, giving (width - n + 1) x (height - n + 1) values
+fn filter_valid(values: &[f64], width: usize, height: usize, weights: &[f64]) -> Vec<f64> {
+    let n = weights.len();
+    let out_width = width - n + 1;
+    let out_height = height - n + 1;
+    
+    let mut rows = Vec::with_capacity(out_width * height);
+    for row in values.chunks(width) {
+        for x in 0..out_width {
+            rows.push(row[x..x + n].iter().zip(weights).map(|(v, w)| v * w).sum::<f64>());
+        }
+    }
+    
+    let mut out = Vec::with_capacity(out_width * out_height);
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let sum: f64 = weights.iter().enumerate().map(|(i, w)| rows[(y + i) * out_width + x] * w).sum();
+            out.push(sum);
+        }
+    }
+    out
+}
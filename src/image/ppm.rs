@@ -0,0 +1,89 @@
+use std::io::{self, Write};
+use super::Image;
+use super::color::Encoding;
+
+/// Streams an image into a plain (P3) PPM file a band of rows at a time,
+/// so the whole image never has to be held at once. The bytes are the same
+/// as `Image::save_ppm` writes.
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::image::color::Encoding;
+/// use rt::image::ppm::PpmWriter;
+/// use rt::image::color::Color;
+///
+/// let mut whole = Image::new(3, 5);
+/// for (index, pixel) in whole.pixels.iter_mut().enumerate() {
+///     *pixel = Color::splat(index as f64 / 15.0);
+/// }
+/// let band = |first: usize, rows: usize| {
+///     let mut band = Image::new(3, rows as u32);
+///     band.pixels.copy_from_slice(&whole.pixels[first * 3..(first + rows) * 3]);
+///     band
+/// };
+///
+/// let mut writer = PpmWriter::new(Vec::new(), 3, 5, 1.0, Encoding::Srgb).unwrap();
+/// writer.write_rows(&band(0, 2)).unwrap();
+/// writer.write_rows(&band(2, 2)).unwrap();
+/// assert!(writer.write_rows(&Image::new(3, 2)).is_err());
+/// writer.write_rows(&band(4, 1)).unwrap();
+/// let streamed = writer.finish().unwrap();
+///
+/// let path = std::env::temp_dir().join("rt_ppm_writer_doctest.ppm");
+/// whole.save_ppm(path.to_str().unwrap(), Encoding::Srgb).unwrap();
+/// assert_eq!(streamed, std::fs::read(&path).unwrap());
+/// ```
+pub struct PpmWriter<W: Write> {
+    out: W,
+    width: u32,
+    // Rows still to come
+    remaining: u32,
+    encoding: Encoding,
+}
+
+impl<W: Write> PpmWriter<W> {
+    // Writes the header for a `width` by `height` image
+    pub fn new(mut out: W, width: u32, height: u32, pixel_aspect: f64, encoding: Encoding) -> io::Result<Self> {
+        writeln!(out, "P3")?;
+        if pixel_aspect != 1.0 {
+            writeln!(out, "# pixel_aspect {}", pixel_aspect)?;
+        }
+        writeln!(out, "{} {}", width, height)?;
+        writeln!(out, "255")?;
+        Ok(PpmWriter { out, width, remaining: height, encoding })
+    }
+    
+    // Appends the rows of `band`, which follow the rows written so far
+    pub fn write_rows(&mut self, band: &Image) -> io::Result<()> {
+        if band.width != self.width || band.height > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "band is {}x{} but the image is {} wide with {} rows to go",
+                    band.width, band.height, self.width, self.remaining
+                ),
+            ));
+        }
+        
+        for pixel in &band.pixels {
+            let r = self.encoding.to_byte(pixel.r);
+            let g = self.encoding.to_byte(pixel.g);
+            let b = self.encoding.to_byte(pixel.b);
+            writeln!(self.out, "{} {} {}", r, g, b)?;
+        }
+        self.remaining -= band.height;
+        Ok(())
+    }
+    
+    // Flushes and hands back the output, failing if rows are missing
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.remaining > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} rows of the image were never written", self.remaining),
+            ));
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
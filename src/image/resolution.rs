@@ -0,0 +1,92 @@
+// Image sizes as written on the command line and in scene files, and how
+// the different ways of picking one combine.
+
+// The size when nothing picks one
+pub const DEFAULT: (u32, u32) = (800, 600);
+
+const PRESETS: [(&str, (u32, u32)); 3] = [
+    ("4k", (3840, 2160)),
+    ("1080p", (1920, 1080)),
+    ("720p", (1280, 720)),
+];
+
+/// A size written as a preset name (4k, 1080p or 720p) or as
+/// WIDTHxHEIGHT.
+///
+/// ```
+/// use rt::image::resolution::parse;
+///
+/// assert_eq!(parse("4k"), Ok((3840, 2160)));
+/// assert_eq!(parse("1080P"), Ok((1920, 1080)));
+/// assert_eq!(parse("640x480"), Ok((640, 480)));
+/// assert!(parse("640").is_err());
+/// assert!(parse("640x0").is_err());
+/// assert!(parse("8k").is_err());
+/// ```
+pub fn parse(s: &str) -> Result<(u32, u32), String> {
+    let lower = s.trim().to_ascii_lowercase();
+    if let Some(&(_, size)) = PRESETS.iter().find(|(name, _)| *name == lower) {
+        return Ok(size);
+    }
+    let Some((width, height)) = lower.split_once('x') else {
+        return Err(format!("expected 4k, 1080p, 720p or WIDTHxHEIGHT, got '{}'", s));
+    };
+    let side = |text: &str| match text.trim().parse::<u32>() {
+        Ok(0) => Err(format!("'{}' has a side of zero pixels", s)),
+        Ok(pixels) => Ok(pixels),
+        Err(_) => Err(format!("expected WIDTHxHEIGHT in whole pixels, got '{}'", s)),
+    };
+    Ok((side(width)?, side(height)?))
+}
+
+// One side of the image, such as a --width, in whole pixels; a single
+// pixel is fine, as for strips, but zero is not
+pub fn parse_side(s: &str) -> Result<u32, String> {
+    match s.trim().parse::<u32>() {
+        Ok(0) => Err("an image needs at least one pixel on each side".to_string()),
+        Ok(pixels) => Ok(pixels),
+        Err(_) => Err(format!("expected a whole number of pixels, got '{}'", s)),
+    }
+}
+
+// A scale factor written as a percentage such as "50%"; the percent sign
+// may be left out
+pub fn parse_scale(s: &str) -> Result<f64, String> {
+    let percent = s.trim().strip_suffix('%').unwrap_or(s.trim());
+    match percent.trim().parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent.is_finite() => Ok(percent / 100.0),
+        _ => Err(format!("expected a positive percentage such as 50%, got '{}'", s)),
+    }
+}
+
+/// The size of the image to render. A resolution picked on the command
+/// line wins over the one the scene file declares, which wins over
+/// `DEFAULT`; a width or height given on its own then replaces that side,
+/// and the result is scaled, rounding to whole pixels.
+///
+/// ```
+/// use rt::image::resolution::{choose, DEFAULT};
+///
+/// let declared = Some((1920, 1080));
+/// let flag = Some((1280, 720));
+/// assert_eq!(choose(None, None, None, None, 1.0), DEFAULT);
+/// assert_eq!(choose(declared, None, None, None, 1.0), (1920, 1080));
+/// assert_eq!(choose(declared, flag, None, None, 1.0), (1280, 720));
+/// assert_eq!(choose(declared, flag, Some(1000), None, 1.0), (1000, 720));
+/// assert_eq!(choose(declared, None, None, Some(800), 1.0), (1920, 800));
+/// // Scaling applies last, to whatever was picked
+/// assert_eq!(choose(declared, flag, None, None, 0.5), (640, 360));
+/// assert_eq!(choose(None, Some((801, 601)), None, None, 0.5), (401, 301));
+/// assert_eq!(choose(None, Some((10, 10)), None, None, 0.01), (1, 1));
+/// ```
+pub fn choose(
+    declared: Option<(u32, u32)>,
+    resolution: Option<(u32, u32)>,
+    width: Option<u32>,
+    height: Option<u32>,
+    scale: f64,
+) -> (u32, u32) {
+    let (base_width, base_height) = resolution.or(declared).unwrap_or(DEFAULT);
+    let scaled = |pixels: u32| ((pixels as f64 * scale).round() as u32).max(1);
+    (scaled(width.unwrap_or(base_width)), scaled(height.unwrap_or(base_height)))
+}
@@ -0,0 +1,125 @@
+use std::io::{self, Write};
+use super::Image;
+use super::color::Encoding;
+
+// Uncompressed YUV4MPEG2 video, which ffmpeg and mpv read directly. Colors
+// are gamma encoded first, then converted to limited-range BT.601 Y'CbCr,
+// the matrix players assume for Y4M input when none is given.
+
+// How finely color is stored relative to brightness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chroma {
+    // One Cb and Cr sample per 2x2 block of pixels, averaged over the block
+    C420,
+    // Cb and Cr at every pixel
+    C444,
+}
+
+impl Chroma {
+    fn tag(&self) -> &'static str {
+        match self {
+            Chroma::C420 => "C420jpeg",
+            Chroma::C444 => "C444",
+        }
+    }
+    
+    // Width and height of each chroma plane
+    fn plane_size(&self, width: u32, height: u32) -> (usize, usize) {
+        match self {
+            Chroma::C420 => (width.div_ceil(2) as usize, height.div_ceil(2) as usize),
+            Chroma::C444 => (width as usize, height as usize),
+        }
+    }
+}
+
+/// Streams frames of one size into a Y4M file.
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::image::color::Encoding;
+/// use rt::image::y4m::{Chroma, Y4mWriter};
+///
+/// let mut video = Y4mWriter::new(Vec::new(), 4, 2, 24, Chroma::C420, Encoding::Srgb).unwrap();
+/// video.write_frame(&Image::new(4, 2)).unwrap();
+/// video.write_frame(&Image::new(4, 2)).unwrap();
+/// let bytes = video.finish().unwrap();
+///
+/// let header_end = bytes.iter().position(|&b| b == b'\n').unwrap() + 1;
+/// assert_eq!(&bytes[..header_end], b"YUV4MPEG2 W4 H2 F24:1 Ip A1:1 C420jpeg XCOLORRANGE=LIMITED\n");
+/// // Each frame: its marker, 4x2 luma samples and two 2x1 chroma planes
+/// let frame_len = b"FRAME\n".len() + 8 + 2 * 2;
+/// assert_eq!(bytes.len(), header_end + 2 * frame_len);
+/// assert!(bytes[header_end..].starts_with(b"FRAME\n"));
+/// assert!(bytes[header_end + frame_len..].starts_with(b"FRAME\n"));
+/// ```
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: u32,
+    height: u32,
+    chroma: Chroma,
+    encoding: Encoding,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    // Writes the stream header; `encoding` is the transfer curve applied
+    // before the color conversion
+    pub fn new(mut out: W, width: u32, height: u32, fps: u32, chroma: Chroma, encoding: Encoding) -> io::Result<Self> {
+        writeln!(out, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 {} XCOLORRANGE=LIMITED", width, height, fps, chroma.tag())?;
+        Ok(Y4mWriter { out, width, height, chroma, encoding })
+    }
+    
+    pub fn write_frame(&mut self, image: &Image) -> io::Result<()> {
+        if image.width != self.width || image.height != self.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame is {}x{} but the video is {}x{}", image.width, image.height, self.width, self.height),
+            ));
+        }
+        
+        let (width, height) = (self.width as usize, self.height as usize);
+        let ycbcr: Vec<(f64, f64, f64)> = image.pixels().iter()
+            .map(|pixel| {
+                let r = self.encoding.encode(pixel.r.clamp(0.0, 1.0));
+                let g = self.encoding.encode(pixel.g.clamp(0.0, 1.0));
+                let b = self.encoding.encode(pixel.b.clamp(0.0, 1.0));
+                let y = 0.299 * r + 0.587 * g + 0.114 * b;
+                (y, (b - y) / 1.772, (r - y) / 1.402)
+            })
+            .collect();
+        
+        let mut frame = Vec::with_capacity(width * height * 3);
+        frame.extend(ycbcr.iter().map(|&(y, _, _)| (16.0 + 219.0 * y).round() as u8));
+        
+        let (chroma_width, chroma_height) = self.chroma.plane_size(self.width, self.height);
+        let (step_x, step_y) = (width.div_ceil(chroma_width.max(1)), height.div_ceil(chroma_height.max(1)));
+        let mut cb = Vec::with_capacity(chroma_width * chroma_height);
+        let mut cr = Vec::with_capacity(chroma_width * chroma_height);
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                // Blocks on the right and bottom edges of odd sizes are cut short
+                let (mut sum_cb, mut sum_cr, mut count) = (0.0, 0.0, 0.0);
+                for y in cy * step_y..((cy + 1) * step_y).min(height) {
+                    for x in cx * step_x..((cx + 1) * step_x).min(width) {
+                        let (_, pixel_cb, pixel_cr) = ycbcr[y * width + x];
+                        sum_cb += pixel_cb;
+                        sum_cr += pixel_cr;
+                        count += 1.0;
+                    }
+                }
+                cb.push((128.0 + 224.0 * sum_cb / count).round() as u8);
+                cr.push((128.0 + 224.0 * sum_cr / count).round() as u8);
+            }
+        }
+        frame.extend(cb);
+        frame.extend(cr);
+        
+        self.out.write_all(b"FRAME\n")?;
+        self.out.write_all(&frame)
+    }
+    
+    // Flushes the stream and hands it back
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
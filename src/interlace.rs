@@ -0,0 +1,83 @@
+use crate::image::Image;
+
+// Spacing between the pixels traced at each level of an interlaced render,
+// coarsest first. Each level traces the pixels on its grid that the
+// coarser ones have not, so every pixel is traced exactly once.
+pub const STEPS: [u32; 4] = [8, 4, 2, 1];
+
+/// The pixels of row `y` first traced at the level with spacing `step`:
+/// every `step`th pixel of every `step`th row, less those on the grid of
+/// the level before.
+///
+/// ```
+/// use rt::interlace::{level_pixels, STEPS};
+///
+/// assert_eq!(level_pixels(20, 0, 8), vec![0, 8, 16]);
+/// assert_eq!(level_pixels(20, 0, 4), vec![4, 12]);
+/// assert_eq!(level_pixels(20, 4, 4), vec![0, 4, 8, 12, 16]);
+/// assert_eq!(level_pixels(20, 2, 4), Vec::<u32>::new());
+/// assert_eq!(level_pixels(5, 3, 1), vec![0, 1, 2, 3, 4]);
+///
+/// // Together the levels trace each pixel once
+/// let (width, height) = (13, 11);
+/// let mut traced = vec![0; (width * height) as usize];
+/// for step in STEPS {
+///     for y in 0..height {
+///         for x in level_pixels(width, y, step) {
+///             traced[(y * width + x) as usize] += 1;
+///         }
+///     }
+/// }
+/// assert!(traced.iter().all(|&count| count == 1));
+/// ```
+pub fn level_pixels(width: u32, y: u32, step: u32) -> Vec<u32> {
+    if !y.is_multiple_of(step) {
+        return Vec::new();
+    }
+    let coarser = step * 2;
+    let row_on_coarser = step < STEPS[0] && y.is_multiple_of(coarser);
+    (0..width)
+        .step_by(step as usize)
+        .filter(|x| !(row_on_coarser && x.is_multiple_of(coarser)))
+        .collect()
+}
+
+/// Fills each `step` by `step` block of the image with the color of its
+/// top left pixel, which the levels so far have traced, for a blocky
+/// preview of what the finer levels will show. Those pixels keep their
+/// colors, so filling again at a finer step refines the preview.
+///
+/// ```
+/// use rt::image::color::Color;
+/// use rt::image::Image;
+/// use rt::interlace::fill_blocks;
+///
+/// let mut image = Image::from_fn(5, 3, |x, y| Color::new(x as f64, y as f64, 0.0));
+/// fill_blocks(&mut image, 2);
+/// assert_eq!(image.get_pixel(1, 1), Color::new(0.0, 0.0, 0.0));
+/// assert_eq!(image.get_pixel(3, 0), Color::new(2.0, 0.0, 0.0));
+/// assert_eq!(image.get_pixel(4, 2), Color::new(4.0, 2.0, 0.0));
+/// assert_eq!(image.get_pixel(3, 2), Color::new(2.0, 2.0, 0.0));
+///
+/// // Blocks of one pixel leave the image as it is
+/// let before = image.clone();
+/// fill_blocks(&mut image, 1);
+/// assert_eq!(image.pixels(), before.pixels());
+/// ```
+pub fn fill_blocks(image: &mut Image, step: u32) {
+    if step <= 1 {
+        return;
+    }
+    let width = image.width as usize;
+    let step = step as usize;
+    for y in 0..image.height as usize {
+        let source_row = (y - y % step) * width;
+        for x in 0..width {
+            let source = source_row + x - x % step;
+            let target = y * width + x;
+            if source != target {
+                image.pixels[target] = image.pixels[source];
+            }
+        }
+    }
+}
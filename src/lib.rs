@@ -0,0 +1,39 @@
+pub mod vector;
+pub mod ray;
+pub mod objects;
+pub mod camera;
+pub mod scene;
+pub mod material;
+pub mod light;
+pub mod image;
+pub mod background;
+pub mod rng;
+pub mod photon;
+pub mod filter;
+pub mod texture;
+pub mod json;
+pub mod scene_file;
+pub mod bvh;
+pub mod font;
+pub mod binary;
+pub mod mesh;
+pub mod clip;
+pub mod outline;
+pub mod log;
+pub mod transform;
+pub mod group;
+pub mod math;
+pub mod summary;
+pub mod overlap;
+pub mod medium;
+pub mod portal;
+pub mod reproject;
+pub mod sky;
+pub mod ray_paths;
+pub mod interlace;
+pub mod lod;
+pub mod sampling;
+pub mod displaced;
+pub mod motion;
+pub mod framing;
+pub mod memory;
@@ -1,21 +1,504 @@
 use crate::vector::Vec3;
+use crate::image::color::Color;
+use crate::rng::Rng;
+use crate::sampling;
+use std::fs;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightShape {
+    Point,
+    // Rectangle spanned by two edges from `position` (a corner). It emits
+    // on the side that edge_u x edge_v points to.
+    Quad { edge_u: Vec3, edge_v: Vec3 },
+    // Parallel light travelling along `Light::direction` from a source so
+    // far away that only its angular size matters, like the sun. Shadow
+    // rays spread over a cone of this half angle in radians, so shadows
+    // are sharp where they meet their caster and blur farther from it.
+    Directional { angular_radius: f64 },
+}
+
+// How far off a directional light's samples are placed, far enough that
+// its light arrives parallel across any scene
+const DIRECTIONAL_DISTANCE: f64 = 1e6;
+
+/// Which objects a light shines on, by object name. Every object still
+/// casts shadows from every light.
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::scene::{RenderSettings, Scene};
+///
+/// // A ball in front of a wall, lit from the right so its shadow falls on
+/// // the wall to its left
+/// let render = |lights: &str, ball: bool| {
+///     let ball = if ball { r#", { "type": "sphere", "name": "ball", "center": [0, 0, 0], "radius": 1 }"# } else { "" };
+///     let scene: Scene = format!(r#"{{
+///         "camera": {{ "position": [0, 0, 6], "look_at": [0, 0, 0], "fov": 90 }},
+///         "lights": [{}],
+///         "objects": [{{ "type": "plane", "name": "wall", "point": [0, 0, -2], "normal": [0, 0, 1] }}{}]
+///     }}"#, lights, ball).parse().unwrap();
+///     let mut image = Image::new(64, 64);
+///     scene.render_into(&mut image, &RenderSettings::default());
+///     image
+/// };
+/// let (ball, shadow, lit_wall) = ((32, 32), (23, 31), (44, 31));
+/// let linked = render(r#"{ "position": [2, 0, 2], "affects": ["wall"] }"#, true);
+/// let unlinked = render(r#"{ "position": [2, 0, 2] }"#, true);
+/// let dark = render("", true);
+/// let at = |image: &Image, (x, y): (usize, usize)| image.get_pixel(x, y).r;
+///
+/// // The ball gets nothing from the light it is not linked to
+/// assert_eq!(at(&linked, ball), at(&dark, ball));
+/// assert!(at(&unlinked, ball) > at(&dark, ball));
+/// // The wall is lit, except where the ball still shadows it
+/// assert_eq!(at(&linked, lit_wall), at(&unlinked, lit_wall));
+/// assert!(at(&linked, lit_wall) > at(&dark, lit_wall));
+/// assert_eq!(at(&linked, shadow), at(&dark, shadow));
+/// let no_ball = render(r#"{ "position": [2, 0, 2], "affects": ["wall"] }"#, false);
+/// assert!(at(&no_ball, shadow) > at(&dark, shadow));
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum LightLinking {
+    #[default]
+    All,
+    Only(Vec<String>),
+    Except(Vec<String>),
+}
+
+// How a point light's intensity varies with the angle between its
+// `direction` and the direction the light leaves in
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum LightProfile {
+    // The same in every direction
+    #[default]
+    Uniform,
+    // Falls off with the cosine of the angle; nothing shines backwards
+    Cosine,
+    // Full strength within 15 degrees of the axis, fading out by 30
+    Beam,
+    // Multipliers at increasing angles in degrees, interpolated linearly.
+    // Past the last angle the light is dark, as with measured fixture data
+    // that only covers the lit half.
+    Table { path: String, points: Arc<[(f64, f64)]> },
+}
+
+impl LightProfile {
+    // Reads a text file of "angle multiplier" lines; '#' starts a comment
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("cannot read light profile '{}': {}", path, e))?;
+        let points = parse_profile(&text).map_err(|e| format!("bad light profile '{}': {}", path, e))?;
+        Ok(LightProfile::Table { path: path.to_string(), points: points.into() })
+    }
+    
+    // Multiplier for light leaving `angle` degrees away from the axis
+    pub fn factor(&self, angle: f64) -> f64 {
+        match self {
+            LightProfile::Uniform => 1.0,
+            LightProfile::Cosine => angle.to_radians().cos().max(0.0),
+            LightProfile::Beam => {
+                let t = ((angle - 15.0) / 15.0).clamp(0.0, 1.0);
+                1.0 - t * t * (3.0 - 2.0 * t)
+            }
+            LightProfile::Table { points, .. } => match points.iter().position(|&(a, _)| a >= angle) {
+                None => 0.0,
+                Some(0) => points[0].1,
+                Some(i) => {
+                    let ((a0, m0), (a1, m1)) = (points[i - 1], points[i]);
+                    m0 + (m1 - m0) * (angle - a0) / (a1 - a0)
+                }
+            },
+        }
+    }
+}
+
+fn parse_profile(text: &str) -> Result<Vec<(f64, f64)>, String> {
+    let mut points: Vec<(f64, f64)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let values: Vec<&str> = line.split_whitespace().collect();
+        let [angle, multiplier] = values[..] else {
+            return Err(format!("line {}: expected an angle and a multiplier", line_number));
+        };
+        let parse = |text: &str| {
+            text.parse::<f64>().ok()
+                .filter(|value| value.is_finite())
+                .ok_or_else(|| format!("line {}: '{}' is not a number", line_number, text))
+        };
+        let (angle, multiplier) = (parse(angle)?, parse(multiplier)?);
+        if !(0.0..=180.0).contains(&angle) {
+            return Err(format!("line {}: angle {} is outside 0 to 180 degrees", line_number, angle));
+        }
+        if multiplier < 0.0 {
+            return Err(format!("line {}: multiplier {} is negative", line_number, multiplier));
+        }
+        if points.last().is_some_and(|&(previous, _)| angle <= previous) {
+            return Err(format!("line {}: angles must increase", line_number));
+        }
+        points.push((angle, multiplier));
+    }
+    if points.is_empty() {
+        return Err("no entries".to_string());
+    }
+    Ok(points)
+}
+
+// How light dims with the distance `d` it travels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attenuation {
+    // Full strength at any distance
+    None,
+    // 1 / (1 + d)
+    Linear,
+    // 1 / (1 + d^2)
+    Quadratic,
+    // 1 / (a + b d + c d^2); the default is a = 1, b = 0.1, c = 0.01
+    Custom { a: f64, b: f64, c: f64 },
+    // Inverse square for a source of this radius, 1 / (d^2 + radius^2),
+    // which stays finite at the light itself
+    Soft { radius: f64 },
+}
+
+impl Attenuation {
+    pub fn factor(&self, distance: f64) -> f64 {
+        match *self {
+            Attenuation::None => 1.0,
+            Attenuation::Linear => 1.0 / (1.0 + distance),
+            Attenuation::Quadratic => 1.0 / (1.0 + distance * distance),
+            Attenuation::Custom { a, b, c } => 1.0 / (a + b * distance + c * distance * distance),
+            Attenuation::Soft { radius } => 1.0 / (distance * distance + radius * radius),
+        }
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Self {
+        Attenuation::Custom { a: 1.0, b: 0.1, c: 0.01 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Light {
     pub position: Vec3,
-    pub color: Vec3,
+    pub color: Color,
     pub intensity: f64,
+    pub shape: LightShape,
+    pub linking: LightLinking,
+    // Axis of a point light's profile, pointing straight down by default
+    pub direction: Vec3,
+    pub profile: LightProfile,
+    pub falloff: Attenuation,
+    // Distances over which the light fades from full to nothing on top of
+    // its attenuation, for lighting only what is near it
+    pub falloff_window: Option<(f64, f64)>,
 }
 
 impl Light {
-    pub fn new(position: Vec3, color: Vec3, intensity: f64) -> Self {
+    pub fn new(position: Vec3, color: Color, intensity: f64) -> Self {
         Light {
             position,
             color,
             intensity,
+            shape: LightShape::Point,
+            linking: LightLinking::All,
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            profile: LightProfile::Uniform,
+            falloff: Attenuation::default(),
+            falloff_window: None,
         }
     }
     
+    // Intensity is per unit area, so a larger panel is brighter
+    pub fn quad(corner: Vec3, edge_u: Vec3, edge_v: Vec3, color: Color, intensity: f64) -> Self {
+        Light {
+            position: corner,
+            color,
+            intensity,
+            shape: LightShape::Quad { edge_u, edge_v },
+            linking: LightLinking::All,
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            profile: LightProfile::Uniform,
+            falloff: Attenuation::default(),
+            falloff_window: None,
+        }
+    }
+    
+    /// Light travelling along `direction` from a source `angular_diameter`
+    /// degrees across (the sun is about 0.53), at full strength everywhere.
+    /// A source with a size casts shadows whose edges blur with distance
+    /// from the caster, which no light at a point, whatever its falloff,
+    /// can do. Each shading point takes `--area-samples` squared shadow
+    /// rays spread over the source's disk, one when its size is 0.
+    ///
+    /// A tall box's shadow on the ground, seen from above, is crisp where
+    /// it leaves the box and soft at its far end:
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::image::Image;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// // Sunlight from 30 degrees above the horizon, travelling along +x;
+    /// // the box's shadow reaches x = 7.2
+    /// let render = |angular_diameter: f64| {
+    ///     let mut scene: Scene = format!(r#"{{
+    ///         "lights": [{{ "type": "directional", "direction": [0.866, -0.5, 0], "angular_diameter": {} }}],
+    ///         "objects": [
+    ///             {{ "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0] }},
+    ///             {{ "type": "group", "transform": {{ "scale": [0.5, 4, 0.5], "translate": [0, 2, 0] }},
+    ///                "children": [{{ "type": "cube", "center": [0, 0, 0], "size": 1 }}] }}
+    ///         ]
+    ///     }}"#, angular_diameter).parse().unwrap();
+    ///     // Looking straight down on x from -1 to 8 and z from -1.5 to 1.5,
+    ///     // 30 pixels to the unit
+    ///     scene.set_camera(Camera::orthographic(Vec3::new(3.5, 10.0, 0.0), Vec3::new(3.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0), 3.0, 3.0));
+    ///     let mut image = Image::new(271, 91);
+    ///     scene.render_into(&mut image, &RenderSettings::default());
+    ///     image
+    /// };
+    /// // Pixels of the column at x that are neither lit nor fully shadowed
+    /// let penumbra = |image: &Image, x: f64| {
+    ///     let column: Vec<f64> = (0..91).map(|y| image.get_pixel(((x + 1.0) * 30.0) as usize, y).r).collect();
+    ///     let (dark, lit) = (column.iter().cloned().fold(f64::MAX, f64::min), column[0]);
+    ///     assert!(lit > dark + 0.1);
+    ///     column.iter().filter(|&&value| value > dark + 0.1 * (lit - dark) && value < lit - 0.1 * (lit - dark)).count()
+    /// };
+    ///
+    /// let sun = render(4.0);
+    /// assert!(penumbra(&sun, 0.6) <= 2, "{}", penumbra(&sun, 0.6));
+    /// assert!(penumbra(&sun, 5.0) >= 10, "{}", penumbra(&sun, 5.0));
+    ///
+    /// // A source without size leaves the far end as crisp as the base
+    /// let hard = render(0.0);
+    /// assert!(penumbra(&hard, 0.6) <= 2 && penumbra(&hard, 5.0) <= 2);
+    /// ```
+    pub fn directional(direction: Vec3, angular_diameter: f64, color: Color, intensity: f64) -> Self {
+        let direction = direction.normalize();
+        Light {
+            position: -direction * DIRECTIONAL_DISTANCE,
+            color,
+            intensity,
+            shape: LightShape::Directional { angular_radius: angular_diameter.to_radians() / 2.0 },
+            linking: LightLinking::All,
+            direction,
+            profile: LightProfile::Uniform,
+            falloff: Attenuation::None,
+            falloff_window: None,
+        }
+    }
+    
+    /// A point light falling off with the inverse square of distance, as
+    /// real lights do, softened by the size of the source so surfaces
+    /// touching it are lit brightly rather than infinitely: `power / (d^2 +
+    /// source_radius^2)`.
+    ///
+    /// ```
+    /// use rt::image::color::Color;
+    /// use rt::light::Light;
+    /// use rt::vector::Vec3;
+    ///
+    /// let light = Light::physical_soft(Vec3::zero(), Color::WHITE, 10.0, 0.05);
+    /// let at = |distance: f64| light.intensity * light.attenuation(distance);
+    /// assert!(at(0.0).is_finite());
+    /// assert!((at(0.0) - 10.0 / (0.05 * 0.05)).abs() < 1e-9);
+    /// // Far away it is inverse square
+    /// for distance in [10.0, 100.0, 1000.0] {
+    ///     let inverse_square = 10.0 / (distance * distance);
+    ///     assert!((at(distance) / inverse_square - 1.0).abs() < 1e-4);
+    /// }
+    /// ```
+    pub fn physical_soft(position: Vec3, color: Color, power: f64, source_radius: f64) -> Self {
+        Light::new(position, color, power).with_attenuation(Attenuation::Soft { radius: source_radius })
+    }
+    
+    pub fn with_linking(mut self, linking: LightLinking) -> Self {
+        self.linking = linking;
+        self
+    }
+    
+    pub fn with_attenuation(mut self, falloff: Attenuation) -> Self {
+        self.falloff = falloff;
+        self
+    }
+    
+    /// Fades the light out between `start` and `end` away from it,
+    /// following a smoothstep, so it lights nothing past `end` however its
+    /// attenuation is set. With `start` equal to `end` the light cuts off
+    /// sharply there.
+    ///
+    /// ```
+    /// use rt::image::color::Color;
+    /// use rt::light::{Attenuation, Light};
+    /// use rt::vector::Vec3;
+    ///
+    /// let light = Light::new(Vec3::zero(), Color::WHITE, 1.0)
+    ///     .with_attenuation(Attenuation::None)
+    ///     .with_falloff_window(2.0, 4.0)
+    ///     .unwrap();
+    /// assert_eq!(light.attenuation(1.0), 1.0);
+    /// assert_eq!(light.attenuation(2.0), 1.0);
+    /// assert!((light.attenuation(3.0) - 0.5).abs() < 1e-12);
+    /// assert!(light.attenuation(2.5) > light.attenuation(3.5));
+    /// assert_eq!(light.attenuation(4.0), 0.0);
+    /// assert!(!light.reaches(4.5));
+    ///
+    /// let sharp = light.clone().with_falloff_window(3.0, 3.0).unwrap();
+    /// assert_eq!((sharp.attenuation(3.0), sharp.attenuation(3.001)), (1.0, 0.0));
+    ///
+    /// // A panel is in range when any of it is: this one's center is 5 away
+    /// // from a point 1 beyond its near edge, so with `--light-samples` it
+    /// // must still be picked there
+    /// let panel = Light::quad(Vec3::zero(), Vec3::new(8.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), Color::WHITE, 1.0)
+    ///     .with_attenuation(Attenuation::None)
+    ///     .with_falloff_window(2.0, 4.0)
+    ///     .unwrap();
+    /// assert!(panel.importance(Vec3::new(-1.0, 0.0, 1.0)) > 0.0);
+    /// assert!(panel.importance(Vec3::new(4.0, -1.5, 1.0)) > 0.0);
+    /// assert_eq!(panel.importance(Vec3::new(-5.0, 0.0, 1.0)), 0.0);
+    ///
+    /// assert!(light.clone().with_falloff_window(4.0, 2.0).is_err());
+    /// assert!(light.clone().with_falloff_window(0.0, 2.0).is_err());
+    /// assert!(Light::directional(Vec3::new(0.0, -1.0, 0.0), 0.0, Color::WHITE, 1.0).with_falloff_window(1.0, 2.0).is_err());
+    /// ```
+    pub fn with_falloff_window(mut self, start: f64, end: f64) -> Result<Self, String> {
+        if matches!(self.shape, LightShape::Directional { .. }) {
+            return Err("directional lights are the same strength everywhere and take no falloff window".to_string());
+        }
+        if !(start > 0.0 && start.is_finite() && end.is_finite()) {
+            return Err(format!("falloff window distances must be positive, got {} and {}", start, end));
+        }
+        if start > end {
+            return Err(format!("falloff window starts at {}, past its end at {}", start, end));
+        }
+        self.falloff_window = Some((start, end));
+        Ok(self)
+    }
+    
+    // Whether any light arrives from `distance` away, so shadow rays from
+    // farther out can be skipped
+    pub fn reaches(&self, distance: f64) -> bool {
+        self.falloff_window.is_none_or(|(_, end)| distance <= end)
+    }
+    
+    // Only point lights use a profile; area lights already fall off with
+    // the cosine to their panel
+    pub fn with_profile(mut self, profile: LightProfile, direction: Vec3) -> Self {
+        self.profile = profile;
+        self.direction = direction.normalize();
+        self
+    }
+    
+    // Profile multiplier for light leaving a point light along `direction`
+    pub fn emission_factor(&self, direction: Vec3) -> f64 {
+        if self.profile == LightProfile::Uniform || self.shape != LightShape::Point {
+            return 1.0;
+        }
+        let cos_angle = self.direction.dot(&direction.normalize()).clamp(-1.0, 1.0);
+        self.profile.factor(cos_angle.acos().to_degrees())
+    }
+    
+    pub fn center(&self) -> Vec3 {
+        match self.shape {
+            LightShape::Point | LightShape::Directional { .. } => self.position,
+            LightShape::Quad { edge_u, edge_v } => self.position + (edge_u + edge_v) * 0.5,
+        }
+    }
+    
+    // Number of shading samples taken for this light; area lights and
+    // directional ones with a size use a `per_axis` x `per_axis` grid
+    pub fn sample_count(&self, per_axis: u32) -> u32 {
+        match self.shape {
+            LightShape::Point => 1,
+            LightShape::Directional { angular_radius: 0.0 } => 1,
+            LightShape::Quad { .. } | LightShape::Directional { .. } => per_axis * per_axis,
+        }
+    }
+    
+    // Position of sample `index` and its weight as seen from `target`. Quad
+    // samples are jittered within their grid cell and weighted by the
+    // panel area and the emission cosine toward the target, so the weights
+    // of all samples together account for the whole panel. Directional
+    // samples are jittered over the source's disk the same way and placed
+    // far off in their direction from the target, sharing one weight.
+    pub fn sample(&self, index: u32, per_axis: u32, rng: &mut Rng, target: Vec3) -> (Vec3, f64) {
+        let mut jittered_cell = || {
+            let cell_u = (index % per_axis) as f64;
+            let cell_v = (index / per_axis) as f64;
+            ((cell_u + rng.next_f64()) / per_axis as f64, (cell_v + rng.next_f64()) / per_axis as f64)
+        };
+        match self.shape {
+            LightShape::Point => (self.position, self.emission_factor(target - self.position)),
+            LightShape::Directional { angular_radius: 0.0 } => {
+                (target - self.direction * DIRECTIONAL_DISTANCE, 1.0)
+            }
+            LightShape::Directional { angular_radius } => {
+                let (s, t) = jittered_cell();
+                let direction = sampling::cone_direction(-self.direction, angular_radius, s, t);
+                (target + direction * DIRECTIONAL_DISTANCE, 1.0 / (per_axis * per_axis) as f64)
+            }
+            LightShape::Quad { edge_u, edge_v } => {
+                let (s, t) = jittered_cell();
+                let point = self.position + edge_u * s + edge_v * t;
+                
+                let area_normal = edge_u.cross(&edge_v);
+                let area = area_normal.length();
+                let cos_emit = area_normal.normalize().dot(&(target - point).normalize()).max(0.0);
+                (point, area * cos_emit / (per_axis * per_axis) as f64)
+            }
+        }
+    }
+    
+    // Rough, unshadowed estimate of this light's contribution at `point`,
+    // used to choose which lights to sample
+    pub fn importance(&self, point: Vec3) -> f64 {
+        let brightness = (self.color.r + self.color.g + self.color.b) / 3.0;
+        let area = match self.shape {
+            LightShape::Point | LightShape::Directional { .. } => 1.0,
+            LightShape::Quad { edge_u, edge_v } => edge_u.cross(&edge_v).length(),
+        };
+        // The falloff window is judged from the nearest part of the light,
+        // so a panel reaching into range is still picked
+        brightness * self.intensity * area * self.emission_factor(point - self.center())
+            * self.falloff.factor((self.center() - point).length())
+            * self.window(self.nearest_distance(point))
+    }
+    
+    // Distance from `point` to the closest point of the light: of its panel
+    // for a quad light
+    fn nearest_distance(&self, point: Vec3) -> f64 {
+        let LightShape::Quad { edge_u, edge_v } = self.shape else {
+            return (self.position - point).length();
+        };
+        // Where `point` lies over the panel's plane, in edge coordinates
+        let offset = point - self.position;
+        let normal = edge_u.cross(&edge_v);
+        let height = offset.dot(&normal) / normal.length_squared();
+        let in_plane = offset - normal * height;
+        let (uu, uv, vv) = (edge_u.dot(&edge_u), edge_u.dot(&edge_v), edge_v.dot(&edge_v));
+        let (pu, pv) = (in_plane.dot(&edge_u), in_plane.dot(&edge_v));
+        let det = uu * vv - uv * uv;
+        let (s, t) = ((pu * vv - pv * uv) / det, (pv * uu - pu * uv) / det);
+        if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
+            return (normal * height).length();
+        }
+        // Otherwise the closest point is on one of the edges
+        let to_segment = |start: Vec3, edge: Vec3| {
+            let along = ((point - start).dot(&edge) / edge.length_squared()).clamp(0.0, 1.0);
+            (start + edge * along - point).length()
+        };
+        [
+            to_segment(self.position, edge_u),
+            to_segment(self.position, edge_v),
+            to_segment(self.position + edge_u, edge_v),
+            to_segment(self.position + edge_v, edge_u),
+        ].into_iter().fold(f64::INFINITY, f64::min)
+    }
+    
     pub fn direction_from(&self, point: Vec3) -> Vec3 {
         (self.position - point).normalize()
     }
@@ -25,6 +508,19 @@ impl Light {
     }
     
     pub fn attenuation(&self, distance: f64) -> f64 {
-        1.0 / (1.0 + 0.1 * distance + 0.01 * distance * distance)
+        self.falloff.factor(distance) * self.window(distance)
+    }
+    
+    // The falloff window's share of the light left at `distance`
+    fn window(&self, distance: f64) -> f64 {
+        match self.falloff_window {
+            None => 1.0,
+            Some((_, end)) if distance > end => 0.0,
+            Some((start, _)) if distance <= start => 1.0,
+            Some((start, end)) => {
+                let t = (distance - start) / (end - start);
+                1.0 - t * t * (3.0 - 2.0 * t)
+            }
+        }
     }
 }
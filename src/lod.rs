@@ -0,0 +1,201 @@
+use crate::vector::Vec3;
+use crate::image::color::Color;
+use crate::ray::{Ray, RayPacket4, HitRecord};
+use crate::material::Material;
+use crate::objects::Object;
+use crate::group::Group;
+use crate::bvh::Aabb;
+use std::any::Any;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// One object at several levels of detail, such as a scanned statue as a
+/// full mesh, a decimated one and a box, of which only the level that
+/// suits its distance from the camera is traced. Each level after the
+/// first takes over at its switch distance from the camera to the center
+/// of the object's box.
+///
+/// The level is picked once per camera position, when a render starts, and
+/// every ray of that render (shadows and reflections too) sees the same
+/// one. So no holes open where levels meet, and surfaces never shadow a
+/// coarser copy of themselves.
+///
+/// ```
+/// use rt::lod::Lod;
+/// use rt::material::Material;
+/// use rt::objects::{Cube, Object, Sphere};
+/// use rt::ray::Ray;
+/// use rt::vector::Vec3;
+///
+/// // A sphere up close, a cube of the same height from 10 units away
+/// let lod = Lod::new(vec![
+///     Box::new(Sphere::new(Vec3::zero(), 1.0, Material::default())),
+///     Box::new(Cube::new(Vec3::zero(), 2.0, Material::default())),
+/// ], vec![10.0]).unwrap();
+/// assert_eq!(lod.level_for(Vec3::new(0.0, 0.0, 9.99)), 0);
+/// assert_eq!(lod.level_for(Vec3::new(0.0, 0.0, 10.0)), 1);
+/// assert_eq!(lod.level_for(Vec3::new(0.0, 30.0, 0.0)), 1);
+///
+/// // Through the corner of the cube's face, which the sphere misses
+/// let corner = |from: Vec3| Ray::new(from, Vec3::new(0.9, 0.9, 0.0) - from);
+/// lod.view_from(Vec3::new(0.0, 0.0, 5.0));
+/// assert!(lod.hit(&corner(Vec3::new(0.0, 0.0, 5.0)), 0.001, f64::INFINITY).is_none());
+/// lod.view_from(Vec3::new(0.0, 0.0, 20.0));
+/// assert!(lod.hit(&corner(Vec3::new(0.0, 0.0, 20.0)), 0.001, f64::INFINITY).is_some());
+///
+/// // Right at the switch distance every ray aimed at the object finds it
+/// lod.view_from(Vec3::new(0.0, 0.0, 10.0));
+/// for i in -9..=9 {
+///     for j in -9..=9 {
+///         let target = Vec3::new(i as f64 * 0.1, j as f64 * 0.1, 0.0);
+///         let ray = Ray::new(Vec3::new(0.0, 0.0, 10.0), target - Vec3::new(0.0, 0.0, 10.0));
+///         assert!(lod.hit(&ray, 0.001, f64::INFINITY).is_some());
+///     }
+/// }
+///
+/// assert!(Lod::new(vec![Box::new(Sphere::new(Vec3::zero(), 1.0, Material::default()))], vec![5.0]).is_err());
+/// ```
+pub struct Lod {
+    levels: Vec<Box<dyn Object>>,
+    // Distance at which each level after the first takes over, increasing
+    distances: Vec<f64>,
+    bounds: Aabb,
+    // Level the last camera position picked; the first until one is set
+    current: AtomicUsize,
+}
+
+impl Lod {
+    pub fn new(levels: Vec<Box<dyn Object>>, distances: Vec<f64>) -> Result<Self, String> {
+        if levels.is_empty() {
+            return Err("a lod needs at least one level".to_string());
+        }
+        if distances.len() != levels.len() - 1 {
+            return Err(format!(
+                "{} levels need {} switch distances, one for each level after the first, not {}",
+                levels.len(), levels.len() - 1, distances.len(),
+            ));
+        }
+        if distances.iter().any(|distance| !(distance.is_finite() && *distance > 0.0)) {
+            return Err("switch distances must be positive".to_string());
+        }
+        if distances.windows(2).any(|pair| pair[1] <= pair[0]) {
+            return Err("switch distances must increase from level to level".to_string());
+        }
+        let mut bounds: Option<Aabb> = None;
+        for (index, level) in levels.iter().enumerate() {
+            let level_bounds = level.bounding_box().ok_or_else(|| format!("level {} is unbounded, so it has no distance", index))?;
+            bounds = Some(bounds.map_or(level_bounds, |bounds| bounds.union(&level_bounds)));
+        }
+        Ok(Lod { levels, distances, bounds: bounds.unwrap(), current: AtomicUsize::new(0) })
+    }
+    
+    pub fn levels(&self) -> &[Box<dyn Object>] {
+        &self.levels
+    }
+    
+    pub fn distances(&self) -> &[f64] {
+        &self.distances
+    }
+    
+    // Level seen from `viewpoint`: the last whose switch distance it is at
+    // or beyond
+    pub fn level_for(&self, viewpoint: Vec3) -> usize {
+        let distance = (self.bounds.centroid() - viewpoint).length();
+        self.distances.partition_point(|&switch| switch <= distance)
+    }
+    
+    // Picks the level rays are traced against from now on. The scene calls
+    // this with its camera's position as each render starts.
+    pub fn view_from(&self, viewpoint: Vec3) {
+        self.current.store(self.level_for(viewpoint), Ordering::Relaxed);
+    }
+    
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+    
+    fn level(&self) -> &dyn Object {
+        self.levels[self.current()].as_ref()
+    }
+}
+
+// Points every lod among `objects`, including those inside groups and
+// other lods, at `viewpoint`, which is in the objects' space
+pub fn view_from_all(objects: &[Box<dyn Object>], viewpoint: Vec3) {
+    for object in objects {
+        if let Some(lod) = object.as_any().downcast_ref::<Lod>() {
+            lod.view_from(viewpoint);
+            view_from_all(lod.levels(), viewpoint);
+        } else if let Some(group) = object.as_any().downcast_ref::<Group>() {
+            if let Some(inverse) = group.transform().inverse() {
+                view_from_all(group.children(), inverse.transform_point(viewpoint));
+            }
+        }
+    }
+}
+
+impl Object for Lod {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.level().hit(ray, t_min, t_max)
+    }
+    
+    fn hit4(&self, packet: &RayPacket4, t_min: [f64; 4], t_max: [f64; 4]) -> [Option<HitRecord>; 4] {
+        self.level().hit4(packet, t_min, t_max)
+    }
+    
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        self.level().hit_range(ray, t_min, t_max)
+    }
+    
+    // The first level's material, as for a group's first child
+    fn material(&self) -> &Material {
+        self.levels[0].material()
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        self.levels[0].material_mut()
+    }
+    
+    // Overrides by the lod's name change every level alike
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        self.levels.iter_mut().flat_map(|level| level.materials_mut()).collect()
+    }
+    
+    fn material_at(&self, hit: &HitRecord) -> Material {
+        self.level().material_at(hit)
+    }
+    
+    fn albedo(&self, hit: &HitRecord, footprint: f64) -> Color {
+        self.level().albedo(hit, footprint)
+    }
+    
+    fn emission(&self, hit: &HitRecord, footprint: f64) -> Color {
+        self.level().emission(hit, footprint)
+    }
+    
+    fn kind(&self) -> &'static str {
+        "lod"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn memory_estimate(&self) -> usize {
+        let levels: usize = self.levels.iter().map(|level| level.memory_estimate()).sum();
+        mem::size_of::<Self>()
+            + self.levels.capacity() * mem::size_of::<Box<dyn Object>>()
+            + levels
+            + self.distances.capacity() * mem::size_of::<f64>()
+    }
+    
+    fn shared_memory(&self) -> Vec<(usize, usize)> {
+        self.levels.iter().flat_map(|level| level.shared_memory()).collect()
+    }
+    
+    // Box around every level, so the scene's hierarchy holds whichever is
+    // picked
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+// Leveled diagnostics. Everything the library and the command line tool
+// report goes through here and ends up on stderr unless a sink is
+// installed, so stdout stays free for image data.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+    
+    // What the default sink puts in front of a message
+    fn prefix(&self) -> &'static str {
+        match self {
+            Level::Error => "Error: ",
+            Level::Warn => "Warning: ",
+            Level::Info | Level::Debug | Level::Trace => "",
+        }
+    }
+}
+
+type Sink = Box<dyn Fn(Level, &str) + Send + Sync>;
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+// Messages above `level` are dropped before they are formatted
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn max_level() -> Level {
+    Level::ALL[MAX_LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+pub fn enabled(level: Level) -> bool {
+    level <= max_level()
+}
+
+/// Sends every message that passes the level to `sink` instead of stderr.
+///
+/// ```
+/// use rt::log::{self, Level};
+/// use std::sync::{Arc, Mutex};
+///
+/// let lines = Arc::new(Mutex::new(Vec::new()));
+/// let captured = Arc::clone(&lines);
+/// log::set_sink(move |level, message| captured.lock().unwrap().push((level, message.to_string())));
+///
+/// rt::warn!("{} lights", 3);
+/// rt::debug!("dropped at the default level");
+/// assert_eq!(*lines.lock().unwrap(), [(Level::Warn, "3 lights".to_string())]);
+/// log::reset_sink();
+/// ```
+pub fn set_sink(sink: impl Fn(Level, &str) + Send + Sync + 'static) {
+    *SINK.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(sink));
+}
+
+// Goes back to writing to stderr
+pub fn reset_sink() {
+    *SINK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+// Called through the `error!` ... `trace!` macros
+pub fn write(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let message = args.to_string();
+    match SINK.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(sink) => sink(level, &message),
+        None => eprintln!("{}{}", level.prefix(), message),
+    }
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Error, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Warn, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Info, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Debug, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Trace, format_args!($($arg)*)) };
+}
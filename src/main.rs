@@ -1,85 +1,2203 @@
-use clap::Parser;
-
-mod vector;
-mod ray;
-mod objects;
-mod camera;
-mod scene;
-mod material;
-mod light;
-mod image;
-
-use vector::Vec3;
-use camera::Camera;
-use scene::Scene;
-use objects::{Sphere, Plane, Cube, Cylinder};
-use material::Material;
-use light::Light;
-use image::Image;
+use clap::{ArgAction, Parser, Subcommand, Args as ClapArgs};
+use std::time::{Duration, Instant};
 
+use rt::vector::Vec3;
+use rt::camera::{Camera, Lens};
+use rt::scene::{Scene, RenderSettings, RenderReport, RenderPass, PixelOrder, TileOrder, ReflectionBlend, Shading, AuxBuffers, Layers, RenderLayer};
+use rt::scene::scatter;
+use rt::objects::{Object, Sphere, Ellipsoid, Capsule, Plane, CheckerPlane, Cube, Cylinder};
+use rt::material::{Material, MaterialOverride};
+use rt::light::Light;
+use rt::image::Image;
+use rt::image::metrics::{self, SsimWindow};
+use rt::image::color::{Color, Encoding};
+use rt::image::y4m::{Chroma, Y4mWriter};
+use rt::image::ppm::PpmWriter;
+use rt::image::resolution;
+use rt::bvh::Bvh;
+use rt::font;
+use rt::json::Value;
+use rt::background::{Backdrop, Background};
+use rt::sky::SkyModel;
+use rt::filter::Filter;
+use rt::texture::{ImageTexture, Texture, TextureFilter};
+use rt::scene_file::{self, load_scene, parse_scene, export_scene, AssetCache, MissingAssets, Units};
+use rt::log::{self, Level};
+use rt::{debug, error, info, warn};
+use rt::mesh::{self, Handedness, ObjConvention, Up};
+use rt::rng::Rng;
+use rt::clip::ClipPlane;
+use rt::outline::Outline;
+use rt::reproject::{reproject, Frame, Reprojection};
+use rt::framing::{parse_aspect, Fit};
+use rt::memory::{format_size, parse_size, MemoryEstimate};
+use rt::motion::MotionVectors;
+use rt::overlap::{self, Overlap};
+use rt::transform::Mat4;
+use rt::group::Group;
+use rt::ray_paths;
+use std::sync::Arc;
+use std::fs::File;
+use std::path::Path;
+use std::io::{self, BufRead, BufWriter, Write};
+
+// Options that pick the scene are global so they can also follow
+// `rt describe`
 #[derive(Parser)]
 #[command(name = "rt")]
 #[command(about = "A ray tracer that renders 3D scenes to PPM images")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(short, long, default_value = "800")]
-    width: u32,
+    #[command(subcommand)]
+    command: Option<Command>,
+    
+    // Image size: 4k, 1080p, 720p or WIDTHxHEIGHT; otherwise the scene
+    // file's resolution, or 800x600
+    #[arg(long, value_name = "SIZE", value_parser = resolution::parse, global = true)]
+    resolution: Option<(u32, u32)>,
+    
+    // Replace one side of the resolution
+    #[arg(short, long, value_parser = resolution::parse_side, global = true)]
+    width: Option<u32>,
+    
+    #[arg(long, value_parser = resolution::parse_side, global = true)]
+    height: Option<u32>,
+    
+    // Percentage to scale the image size by, e.g. 50% for a quick preview
+    // of a final render
+    #[arg(long, value_name = "PERCENT", default_value = "100%", value_parser = resolution::parse_scale, global = true)]
+    scale: f64,
+    
+    // Width over height of each pixel, for anamorphic output; wider pixels
+    // widen the view without changing the image size
+    #[arg(long, default_value = "1.0", value_parser = parse_positive)]
+    pixel_aspect: f64,
     
-    #[arg(long, default_value = "600")]
-    height: u32,
+    // Render the scene file's framing for this aspect ratio, e.g. 16x9,
+    // instead of its camera's own view
+    #[arg(long, value_name = "WxH", value_parser = parse_aspect)]
+    framing: Option<(u32, u32)>,
     
-    #[arg(short, long, default_value = "scene1")]
+    // How a framing fills an image of another aspect ratio: crop it, or
+    // letterbox it with black bars
+    #[arg(long, value_name = "POLICY", default_value = "crop", value_parser = parse_fit, requires = "framing")]
+    framing_fit: Fit,
+    
+    #[arg(short, long, default_value = "scene1", global = true)]
     scene: String,
     
+    // Load the scene from a JSON scene file instead of a built-in scene
+    #[arg(long, value_name = "PATH", global = true)]
+    scene_file: Option<String>,
+    
+    // The scene file's JSON given directly, for one-off renders from shell
+    // scripts; paths in it are relative to the current directory
+    #[arg(long, value_name = "JSON", conflicts_with = "scene_file", global = true)]
+    scene_inline: Option<String>,
+    
+    // Units the scene file's lengths are in (mm, cm, m, km, in, ft or meters
+    // per unit), overriding any it declares
+    #[arg(long, value_name = "UNITS", value_parser = Units::parse, global = true)]
+    scene_scale: Option<Units>,
+    
+    // Up axis (y or z) and handedness (right or left) of the scene file's
+    // mesh files, for meshes that do not give their own
+    #[arg(long, value_name = "AXIS", value_parser = Up::parse, default_value = "y", global = true)]
+    obj_up: Up,
+    #[arg(long, value_name = "HAND", value_parser = Handedness::parse, default_value = "right", global = true)]
+    obj_handedness: Handedness,
+    
+    // What to do when the scene file's texture or mesh files are missing:
+    // fail, or render with placeholders and warn
+    #[arg(long, value_name = "POLICY", value_parser = MissingAssets::parse, default_value = "error", global = true)]
+    missing_assets: MissingAssets,
+    
+    // Refuse scenes whose objects are estimated to need more memory than
+    // this, e.g. 4GiB: mesh and texture files are measured before they are
+    // loaded, and the whole scene once it is built, before rendering starts
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, global = true)]
+    memory_budget: Option<usize>,
+    
+    // Write the scene (after overrides) to a JSON scene file and exit
+    #[arg(long, value_name = "PATH")]
+    export_scene: Option<String>,
+    
     #[arg(short = 'r', long)]
     reflection: bool,
     
-    #[arg(short = 't', long)]
+    // Reflection and refraction rays that would make up less of the pixel
+    // than this are not traced
+    #[arg(long, value_name = "WEIGHT", default_value_t = 1.0 / 512.0, value_parser = parse_min_contribution)]
+    min_contribution: f64,
+    
+    // How reflections mix with the surface color: linear, or fresnel to
+    // keep colors head-on and mirror strongly at grazing angles
+    #[arg(long, value_name = "BLEND", default_value = "linear", value_parser = parse_reflection_blend)]
+    reflection_blend: ReflectionBlend,
+    
+    // Picks the random numbers; the same seed gives the same image on any
+    // number of threads
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    seed: u64,
+    
+    #[arg(short = 't', long, global = true)]
     textures: bool,
+    
+    // Intersect primary rays in 4-wide packets
+    #[arg(long)]
+    packets: bool,
+    
+    // Draw a small glowing sphere at each light position
+    #[arg(long)]
+    show_lights: bool,
+    
+    // What to render: the shaded image or a diagnostic pass
+    #[arg(long, default_value = "beauty", value_parser = parse_pass)]
+    pass: RenderPass,
+    
+    // Change one object's material: name=preset or name=field:value,...
+    // (repeatable)
+    #[arg(long, value_name = "NAME=SPEC", global = true)]
+    override_material: Vec<MaterialOverride>,
+    
+    // Area lights are sampled on an N x N jittered grid per shading point
+    #[arg(long, default_value = "4")]
+    area_samples: u32,
+    
+    // Size of the softbox light in the softbox scene
+    #[arg(long, default_value = "2.0", value_parser = parse_positive, global = true)]
+    softbox_size: f64,
+    
+    // Recursion depth of the menger scene's sponge (0 is a single cube)
+    #[arg(long, default_value = "3", value_parser = parse_level, global = true)]
+    level: u32,
+    
+    // Objects may sink this far into each other, or into the floor, before
+    // they are reported as overlapping
+    #[arg(long, value_name = "DISTANCE", default_value = "0.001", value_parser = parse_finite, global = true)]
+    overlap_tolerance: f64,
+    
+    // How the random scene scatters its spheres: poisson keeps them apart,
+    // uniform lets them clump and overlap
+    #[arg(long, default_value = "poisson", value_parser = parse_placement, global = true)]
+    placement: Placement,
+    
+    // Add caustics from a photon map traced before rendering
+    #[arg(long)]
+    caustics: bool,
+    
+    // Photons traced per light for --caustics
+    #[arg(long, default_value = "200000")]
+    photons: u32,
+    
+    // Gather radius for caustic photons
+    #[arg(long, default_value = "0.1", value_parser = parse_positive)]
+    photon_radius: f64,
+    
+    // Darken the ambient light in corners and under objects by tracing
+    // occlusion rays from what the camera sees
+    #[arg(long)]
+    ao_in_shading: bool,
+    
+    // Occlusion rays per pixel sample for --ao-in-shading
+    #[arg(long, default_value = "8")]
+    ao_samples: u32,
+    
+    // How far an object can be and still occlude, for --ao-in-shading
+    #[arg(long, default_value = "1.5", value_parser = parse_positive)]
+    ao_distance: f64,
+    
+    // Share of --ao-in-shading rays aimed through the scene's portals
+    #[arg(long, default_value = "0.5", value_parser = parse_portal_fraction)]
+    portal_fraction: f64,
+    
+    // Write the image to a file instead of stdout
+    #[arg(short, long)]
+    output: Option<String>,
+    
+    // Solid background color overriding the scene's, as r,g,b in 0..1, a
+    // hex color like #cc3344 or a name like slategray
+    #[arg(long, value_name = "R,G,B", value_parser = parse_color)]
+    background: Option<Color>,
+    
+    // Replace the background with a daylight sky and add its sun as a light
+    #[arg(long, value_name = "MODEL", value_parser = parse_sky, conflicts_with = "background")]
+    sky: Option<Sky>,
+    
+    // Sun position for --sky in degrees: above the horizon (default 45), and
+    // clockwise from straight ahead of an unturned camera (default 150,
+    // behind the right shoulder)
+    #[arg(long, value_name = "DEGREES", value_parser = parse_sun_elevation, requires = "sky")]
+    sun_elevation: Option<f64>,
+    #[arg(long, value_name = "DEGREES", value_parser = parse_finite, requires = "sky")]
+    sun_azimuth: Option<f64>,
+    
+    // Haziness of the --sky air, from 1 for perfectly clean (default 3)
+    #[arg(long, value_name = "T", value_parser = parse_turbidity, requires = "sky")]
+    turbidity: Option<f64>,
+    
+    // Photo (PPM) shown behind the scene where camera rays miss; reflections
+    // still see the background
+    #[arg(long, value_name = "PATH")]
+    backdrop: Option<String>,
+    
+    // Cut away everything on the side of a plane that its normal points to,
+    // for section views (repeatable)
+    #[arg(long, value_name = "PX,PY,PZ,NX,NY,NZ", value_parser = parse_clip_plane)]
+    clip: Vec<(Vec3, Vec3)>,
+    
+    // Cap the cuts made by --clip with a matte section of this color
+    #[arg(long, value_name = "R,G,B", value_parser = parse_color)]
+    section_color: Option<Color>,
+    
+    // Shade each hit with K lights picked by importance instead of all lights
+    #[arg(long, value_name = "K")]
+    light_samples: Option<u32>,
+    
+    // How image textures are sampled: nearest, bilinear or mipmap
+    #[arg(long, default_value = "bilinear", value_parser = parse_texture_filter)]
+    texture_filter: TextureFilter,
+    
+    // Order pixels are traced in: scanline, morton (Z-order within 16x16
+    // tiles) or interlaced (coarse to fine, writing a preview to the output
+    // after each level); the finished image is the same either way
+    #[arg(long, default_value = "scanline", value_parser = parse_pixel_order)]
+    pixel_order: PixelOrder,
+    
+    // Order 16x16 tiles are handed to the threads in: scanline (rows split
+    // up front), hilbert or cost (costliest first, predicted from the last
+    // frame or a one-sample probe), the last two taken one at a time from
+    // a shared queue; the finished image is the same either way
+    #[arg(long, default_value = "scanline", value_parser = parse_tile_order)]
+    tile_order: TileOrder,
+    
+    // Test the last object that shadowed each light before searching the
+    // whole scene (same output, for A/B timing)
+    #[arg(long)]
+    shadow_cache: bool,
+    
+    // Trace camera rays that miss between neighbours that hit again a
+    // thousandth of a pixel over, closing pinholes along seams
+    #[arg(long)]
+    seal_pinholes: bool,
+    
+    // Print render time and ray counts to stderr
+    #[arg(long)]
+    stats: bool,
+    
+    // Threads to render with (default: one per core); with 1 every render
+    // runs on the main thread without the thread pool
+    #[arg(long, value_name = "N", value_parser = parse_threads, global = true)]
+    threads: Option<usize>,
+    
+    // More detail on stderr: -v adds debug messages, -vv traces
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    
+    // Only warnings and errors on stderr
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    
+    // Report failures on stderr as text or, for scripts, as one JSON object
+    #[arg(long, value_name = "FORMAT", default_value = "text", value_parser = parse_error_format, global = true)]
+    error_format: ErrorFormat,
+    
+    // Jittered camera rays per pixel
+    #[arg(long, default_value = "1")]
+    samples: u32,
+    
+    // Reconstruction filter for combining samples: box, tent or gaussian
+    #[arg(long, default_value = "box", value_parser = parse_filter)]
+    filter: Filter,
+    
+    // Filter radius in pixels (defaults to 0.5 for box, 1.0 for tent and
+    // 1.5 for gaussian); at least 0.5, as none narrower covers a pixel
+    #[arg(long, value_parser = parse_filter_radius)]
+    filter_radius: Option<f64>,
+    
+    // Stop tracing new rows after this many seconds; the rest of the image
+    // is filled with the background
+    #[arg(long, value_name = "SECS", value_parser = parse_time_budget)]
+    time_budget: Option<Duration>,
+    
+    // Render and write N rows at a time instead of holding the whole
+    // image, for frames too large to fit in memory
+    #[arg(long, value_name = "N", value_parser = parse_stream_rows)]
+    stream_rows: Option<u32>,
+    
+    // Render a sequence of frames that narrows the FOV while dollying in
+    #[arg(long, value_name = "FRAMES")]
+    dolly_zoom: Option<u32>,
+    
+    // Keep the camera of an animation steady even if the scene shakes it
+    #[arg(long)]
+    no_shake: bool,
+    
+    // Also write how far each pixel moved since the previous frame of an
+    // animated render, as <name>_motion_NNN.pfm
+    #[arg(long, requires = "dolly_zoom")]
+    motion_vectors: bool,
+    
+    // Preview an animated render by predicting each frame from the one
+    // before and tracing only the pixels that could not be predicted;
+    // every Nth frame is traced in full so errors do not pile up
+    #[arg(long, value_name = "N", value_parser = parse_reproject)]
+    reproject: Option<u32>,
+    
+    // Write the frames of an animated render into one Y4M video instead of
+    // numbered PPM files
+    #[arg(long, value_name = "PATH")]
+    video: Option<String>,
+    
+    // Frame rate recorded in --video output
+    #[arg(long, default_value = "24")]
+    fps: u32,
+    
+    // Chroma subsampling of --video output: 420 or 444
+    #[arg(long, default_value = "420", value_parser = parse_chroma)]
+    chroma: Chroma,
+    
+    // Near clipping plane: camera rays ignore everything closer than this
+    // distance along the view direction (default 0.001)
+    #[arg(long, value_parser = parse_positive)]
+    near: Option<f64>,
+    
+    // Far clipping plane: camera rays ignore everything beyond this
+    // distance along the view direction
+    #[arg(long, value_parser = parse_far)]
+    far: Option<f64>,
+    
+    // Width of the lens opening in scene units, for depth of field; only
+    // surfaces at the focus distance stay sharp
+    #[arg(long, value_name = "D", value_parser = parse_aperture)]
+    aperture: Option<f64>,
+    
+    // Distance along the view direction that the lens keeps in focus
+    // (default: the distance to the camera's look_at point)
+    #[arg(long, value_name = "D", value_parser = parse_positive, requires = "aperture")]
+    focus_distance: Option<f64>,
+    
+    // Number of aperture blades, shaping out-of-focus highlights into
+    // polygons (default 0, a round opening)
+    #[arg(long, value_name = "N", default_value = "0", value_parser = parse_blades, requires = "aperture")]
+    aperture_blades: u32,
+    
+    // Turn of the aperture polygon from the camera's right, in degrees
+    #[arg(long, value_name = "DEGREES", default_value = "0", requires = "aperture_blades")]
+    aperture_rotation: f64,
+    
+    // Print luminance statistics of the rendered image to stderr
+    #[arg(long)]
+    analyze: bool,
+    
+    // Scale the image so its 99th percentile luminance lands just under 1.0
+    #[arg(long)]
+    auto_expose: bool,
+    
+    // Shading model: phong, or toon for flat bands of diffuse light, hard
+    // highlights and no reflections
+    #[arg(long, default_value = "phong", value_parser = parse_shading)]
+    shading: ShadingModel,
+    
+    // Number of diffuse levels in toon shading, from unlit to fully lit
+    #[arg(long, default_value = "3", value_parser = parse_bands)]
+    bands: u32,
+    
+    // Width of the blend between toon bands, in units of the cosine to the
+    // light (0 for hard steps)
+    #[arg(long, default_value = "0", value_parser = parse_finite)]
+    band_smoothing: f64,
+    
+    // Draw toon-style outlines where depth or normals change abruptly
+    #[arg(long)]
+    toon_outline: bool,
+    
+    // Color of --toon-outline lines
+    #[arg(long, default_value = "0,0,0", value_name = "R,G,B", value_parser = parse_color)]
+    outline_color: Color,
+    
+    // Depth jump between neighbouring pixels, relative to the nearer one,
+    // that --toon-outline treats as an edge
+    #[arg(long, default_value = "0.1", value_parser = parse_positive)]
+    outline_depth: f64,
+    
+    // Angle in degrees between neighbouring normals that --toon-outline
+    // treats as an edge
+    #[arg(long, default_value = "30", value_parser = parse_positive)]
+    outline_angle: f64,
+    
+    // Width of --toon-outline lines in pixels
+    #[arg(long, default_value = "1", value_name = "PIXELS")]
+    outline_thickness: u32,
+    
+    // Text burned into the bottom-left corner of the image; "{frame}" is
+    // replaced by the frame number in dolly zoom frames
+    #[arg(long, value_name = "TEXT")]
+    label: Option<String>,
+    
+    // Draw rule-of-thirds lines and the title-safe area over the image,
+    // within the framing when letterboxed
+    #[arg(long)]
+    guides: bool,
+    
+    // Log every ray traced for this pixel to stderr: hits, materials,
+    // shadow tests, bounces and the colors they return
+    #[arg(long, value_name = "X,Y", value_parser = parse_pixel)]
+    debug_pixel: Option<(u32, u32)>,
+    
+    // Write every ray traced for these pixels to --trace-rays-file as line
+    // segments, for viewing the paths in a 3D program
+    #[arg(long, value_name = "X,Y", value_parser = parse_pixel, num_args = 1..)]
+    trace_rays: Vec<(u32, u32)>,
+    
+    // OBJ or PLY file for --trace-rays, picked by the extension
+    #[arg(long, value_name = "PATH", default_value = "rays.obj")]
+    trace_rays_file: String,
+    
+    // Also write the render split into layers next to -o: "shadows" for
+    // the unshadowed direct light and the shadow mask, "reflections" for
+    // the mirrored light; whatever is left goes into a rest layer
+    #[arg(long, value_name = "LIST", value_parser = parse_aovs)]
+    aovs: Option<Aovs>,
+    
+    // Write the image once per exposure in a list of stops, each doubling
+    // the light, as <name>_<stop>.ppm next to -o instead of the -o file
+    #[arg(long, value_name = "STOPS", value_parser = parse_bracket, allow_hyphen_values = true)]
+    bracket: Option<Bracket>,
+    
+    // Also render the image in parts for compositing, one per layer of
+    // objects tagged in the scene file, with the other layers held out;
+    // each is written next to -o as <name>_<layer>.pam, with alpha
+    #[arg(long, value_name = "LIST", value_parser = parse_render_layers)]
+    layers: Option<RenderLayers>,
+    
+    // Also write a smaller copy of the finished image next to -o as
+    // <name>_thumb.<ext>, either WxH or a percentage of the full size
+    #[arg(long, value_name = "SIZE", value_parser = parse_thumbnail)]
+    thumbnail: Option<Thumbnail>,
+    
+    // Report NaN or infinite pixels in the final image and paint them
+    // magenta
+    #[arg(long)]
+    check_finite: bool,
+    
+    // How colors are encoded in the output file: srgb, gamma2.2 or linear
+    #[arg(long, default_value = "srgb", value_parser = parse_encoding)]
+    encoding: Encoding,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    // Compare two PPM images
+    Diff(DiffArgs),
+    // Build a mesh's BVH once and save it for scene files to load
+    Bake(BakeArgs),
+    // Look at the whole scene along one direction with parallel rays and
+    // save the depth under each pixel as a 16-bit image, such as a height
+    // map when looking down
+    BakeDepth(BakeDepthArgs),
+    // Render jobs read from stdin, one JSON object per line, keeping loaded
+    // textures and meshes between jobs
+    Serve,
+    // Print a summary of the scene without rendering it
+    Describe(DescribeArgs),
+    // Check the scene for mistakes, such as objects sunk into each other,
+    // without rendering it
+    Validate,
+}
+
+#[derive(ClapArgs)]
+struct DiffArgs {
+    first: String,
+    
+    second: String,
+    
+    // ssim, mse or psnr, computed on luminance
+    #[arg(long, default_value = "ssim", value_parser = parse_metric)]
+    metric: Metric,
+    
+    // Window for ssim: box (8x8) or gaussian (11x11, sigma 1.5)
+    #[arg(long, default_value = "gaussian", value_parser = parse_ssim_window)]
+    window: SsimWindow,
+    
+    // Exit with status 1 if the images are less similar than this (at
+    // least this for ssim and psnr, at most this for mse)
+    #[arg(long)]
+    threshold: Option<f64>,
+}
+
+#[derive(ClapArgs)]
+struct DescribeArgs {
+    // Print the summary as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(ClapArgs)]
+struct BakeArgs {
+    // Wavefront OBJ file to read
+    #[arg(long)]
+    obj: String,
+    
+    // Cache file to write, conventionally ending in .rtcache
+    #[arg(short, long)]
+    output: String,
+}
+
+#[derive(ClapArgs)]
+struct BakeDepthArgs {
+    // Direction to look along; the image's top is -z when it is vertical
+    // and up otherwise
+    #[arg(long, value_name = "X,Y,Z", default_value = "0,-1,0", value_parser = parse_direction, allow_hyphen_values = true)]
+    direction: Vec3,
+    
+    // Depths written as 0 and as the largest value, measured along the
+    // direction from the side of the scene's bounds facing the camera;
+    // the bounds' own depth when not given. Rays that hit nothing write
+    // the far value.
+    #[arg(long, value_name = "NEAR,FAR", value_parser = parse_depth_range, allow_hyphen_values = true)]
+    range: Option<(f64, f64)>,
+    
+    // PPM file to write, with 16 bits per channel
+    #[arg(short, long)]
+    output: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Ssim,
+    Mse,
+    Psnr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placement {
+    Uniform,
+    Poisson,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sky {
+    Sun,
+}
+
+// Layers asked for with --aovs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Aovs {
+    shadows: bool,
+    reflections: bool,
+}
+
+// Exposures asked for with --bracket, in stops
+#[derive(Debug, Clone, PartialEq)]
+struct Bracket {
+    stops: Vec<f64>,
+}
+
+// Layers asked for with --layers, from front to back
+#[derive(Debug, Clone, PartialEq)]
+struct RenderLayers {
+    names: Vec<String>,
+}
+
+// Size asked for with --thumbnail
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Thumbnail {
+    Size(u32, u32),
+    Scale(f64),
+}
+
+impl Thumbnail {
+    // Rounds like --scale, keeping at least one pixel on each side
+    fn size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Thumbnail::Size(width, height) => (width, height),
+            Thumbnail::Scale(scale) => {
+                let scaled = |pixels: u32| ((pixels as f64 * scale).round() as u32).max(1);
+                (scaled(width), scaled(height))
+            }
+        }
+    }
+}
+
+// How failures are reported on stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorFormat {
+    Text,
+    Json,
+}
+
+// Why a command failed. Each kind exits with its own status so scripts can
+// tell a broken scene from a full disk; clap exits with 2 for options it
+// rejects, the same as for options that do not fit together.
+#[derive(Debug)]
+enum Failure {
+    Usage(String),
+    Scene(String),
+    Render(String),
+    Output(String),
+}
+
+impl Failure {
+    fn status(&self) -> i32 {
+        match self {
+            Failure::Usage(_) => 2,
+            Failure::Scene(_) => 3,
+            Failure::Render(_) => 4,
+            Failure::Output(_) => 5,
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Failure::Usage(message) | Failure::Scene(message) | Failure::Render(message) | Failure::Output(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+// Shading model picked on the command line; toon takes its settings from
+// --bands and --band-smoothing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShadingModel {
+    Phong,
+    Toon,
+}
+
+fn main() {
+    let args = Args::try_parse().unwrap_or_else(|err| {
+        // Help and --version go to stdout as usual
+        if err.use_stderr() && json_errors_requested() {
+            let text = err.to_string();
+            let message = text.lines().next().unwrap_or_default().trim_start_matches("error: ");
+            report_failure(ErrorFormat::Json, 2, message, None);
+        }
+        err.exit()
+    });
+    log::set_max_level(match (args.quiet, args.verbose) {
+        (true, _) => Level::Warn,
+        (false, 0) => Level::Info,
+        (false, 1) => Level::Debug,
+        (false, _) => Level::Trace,
+    });
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("nothing has used the thread pool yet");
+    }
+    
+    let result = match &args.command {
+        Some(Command::Diff(diff_args)) => {
+            run_diff(diff_args, args.error_format);
+            Ok(())
+        }
+        Some(Command::Bake(bake_args)) => run_bake(bake_args),
+        Some(Command::BakeDepth(depth_args)) => run_bake_depth(&args, depth_args),
+        Some(Command::Serve) => {
+            run_serve();
+            Ok(())
+        }
+        Some(Command::Describe(describe_args)) => run_describe(&args, describe_args),
+        Some(Command::Validate) => match run_validate(&args) {
+            Ok(false) => std::process::exit(1),
+            result => result.map(|_| ()),
+        },
+        None => run_render(&args, &AssetCache::default()).map(|_| ()),
+    };
+    if let Err(failure) = result {
+        let file = match &failure {
+            Failure::Scene(message) => args.scene_file.as_deref().filter(|path| message.contains(*path)),
+            _ => None,
+        };
+        report_failure(args.error_format, failure.status(), &failure.to_string(), file);
+    }
+}
+
+// Whether the command line asks for JSON errors, for the errors clap finds
+// before there are parsed options to look at
+fn json_errors_requested() -> bool {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().any(|arg| arg == "--error-format=json")
+        || args.windows(2).any(|pair| pair[0] == "--error-format" && pair[1] == "json")
+}
+
+// Writes the error to stderr and exits with `status`. As JSON it is one
+// object on one line with the status as "code", and the file, line and
+// column when the message points into a scene file.
+fn report_failure(format: ErrorFormat, status: i32, message: &str, file: Option<&str>) -> ! {
+    match format {
+        ErrorFormat::Text => error!("{}", message),
+        ErrorFormat::Json => {
+            let mut report = vec![("code".to_string(), Value::from(status as f64)), ("message".to_string(), message.into())];
+            if let Some(file) = file {
+                report.push(("file".to_string(), file.into()));
+            }
+            if let Some((line, column)) = source_position(message) {
+                report.push(("line".to_string(), Value::from(line as f64)));
+                report.push(("column".to_string(), Value::from(column as f64)));
+            }
+            eprintln!("{}", Value::Object(report).to_compact_string());
+        }
+    }
+    std::process::exit(status)
+}
+
+// The "line L, column C: " that JSON parse errors start with, wherever it
+// is in the message
+fn source_position(message: &str) -> Option<(usize, usize)> {
+    let (_, rest) = message.split_once("line ")?;
+    let (line, rest) = rest.split_once(", column ")?;
+    let (column, _) = rest.split_once(':')?;
+    Some((line.parse().ok()?, column.parse().ok()?))
+}
+
+// Builds the scene `args` describe and renders it, or exports it with
+// --export-scene, returning the scene's warnings. Scene files load their
+// textures and meshes through `assets`.
+fn run_render(args: &Args, assets: &AssetCache) -> Result<Vec<String>, Failure> {
+    let (width, height) = image_size(args).map_err(Failure::Scene)?;
+    let (scene, warnings) = build_scene(args, (width, height), assets)?;
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    render_scene(args, scene, (width, height))?;
+    Ok(warnings)
+}
+
+fn render_scene(args: &Args, mut scene: Scene, (width, height): (u32, u32)) -> Result<(), Failure> {
+    info!(
+        "Scene: {} objects, {} lights, {} clipping planes",
+        scene.objects().len(),
+        scene.lights.len(),
+        scene.clip_planes.len(),
+    );
+    
+    if let Some(path) = &args.export_scene {
+        let (text, warnings) = export_scene(&scene);
+        for warning in &warnings {
+            warn!("{}", warning);
+        }
+        std::fs::write(path, &text).map_err(|err| Failure::Output(format!("cannot write '{}': {}", path, err)))?;
+        info!("Wrote {} ({} bytes)", path, text.len());
+        return Ok(());
+    }
+    
+    // Render the scene
+    if args.video.is_some() && args.dolly_zoom.is_none() {
+        return Err(Failure::Usage("--video needs an animated render (--dolly-zoom)".to_string()));
+    }
+    if args.fps == 0 {
+        return Err(Failure::Usage("--fps must be at least 1".to_string()));
+    }
+    if args.aovs.is_some() {
+        if args.output.is_none() {
+            return Err(Failure::Usage("--aovs writes files next to the image, so it needs -o".to_string()));
+        }
+        if args.dolly_zoom.is_some() {
+            return Err(Failure::Usage("--aovs cannot be combined with --dolly-zoom".to_string()));
+        }
+    }
+    if args.bracket.is_some() {
+        if args.output.is_none() {
+            return Err(Failure::Usage("--bracket writes one file per stop next to the image, so it needs -o".to_string()));
+        }
+        if args.dolly_zoom.is_some() {
+            return Err(Failure::Usage("--bracket cannot be combined with --dolly-zoom".to_string()));
+        }
+    }
+    if args.layers.is_some() {
+        if args.output.is_none() {
+            return Err(Failure::Usage("--layers writes files next to the image, so it needs -o".to_string()));
+        }
+        if args.dolly_zoom.is_some() {
+            return Err(Failure::Usage("--layers cannot be combined with --dolly-zoom".to_string()));
+        }
+    }
+    let render_layers = match &args.layers {
+        Some(layers) => {
+            let split = scene.render_layers(&layers.names).map_err(|e| Failure::Usage(format!("--layers: {}", e)))?;
+            layers.names.iter().cloned().zip(split).collect()
+        }
+        None => Vec::new(),
+    };
+    if args.thumbnail.is_some() {
+        if args.output.is_none() {
+            return Err(Failure::Usage("--thumbnail writes a file next to the image, so it needs -o".to_string()));
+        }
+        if args.dolly_zoom.is_some() {
+            return Err(Failure::Usage("--thumbnail cannot be combined with --dolly-zoom".to_string()));
+        }
+    }
+    if let Some((x, y)) = args.debug_pixel {
+        if x >= width || y >= height {
+            return Err(Failure::Usage(format!("--debug-pixel {},{} is outside the {}x{} image", x, y, width, height)));
+        }
+    }
+    
+    // Post-processing that looks at the whole image cannot work on bands,
+    // so it is refused rather than silently skipped
+    if args.stream_rows.is_some() {
+        let whole_image = [
+            (args.dolly_zoom.is_some(), "--dolly-zoom"),
+            (args.toon_outline, "--toon-outline"),
+            (args.analyze, "--analyze"),
+            (args.auto_expose, "--auto-expose"),
+            (args.check_finite, "--check-finite"),
+            (args.label.is_some(), "--label"),
+            (args.guides, "--guides"),
+            (args.framing.is_some() && args.framing_fit == Fit::Letterbox, "--framing-fit letterbox"),
+            (args.aovs.is_some(), "--aovs"),
+            (args.layers.is_some(), "--layers"),
+            (args.bracket.is_some(), "--bracket"),
+            (args.thumbnail.is_some(), "--thumbnail"),
+            (args.pass == RenderPass::Cost, "--pass cost"),
+        ];
+        if let Some((_, flag)) = whole_image.iter().find(|(used, _)| *used) {
+            return Err(Failure::Usage(format!("--stream-rows cannot be combined with {}, which needs the whole image at once", flag)));
+        }
+    }
+    
+    let mut settings = RenderSettings {
+        reflection: args.reflection,
+        min_contribution: args.min_contribution,
+        reflection_blend: args.reflection_blend,
+        seed: args.seed,
+        packets: args.packets,
+        pass: args.pass,
+        show_lights: args.show_lights,
+        area_samples: args.area_samples,
+        caustics: args.caustics,
+        photons_per_light: args.photons,
+        photon_radius: args.photon_radius,
+        ambient_occlusion: args.ao_in_shading,
+        ao_samples: args.ao_samples,
+        ao_distance: args.ao_distance,
+        portal_fraction: args.portal_fraction,
+        light_samples: args.light_samples,
+        samples_per_pixel: args.samples,
+        pixel_order: args.pixel_order,
+        tile_order: args.tile_order,
+        shadow_cache: args.shadow_cache,
+        seal_pinholes: args.seal_pinholes,
+        debug_pixel: args.debug_pixel,
+        shading: match args.shading {
+            ShadingModel::Phong => Shading::Phong,
+            ShadingModel::Toon => Shading::Toon { bands: args.bands, smoothing: args.band_smoothing.max(0.0) },
+        },
+        filter: match args.filter_radius {
+            Some(radius) => args.filter.with_radius(radius).map_err(|e| Failure::Usage(format!("--filter-radius: {}", e)))?,
+            None => args.filter,
+        },
+        ..RenderSettings::default()
+    };
+    
+    if settings.tile_order != TileOrder::Scanline {
+        if settings.pixel_order == PixelOrder::Interlaced {
+            return Err(Failure::Usage("--tile-order cannot be combined with --pixel-order interlaced, which traces whole levels at a time".to_string()));
+        }
+        if !settings.filter.is_pixel_local() {
+            return Err(Failure::Usage("--tile-order needs a filter no wider than a pixel; wider ones splat samples row by row".to_string()));
+        }
+    }
+    
+    if !args.trace_rays.is_empty() {
+        if args.dolly_zoom.is_some() {
+            return Err(Failure::Usage("--trace-rays cannot be combined with --dolly-zoom".to_string()));
+        }
+        write_ray_paths(&scene, &settings, &args.trace_rays, (width, height), &args.trace_rays_file)?;
+    }
+    
+    if args.reproject.is_some() {
+        if args.dolly_zoom.is_none() {
+            return Err(Failure::Usage("--reproject needs an animated render (--dolly-zoom)".to_string()));
+        }
+        if !settings.filter.is_pixel_local() {
+            return Err(Failure::Usage("--reproject needs a filter no wider than a pixel, since it traces pixels one at a time".to_string()));
+        }
+        if args.pass == RenderPass::Cost {
+            return Err(Failure::Usage("--reproject cannot be combined with --pass cost, which needs every pixel traced".to_string()));
+        }
+    }
+    
+    if let Some(frames) = args.dolly_zoom {
+        let output = args.output.as_deref().unwrap_or("dolly_zoom.ppm");
+        let mut video = args.video.as_deref().map(|path| open_video(path, args, width, height)).transpose().map_err(Failure::Output)?;
+        let mut previous_camera: Option<Camera> = None;
+        let save_frame = |mut image: Image, aux: Option<AuxBuffers>, camera: &Camera, frame: u32, report: &RenderReport| {
+            if args.stats {
+                print_stats(report);
+            }
+            if let (true, Some(aux)) = (args.motion_vectors, &aux) {
+                let motion = MotionVectors::new(previous_camera.as_ref().unwrap_or(camera), camera, aux);
+                let path = Path::new(&suffixed_path(output, &format!("_motion_{:03}", frame))).with_extension("pfm");
+                let path = path.to_string_lossy();
+                motion.save_pfm(&path).map_err(|err| Failure::Output(format!("cannot write '{}': {}", path, err)))?;
+                report_written(&path, &format!("motion vectors, {}x{}", width, height));
+                previous_camera = Some(camera.clone());
+            }
+            print_peak_cost(report);
+            let label = args.label.as_ref().map(|label| label.replace("{frame}", &frame.to_string()));
+            finish_image(&mut image, aux.as_ref(), args, label.as_deref());
+            match &mut video {
+                Some(video) => video.write_frame(&image)
+                    .map_err(|err| format!("cannot write '{}': {}", args.video.as_deref().unwrap_or_default(), err)),
+                None => write_image(&image, Some(&frame_path(output, frame)), args.encoding),
+            }
+            .map_err(Failure::Output)
+        };
+        let with_aux = args.toon_outline || args.motion_vectors;
+        render_dolly_zoom(&mut scene, &settings, args.time_budget, (width, height), (frames, args.fps), with_aux, args.reproject, save_frame)?;
+        if let (Some(video), Some(path)) = (video, &args.video) {
+            video.finish().map_err(|err| Failure::Output(format!("cannot write '{}': {}", path, err)))?;
+            report_written(path, &format!("{} frames", frames));
+        }
+        return Ok(());
+    }
+    
+    settings.deadline = args.time_budget.and_then(|budget| Instant::now().checked_add(budget));
+    if let Some(rows) = args.stream_rows {
+        return stream_render(&scene, &settings, args, width, height, rows);
+    }
+    let mut image = Image::try_new(width, height).map_err(Failure::Render)?;
+    image.pixel_aspect = args.pixel_aspect;
+    // Depth and normals are only gathered when a post-process needs them,
+    // and the split layers only when asked for
+    let mut aux = (args.toon_outline || args.aovs.is_some()).then(|| {
+        let aux = AuxBuffers::new(width, height);
+        if args.aovs.is_some() { aux.with_layers() } else { aux }
+    });
+    // Interlaced renders overwrite the output file as each level finishes,
+    // before any post-processing; bracketed renders have no single file
+    let write_preview = |preview: &Image, step: u32| {
+        if let (Some(path), None) = (&args.output, &args.bracket) {
+            match preview.save_ppm(path, args.encoding) {
+                Ok(()) => info!("Wrote a preview to {} from one pixel in every {}x{} block", path, step, step),
+                Err(err) => warn!("cannot write a preview to '{}': {}", path, err),
+            }
+        }
+    };
+    let report = scene.render_with_previews(&mut image, aux.as_mut(), &settings, write_preview);
+    if args.stats {
+        print_stats(&report);
+    }
+    print_peak_cost(&report);
+    if let (Some(aovs), Some(layers), Some(output)) = (args.aovs, aux.as_ref().and_then(|aux| aux.layers.as_deref()), &args.output) {
+        write_aovs(aovs, layers, width, height, output, args).map_err(Failure::Output)?;
+    }
+    if let Some(output) = &args.output {
+        write_render_layers(&scene, &settings, &render_layers, (width, height), output, args)?;
+    }
+    finish_image(&mut image, aux.as_ref(), args, args.label.as_deref());
+    
+    // Output PPM format
+    match (&args.bracket, &args.output) {
+        (Some(bracket), Some(output)) => {
+            for &stop in &bracket.stops {
+                write_image(&image.exposed(stop), Some(&bracket_path(output, stop)), args.encoding).map_err(Failure::Output)?;
+            }
+        }
+        _ => write_image(&image, args.output.as_deref(), args.encoding).map_err(Failure::Output)?,
+    }
+    // The thumbnail is averaged from the linear image, so it goes through
+    // the same encoding and matches the full frame's brightness
+    if let (Some(thumbnail), Some(output)) = (args.thumbnail, &args.output) {
+        let (thumb_width, thumb_height) = thumbnail.size(width, height);
+        let thumb = image.resized(thumb_width, thumb_height);
+        write_image(&thumb, Some(&suffixed_path(output, "_thumb")), args.encoding).map_err(Failure::Output)?;
+    }
+    Ok(())
+}
+
+// Renders each layer again with the others held out, then its coverage,
+// and writes the two as <stem>_<layer>.pam next to `output`. Like the
+// AOVs, the layers are written before any post-processing.
+fn write_render_layers(
+    scene: &Scene,
+    settings: &RenderSettings,
+    layers: &[(String, RenderLayer)],
+    (width, height): (u32, u32),
+    output: &str,
+    args: &Args,
+) -> Result<(), Failure> {
+    for (name, layer) in layers {
+        let layer_settings = RenderSettings { layer: Some(*layer), ..*settings };
+        let mut color = Image::try_new(width, height).map_err(Failure::Render)?;
+        color.pixel_aspect = args.pixel_aspect;
+        scene.render_into(&mut color, &layer_settings);
+        let mut coverage = Image::try_new(width, height).map_err(Failure::Render)?;
+        scene.render_into(&mut coverage, &RenderSettings { pass: RenderPass::Alpha, caustics: false, ..layer_settings });
+        let alpha: Vec<f64> = coverage.pixels().iter().map(|pixel| pixel.r).collect();
+        
+        let path = Path::new(&suffixed_path(output, &format!("_{}", name))).with_extension("pam");
+        let path = path.to_string_lossy();
+        color.save_pam(&path, &alpha, args.encoding).map_err(|err| Failure::Output(format!("cannot write '{}': {}", path, err)))?;
+        report_written(&path, &format!("layer {}, {}x{}", name, width, height));
+    }
+    Ok(())
+}
+
+// <stem>_<stop>.<ext> for one exposure of a bracket, e.g. out_-1.ppm
+fn bracket_path(path: &str, stop: f64) -> String {
+    suffixed_path(path, &format!("_{}", stop))
+}
+
+// `path` with `suffix` added to the file name before its extension. Only
+// the last component is looked at, so "renders.v2/out" gains the suffix at
+// the end, and a leading dot as in ".out" is not an extension.
+fn suffixed_path(path: &str, suffix: &str) -> String {
+    let name_start = path.rfind(['/', '\\']).map_or(0, |slash| slash + 1);
+    match path[name_start..].rfind('.').filter(|&dot| dot > 0) {
+        Some(dot) => format!("{}{}{}", &path[..name_start + dot], suffix, &path[name_start + dot..]),
+        None => format!("{}{}", path, suffix),
+    }
+}
+
+// Writes each layer asked for next to `output` as <stem>_<layer>.ppm.
+// The shadow mask is a ratio rather than a color, so it is stored
+// linearly.
+fn write_aovs(aovs: Aovs, layers: &[Layers], width: u32, height: u32, output: &str, args: &Args) -> Result<(), String> {
+    let write = |name: &str, encoding: Encoding, layer: &dyn Fn(&Layers) -> Color| {
+        let mut image = Image::new(width, height);
+        image.pixel_aspect = args.pixel_aspect;
+        for (pixel, layers) in image.pixels_mut().iter_mut().zip(layers) {
+            *pixel = layer(layers);
+        }
+        let path = Path::new(&suffixed_path(output, &format!("_{}", name))).with_extension("ppm");
+        write_image(&image, Some(&path.to_string_lossy()), encoding)
+    };
+    
+    if aovs.shadows {
+        write("unshadowed", args.encoding, &|layers| layers.unshadowed)?;
+        write("shadow", Encoding::Linear, &|layers| layers.shadow())?;
+    }
+    if aovs.reflections {
+        write("reflection", args.encoding, &|layers| layers.reflection)?;
+    }
+    write("rest", args.encoding, &|layers| {
+        let mut rest = layers.rest;
+        if !aovs.shadows {
+            rest = rest + layers.direct;
+        }
+        if !aovs.reflections {
+            rest = rest + layers.reflection;
+        }
+        rest
+    })
+}
+
+// Renders `rows` rows at a time, writing each band out as soon as it is
+// done
+fn stream_render(scene: &Scene, settings: &RenderSettings, args: &Args, width: u32, height: u32, rows: u32) -> Result<(), Failure> {
+    let target = args.output.as_deref().unwrap_or("stdout");
+    let write_error = |err: io::Error| Failure::Output(format!("cannot write '{}': {}", target, err));
+    let out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path).map_err(write_error)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut writer = PpmWriter::new(out, width, height, args.pixel_aspect, args.encoding).map_err(write_error)?;
+    let mut done = 0;
+    // Errors from writing a band come back out of render_bands as strings
+    let mut write_failed = false;
+    let report = scene.render_bands(width, height, rows, settings, |band| {
+        if let Err(err) = writer.write_rows(band) {
+            write_failed = true;
+            return Err(write_error(err).to_string());
+        }
+        done += band.height;
+        info!("Rows written: {} of {}", done, height);
+        Ok(())
+    });
+    let report = report.map_err(|err| if write_failed { Failure::Output(err) } else { Failure::Render(err) })?;
+    writer.finish().map_err(write_error)?;
+    info!("Done.");
+    if report.truncated {
+        warn!("time budget exceeded; unfinished rows show the background");
+    }
+    if args.stats {
+        print_stats(&report);
+    }
+    if let Some(path) = &args.output {
+        report_written(path, &format!("{}x{}", width, height));
+    }
+    Ok(())
+}
+
+// Width and height of the image to render, from the command line and the
+// resolution the scene file declares
+fn image_size(args: &Args) -> Result<(u32, u32), String> {
+    let declared = match (&args.scene_inline, &args.scene_file) {
+        (Some(text), _) => scene_file::declared_resolution(text).map_err(|err| format!("--scene-inline: {}", err))?,
+        (None, Some(path)) => {
+            let text = std::fs::read_to_string(path).map_err(|err| format!("cannot read scene file '{}': {}", path, err))?;
+            scene_file::declared_resolution(&text).map_err(|err| format!("{}: {}", path, err))?
+        }
+        (None, None) => None,
+    };
+    Ok(resolution::choose(declared, args.resolution, args.width, args.height, args.scale))
+}
+
+// The scene, camera and lights `args` select, with the command line's
+// overrides applied and the camera framed for an image of `size`, and
+// warnings about mistakes in it: problems in the scene file and objects
+// sunk into each other. Options that do not fit the scene, such as a
+// framing it lacks, are usage failures.
+fn build_scene(args: &Args, size: (u32, u32), assets: &AssetCache) -> Result<(Scene, Vec<String>), Failure> {
+    let mut scene = Scene::new();
+    let mut warnings = Vec::new();
+    
+    let aspect_ratio = size.0 as f64 / size.1 as f64;
+    scene.set_camera(default_camera(aspect_ratio));
+    
+    // Add lighting - positioned to better illuminate objects
+    scene.add_light(Light::new(
+        Vec3::new(2.0, 3.0, 1.0), // Light positioned above and to the side
+        Color::new(1.0, 1.0, 1.0),
+        0.8, // Slightly reduced intensity for better contrast
+    ));
+    
+    let meshes = ObjConvention { up: args.obj_up, handedness: args.obj_handedness };
+    let loaded = match (&args.scene_inline, &args.scene_file) {
+        (Some(text), _) => Some(
+            parse_scene(text, Path::new(""), aspect_ratio, assets, args.scene_scale, meshes, args.missing_assets, args.memory_budget)
+                .map_err(|err| Failure::Scene(format!("--scene-inline: {}", err)))?,
+        ),
+        (None, Some(path)) => {
+            debug!("Loading {}", path);
+            Some(load_scene(path, aspect_ratio, assets, args.scene_scale, meshes, args.missing_assets, args.memory_budget).map_err(Failure::Scene)?)
+        }
+        (None, None) => None,
+    };
+    if let Some(loaded) = loaded {
+        warnings = loaded.warnings;
+        scene = loaded.scene;
+        if scene.camera.is_none() {
+            scene.set_camera(default_camera(aspect_ratio));
+        }
+    } else {
+        // Create scenes based on argument
+        match args.scene.as_str() {
+            "scene1" => create_sphere_scene(&mut scene),
+            "scene2" => create_plane_cube_scene(&mut scene, args.textures),
+            "scene3" => create_all_objects_scene(&mut scene, args.textures),
+            "scene4" => create_different_perspective_scene(&mut scene, aspect_ratio),
+            "softbox" => create_softbox_scene(&mut scene, args.softbox_size),
+            "caustics" => create_caustics_scene(&mut scene),
+            "ring" => create_light_ring_scene(&mut scene),
+            "ellipsoids" => create_ellipsoid_scene(&mut scene),
+            "checker" => create_checker_scene(&mut scene, args.texture_filter, aspect_ratio),
+            "gold" => create_gold_scene(&mut scene),
+            "water" => create_water_scene(&mut scene, aspect_ratio),
+            "window" => create_window_scene(&mut scene, aspect_ratio),
+            "random" => create_random_scene(&mut scene, args.placement, aspect_ratio),
+            "menger" => {
+                if let Some(budget) = args.memory_budget.filter(|&budget| menger_memory(args.level) > budget) {
+                    return Err(Failure::Scene(format!(
+                        "--memory-budget: a level {} Menger sponge needs about {}, over the memory budget of {}",
+                        args.level, format_size(menger_memory(args.level)), format_size(budget),
+                    )));
+                }
+                create_menger_scene(&mut scene, args.level, aspect_ratio);
+            }
+            _ => create_sphere_scene(&mut scene),
+        }
+    }
+    
+    if let Some(aspect) = args.framing {
+        let Some(framing) = scene.framing(aspect).cloned() else {
+            let declared: Vec<String> = scene.framings.iter().map(|framing| format!("{}x{}", framing.aspect.0, framing.aspect.1)).collect();
+            let declared = if declared.is_empty() { "none".to_string() } else { declared.join(", ") };
+            return Err(Failure::Usage(format!("--framing: the scene has no {}x{} framing (it has {})", aspect.0, aspect.1, declared)));
+        };
+        // The fit works on the view's shape, which wider pixels widen
+        let (fov, _) = args.framing_fit.view(framing.fov, framing.ratio(), aspect_ratio * args.pixel_aspect);
+        let camera = scene.camera.take().expect("Camera not set");
+        let framed = Camera::new(framing.position, framing.look_at, framing.up, fov, aspect_ratio)
+            .and_then(|framed| framed.with_clip(camera.near, camera.far))
+            .map_err(|err| Failure::Scene(format!("--framing {}x{}: {}", aspect.0, aspect.1, err)))?;
+        scene.set_camera(framed);
+    }
+    
+    if let Some(color) = args.background {
+        scene.set_background(Background::Solid(color));
+    }
+    if let Some(Sky::Sun) = args.sky {
+        let sky = SkyModel::new(
+            args.sun_elevation.unwrap_or(45.0),
+            args.sun_azimuth.unwrap_or(150.0),
+            args.turbidity.unwrap_or(3.0),
+        )
+        .map_err(|err| Failure::Usage(format!("--sky: {}", err)))?;
+        scene.set_background(Background::Sky(sky));
+        scene.add_light(sky.sun_light());
+    }
+    if let Some(path) = &args.backdrop {
+        scene.set_backdrop(Backdrop::load(path).map_err(Failure::Scene)?);
+    }
+    
+    if args.pixel_aspect != 1.0 {
+        let camera = scene.camera.take().expect("Camera not set");
+        scene.set_camera(camera.with_pixel_aspect(args.pixel_aspect));
+    }
+    
+    if args.near.is_some() || args.far.is_some() {
+        let camera = scene.camera.take().expect("Camera not set");
+        let (near, far) = (args.near.unwrap_or(camera.near), args.far.unwrap_or(camera.far));
+        scene.set_camera(camera.with_clip(near, far).map_err(|err| Failure::Usage(format!("--near/--far: {}", err)))?);
+    }
+    
+    if let Some(aperture) = args.aperture {
+        let camera = scene.camera.take().expect("Camera not set");
+        let focus_distance = args.focus_distance.unwrap_or_else(|| (camera.look_at - camera.position).length());
+        let lens = Lens::new(aperture, focus_distance)
+            .map_err(|err| Failure::Usage(format!("--aperture: {}", err)))?
+            .with_blades(args.aperture_blades, args.aperture_rotation.to_radians());
+        scene.set_camera(camera.with_lens(lens).map_err(|err| Failure::Usage(format!("--aperture: {}", err)))?);
+    }
+    
+    if args.no_shake {
+        scene.shake = None;
+    }
+    
+    for material_override in &args.override_material {
+        scene.apply_material_override(material_override).map_err(Failure::Scene)?;
+    }
+    
+    for &(point, normal) in &args.clip {
+        let mut clip_plane = ClipPlane::new(point, normal);
+        if let Some(color) = args.section_color {
+            clip_plane = clip_plane.with_section(Material::preset("matte", color).expect("matte is a preset"));
+        }
+        scene.add_clip_plane(clip_plane);
+    }
+    
+    let memory = MemoryEstimate::new(&scene);
+    debug!("Estimated memory for the scene's objects: {}", format_size(memory.total));
+    if let Some(budget) = args.memory_budget {
+        memory.check(budget).map_err(|err| Failure::Scene(format!("--memory-budget: {}", err)))?;
+    }
+    
+    for (a, b) in scene.detect_overlaps(args.overlap_tolerance) {
+        let (first, second) = (scene.objects()[a].as_ref(), scene.objects()[b].as_ref());
+        if let Some(Overlap::Penetrating(depth)) = overlap::between(first, second, args.overlap_tolerance) {
+            warnings.push(format!("'{}' and '{}' intersect by {:.4}", scene.object_name(a), scene.object_name(b), depth));
+        }
+    }
+    Ok((scene, warnings))
+}
+
+// Prints what the scene `args` select holds, without rendering it
+fn run_describe(args: &Args, describe_args: &DescribeArgs) -> Result<(), Failure> {
+    let size = image_size(args).map_err(Failure::Scene)?;
+    let (scene, warnings) = build_scene(args, size, &AssetCache::default())?;
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    let summary = scene.describe();
+    let text = if describe_args.json {
+        summary.to_json().to_pretty_string() + "\n"
+    } else {
+        summary.to_string()
+    };
+    std::io::stdout().write_all(text.as_bytes()).map_err(|err| Failure::Output(format!("cannot write the summary: {}", err)))
+}
+
+// Prints the scene's warnings, then pairs of objects that may intersect
+// but could only be compared by their bounding boxes. Returns whether the
+// scene is free of warnings; possible overlaps alone do not fail it.
+fn run_validate(args: &Args) -> Result<bool, Failure> {
+    let size = image_size(args).map_err(Failure::Scene)?;
+    let (scene, warnings) = build_scene(args, size, &AssetCache::default())?;
+    let mut lines: Vec<String> = warnings.iter().map(|warning| format!("warning: {}", warning)).collect();
+    for (a, b) in scene.detect_overlaps(args.overlap_tolerance) {
+        let (first, second) = (scene.objects()[a].as_ref(), scene.objects()[b].as_ref());
+        if overlap::between(first, second, args.overlap_tolerance) == Some(Overlap::Possible) {
+            lines.push(format!(
+                "note: '{}' ({}) and '{}' ({}) may intersect; only their bounding boxes could be compared",
+                scene.object_name(a), first.kind(), scene.object_name(b), second.kind(),
+            ));
+        }
+    }
+    lines.push(match warnings.len() {
+        0 => "No problems found".to_string(),
+        1 => "1 problem found".to_string(),
+        n => format!("{} problems found", n),
+    });
+    let text = lines.join("\n") + "\n";
+    std::io::stdout().write_all(text.as_bytes()).map_err(|err| Failure::Output(format!("cannot write the report: {}", err)))?;
+    Ok(warnings.is_empty())
+}
+
+fn parse_pass(s: &str) -> Result<RenderPass, String> {
+    match s {
+        "beauty" => Ok(RenderPass::Beauty),
+        "irradiance" => Ok(RenderPass::Irradiance),
+        "cost" => Ok(RenderPass::Cost),
+        _ => Err(format!("unknown pass '{}' (expected beauty, irradiance or cost)", s)),
+    }
+}
+
+// "x,y" pixel coordinates from the top left
+fn parse_error_format(s: &str) -> Result<ErrorFormat, String> {
+    match s {
+        "text" => Ok(ErrorFormat::Text),
+        "json" => Ok(ErrorFormat::Json),
+        _ => Err(format!("unknown error format '{}' (expected text or json)", s)),
+    }
+}
+
+fn parse_pixel(s: &str) -> Result<(u32, u32), String> {
+    let (x, y) = s.split_once(',').ok_or_else(|| format!("expected X,Y, got '{}'", s))?;
+    let coordinate = |c: &str| c.trim().parse::<u32>().map_err(|_| format!("bad pixel coordinate '{}'", c));
+    Ok((coordinate(x)?, coordinate(y)?))
+}
+
+fn parse_encoding(s: &str) -> Result<Encoding, String> {
+    match s {
+        "srgb" => Ok(Encoding::Srgb),
+        "gamma2.2" => Ok(Encoding::Gamma22),
+        "linear" => Ok(Encoding::Linear),
+        _ => Err(format!("unknown encoding '{}' (expected srgb, gamma2.2 or linear)", s)),
+    }
+}
+
+fn parse_reflection_blend(s: &str) -> Result<ReflectionBlend, String> {
+    match s {
+        "linear" => Ok(ReflectionBlend::Linear),
+        "fresnel" => Ok(ReflectionBlend::Fresnel),
+        _ => Err(format!("unknown reflection blend '{}' (expected linear or fresnel)", s)),
+    }
+}
+
+fn parse_pixel_order(s: &str) -> Result<PixelOrder, String> {
+    match s {
+        "scanline" => Ok(PixelOrder::Scanline),
+        "morton" => Ok(PixelOrder::Morton),
+        "interlaced" => Ok(PixelOrder::Interlaced),
+        _ => Err(format!("unknown pixel order '{}' (expected scanline, morton or interlaced)", s)),
+    }
+}
+
+fn parse_tile_order(s: &str) -> Result<TileOrder, String> {
+    match s {
+        "scanline" => Ok(TileOrder::Scanline),
+        "hilbert" => Ok(TileOrder::Hilbert),
+        "cost" => Ok(TileOrder::Cost),
+        _ => Err(format!("unknown tile order '{}' (expected cost, scanline or hilbert)", s)),
+    }
+}
+
+fn parse_placement(s: &str) -> Result<Placement, String> {
+    match s {
+        "uniform" => Ok(Placement::Uniform),
+        "poisson" => Ok(Placement::Poisson),
+        _ => Err(format!("unknown placement '{}' (expected uniform or poisson)", s)),
+    }
+}
+
+fn parse_sky(s: &str) -> Result<Sky, String> {
+    match s {
+        "sun" => Ok(Sky::Sun),
+        _ => Err(format!("unknown sky '{}' (expected sun)", s)),
+    }
+}
+
+fn parse_shading(s: &str) -> Result<ShadingModel, String> {
+    match s {
+        "phong" => Ok(ShadingModel::Phong),
+        "toon" => Ok(ShadingModel::Toon),
+        _ => Err(format!("unknown shading '{}' (expected phong or toon)", s)),
+    }
+}
+
+fn parse_bands(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(bands) if bands >= 2 => Ok(bands),
+        Ok(_) => Err("toon shading needs at least 2 bands".to_string()),
+        Err(_) => Err(format!("bad band count '{}'", s)),
+    }
+}
+
+fn parse_blades(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(blades) if blades == 0 || blades >= 3 => Ok(blades),
+        Ok(_) => Err("an aperture needs at least 3 blades, or 0 for a round one".to_string()),
+        Err(_) => Err(format!("bad blade count '{}'", s)),
+    }
+}
+
+fn parse_number(s: &str) -> Result<f64, String> {
+    s.parse::<f64>().map_err(|_| format!("bad number '{}'", s))
+}
+
+fn parse_finite(s: &str) -> Result<f64, String> {
+    match parse_number(s)? {
+        value if value.is_finite() => Ok(value),
+        value => Err(format!("must be a finite number (got {})", value)),
+    }
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    match parse_number(s)? {
+        value if value > 0.0 && value.is_finite() => Ok(value),
+        value => Err(format!("must be positive (got {})", value)),
+    }
+}
+
+// Positive, or inf for no far plane
+fn parse_far(s: &str) -> Result<f64, String> {
+    match parse_number(s)? {
+        value if value > 0.0 => Ok(value),
+        value => Err(format!("must be positive (got {})", value)),
+    }
+}
+
+fn parse_aperture(s: &str) -> Result<f64, String> {
+    match parse_number(s)? {
+        value if value >= 0.0 && value.is_finite() => Ok(value),
+        value => Err(format!("must not be negative (got {})", value)),
+    }
+}
+
+fn parse_filter_radius(s: &str) -> Result<f64, String> {
+    match parse_number(s)? {
+        value if value >= 0.5 && value.is_finite() => Ok(value),
+        value => Err(format!("must be at least 0.5 pixels (got {})", value)),
+    }
+}
+
+// A budget too long to end in this machine's clock gives no deadline at all
+fn parse_time_budget(s: &str) -> Result<Duration, String> {
+    let value = parse_number(s)?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("must be a finite number of seconds (got {})", value));
+    }
+    Duration::try_from_secs_f64(value).map_err(|_| format!("{} seconds is too long", value))
+}
+
+fn parse_sun_elevation(s: &str) -> Result<f64, String> {
+    match parse_number(s)? {
+        value if (0.0..=90.0).contains(&value) => Ok(value),
+        value => Err(format!("must be between 0 and 90 degrees (got {})", value)),
+    }
+}
+
+fn parse_turbidity(s: &str) -> Result<f64, String> {
+    match parse_number(s)? {
+        value if value >= 1.0 && value.is_finite() => Ok(value),
+        value => Err(format!("must be at least 1 (got {})", value)),
+    }
+}
+
+fn parse_level(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(level) if level <= MAX_MENGER_LEVEL => Ok(level),
+        Ok(level) => Err(format!("{} is too deep (at most {})", level, MAX_MENGER_LEVEL)),
+        Err(_) => Err(format!("bad level '{}'", s)),
+    }
+}
+
+fn parse_threads(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(threads) if threads >= 1 => Ok(threads),
+        Ok(_) => Err("--threads needs at least 1 thread".to_string()),
+        Err(_) => Err(format!("bad thread count '{}'", s)),
+    }
+}
+
+fn parse_stream_rows(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(rows) if rows >= 1 => Ok(rows),
+        Ok(_) => Err("--stream-rows needs at least 1 row per band".to_string()),
+        Err(_) => Err(format!("bad row count '{}'", s)),
+    }
+}
+
+fn parse_aovs(s: &str) -> Result<Aovs, String> {
+    let mut aovs = Aovs { shadows: false, reflections: false };
+    for name in s.split(',').map(str::trim) {
+        match name {
+            "shadows" => aovs.shadows = true,
+            "reflections" => aovs.reflections = true,
+            _ => return Err(format!("unknown AOV '{}' (expected shadows or reflections)", name)),
+        }
+    }
+    Ok(aovs)
+}
+
+fn parse_render_layers(s: &str) -> Result<RenderLayers, String> {
+    let names: Vec<String> = s.split(',').map(|name| name.trim().to_string()).collect();
+    if names.iter().any(String::is_empty) {
+        return Err(format!("expected layer names separated by commas, got '{}'", s));
+    }
+    Ok(RenderLayers { names })
+}
+
+fn parse_thumbnail(s: &str) -> Result<Thumbnail, String> {
+    if s.trim().ends_with('%') {
+        return match resolution::parse_scale(s)? {
+            scale if scale <= 1.0 => Ok(Thumbnail::Scale(scale)),
+            _ => Err(format!("a thumbnail cannot be larger than the image, got '{}'", s)),
+        };
+    }
+    let (width, height) = resolution::parse(s).map_err(|_| format!("expected WxH or a percentage such as 25%, got '{}'", s))?;
+    Ok(Thumbnail::Size(width, height))
+}
+
+fn parse_bracket(s: &str) -> Result<Bracket, String> {
+    let mut stops = Vec::new();
+    for part in s.split(',').map(str::trim) {
+        let stop = match part.parse::<f64>() {
+            Ok(stop) if stop.is_finite() => stop + 0.0, // no "-0" in file names
+            _ => return Err(format!("bad stop '{}'", part)),
+        };
+        if stops.contains(&stop) {
+            return Err(format!("stop {} is listed twice", stop));
+        }
+        stops.push(stop);
+    }
+    Ok(Bracket { stops })
+}
+
+fn parse_portal_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fraction) if (0.0..=1.0).contains(&fraction) => Ok(fraction),
+        Ok(_) => Err("the portal fraction must be between 0 and 1".to_string()),
+        Err(_) => Err(format!("bad fraction '{}'", s)),
+    }
+}
+
+fn parse_reproject(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(every) if every >= 1 => Ok(every),
+        Ok(_) => Err("--reproject needs a full frame every N frames, with N at least 1".to_string()),
+        Err(_) => Err(format!("bad frame count '{}'", s)),
+    }
+}
+
+fn parse_min_contribution(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(weight) if (0.0..=1.0).contains(&weight) => Ok(weight),
+        Ok(_) => Err("the minimum contribution must be between 0 and 1".to_string()),
+        Err(_) => Err(format!("bad contribution '{}'", s)),
+    }
+}
+
+fn parse_chroma(s: &str) -> Result<Chroma, String> {
+    match s {
+        "420" => Ok(Chroma::C420),
+        "444" => Ok(Chroma::C444),
+        _ => Err(format!("unknown chroma subsampling '{}' (expected 420 or 444)", s)),
+    }
+}
+
+fn parse_metric(s: &str) -> Result<Metric, String> {
+    match s {
+        "ssim" => Ok(Metric::Ssim),
+        "mse" => Ok(Metric::Mse),
+        "psnr" => Ok(Metric::Psnr),
+        _ => Err(format!("unknown metric '{}' (expected ssim, mse or psnr)", s)),
+    }
+}
+
+fn parse_ssim_window(s: &str) -> Result<SsimWindow, String> {
+    match s {
+        "box" => Ok(SsimWindow::Box8),
+        "gaussian" => Ok(SsimWindow::Gaussian),
+        _ => Err(format!("unknown window '{}' (expected box or gaussian)", s)),
+    }
+}
+
+fn parse_filter(s: &str) -> Result<Filter, String> {
+    match s {
+        "box" => Ok(Filter::Box { radius: 0.5 }),
+        "tent" => Ok(Filter::Tent { radius: 1.0 }),
+        "gaussian" => Ok(Filter::Gaussian { radius: 1.5 }),
+        _ => Err(format!("unknown filter '{}' (expected box, tent or gaussian)", s)),
+    }
+}
+
+fn parse_fit(s: &str) -> Result<Fit, String> {
+    match s {
+        "crop" => Ok(Fit::Crop),
+        "letterbox" => Ok(Fit::Letterbox),
+        _ => Err(format!("unknown framing fit '{}' (expected crop or letterbox)", s)),
+    }
 }
 
-fn main() {
-    let args = Args::parse();
-    
-    let mut scene = Scene::new();
-    
+fn parse_texture_filter(s: &str) -> Result<TextureFilter, String> {
+    match s {
+        "nearest" => Ok(TextureFilter::Nearest),
+        "bilinear" => Ok(TextureFilter::Bilinear),
+        "mipmap" => Ok(TextureFilter::Mipmap),
+        _ => Err(format!("unknown texture filter '{}' (expected nearest, bilinear or mipmap)", s)),
+    }
+}
+
+// Three comma-separated numbers
+fn parse_triple(s: &str) -> Result<[f64; 3], String> {
+    let parts: Vec<&str> = s.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!("expected r,g,b but got '{}'", s));
+    }
+    let mut values = [0.0; 3];
+    for (value, part) in values.iter_mut().zip(&parts) {
+        *value = part.trim().parse::<f64>().map_err(|_| format!("invalid number '{}'", part))?;
+    }
+    Ok(values)
+}
+
+// Linear r,g,b, or an sRGB hex color like #cc3344 or a color name
+fn parse_color(s: &str) -> Result<Color, String> {
+    if !s.contains(',') {
+        return Color::parse(s);
+    }
+    let [r, g, b] = parse_triple(s)?;
+    Ok(Color::new(r, g, b))
+}
+
+// Three comma-separated numbers, scaled to unit length
+fn parse_direction(s: &str) -> Result<Vec3, String> {
+    let [x, y, z] = parse_triple(s).map_err(|_| format!("expected x,y,z but got '{}'", s))?;
+    let direction = Vec3::new(x, y, z);
+    if direction.length_squared() == 0.0 || !direction.length_squared().is_finite() {
+        return Err("the direction must be finite and not zero".to_string());
+    }
+    Ok(direction.normalize())
+}
+
+fn parse_depth_range(s: &str) -> Result<(f64, f64), String> {
+    let (near, far) = s.split_once(',').ok_or_else(|| format!("expected NEAR,FAR but got '{}'", s))?;
+    let number = |part: &str| part.trim().parse::<f64>().map_err(|_| format!("invalid number '{}'", part));
+    let (near, far) = (number(near)?, number(far)?);
+    if !(near.is_finite() && far.is_finite() && near < far) {
+        return Err(format!("the range must run from a smaller to a larger finite depth, not {} to {}", near, far));
+    }
+    Ok((near, far))
+}
+
+// A point on the plane and its normal, as six comma-separated numbers
+fn parse_clip_plane(s: &str) -> Result<(Vec3, Vec3), String> {
+    let values = s.split(',')
+        .map(|part| part.trim().parse::<f64>().map_err(|_| format!("invalid number '{}'", part)))
+        .collect::<Result<Vec<f64>, String>>()?;
+    let [px, py, pz, nx, ny, nz] = values[..] else {
+        return Err(format!("expected px,py,pz,nx,ny,nz but got '{}'", s));
+    };
+    let normal = Vec3::new(nx, ny, nz);
+    if normal.length_squared() == 0.0 {
+        return Err("the clipping plane's normal must not be zero".to_string());
+    }
+    Ok((Vec3::new(px, py, pz), normal))
+}
+
+fn open_video(path: &str, args: &Args, width: u32, height: u32) -> Result<Y4mWriter<BufWriter<File>>, String> {
+    File::create(path)
+        .and_then(|file| Y4mWriter::new(BufWriter::new(file), width, height, args.fps, args.chroma, args.encoding))
+        .map_err(|err| format!("cannot write '{}': {}", path, err))
+}
+
+fn write_image(image: &Image, output: Option<&str>, encoding: Encoding) -> Result<(), String> {
+    match output {
+        Some(path) => {
+            image.save_ppm(path, encoding).map_err(|err| format!("cannot write '{}': {}", path, err))?;
+            report_written(path, &format!("{}x{}", image.width, image.height));
+            Ok(())
+        }
+        None => image.output_ppm(encoding).map_err(|err| format!("cannot write 'stdout': {}", err)),
+    }
+}
+
+// Traces `pixels` again recording their rays and writes them to `path`
+fn write_ray_paths(scene: &Scene, settings: &RenderSettings, pixels: &[(u32, u32)], (width, height): (u32, u32), path: &str) -> Result<(), Failure> {
+    type Writer = fn(&[ray_paths::RaySegment], BufWriter<File>) -> io::Result<()>;
+    let extension = Path::new(path).extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+    let write: Writer = match extension.as_deref() {
+        Some("obj") => ray_paths::write_obj,
+        Some("ply") => ray_paths::write_ply,
+        _ => return Err(Failure::Usage(format!("--trace-rays-file: '{}' should end in .obj or .ply", path))),
+    };
+    let segments = scene.trace_paths(width, height, settings, pixels).map_err(|err| Failure::Usage(format!("--trace-rays: {}", err)))?;
+    let write_error = |err: io::Error| Failure::Output(format!("cannot write '{}': {}", path, err));
+    let file = File::create(path).map_err(write_error)?;
+    write(&segments, BufWriter::new(file)).map_err(write_error)?;
+    report_written(path, &format!("{} ray segments", segments.len()));
+    Ok(())
+}
+
+// Names a file just written, with a note on its contents and its size
+fn report_written(path: &str, note: &str) {
+    match std::fs::metadata(path) {
+        Ok(metadata) => info!("Wrote {} ({}, {} bytes)", path, note, metadata.len()),
+        Err(_) => info!("Wrote {} ({})", path, note),
+    }
+}
+
+fn default_camera(aspect_ratio: f64) -> Camera {
     // Set up camera for proper perspective with wider field of view
-    let camera = Camera::new(
+    Camera::new(
         Vec3::new(0.0, 1.0, 2.0),  // camera positioned back and slightly up
         Vec3::new(0.0, 0.0, -4.0), // looking at objects
         Vec3::new(0.0, 1.0, 0.0),  // up vector
         60.0,                      // wider field of view for better framing
-        args.width as f64 / args.height as f64, // aspect ratio
+        aspect_ratio,
+    ).expect("the field of view is in range")
+}
+
+// Prints the metric and exits with status 1 when it misses the threshold
+// and 2 on errors
+fn run_diff(args: &DiffArgs, format: ErrorFormat) {
+    let load = |path: &str| Image::load_ppm(path).unwrap_or_else(|err| report_failure(format, 2, &err, None));
+    let (first, second) = (load(&args.first), load(&args.second));
+    
+    let value = match args.metric {
+        Metric::Ssim => metrics::ssim(&first, &second, args.window),
+        Metric::Mse => metrics::mse(&first, &second),
+        Metric::Psnr => metrics::psnr(&first, &second),
+    };
+    let value = value.unwrap_or_else(|err| report_failure(format, 2, &err, None));
+    println!("{:.6}", value);
+    
+    if let Some(threshold) = args.threshold {
+        let passed = match args.metric {
+            Metric::Ssim | Metric::Psnr => value >= threshold,
+            Metric::Mse => value <= threshold,
+        };
+        if !passed {
+            report_failure(format, 1, &format!("images differ: {:.6} misses the threshold {}", value, threshold), None);
+        }
+    }
+}
+
+fn run_bake(args: &BakeArgs) -> Result<(), Failure> {
+    let start = Instant::now();
+    let mesh = mesh::bake(&args.obj, &args.output).map_err(Failure::Scene)?;
+    info!(
+        "Baked {} triangles from {} to {} in {:.3}s",
+        mesh.triangle_count(),
+        args.obj,
+        args.output,
+        start.elapsed().as_secs_f64(),
     );
-    scene.set_camera(camera);
+    Ok(())
+}
+
+// Frames the scene's bounds with an orthographic camera looking along the
+// direction, just outside the side facing it, and writes the depth under
+// each pixel normalized to the range
+fn run_bake_depth(args: &Args, depth_args: &BakeDepthArgs) -> Result<(), Failure> {
+    let start = Instant::now();
+    let (width, height) = image_size(args).map_err(Failure::Scene)?;
+    let (mut scene, warnings) = build_scene(args, (width, height), &AssetCache::default())?;
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
+    let bounds = scene.describe().bounds.ok_or_else(|| Failure::Scene("the scene has no bounded objects to frame".to_string()))?;
     
-    // Add lighting - positioned to better illuminate objects
-    scene.add_light(Light::new(
-        Vec3::new(2.0, 3.0, 1.0), // Light positioned above and to the side
-        Vec3::new(1.0, 1.0, 1.0),
-        0.8, // Slightly reduced intensity for better contrast
-    ));
+    let direction = depth_args.direction;
+    let up = if direction.cross(&Vec3::new(0.0, 1.0, 0.0)).length_squared() < 1e-12 {
+        Vec3::new(0.0, 0.0, -1.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    };
+    let w = -direction;
+    let u = up.cross(&w).normalize();
+    let v = w.cross(&u);
+    let center = bounds.centroid();
+    let half_extent = |axis: Vec3| (0..8)
+        .map(|corner| Vec3::new(
+            if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+            if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+            if corner & 4 == 0 { bounds.min.z } else { bounds.max.z },
+        ))
+        .map(|corner| (corner - center).dot(&axis).abs())
+        .fold(0.0, f64::max);
+    let (half_width, half_height, half_depth) = (half_extent(u), half_extent(v), half_extent(w));
     
-    // Create scenes based on argument
-    match args.scene.as_str() {
-        "scene1" => create_sphere_scene(&mut scene),
-        "scene2" => create_plane_cube_scene(&mut scene),
-        "scene3" => create_all_objects_scene(&mut scene),
-        "scene4" => create_different_perspective_scene(&mut scene),
-        _ => create_sphere_scene(&mut scene),
+    let aspect_ratio = width as f64 / height as f64;
+    let view_height = (2.0 * half_height).max(2.0 * half_width / aspect_ratio);
+    if view_height <= 0.0 {
+        return Err(Failure::Scene("the scene has no extent across the direction".to_string()));
     }
+    // Far enough back that the near clipping distance cuts nothing away
+    let standoff = half_depth.max(1.0);
+    let position = center - direction * (half_depth + standoff);
+    scene.set_camera(Camera::orthographic(position, center, up, view_height, aspect_ratio));
     
-    // Render the scene
-    let mut image = Image::new(args.width, args.height);
-    scene.render(&mut image, args.reflection);
+    let (near, far) = depth_args.range.unwrap_or((0.0, (2.0 * half_depth).max(f64::EPSILON)));
+    let depth = scene.depth_map(width, height);
+    let image = Image::from_fn(width, height, |x, y| {
+        let distance = depth[y * width as usize + x] - standoff;
+        let value = if distance.is_finite() { ((distance - near) / (far - near)).clamp(0.0, 1.0) } else { 1.0 };
+        Color::new(value, value, value)
+    });
+    image.save_ppm16(&depth_args.output).map_err(|err| Failure::Output(format!("cannot write '{}': {}", depth_args.output, err)))?;
+    info!(
+        "Baked {}x{} depths from {:.3} to {:.3} to {} in {:.3}s",
+        width, height, near, far, depth_args.output, start.elapsed().as_secs_f64(),
+    );
+    Ok(())
+}
+
+// Each line of stdin is a job: a JSON object whose keys are render options
+// as on the command line, with or without their dashes, for example
+// {"scene-file": "room.json", "width": 320, "reflection": true, "output": "a.ppm"}.
+// An "id" is echoed back. Every job gets one line on stdout with its
+// status, output, time in seconds and any warnings about its scene; a bad
+// job is reported there and the next one is read.
+fn run_serve() {
+    let assets = AssetCache::default();
+    let mut stdout = std::io::stdout();
+    for (index, line) in std::io::stdin().lock().lines().enumerate() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let start = Instant::now();
+        let job = Value::parse(&line);
+        let mut result = vec![("job".to_string(), Value::from((index + 1) as f64))];
+        if let Some(id) = job.as_ref().ok().and_then(|job| job.get("id")) {
+            result.push(("id".to_string(), id.clone()));
+        }
+        let outcome = job.and_then(|job| job_args(&job)).and_then(|args| {
+            let warnings = run_render(&args, &assets).map_err(|failure| failure.to_string())?;
+            Ok((args.video.or(args.export_scene).or(args.output).unwrap_or_default(), warnings))
+        });
+        match outcome {
+            Ok((output, warnings)) => {
+                result.push(("status".to_string(), "ok".into()));
+                result.push(("output".to_string(), output.as_str().into()));
+                result.push(("seconds".to_string(), ((start.elapsed().as_secs_f64() * 1000.0).round() / 1000.0).into()));
+                if !warnings.is_empty() {
+                    let warnings = warnings.iter().map(|warning| warning.as_str().into()).collect();
+                    result.push(("warnings".to_string(), Value::Array(warnings)));
+                }
+            }
+            Err(err) => {
+                result.push(("status".to_string(), "error".into()));
+                result.push(("error".to_string(), err.as_str().into()));
+            }
+        }
+        if writeln!(stdout, "{}", Value::Object(result).to_compact_string()).and_then(|_| stdout.flush()).is_err() {
+            break;
+        }
+    }
+}
+
+// The command line a job stands for. Booleans switch flags on or off,
+// arrays of numbers become comma-separated values (colors, vectors) and
+// other arrays repeat the option.
+fn job_args(job: &Value) -> Result<Args, String> {
+    let entries = job.as_object().ok_or_else(|| format!("a job must be a JSON object, not {}", job.type_name()))?;
+    let scalar = |key: &str, value: &Value| match value {
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(s.clone()),
+        Value::Array(items) if items.iter().all(|item| item.as_f64().is_some()) => {
+            Ok(items.iter().map(|item| item.as_f64().unwrap_or_default().to_string()).collect::<Vec<_>>().join(","))
+        }
+        other => Err(format!("{}: unexpected {}", key, other.type_name())),
+    };
     
-    // Output PPM format
-    image.output_ppm();
+    let mut argv = vec!["rt".to_string()];
+    for (key, value) in entries {
+        if key == "id" {
+            continue;
+        }
+        let option = format!("--{}", key.trim_start_matches('-').replace('_', "-"));
+        match value {
+            Value::Bool(true) => argv.push(option),
+            Value::Bool(false) | Value::Null => {}
+            Value::Array(items) if items.iter().any(|item| item.as_f64().is_none()) => {
+                for item in items {
+                    argv.push(option.clone());
+                    argv.push(scalar(key, item)?);
+                }
+            }
+            other => {
+                argv.push(option);
+                argv.push(scalar(key, other)?);
+            }
+        }
+    }
+    
+    let args = Args::try_parse_from(&argv).map_err(|err| {
+        let message = err.to_string();
+        let first_line = message.lines().next().unwrap_or_default();
+        first_line.trim_start_matches("error: ").to_string()
+    })?;
+    // Stdout carries the results, so images have to go to files
+    if args.output.is_none() && args.video.is_none() && args.export_scene.is_none() {
+        return Err("a job needs an output file".to_string());
+    }
+    Ok(args)
+}
+
+fn print_stats(report: &RenderReport) {
+    let stats = &report.stats;
+    info!(
+        "Rendered in {:.3}s: {} primary, {} shadow and {} secondary rays",
+        report.elapsed.as_secs_f64(),
+        stats.primary_rays,
+        stats.shadow_rays,
+        stats.secondary_rays,
+    );
+    if stats.shadow_cache_tests > 0 {
+        info!(
+            "Shadow cache: {} of {} cached occluders still blocked ({:.1}% of all shadow rays)",
+            stats.shadow_cache_hits,
+            stats.shadow_cache_tests,
+            100.0 * stats.shadow_cache_hits as f64 / stats.shadow_rays as f64,
+        );
+    }
+    if stats.pinholes_sealed > 0 {
+        info!("Pinholes sealed: {}", stats.pinholes_sealed);
+    }
+    if let Some(tiles) = &report.tiles {
+        info!(
+            "Tiles: {} taking {:.2}ms on average, the slowest {:.2}ms at ({}, {}); {:.3}s from the last tile starting to the end",
+            tiles.count,
+            tiles.mean.as_secs_f64() * 1000.0,
+            tiles.slowest.as_secs_f64() * 1000.0,
+            tiles.slowest_at.0,
+            tiles.slowest_at.1,
+            tiles.tail.as_secs_f64(),
+        );
+    }
+}
+
+// The cost pass scales its colors to the costliest pixel, so that pixel's
+// count is needed to read the image
+fn print_peak_cost(report: &RenderReport) {
+    if let Some(peak) = report.peak_cost {
+        info!("Cost pass: the top of the scale is {:.1} intersection tests per sample", peak);
+    }
+}
+
+// Analysis and exposure look at the rendered image before the label is
+// drawn over it
+fn finish_image(image: &mut Image, aux: Option<&AuxBuffers>, args: &Args, label: Option<&str>) {
+    image.pixel_aspect = args.pixel_aspect;
+    if args.check_finite {
+        check_finite(image);
+    }
+    let stats = if args.analyze || args.auto_expose { image.luminance_stats() } else { None };
+    if let Some(stats) = stats.filter(|_| args.analyze) {
+        info!(
+            "Luminance: min {:.4}, max {:.4}, mean {:.4}, 1st/50th/99th percentiles {:.4}/{:.4}/{:.4}, {:.2}% of pixels clipped",
+            stats.min, stats.max, stats.mean, stats.p1, stats.p50, stats.p99, stats.clipped * 100.0,
+        );
+    }
+    if let Some(stats) = stats.filter(|_| args.auto_expose) {
+        let exposure = stats.auto_exposure();
+        info!("Auto exposure: x{:.3}", exposure);
+        image.apply_exposure(exposure);
+    }
+    if let (true, Some(aux)) = (args.toon_outline, aux) {
+        let outline = Outline {
+            color: args.outline_color,
+            depth_threshold: args.outline_depth,
+            angle_threshold: args.outline_angle,
+            thickness: args.outline_thickness,
+        };
+        outline.apply(image, aux);
+    }
+    let view_aspect = image.width as f64 / image.height as f64 * args.pixel_aspect;
+    let frame = args.framing.map(|(width, height)| args.framing_fit.view(1.0, width as f64 / height as f64, view_aspect).1);
+    // The part of the image the framing covers, as (x, y, width, height)
+    let area = frame.map_or((0, 0, image.width, image.height), |frame| framed_area(image, frame));
+    if args.framing_fit == Fit::Letterbox && args.framing.is_some() {
+        draw_letterbox(image, area);
+    }
+    if args.guides {
+        draw_guides(image, area);
+    }
+    if let Some(label) = label {
+        draw_label(image, label);
+    }
+}
+
+// Pixels of `image` covered by a framing centered in it at `frame`, its
+// size as fractions of the image's width and height
+fn framed_area(image: &Image, frame: (f64, f64)) -> (u32, u32, u32, u32) {
+    let side = |fraction: f64, pixels: u32| {
+        let covered = ((fraction.min(1.0) * pixels as f64).round() as u32).clamp(1, pixels);
+        ((pixels - covered) / 2, covered)
+    };
+    let ((x, width), (y, height)) = (side(frame.0, image.width), side(frame.1, image.height));
+    (x, y, width, height)
+}
+
+fn draw_letterbox(image: &mut Image, (x, y, width, height): (u32, u32, u32, u32)) {
+    let (right, bottom) = (x + width, y + height);
+    image.draw_rect(0, 0, image.width, y, Color::BLACK);
+    image.draw_rect(0, bottom as i64, image.width, image.height - bottom, Color::BLACK);
+    image.draw_rect(0, 0, x, image.height, Color::BLACK);
+    image.draw_rect(right as i64, 0, image.width - right, image.height, Color::BLACK);
+}
+
+// Lines at thirds of the area, and the outline of the title-safe area, the
+// middle 80% of it that text should stay within
+fn draw_guides(image: &mut Image, (x, y, width, height): (u32, u32, u32, u32)) {
+    const COLOR: Color = Color { r: 1.0, g: 0.85, b: 0.0 };
+    let thickness = (width.min(height) / 300).max(1);
+    let (x, y) = (x as i64, y as i64);
+    for third in [1, 2] {
+        let across = x + (width * third / 3) as i64 - thickness as i64 / 2;
+        let down = y + (height * third / 3) as i64 - thickness as i64 / 2;
+        image.draw_rect(across, y, thickness, height, COLOR);
+        image.draw_rect(x, down, width, thickness, COLOR);
+    }
+    let (safe_width, safe_height) = (width * 4 / 5, height * 4 / 5);
+    let (left, top) = (x + (width - safe_width) as i64 / 2, y + (height - safe_height) as i64 / 2);
+    image.draw_rect(left, top, safe_width, thickness, COLOR);
+    image.draw_rect(left, top + (safe_height - thickness) as i64, safe_width, thickness, COLOR);
+    image.draw_rect(left, top, thickness, safe_height, COLOR);
+    image.draw_rect(left + (safe_width - thickness) as i64, top, thickness, safe_height, COLOR);
+}
+
+fn check_finite(image: &mut Image) {
+    const LISTED: usize = 20;
+    let replaced = image.replace_non_finite(Color::new(1.0, 0.0, 1.0));
+    if replaced.is_empty() {
+        info!("All pixels are finite");
+        return;
+    }
+    let mut listed: Vec<String> = replaced.iter().take(LISTED).map(|(x, y)| format!("({}, {})", x, y)).collect();
+    if replaced.len() > LISTED {
+        listed.push("...".to_string());
+    }
+    warn!("{} non-finite pixels, painted magenta: {}", replaced.len(), listed.join(", "));
+}
+
+// White text on a black box in the bottom-left corner, scaled up with
+// the image so it stays readable
+fn draw_label(image: &mut Image, text: &str) {
+    let scale = (image.height / 240).max(1);
+    let padding = 2 * scale;
+    let (text_width, text_height) = font::text_size(text, scale);
+    let top = image.height as i64 - (text_height + 2 * padding) as i64;
+    image.draw_rect(0, top, text_width + 2 * padding, text_height + 2 * padding, Color::BLACK);
+    image.draw_text(padding as i64, top + padding as i64, text, Color::WHITE, scale);
+}
+
+// "out.ppm" -> "out_007.ppm"
+fn frame_path(path: &str, frame: u32) -> String {
+    suffixed_path(path, &format!("_{:03}", frame))
+}
+
+// The time budget applies to each frame separately. Each finished frame
+// is handed to `save_frame` with its camera, number and render report,
+// along with depth and normal buffers when `with_aux` is set; the first
+// frame it fails to save ends the sequence. With `reproject` set to N, frames are
+// predicted from the one before except every Nth, which is traced in
+// full. A scene's camera shake is applied to each frame's camera, frames
+// being 1/fps seconds apart.
+#[allow(clippy::too_many_arguments)]
+fn render_dolly_zoom(
+    scene: &mut Scene,
+    settings: &RenderSettings,
+    time_budget: Option<Duration>,
+    (width, height): (u32, u32),
+    (frames, fps): (u32, u32),
+    with_aux: bool,
+    reproject_every: Option<u32>,
+    mut save_frame: impl FnMut(Image, Option<AuxBuffers>, &Camera, u32, &RenderReport) -> Result<(), Failure>,
+) -> Result<(), Failure> {
+    let start = scene.camera.take().expect("Camera not set");
+    
+    // Keep the width of the view at the look-at point constant while the
+    // FOV narrows to a third of its starting value
+    let forward = (start.look_at - start.position).normalize();
+    let distance = (start.look_at - start.position).length();
+    let half_width = distance * (start.fov.to_radians() / 2.0).tan();
+    let end_fov = start.fov / 3.0;
+    start.with_fov(end_fov).map_err(|e| Failure::Scene(format!("--dolly-zoom narrows the view to a third of its fov: {}", e)))?;
+    let mut previous: Option<Frame> = None;
+    let started = Instant::now();
+    // Time spent on the frames themselves, leaving out saving them
+    let mut rendering = Duration::ZERO;
+    
+    for frame in 0..frames {
+        let t = if frames > 1 { frame as f64 / (frames - 1) as f64 } else { 0.0 };
+        let fov = start.fov + (end_fov - start.fov) * t;
+        let new_distance = half_width / (fov.to_radians() / 2.0).tan();
+        
+        let mut camera = start.translated(forward * (distance - new_distance)).with_fov(fov).map_err(Failure::Scene)?;
+        if let Some(shake) = &scene.shake {
+            camera = camera.shaken(shake, settings.seed, frame as f64 / fps as f64);
+        }
+        scene.set_camera(camera.clone());
+        
+        let frame_start = Instant::now();
+        let frame_settings = RenderSettings {
+            deadline: time_budget.and_then(|budget| Instant::now().checked_add(budget)),
+            ..*settings
+        };
+        let predicted = match (&previous, reproject_every) {
+            (Some(previous), Some(every)) if frame % every != 0 => Some(reproject(previous, &camera)),
+            _ => None,
+        };
+        let (image, aux, report) = match predicted {
+            Some(Reprojection { mut image, mut aux, retrace }) => {
+                let report = scene.retrace(&mut image, Some(&mut aux), &frame_settings, &retrace).map_err(Failure::Render)?;
+                let total = image.pixels.len();
+                info!(
+                    "Frame {}: {} of {} pixels predicted ({:.1}%), {} traced",
+                    frame, total - retrace.len(), total, 100.0 * (total - retrace.len()) as f64 / total as f64, retrace.len(),
+                );
+                (image, Some(aux), report)
+            }
+            None => {
+                let mut image = Image::try_new(width, height).map_err(Failure::Render)?;
+                let mut aux = (with_aux || reproject_every.is_some()).then(|| AuxBuffers::new(width, height));
+                let report = scene.render_with_aux(&mut image, aux.as_mut(), &frame_settings);
+                (image, aux, report)
+            }
+        };
+        
+        // The next frame is predicted from this one as rendered, before any
+        // post-processing
+        let aux = match (reproject_every, aux) {
+            (Some(_), Some(aux)) => {
+                let saved_aux = with_aux.then(|| aux.clone());
+                previous = Some(Frame { camera: camera.clone(), image: image.clone(), aux });
+                saved_aux
+            }
+            (_, aux) => aux,
+        };
+        rendering += frame_start.elapsed();
+        save_frame(image, aux, &camera, frame, &report)?;
+    }
+    info!("Rendered {} frames in {:.2?}, {:.2?} of it rendering rather than saving", frames, started.elapsed(), rendering);
+    Ok(())
+}
+
+// Horizontal ground plane. With --textures it is checkered, alternating
+// `material` with darker, slightly reflective tiles.
+fn ground_plane(point: Vec3, material: Material, textures: bool) -> Box<dyn Object> {
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    if textures {
+        let dark = Material {
+            color: material.color * 0.3,
+            reflectivity: Color::splat(0.3),
+            ..material
+        };
+        Box::new(CheckerPlane::new(point, up, material, dark, 1.0))
+    } else {
+        Box::new(Plane::new(point, up, material))
+    }
 }
 
 fn create_sphere_scene(scene: &mut Scene) {
     // Scene 1: A scene with a sphere
     let sphere_material = Material::new(
-        Vec3::new(0.8, 0.2, 0.2), // bright red
+        Color::new(0.8, 0.2, 0.2), // bright red
         0.2, 0.8, 0.3, 100.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Sphere::new(
@@ -89,30 +2207,32 @@ fn create_sphere_scene(scene: &mut Scene) {
     )));
 }
 
-fn create_plane_cube_scene(scene: &mut Scene) {
+fn create_plane_cube_scene(scene: &mut Scene, textures: bool) {
     // Scene 2: A scene with a flat plane and a cube with lower brightness
     scene.lights.clear();
     scene.add_light(Light::new(
         Vec3::new(3.0, 4.0, 2.0),
-        Vec3::new(0.4, 0.4, 0.4), // Lower brightness than sphere scene
+        Color::new(0.4, 0.4, 0.4), // Lower brightness than sphere scene
         0.4,
     ));
     
+    // A dusky sky to match the dim lighting
+    scene.set_background(Background::Gradient {
+        bottom: Color::new(0.15, 0.15, 0.18),
+        top: Color::new(0.3, 0.35, 0.45),
+    });
+    
     let plane_material = Material::new(
-        Vec3::new(0.6, 0.6, 0.6), // gray plane
+        Color::new(0.6, 0.6, 0.6), // gray plane
         0.2, 0.7, 0.2, 200.0, 0.0, 0.0, 1.0
     );
     
     let cube_material = Material::new(
-        Vec3::new(0.2, 0.8, 0.2), // green cube
+        Color::new(0.2, 0.8, 0.2), // green cube
         0.2, 0.7, 0.3, 200.0, 0.0, 0.0, 1.0
     );
     
-    scene.add_object(Box::new(Plane::new(
-        Vec3::new(0.0, -2.0, 0.0),
-        Vec3::new(0.0, 1.0, 0.0),
-        plane_material,
-    )));
+    scene.add_object(ground_plane(Vec3::new(0.0, -2.0, 0.0), plane_material, textures));
     
     scene.add_object(Box::new(Cube::new(
         Vec3::new(0.0, -1.0, -5.0),
@@ -121,23 +2241,19 @@ fn create_plane_cube_scene(scene: &mut Scene) {
     )));
 }
 
-fn create_all_objects_scene(scene: &mut Scene) {
+fn create_all_objects_scene(scene: &mut Scene, textures: bool) {
     // Scene 3: All objects (sphere, cube, cylinder, plane)
     
     // Ground plane
     let plane_material = Material::new(
-        Vec3::new(0.5, 0.5, 0.5), // gray
+        Color::new(0.5, 0.5, 0.5), // gray
         0.1, 0.7, 0.2, 200.0, 0.0, 0.0, 1.0
     );
-    scene.add_object(Box::new(Plane::new(
-        Vec3::new(0.0, -2.0, 0.0),
-        Vec3::new(0.0, 1.0, 0.0),
-        plane_material,
-    )));
+    scene.add_object(ground_plane(Vec3::new(0.0, -2.0, 0.0), plane_material, textures));
     
     // Sphere (red) - left side
     let sphere_material = Material::new(
-        Vec3::new(0.8, 0.2, 0.2), // red
+        Color::new(0.8, 0.2, 0.2), // red
         0.1, 0.7, 0.3, 200.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Sphere::new(
@@ -148,7 +2264,7 @@ fn create_all_objects_scene(scene: &mut Scene) {
     
     // Cube (green) - right side
     let cube_material = Material::new(
-        Vec3::new(0.2, 0.8, 0.2), // green
+        Color::new(0.2, 0.8, 0.2), // green
         0.1, 0.7, 0.3, 200.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Cube::new(
@@ -159,7 +2275,7 @@ fn create_all_objects_scene(scene: &mut Scene) {
     
     // Cylinder (blue) - center back
     let cylinder_material = Material::new(
-        Vec3::new(0.2, 0.2, 0.8), // blue
+        Color::new(0.2, 0.2, 0.8), // blue
         0.1, 0.7, 0.3, 200.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Cylinder::new(
@@ -170,21 +2286,21 @@ fn create_all_objects_scene(scene: &mut Scene) {
     )));
 }
 
-fn create_different_perspective_scene(scene: &mut Scene) {
+fn create_different_perspective_scene(scene: &mut Scene, aspect_ratio: f64) {
     // Scene 4: Same as scene 3 but with different camera perspective
     let camera = Camera::new(
         Vec3::new(-5.0, 3.0, 0.0),  // elevated side position for better view
         Vec3::new(0.0, -1.0, -7.0), // looking at the center of objects
         Vec3::new(0.0, 1.0, 0.0),   // up
         65.0,                       // wider fov for full visibility
-        800.0 / 600.0,              // aspect ratio for 800x600
-    );
+        aspect_ratio,
+    ).expect("the field of view is in range");
     scene.set_camera(camera);
     
     // Same objects as scene3 but from different angle
     // Ground plane
     let plane_material = Material::new(
-        Vec3::new(0.5, 0.5, 0.5), // gray
+        Color::new(0.5, 0.5, 0.5), // gray
         0.1, 0.7, 0.2, 200.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Plane::new(
@@ -195,7 +2311,7 @@ fn create_different_perspective_scene(scene: &mut Scene) {
     
     // Sphere (red) - left side
     let sphere_material = Material::new(
-        Vec3::new(0.8, 0.2, 0.2), // red
+        Color::new(0.8, 0.2, 0.2), // red
         0.1, 0.7, 0.3, 200.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Sphere::new(
@@ -206,7 +2322,7 @@ fn create_different_perspective_scene(scene: &mut Scene) {
     
     // Cube (green) - right side
     let cube_material = Material::new(
-        Vec3::new(0.2, 0.8, 0.2), // green
+        Color::new(0.2, 0.8, 0.2), // green
         0.1, 0.7, 0.3, 200.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Cube::new(
@@ -217,7 +2333,7 @@ fn create_different_perspective_scene(scene: &mut Scene) {
     
     // Cylinder (blue) - center back
     let cylinder_material = Material::new(
-        Vec3::new(0.2, 0.2, 0.8), // blue
+        Color::new(0.2, 0.2, 0.8), // blue
         0.1, 0.7, 0.3, 200.0, 0.0, 0.0, 1.0
     );
     scene.add_object(Box::new(Cylinder::new(
@@ -227,3 +2343,454 @@ fn create_different_perspective_scene(scene: &mut Scene) {
         cylinder_material,
     )));
 }
+
+fn create_softbox_scene(scene: &mut Scene, size: f64) {
+    // A rectangular area light over a sphere and a cube; the penumbrae
+    // widen as the softbox grows
+    scene.lights.clear();
+    scene.add_light_with_emitter(Light::quad(
+        Vec3::new(-size / 2.0, 2.5, -5.0 - size / 2.0),
+        Vec3::new(size, 0.0, 0.0),  // edges ordered so the panel faces down
+        Vec3::new(0.0, 0.0, size),
+        Color::new(1.0, 1.0, 1.0),
+        1.5 / (size * size),        // same total output for every size
+    ));
+    
+    let plane_material = Material::new(
+        Color::new(0.6, 0.6, 0.6), // gray
+        0.1, 0.8, 0.1, 50.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Plane::new(
+        Vec3::new(0.0, -1.5, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        plane_material,
+    )));
+    
+    let sphere_material = Material::new(
+        Color::new(0.8, 0.3, 0.2), // orange
+        0.1, 0.7, 0.4, 100.0, 0.2, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Sphere::new(
+        Vec3::new(-1.2, -0.5, -5.5),
+        1.0,
+        sphere_material,
+    )));
+    
+    let cube_material = Material::new(
+        Color::new(0.3, 0.5, 0.8), // blue
+        0.1, 0.7, 0.3, 100.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Cube::new(
+        Vec3::new(1.4, -0.9, -5.0),
+        1.2,
+        cube_material,
+    )));
+}
+
+fn create_caustics_scene(scene: &mut Scene) {
+    // A glass sphere under a point light, focusing a bright spot onto the
+    // floor when rendered with --caustics
+    scene.lights.clear();
+    scene.add_light(Light::new(
+        Vec3::new(0.5, 3.0, -5.0),
+        Color::new(1.0, 1.0, 1.0),
+        1.0,
+    ));
+    
+    let floor_material = Material::new(
+        Color::new(0.8, 0.8, 0.8), // light gray
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Plane::new(
+        Vec3::new(0.0, -1.5, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+    )));
+    
+    scene.add_object(Box::new(Sphere::new(
+        Vec3::new(0.0, 0.2, -5.0),
+        0.8,
+        Material::transparent(Color::new(1.0, 1.0, 1.0), 0.9, 1.5),
+    )));
+}
+
+fn create_checker_scene(scene: &mut Scene, texture_filter: TextureFilter, aspect_ratio: f64) {
+    // A fine checkerboard floor running off to the horizon, seen from low
+    // down so distant squares shrink below a pixel and shimmer unless the
+    // texture is mipmapped
+    scene.set_camera(Camera::new(
+        Vec3::new(0.0, 0.5, 2.0),
+        Vec3::new(0.0, 0.2, -6.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        60.0,
+        aspect_ratio,
+    ).expect("the field of view is in range"));
+    
+    let checker = ImageTexture::checker(256, 8, Color::new(0.9, 0.9, 0.9), Color::new(0.1, 0.1, 0.1))
+        .with_filter(texture_filter);
+    let floor_material = Material::new(
+        Color::new(1.0, 1.0, 1.0),
+        0.2, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(
+        Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), floor_material)
+            .with_texture(Arc::new(Texture::Image(checker)), 2.0),
+    ));
+    
+    scene.add_object(Box::new(Sphere::new(
+        Vec3::new(0.0, 0.0, -5.0),
+        1.0,
+        Material::new(Color::new(0.8, 0.2, 0.2), 0.2, 0.8, 0.3, 100.0, 0.0, 0.0, 1.0),
+    )));
+}
+
+fn create_light_ring_scene(scene: &mut Scene) {
+    // Spheres on a floor lit by a ring of 64 dim colored lights, for
+    // comparing --light-samples against shading with every light
+    scene.lights.clear();
+    const LIGHTS: usize = 64;
+    for i in 0..LIGHTS {
+        let angle = i as f64 / LIGHTS as f64 * std::f64::consts::TAU;
+        let color = Color::new(
+            0.6 + 0.4 * angle.cos(),
+            0.6 + 0.4 * (angle + 2.0).cos(),
+            0.6 + 0.4 * (angle + 4.0).cos(),
+        );
+        scene.add_light(Light::new(
+            Vec3::new(4.0 * angle.cos(), 2.0, -5.0 + 4.0 * angle.sin()),
+            color,
+            0.04,
+        ));
+    }
+    
+    let floor_material = Material::new(
+        Color::new(0.8, 0.8, 0.8),
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Plane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+    )));
+    
+    for (x, z) in [(-1.5, -5.0), (0.0, -6.0), (1.5, -5.0)] {
+        scene.add_object(Box::new(Sphere::new(
+            Vec3::new(x, -0.2, z),
+            0.8,
+            Material::new(Color::new(0.9, 0.9, 0.9), 0.1, 0.8, 0.4, 50.0, 0.0, 0.0, 1.0),
+        )));
+    }
+}
+
+fn create_ellipsoid_scene(scene: &mut Scene) {
+    // A squat character built from ellipsoids with capsule arms, standing
+    // next to a tall glass egg
+    let floor_material = Material::new(
+        Color::new(0.8, 0.8, 0.8),
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Plane::new(
+        Vec3::new(0.0, -1.5, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+    )));
+    
+    let body_material = Material::new(Color::new(0.9, 0.5, 0.2), 0.2, 0.7, 0.3, 50.0, 0.0, 0.0, 1.0);
+    scene.add_named_object("body", Box::new(Ellipsoid::new(
+        Vec3::new(-0.8, -0.8, -5.0),
+        Vec3::new(1.0, 0.7, 0.8),
+        body_material,
+    )));
+    scene.add_named_object("head", Box::new(Ellipsoid::new(
+        Vec3::new(-0.8, 0.3, -5.0),
+        Vec3::new(0.55, 0.45, 0.5),
+        body_material,
+    )));
+    let eye_material = Material::new(Color::new(0.05, 0.05, 0.05), 0.1, 0.5, 0.8, 200.0, 0.0, 0.0, 1.0);
+    for x in [-1.0, -0.6] {
+        scene.add_object(Box::new(Ellipsoid::new(
+            Vec3::new(x, 0.4, -4.55),
+            Vec3::new(0.08, 0.12, 0.05),
+            eye_material,
+        )));
+    }
+    
+    scene.add_named_object("left_arm", Box::new(Capsule::new(
+        Vec3::new(-1.75, -0.55, -4.9),
+        Vec3::new(-2.2, -1.1, -4.6),
+        0.15,
+        body_material,
+    )));
+    scene.add_named_object("right_arm", Box::new(Capsule::new(
+        Vec3::new(0.15, -0.55, -4.9),
+        Vec3::new(0.55, 0.0, -4.6),
+        0.15,
+        body_material,
+    )));
+    
+    scene.add_named_object("egg", Box::new(Ellipsoid::new(
+        Vec3::new(1.3, -0.4, -4.5),
+        Vec3::new(0.5, 1.1, 0.5),
+        Material::transparent(Color::new(0.9, 0.95, 1.0), 0.9, 1.5),
+    )));
+}
+
+fn create_gold_scene(scene: &mut Scene) {
+    // A gold sphere next to a chrome one under a blue sky. Gold reflects
+    // red and green more strongly than blue, so the sky it mirrors comes
+    // out warm, while chrome mirrors it unchanged.
+    scene.set_background(Background::Gradient {
+        bottom: Color::new(0.75, 0.85, 1.0),
+        top: Color::new(0.2, 0.4, 0.9),
+    });
+    
+    let floor_material = Material::new(
+        Color::new(0.7, 0.7, 0.7),
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(CheckerPlane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+        Material { color: Color::new(0.2, 0.2, 0.2), ..floor_material },
+        1.0,
+    )));
+    
+    let gold_tint = Color::new(1.0, 0.71, 0.29);
+    let gold = Material {
+        color: gold_tint,
+        specular: gold_tint,
+        reflectivity: gold_tint * 0.9,
+        ..Material::new(Color::BLACK, 0.05, 0.2, 1.0, 300.0, 0.0, 0.0, 1.0)
+    };
+    scene.add_named_object("gold", Box::new(Sphere::new(Vec3::new(-1.1, 0.0, -5.0), 1.0, gold)));
+    
+    let chrome = Material::new(Color::new(0.9, 0.9, 0.9), 0.05, 0.2, 1.0, 300.0, 0.9, 0.0, 1.0);
+    scene.add_named_object("chrome", Box::new(Sphere::new(Vec3::new(1.1, 0.0, -5.0), 1.0, chrome)));
+}
+
+fn create_water_scene(scene: &mut Scene, aspect_ratio: f64) {
+    // A straw standing in a glass of water. The water sits inside the
+    // glass, so light passing between the two bends by their ratio of
+    // refractive indices, and the part of the straw under water looks
+    // broken off from the part above it.
+    let floor_material = Material::new(
+        Color::new(0.8, 0.8, 0.8),
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(CheckerPlane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+        Material { color: Color::new(0.3, 0.3, 0.3), ..floor_material },
+        0.5,
+    )));
+    
+    // A thick base and a thin layer of glass over the water
+    scene.add_named_object("glass", Box::new(Cylinder::new(
+        Vec3::new(0.0, -0.15, -4.5),
+        0.8,
+        1.7,
+        Material::transparent(Color::new(1.0, 1.0, 1.0), 0.95, 1.5),
+    )));
+    scene.add_named_object("water", Box::new(Cylinder::new(
+        Vec3::new(0.0, -0.115, -4.5),
+        0.72,
+        1.57,
+        Material::transparent(Color::new(0.85, 0.95, 1.0), 0.95, 1.33),
+    )));
+    
+    let straw_material = Material::new(
+        Color::new(0.9, 0.2, 0.15),
+        0.2, 0.8, 0.3, 50.0, 0.0, 0.0, 1.0
+    );
+    let straw = Cylinder::new(Vec3::zero(), 0.05, 2.6, straw_material);
+    let tilt = Mat4::translation(Vec3::new(0.35, 0.25, -4.6)) * Mat4::rotation_z(-20.0) * Mat4::rotation_x(-30.0);
+    let straw = Group::new(vec![Box::new(straw)], tilt).expect("the tilt keeps the straw's shape");
+    scene.add_named_object("straw", Box::new(straw));
+    
+    // Close to the glass and just above the water line
+    scene.set_camera(Camera::new(
+        Vec3::new(0.0, 0.78, -1.0),
+        Vec3::new(0.0, 0.0, -4.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        45.0,
+        aspect_ratio,
+    ).expect("the field of view is in range"));
+}
+
+fn create_window_scene(scene: &mut Scene, aspect_ratio: f64) {
+    // A mirror sphere on a floor, lit by a glowing window behind the
+    // camera. The window's panes and bars show up in the reflection, and
+    // its warm and cool glass tints the light on the floor.
+    scene.lights.clear();
+    scene.add_textured_light(
+        Light::quad(
+            Vec3::new(3.0, -1.0, 1.0),
+            Vec3::new(-6.0, 0.0, 0.0), // edges ordered so the panel faces the sphere
+            Vec3::new(0.0, 5.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            0.08,
+        ),
+        Arc::new(window_texture(64)),
+    );
+    scene.set_background(Background::Solid(Color::new(0.06, 0.07, 0.1)));
+    
+    let floor_material = Material::new(
+        Color::new(0.8, 0.8, 0.8),
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Plane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+    )));
+    scene.add_named_object("mirror", Box::new(Sphere::new(
+        Vec3::new(0.0, 0.2, -4.0),
+        1.2,
+        Material::reflective(Color::new(0.9, 0.9, 0.9), 0.95),
+    )));
+    
+    scene.set_camera(Camera::new(
+        Vec3::new(0.0, 0.6, 0.0),
+        Vec3::new(0.0, 0.1, -4.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        45.0,
+        aspect_ratio,
+    ).expect("the field of view is in range"));
+}
+
+// Four panes in a dark frame, pale blue sky in the top two and a warm
+// sunset glow in the bottom two
+fn window_texture(size: usize) -> ImageTexture {
+    let bar = (size / 12).max(1);
+    let texels = (0..size * size)
+        .map(|i| {
+            let (x, y) = (i % size, i / size);
+            let in_bar = |p: usize| p < bar || p >= size - bar || p.abs_diff(size / 2) < bar / 2 + 1;
+            if in_bar(x) || in_bar(y) {
+                Color::new(0.05, 0.04, 0.03)
+            } else if y < size / 2 {
+                Color::new(0.6, 0.8, 1.0)
+            } else {
+                Color::new(1.0, 0.7, 0.35)
+            }
+        })
+        .collect();
+    ImageTexture::new(size, size, texels)
+}
+
+fn create_random_scene(scene: &mut Scene, placement: Placement, aspect_ratio: f64) {
+    // Spheres of assorted sizes and colors strewn over a floor, seen from
+    // above. Uniform placement leaves gaps and clumps of overlapping
+    // spheres; Poisson-disk placement spaces them evenly yet irregularly.
+    const SPHERES: usize = 40;
+    const SEED: u64 = 11;
+    scene.set_camera(Camera::new(
+        Vec3::new(0.0, 2.5, -1.0),
+        Vec3::new(0.0, -1.0, -6.5),
+        Vec3::new(0.0, 1.0, 0.0),
+        60.0,
+        aspect_ratio,
+    ).expect("the field of view is in range"));
+    
+    let floor_material = Material::new(
+        Color::new(0.8, 0.8, 0.8),
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Plane::new(
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+    )));
+    
+    let center = Vec3::new(0.0, -1.0, -7.0);
+    let size = (8.0, 6.0);
+    let positions = match placement {
+        Placement::Poisson => scatter::on_ground(center, size, 0.8, SPHERES, SEED),
+        Placement::Uniform => scatter::uniform(size, SPHERES, SEED).into_iter()
+            .map(|(x, z)| Vec3::new(center.x + x - size.0 / 2.0, center.y, center.z + z - size.1 / 2.0))
+            .collect(),
+    };
+    let palette = [
+        Color::new(0.8, 0.2, 0.2),
+        Color::new(0.9, 0.6, 0.1),
+        Color::new(0.2, 0.6, 0.3),
+        Color::new(0.2, 0.4, 0.8),
+        Color::new(0.6, 0.3, 0.7),
+    ];
+    let mut rng = Rng::new(SEED + 1);
+    for position in positions {
+        // Under half the spacing, so spaced spheres never touch
+        let radius = 0.2 + 0.15 * rng.next_f64();
+        let color = palette[(rng.next_f64() * palette.len() as f64) as usize];
+        let material = Material::new(color, 0.1, 0.8, 0.4, 60.0, 0.0, 0.0, 1.0);
+        scene.add_object(Box::new(Sphere::new(position + Vec3::new(0.0, radius, 0.0), radius, material)));
+    }
+}
+
+// Each level multiplies the cube count by 20, so level 5 would already
+// be over three million cubes
+const MAX_MENGER_LEVEL: u32 = 4;
+
+fn create_menger_scene(scene: &mut Scene, level: u32, aspect_ratio: f64) {
+    // A Menger sponge standing on a floor, seen from above one corner
+    scene.set_camera(Camera::new(
+        Vec3::new(3.3, 2.1, 0.3),
+        Vec3::new(0.0, -0.2, -4.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        60.0,
+        aspect_ratio,
+    ).expect("the field of view is in range"));
+    
+    let floor_material = Material::new(
+        Color::new(0.8, 0.8, 0.8),
+        0.1, 0.8, 0.0, 1.0, 0.0, 0.0, 1.0
+    );
+    scene.add_object(Box::new(Plane::new(
+        Vec3::new(0.0, -1.5, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        floor_material,
+    )));
+    
+    info!(
+        "Building level {} Menger sponge: {} cubes, about {}",
+        level,
+        20usize.pow(level),
+        format_size(menger_memory(level)),
+    );
+    
+    let material = Material::new(Color::new(0.85, 0.75, 0.5), 0.15, 0.8, 0.3, 40.0, 0.0, 0.0, 1.0);
+    add_menger_cubes(scene, Vec3::new(0.0, 0.0, -4.0), 3.0, level, material);
+}
+
+// Rough cost of a Menger sponge's 20^level cubes: each cube, its box and
+// name in the scene, and the BVH over them all
+fn menger_memory(level: u32) -> usize {
+    let cubes = 20usize.pow(level);
+    let per_cube = std::mem::size_of::<Cube>() + std::mem::size_of::<Box<dyn Object>>() + std::mem::size_of::<String>() + 8;
+    cubes * per_cube + Bvh::memory_for(cubes)
+}
+
+// Splits the cube into 27 and recurses into the 20 that are not at the
+// center of a face or of the whole cube
+fn add_menger_cubes(scene: &mut Scene, center: Vec3, size: f64, level: u32, material: Material) {
+    if level == 0 {
+        scene.add_object(Box::new(Cube::new(center, size, material)));
+        return;
+    }
+    
+    let step = size / 3.0;
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let centered_axes = [x, y, z].iter().filter(|&&c| c == 0).count();
+                if centered_axes >= 2 {
+                    continue;
+                }
+                let offset = Vec3::new(x as f64, y as f64, z as f64) * step;
+                add_menger_cubes(scene, center + offset, step, level - 1, material);
+            }
+        }
+    }
+}
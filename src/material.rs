@@ -1,20 +1,50 @@
-use crate::vector::Vec3;
+use crate::image::color::Color;
+use std::f64::consts::PI;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+// Wavelengths in nanometers that thin-film interference is worked out at
+// for the red, green and blue channels
+const FILM_WAVELENGTHS: [f64; 3] = [650.0, 532.0, 450.0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Material {
-    pub color: Vec3,
+    pub color: Color,
     pub ambient: f64,
     pub diffuse: f64,
-    pub specular: f64,
+    // Per channel, so metals can tint their highlights and reflections
+    pub specular: Color,
     pub shininess: f64,
-    pub reflectivity: f64,
+    pub reflectivity: Color,
     pub transparency: f64,
     pub refractive_index: f64,
+    // Light given off by the surface itself, independent of scene lights
+    pub emission: Color,
+    // Subsurface scattering: how far light travels through the inside
+    // before dying away to 1/e, or zero for an opaque surface, and the
+    // color the light picks up on the way
+    pub scatter_distance: f64,
+    pub scatter_tint: Color,
+    // Clear coat layered over the rest, as on car paint: its strength, or
+    // zero for none, and its refractive index, which sets how much more it
+    // reflects at grazing angles than head on
+    pub coat: f64,
+    pub coat_ior: f64,
+    // Sheen, the soft glow along the silhouette of cloth such as velvet:
+    // its strength, or zero for none, and its color
+    pub sheen: f64,
+    pub sheen_color: Color,
+    // Thin film over the surface, as on soap bubbles and oil slicks: its
+    // thickness in nanometers at the top of the object, or zero for none,
+    // how much thicker it is at the bottom, and its refractive index
+    pub film_thickness: f64,
+    pub film_gradient: f64,
+    pub film_ior: f64,
 }
 
 impl Material {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        color: Vec3,
+        color: Color,
         ambient: f64,
         diffuse: f64,
         specular: f64,
@@ -27,32 +57,403 @@ impl Material {
             color,
             ambient,
             diffuse,
-            specular,
+            specular: Color::splat(specular),
             shininess,
-            reflectivity,
+            reflectivity: Color::splat(reflectivity),
             transparency,
             refractive_index,
+            emission: Color::BLACK,
+            scatter_distance: 0.0,
+            scatter_tint: Color::WHITE,
+            coat: 0.0,
+            coat_ior: 1.5,
+            sheen: 0.0,
+            sheen_color: Color::WHITE,
+            film_thickness: 0.0,
+            film_gradient: 0.0,
+            film_ior: 1.33,
         }
     }
     
-    pub fn default() -> Self {
+    pub fn reflective(color: Color, reflectivity: f64) -> Self {
         Material::new(
-            Vec3::new(0.5, 0.5, 0.5), // gray
-            0.1, 0.7, 0.2, 200.0, 0.0, 0.0, 1.0
+            color,
+            0.1, 0.3, 0.6, 200.0, reflectivity, 0.0, 1.0
         )
     }
     
-    pub fn reflective(color: Vec3, reflectivity: f64) -> Self {
-        Material::new(
+    // Named presets, tinted with the given color where that makes sense
+    pub fn preset(name: &str, color: Color) -> Option<Self> {
+        match name {
+            "default" => Some(Material { color, ..Material::default() }),
+            "matte" => Some(Material::new(color, 0.1, 0.9, 0.0, 1.0, 0.0, 0.0, 1.0)),
+            "plastic" => Some(Material::new(color, 0.1, 0.7, 0.5, 100.0, 0.05, 0.0, 1.0)),
+            "mirror" => Some(Material::reflective(color, 0.9)),
+            "metal" => Some(Material::new(color, 0.1, 0.3, 0.9, 300.0, 0.6, 0.0, 1.0)),
+            "glass" => Some(Material::transparent(color, 0.9, 1.5)),
+            "glow" => Some(Material::emissive(color)),
+            "wax" => Some(Material::translucent(color, 0.3)),
+            "car_paint" => Some(Material::coated(Material::new(color, 0.1, 0.7, 0.3, 60.0, 0.0, 0.0, 1.0), 1.0)),
+            "velvet" => Some(Material::velvet(color)),
+            _ => None,
+        }
+    }
+    
+    pub const PRESET_NAMES: [&'static str; 10] = [
+        "default", "matte", "plastic", "mirror", "metal", "glass", "glow", "wax", "car_paint", "velvet",
+    ];
+    
+    // Sets a field to a number; per-channel fields get it on every channel
+    pub fn set_field(&mut self, field: &str, value: f64) -> Result<(), String> {
+        match field {
+            "ambient" => self.ambient = value,
+            "diffuse" => self.diffuse = value,
+            "specular" | "reflectivity" => self.set_channels(field, Color::splat(value))?,
+            "shininess" => self.shininess = value,
+            "transparency" => self.transparency = value,
+            "refractive_index" | "ior" => self.refractive_index = value,
+            "scatter_distance" => self.scatter_distance = value,
+            "scatter_tint" => self.set_channels(field, Color::splat(value))?,
+            "coat" => self.coat = value,
+            "coat_ior" => self.coat_ior = value,
+            "sheen" => self.sheen = value,
+            "sheen_color" => self.set_channels(field, Color::splat(value))?,
+            "film_thickness" => self.film_thickness = value,
+            "film_gradient" => self.film_gradient = value,
+            "film_ior" => self.film_ior = value,
+            _ => return Err(format!("unknown material field '{}'", field)),
+        }
+        Ok(())
+    }
+    
+    // Sets a per-channel field, or the color or emission, to an [r, g, b]
+    // value
+    pub fn set_channels(&mut self, field: &str, value: Color) -> Result<(), String> {
+        match field {
+            "color" => self.color = value,
+            "emission" => self.emission = value,
+            "specular" => self.specular = value,
+            "reflectivity" => self.reflectivity = value,
+            "scatter_tint" => self.scatter_tint = value,
+            "sheen_color" => self.sheen_color = value,
+            "ambient" | "diffuse" | "shininess" | "transparency" | "refractive_index" | "ior" | "scatter_distance"
+            | "coat" | "coat_ior" | "sheen" | "film_thickness" | "film_gradient" | "film_ior" => {
+                return Err(format!("material field '{}' takes a single number", field));
+            }
+            _ => return Err(format!("unknown material field '{}'", field)),
+        }
+        Ok(())
+    }
+    
+    pub fn emissive(color: Color) -> Self {
+        let mut material = Material::new(
             color,
-            0.1, 0.3, 0.6, 200.0, reflectivity, 0.0, 1.0
-        )
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0
+        );
+        material.emission = color;
+        material
     }
     
-    pub fn transparent(color: Vec3, transparency: f64, refractive_index: f64) -> Self {
+    pub fn transparent(color: Color, transparency: f64, refractive_index: f64) -> Self {
         Material::new(
             color,
             0.1, 0.1, 0.8, 200.0, 0.1, transparency, refractive_index
         )
     }
+    
+    // `base` under a clear coat of the given strength
+    pub fn coated(base: Material, coat: f64) -> Self {
+        Material { coat, ..base }
+    }
+    
+    // Share of light the clear coat reflects when seen at `cosine` to its
+    // normal, by Schlick's approximation of the Fresnel equations
+    pub fn coat_reflectance(&self, cosine: f64) -> f64 {
+        if self.coat <= 0.0 {
+            return 0.0;
+        }
+        let r0 = ((self.coat_ior - 1.0) / (self.coat_ior + 1.0)).powi(2);
+        self.coat * (r0 + (1.0 - r0) * (1.0 - cosine.clamp(0.0, 1.0)).powi(5))
+    }
+    
+    /// Share of red, green and blue light the thin film reflects when seen
+    /// at `cosine` to its normal, at a point `height` up the object: the y
+    /// of its outward normal, from -1 at the bottom to 1 at the top. Light
+    /// reflected off the top of the film and off the surface under it
+    /// (with the material's `refractive_index`) interferes, so the film
+    /// reflects some wavelengths far more than others depending on its
+    /// thickness and the angle, by the Airy formula for one layer averaged
+    /// over both polarizations. Zero without a film.
+    ///
+    /// ```
+    /// use rt::material::Material;
+    ///
+    /// let bubble = Material { film_thickness: 100.0, ..Material::transparent(Material::default().color, 1.0, 1.0) };
+    /// assert_eq!(Material { film_thickness: 0.0, ..bubble }.film_reflectance(1.0, 1.0).max_component(), 0.0);
+    ///
+    /// // 100 nm of soap is a quarter of green's wavelength inside the film,
+    /// // where the two reflections add up best: 4 r² / (1 + r²)² with
+    /// // r = 0.33 / 2.33
+    /// let head_on = bubble.film_reflectance(1.0, 1.0);
+    /// let r2 = (0.33f64 / 2.33).powi(2);
+    /// assert!((head_on.g - 4.0 * r2 / (1.0 + r2).powi(2)).abs() < 1e-9);
+    /// assert!(head_on.g > head_on.r && head_on.g > head_on.b);
+    ///
+    /// // Seen at a slant the path through the film is shorter, so the
+    /// // strongest color moves toward blue
+    /// let slanted = bubble.film_reflectance(0.5, 1.0);
+    /// assert!(slanted.b > slanted.g && slanted.g > slanted.r);
+    ///
+    /// // Lower down the film is thicker and reflects other colors
+    /// let drained = Material { film_gradient: 300.0, ..bubble };
+    /// assert_eq!(drained.film_reflectance(1.0, 1.0), head_on);
+    /// assert_ne!(drained.film_reflectance(1.0, -1.0), head_on);
+    /// ```
+    pub fn film_reflectance(&self, cosine: f64, height: f64) -> Color {
+        if self.film_thickness <= 0.0 {
+            return Color::BLACK;
+        }
+        let thickness = self.film_thickness + self.film_gradient * (1.0 - height.clamp(-1.0, 1.0)) / 2.0;
+        let (n1, n2, n3) = (1.0, self.film_ior, self.refractive_index);
+        let cos1 = cosine.clamp(0.0, 1.0);
+        let sin1 = (1.0 - cos1 * cos1).sqrt();
+        let refracted_cos = |n: f64| (1.0 - (sin1 * n1 / n).powi(2)).max(0.0).sqrt();
+        let (cos2, cos3) = (refracted_cos(n2), refracted_cos(n3));
+        
+        // Amplitude reflection coefficients at each interface, for light
+        // polarized across and along the plane of incidence
+        let s = |na: f64, ca: f64, nb: f64, cb: f64| (na * ca - nb * cb) / (na * ca + nb * cb);
+        let p = |na: f64, ca: f64, nb: f64, cb: f64| (nb * ca - na * cb) / (nb * ca + na * cb);
+        let polarizations = [(s(n1, cos1, n2, cos2), s(n2, cos2, n3, cos3)), (p(n1, cos1, n2, cos2), p(n2, cos2, n3, cos3))];
+        
+        let [red, green, blue] = FILM_WAVELENGTHS.map(|wavelength| {
+            let phase = (4.0 * PI * n2 * thickness * cos2 / wavelength).cos();
+            polarizations.iter().map(|&(r12, r23)| {
+                let cross = 2.0 * r12 * r23 * phase;
+                let denominator = 1.0 + r12 * r12 * r23 * r23 + cross;
+                // Only zero at grazing angles, where everything is reflected
+                if denominator > 0.0 { (r12 * r12 + r23 * r23 + cross) / denominator } else { 1.0 }
+            }).sum::<f64>() / 2.0
+        });
+        Color::new(red, green, blue)
+    }
+    
+    /// Matte cloth with a sheen, which brightens the surface where it turns
+    /// away from the viewer, so the silhouette glows where light reaches it.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::image::color::Color;
+    /// use rt::image::Image;
+    /// use rt::light::Light;
+    /// use rt::material::Material;
+    /// use rt::objects::Sphere;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// let gray = Color::splat(0.5);
+    /// let render = |material: Material| {
+    ///     let mut scene = Scene::new();
+    ///     scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -3.0), 1.0, material)));
+    ///     scene.add_light(Light::new(Vec3::new(0.0, 0.0, 0.0), Color::WHITE, 1.0));
+    ///     scene.set_camera(Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 45.0, 1.0).unwrap());
+    ///     let mut image = Image::new(33, 33);
+    ///     scene.render_into(&mut image, &RenderSettings::default());
+    ///     image
+    /// };
+    /// let velvet = render(Material::velvet(gray));
+    /// let plain = render(Material { sheen: 0.0, ..Material::velvet(gray) });
+    ///
+    /// // Head on the sheen adds nothing; toward the silhouette it outshines
+    /// // the center
+    /// let background = velvet.get_pixel(0, 16);
+    /// let edge = (0..16).find(|&x| velvet.get_pixel(x, 16) != background).unwrap();
+    /// assert_eq!(velvet.get_pixel(16, 16), plain.get_pixel(16, 16));
+    /// assert!(velvet.get_pixel(edge, 16).r > plain.get_pixel(edge, 16).r + 0.1);
+    /// assert!(velvet.get_pixel(edge, 16).r > velvet.get_pixel(16, 16).r);
+    /// ```
+    pub fn velvet(color: Color) -> Self {
+        let mut material = Material::new(
+            color,
+            0.1, 0.6, 0.0, 1.0, 0.0, 0.0, 1.0
+        );
+        material.sheen = 1.0;
+        material.sheen_color = color.lerp(&Color::WHITE, 0.5);
+        material
+    }
+    
+    /// A material described the way PBR workflows do, by base color,
+    /// metalness and roughness (each 0 to 1, clamped), mapped onto the
+    /// Phong parameters:
+    ///
+    /// - diffuse is 0.9 for dielectrics and fades out with metalness, but
+    ///   rough metals scatter their color diffusely too: 0.9 (1 - m + m r)
+    /// - the highlight and mirror reflection are white for dielectrics,
+    ///   at 0.5 and 0.04, and the base color for metals, blended by
+    ///   metalness
+    /// - shininess is 2 / r⁴ - 2, the Blinn-Phong exponent matching a
+    ///   microfacet roughness of r², kept within 1 to 1000
+    /// - there is no glossy blur, so instead highlights dim by 1 - r and
+    ///   mirror reflection by (1 - r)²
+    ///
+    /// Scene files keep looking the same only as long as this does, so the
+    /// mapping is pinned here.
+    ///
+    /// ```
+    /// use rt::image::color::Color;
+    /// use rt::material::Material;
+    /// use rt::vector::Vec3;
+    ///
+    /// let close = |a: Color, b: Color| Vec3::from(a - b).length() < 1e-12;
+    /// let rust = Color::new(0.8, 0.3, 0.1);
+    ///
+    /// let plastic = Material::from_pbr(rust, 0.0, 0.5);
+    /// assert_eq!((plastic.ambient, plastic.diffuse, plastic.shininess), (0.1, 0.9, 30.0));
+    /// assert!(close(plastic.specular, Color::splat(0.25)));
+    /// assert!(close(plastic.reflectivity, Color::splat(0.01)));
+    ///
+    /// let polished = Material::from_pbr(rust, 1.0, 0.0);
+    /// assert_eq!((polished.diffuse, polished.shininess), (0.0, 1000.0));
+    /// assert!(close(polished.specular, rust) && close(polished.reflectivity, rust));
+    ///
+    /// let brushed = Material::from_pbr(rust, 1.0, 0.5);
+    /// assert_eq!((brushed.diffuse, brushed.shininess), (0.45, 30.0));
+    /// assert!(close(brushed.specular, rust * 0.5) && close(brushed.reflectivity, rust * 0.25));
+    ///
+    /// let chalk = Material::from_pbr(rust, 0.0, 1.0);
+    /// assert_eq!((chalk.diffuse, chalk.shininess), (0.9, 1.0));
+    /// assert_eq!((chalk.specular, chalk.reflectivity), (Color::BLACK, Color::BLACK));
+    /// assert_eq!(chalk.color, rust);
+    ///
+    /// assert_eq!(Material::from_pbr(rust, 2.0, -1.0), Material::from_pbr(rust, 1.0, 0.0));
+    /// ```
+    pub fn from_pbr(base_color: Color, metalness: f64, roughness: f64) -> Self {
+        let metalness = metalness.clamp(0.0, 1.0);
+        let roughness = roughness.clamp(0.0, 1.0);
+        let smoothness = 1.0 - roughness;
+        let shininess = (2.0 / roughness.powi(4) - 2.0).clamp(1.0, 1000.0);
+        let mut material = Material::new(
+            base_color,
+            0.1, 0.9 * (1.0 - metalness + metalness * roughness), 0.0, shininess, 0.0, 0.0, 1.0
+        );
+        material.specular = Color::splat(0.5).lerp(&base_color, metalness) * smoothness;
+        material.reflectivity = Color::splat(0.04).lerp(&base_color, metalness) * (smoothness * smoothness);
+        material
+    }
+    
+    // Soft, waxy surface that lets light through its thin parts
+    pub fn translucent(color: Color, scatter_distance: f64) -> Self {
+        let mut material = Material::new(
+            color,
+            0.1, 0.6, 0.2, 30.0, 0.0, 0.0, 1.0
+        );
+        material.scatter_distance = scatter_distance;
+        material
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new(
+            Color::new(0.5, 0.5, 0.5), // gray
+            0.1, 0.7, 0.2, 200.0, 0.0, 0.0, 1.0
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaterialEdit {
+    Preset(String),
+    Field(String, f64),
+    Color(String, Color),
+}
+
+// A change to one named object's material, written on the command line as
+// `name=glass` or `name=reflectivity:0.9,shininess:50` (edits apply left to
+// right, so `name=glass,ior:1.33` also works). Colors are hex or named, as
+// in `name=color:#cc3344` or `name=plastic,color:slategray`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialOverride {
+    pub object: String,
+    pub edits: Vec<MaterialEdit>,
+}
+
+impl MaterialOverride {
+    pub fn apply(&self, material: &mut Material) -> Result<(), String> {
+        for edit in &self.edits {
+            match edit {
+                MaterialEdit::Preset(name) => {
+                    *material = Material::preset(name, material.color).ok_or_else(|| {
+                        format!("unknown material preset '{}' (presets: {})", name, Material::PRESET_NAMES.join(", "))
+                    })?;
+                }
+                MaterialEdit::Field(field, value) => material.set_field(field, *value)?,
+                MaterialEdit::Color(field, color) => material.set_channels(field, *color)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MaterialOverride {
+    type Err = String;
+    
+    /// ```
+    /// use rt::image::color::Color;
+    /// use rt::material::{Material, MaterialEdit, MaterialOverride};
+    ///
+    /// let glass: MaterialOverride = "ball = glass, ior:1.33, color:#ff0000".parse().unwrap();
+    /// assert_eq!(glass, MaterialOverride {
+    ///     object: "ball".to_string(),
+    ///     edits: vec![
+    ///         MaterialEdit::Preset("glass".to_string()),
+    ///         MaterialEdit::Field("ior".to_string(), 1.33),
+    ///         MaterialEdit::Color("color".to_string(), Color::new(1.0, 0.0, 0.0)),
+    ///     ],
+    /// });
+    /// let mut material = Material::default();
+    /// glass.apply(&mut material).unwrap();
+    /// assert_eq!((material.refractive_index, material.color), (1.33, Color::new(1.0, 0.0, 0.0)));
+    ///
+    /// // Malformed specs and bad numbers are caught while parsing
+    /// assert!("glass".parse::<MaterialOverride>().is_err());
+    /// assert!("=glass".parse::<MaterialOverride>().is_err());
+    /// assert!("ball=glass,,ior:1.5".parse::<MaterialOverride>().is_err());
+    /// assert!("ball=shininess:1.5.0".parse::<MaterialOverride>().is_err());
+    ///
+    /// // Unknown fields and presets only when applied to a material
+    /// let unknown = "ball=glossiness:0.5".parse::<MaterialOverride>().unwrap();
+    /// assert_eq!(unknown.apply(&mut material), Err("unknown material field 'glossiness'".to_string()));
+    /// let unknown = "ball=chrome".parse::<MaterialOverride>().unwrap();
+    /// assert!(unknown.apply(&mut material).unwrap_err().starts_with("unknown material preset 'chrome'"));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (object, spec) = s.split_once('=')
+            .ok_or_else(|| format!("expected <object>=<preset or key:value list> but got '{}'", s))?;
+        if object.trim().is_empty() {
+            return Err(format!("missing object name in '{}'", s));
+        }
+        
+        let mut edits = Vec::new();
+        for item in spec.split(',').map(str::trim) {
+            match item.split_once(':') {
+                Some((field, value)) => {
+                    let (field, value) = (field.trim().to_string(), value.trim());
+                    let edit = match value.parse::<f64>() {
+                        Ok(number) => MaterialEdit::Field(field, number),
+                        Err(_) => {
+                            let color = Color::parse(value)
+                                .map_err(|_| format!("invalid value '{}' for '{}' (expected a number or a color)", value, field))?;
+                            MaterialEdit::Color(field, color)
+                        }
+                    };
+                    edits.push(edit);
+                }
+                None if item.is_empty() => return Err(format!("empty material edit in '{}'", s)),
+                None => edits.push(MaterialEdit::Preset(item.to_string())),
+            }
+        }
+        
+        Ok(MaterialOverride { object: object.trim().to_string(), edits })
+    }
 }
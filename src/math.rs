@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+
+// Polynomial root finding for ray-surface intersections. Roots come back
+// in increasing order, and only real ones are returned.
+
+/// Roots of `a x^2 + b x + c = 0`, or None when there are no real ones. A
+/// zero `a` leaves the linear equation, whose single root is returned twice.
+///
+/// The textbook formula loses the root nearer zero to cancellation when
+/// `b^2` is much larger than `4ac`; this finds the larger root first and
+/// gets the other from their product.
+///
+/// ```
+/// use rt::math::solve_quadratic;
+///
+/// // x^2 - 1e8 x + 1 = 0 has roots 1e8 - 1e-8 and 1e-8 + 1e-24
+/// let (small, large) = solve_quadratic(1.0, -1.0e8, 1.0).unwrap();
+/// assert!((small - 1.000_000_000_000_000_1e-8).abs() / 1e-8 < 1e-15);
+/// assert!((large - (1.0e8 - 1.0e-8)).abs() / 1e8 < 1e-15);
+/// // The textbook formula gets the small root wrong by a quarter
+/// let naive = (1.0e8 - (1.0e16_f64 - 4.0).sqrt()) / 2.0;
+/// assert!((naive - 1.0e-8).abs() / 1e-8 > 0.2);
+///
+/// assert_eq!(solve_quadratic(1.0, 0.0, 1.0), None);
+/// assert_eq!(solve_quadratic(0.0, 2.0, -4.0), Some((2.0, 2.0)));
+/// assert_eq!(solve_quadratic(2.0, -8.0, 8.0), Some((2.0, 2.0)));
+/// ```
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    quadratic_roots(a, b, c, b * b - 4.0 * a * c)
+}
+
+// Like `solve_quadratic`, for callers that can work out the discriminant
+// `b^2 - 4ac` more accurately than the coefficients allow, as sphere
+// intersection can from the ray's closest approach to the center
+pub fn quadratic_roots(a: f64, b: f64, c: f64, discriminant: f64) -> Option<(f64, f64)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        let root = -c / b;
+        return Some((root, root));
+    }
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    if q == 0.0 {
+        // Only when b and c are both zero
+        return Some((0.0, 0.0));
+    }
+    let (first, second) = (q / a, c / q);
+    Some((first.min(second), first.max(second)))
+}
+
+// Real roots of `a x^3 + b x^2 + c x + d = 0` with `a` non-zero, each
+// polished with a Newton step
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    let (b, c, d) = (b / a, c / a, d / a);
+    let q = (b * b - 3.0 * c) / 9.0;
+    let r = (2.0 * b * b * b - 9.0 * b * c + 27.0 * d) / 54.0;
+    let shift = b / 3.0;
+    
+    let mut roots = if r * r < q * q * q {
+        // Three real roots
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let scale = -2.0 * q.sqrt();
+        vec![
+            scale * (theta / 3.0).cos() - shift,
+            scale * ((theta + 2.0 * PI) / 3.0).cos() - shift,
+            scale * ((theta - 2.0 * PI) / 3.0).cos() - shift,
+        ]
+    } else {
+        let big = -(r.abs() + (r * r - q * q * q).sqrt()).cbrt().copysign(r);
+        let small = if big == 0.0 { 0.0 } else { q / big };
+        vec![big + small - shift]
+    };
+    
+    let cubic = |x: f64| ((x + b) * x + c) * x + d;
+    let slope = |x: f64| (3.0 * x + 2.0 * b) * x + c;
+    for root in &mut roots {
+        *root = newton_step(*root, cubic, slope);
+    }
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Real roots of `a x^4 + b x^3 + c x^2 + d x + e = 0` with `a` non-zero, as
+/// needed for tori. Ferrari's method splits the quartic into two quadratics
+/// using the largest root of a resolvent cubic; each root is then polished
+/// with Newton steps on the original quartic.
+///
+/// ```
+/// use rt::math::solve_quartic;
+///
+/// // (x - 0.001)(x - 1)(x - 10)(x - 1000)
+/// let roots = solve_quartic(1.0, -1011.001, 11011.011, -10011.01, 10.0);
+/// let expected = [0.001, 1.0, 10.0, 1000.0];
+/// assert_eq!(roots.len(), 4);
+/// for (root, expected) in roots.iter().zip(expected) {
+///     assert!((root - expected).abs() / expected < 1e-9, "{} is not {}", root, expected);
+/// }
+///
+/// // (x^2 + 1)(x - 2)(x + 3): two real roots
+/// let roots = solve_quartic(1.0, 1.0, -5.0, 1.0, -6.0);
+/// assert_eq!(roots.len(), 2);
+/// assert!((roots[0] + 3.0).abs() < 1e-12 && (roots[1] - 2.0).abs() < 1e-12);
+///
+/// // x^4 - 5x^2 + 4, a biquadratic
+/// assert_eq!(solve_quartic(1.0, 0.0, -5.0, 0.0, 4.0), [-2.0, -1.0, 1.0, 2.0]);
+/// assert!(solve_quartic(1.0, 0.0, 1.0, 0.0, 1.0).is_empty());
+/// ```
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    // Substituting x = y - b/4 leaves y^4 + p y^2 + q y + r
+    let shift = b / 4.0;
+    let b2 = b * b;
+    let p = c - 3.0 * b2 / 8.0;
+    let q = d - b * c / 2.0 + b2 * b / 8.0;
+    let r = e - b * d / 4.0 + b2 * c / 16.0 - 3.0 * b2 * b2 / 256.0;
+    
+    let mut depressed = Vec::with_capacity(4);
+    if q.abs() < 1e-12 * (1.0 + p.abs() + r.abs()) {
+        // Biquadratic: a quadratic in y^2
+        if let Some((low, high)) = solve_quadratic(1.0, p, r) {
+            for z in [low, high] {
+                if z >= 0.0 {
+                    depressed.push(-z.sqrt());
+                    depressed.push(z.sqrt());
+                }
+            }
+        }
+    } else {
+        // Adding m to the square y^2 + p/2 makes the remainder a perfect
+        // square when 8m^3 + 8p m^2 + (2p^2 - 8r) m - q^2 = 0, whose largest
+        // root is positive since the cubic is -q^2 at zero
+        let m = solve_cubic(8.0, 8.0 * p, 2.0 * p * p - 8.0 * r, -q * q).last().copied().unwrap_or(0.0);
+        if m > 0.0 {
+            let s = (2.0 * m).sqrt();
+            for (sign, offset) in [(1.0, q / (2.0 * s)), (-1.0, -q / (2.0 * s))] {
+                if let Some((low, high)) = solve_quadratic(1.0, -sign * s, p / 2.0 + m + offset) {
+                    depressed.push(low);
+                    if high != low {
+                        depressed.push(high);
+                    }
+                }
+            }
+        }
+    }
+    
+    let quartic = |x: f64| (((x + b) * x + c) * x + d) * x + e;
+    let slope = |x: f64| ((4.0 * x + 3.0 * b) * x + 2.0 * c) * x + d;
+    let mut roots: Vec<f64> = depressed.into_iter()
+        .map(|y| {
+            let x = y - shift;
+            newton_step(newton_step(x, quartic, slope), quartic, slope)
+        })
+        .collect();
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+// One Newton step, kept only when it makes the polynomial smaller, so a
+// root at a flat spot is not thrown away
+fn newton_step(x: f64, f: impl Fn(f64) -> f64, slope: impl Fn(f64) -> f64) -> f64 {
+    let (value, slope) = (f(x), slope(x));
+    if slope == 0.0 {
+        return x;
+    }
+    let next = x - value / slope;
+    if next.is_finite() && f(next).abs() <= value.abs() {
+        next
+    } else {
+        x
+    }
+}
@@ -0,0 +1,226 @@
+use crate::objects::Object;
+use crate::ray::HitRecord;
+use std::mem;
+
+// Keeps track of what a refracted ray is travelling through, so that light
+// leaving water inside a glass bends as it goes back into the glass rather
+// than as if it went out into the air.
+
+/// The transparent objects a ray is inside, innermost last, each with its
+/// refractive index. Outside all of them is vacuum.
+///
+/// ```
+/// use rt::medium::MediumStack;
+///
+/// // Into a glass ball, then a drop of water inside it
+/// let (glass, water) = ((1, 0), (2, 0));
+/// let mut media = MediumStack::new();
+/// assert_eq!(media.indices(glass, 1.5, true), (1.0, 1.5));
+/// media.cross(glass, 1.5, true);
+/// assert_eq!(media.indices(water, 1.33, true), (1.5, 1.33));
+/// let into_water = media.cross(water, 1.33, true);
+/// assert_eq!(media.current(), 1.33);
+///
+/// // Out of the water goes back into the glass, and out of that into air
+/// assert_eq!(media.indices(water, 1.33, false), (1.33, 1.5));
+/// let out_of_water = media.cross(water, 1.33, false);
+/// assert_eq!(media.indices(glass, 1.5, false), (1.5, 1.0));
+///
+/// // Undoing crossings in reverse puts the stack back
+/// media.undo(out_of_water);
+/// media.undo(into_water);
+/// assert_eq!(media.current(), 1.5);
+///
+/// // Where the two overlap the water wins, since it was entered last, so
+/// // leaving the glass there bends nothing
+/// media.cross(water, 1.33, true);
+/// assert_eq!(media.indices(glass, 1.5, false), (1.33, 1.33));
+/// ```
+///
+/// A glass ball holding a smaller ball of the same glass looks like the
+/// ball alone, since nothing bends where the two meet:
+///
+/// ```
+/// use rt::camera::Camera;
+/// use rt::image::color::Color;
+/// use rt::image::Image;
+/// use rt::material::Material;
+/// use rt::objects::{CheckerPlane, Sphere};
+/// use rt::scene::{RenderSettings, Scene};
+/// use rt::vector::Vec3;
+///
+/// let render = |inner_index: Option<f64>| {
+///     let mut scene = Scene::new();
+///     let m = Material::default();
+///     let dark = Material { color: Color::BLACK, ..m };
+///     scene.add_object(Box::new(CheckerPlane::new(Vec3::new(0.0, 0.0, -6.0), Vec3::new(0.0, 0.0, 1.0), m, dark, 0.25)));
+///     let glass = |index| Material { transparency: 1.0, ..Material::transparent(Color::WHITE, 1.0, index) };
+///     scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -3.0), 1.0, glass(1.5))));
+///     if let Some(index) = inner_index {
+///         scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -3.0), 0.5, glass(index))));
+///     }
+///     scene.set_camera(Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 1.0).unwrap());
+///     let mut image = Image::new(32, 32);
+///     scene.render_into(&mut image, &RenderSettings::default());
+///     image.pixels
+/// };
+/// let differing = |a: &[Color], b: &[Color]| a.iter().zip(b).filter(|(a, b)| Vec3::from(**a - **b).length() > 0.01).count();
+///
+/// let alone = render(None);
+/// assert_eq!(differing(&alone, &render(Some(1.5))), 0);
+/// // Water inside the glass does bend the light
+/// assert!(differing(&alone, &render(Some(1.33))) > 50);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MediumStack {
+    entered: Entries,
+}
+
+// Objects a ray can be inside before the stack allocates; each needs a
+// refraction to enter, so only very deep ray trees go past it
+const INLINE_MEDIA: usize = 16;
+
+// The entries of a `MediumStack` in order, the first `INLINE_MEDIA` held
+// inline so that a new stack for every pixel costs no allocation
+#[derive(Debug, Clone, Default)]
+struct Entries {
+    inline: [(Surface, f64); INLINE_MEDIA],
+    len: usize,
+    deeper: Vec<(Surface, f64)>,
+}
+
+impl Entries {
+    fn count(&self) -> usize {
+        self.len + self.deeper.len()
+    }
+    
+    fn get(&self, i: usize) -> (Surface, f64) {
+        if i < self.len { self.inline[i] } else { self.deeper[i - self.len] }
+    }
+    
+    fn push(&mut self, entry: (Surface, f64)) {
+        if self.len < INLINE_MEDIA {
+            self.inline[self.len] = entry;
+            self.len += 1;
+        } else {
+            self.deeper.push(entry);
+        }
+    }
+    
+    fn pop(&mut self) -> Option<(Surface, f64)> {
+        if let Some(entry) = self.deeper.pop() {
+            return Some(entry);
+        }
+        self.len = self.len.checked_sub(1)?;
+        Some(self.inline[self.len])
+    }
+    
+    fn remove(&mut self, i: usize) -> (Surface, f64) {
+        if self.deeper.is_empty() {
+            let entry = self.inline[i];
+            self.inline.copy_within(i + 1..self.len, i);
+            self.len -= 1;
+            return entry;
+        }
+        let mut all = self.spilled();
+        let entry = all.remove(i);
+        self.refill(all);
+        entry
+    }
+    
+    fn insert(&mut self, i: usize, entry: (Surface, f64)) {
+        if self.len < INLINE_MEDIA {
+            self.inline.copy_within(i..self.len, i + 1);
+            self.inline[i] = entry;
+            self.len += 1;
+            return;
+        }
+        let mut all = self.spilled();
+        all.insert(i, entry);
+        self.refill(all);
+    }
+    
+    // Every entry, moved out into a vector in order
+    fn spilled(&mut self) -> Vec<(Surface, f64)> {
+        let mut all = mem::take(&mut self.deeper);
+        all.splice(0..0, self.inline[..self.len].iter().copied());
+        self.len = 0;
+        all
+    }
+    
+    fn refill(&mut self, all: Vec<(Surface, f64)>) {
+        for entry in all {
+            self.push(entry);
+        }
+    }
+}
+
+// What `MediumStack::cross` changed, for putting it back
+#[derive(Debug, Clone, Copy)]
+pub enum Crossing {
+    Entered,
+    Left(usize, (Surface, f64)),
+    // Left an object the ray was never inside, as when the camera starts
+    // inside one
+    Unchanged,
+}
+
+impl MediumStack {
+    pub fn new() -> Self {
+        MediumStack::default()
+    }
+    
+    // Refractive index where the ray is now
+    pub fn current(&self) -> f64 {
+        self.entered.count().checked_sub(1).map_or(1.0, |last| self.entered.get(last).1)
+    }
+    
+    // Refractive indices on the near and far side of a surface of `object`,
+    // which the ray is entering or leaving
+    pub fn indices(&self, object: Surface, refractive_index: f64, entering: bool) -> (f64, f64) {
+        if entering {
+            return (self.current(), refractive_index);
+        }
+        let Some(left) = self.innermost(object) else {
+            return (refractive_index, self.current());
+        };
+        let beyond = (0..self.entered.count()).rev()
+            .find(|&i| i != left)
+            .map_or(1.0, |i| self.entered.get(i).1);
+        (self.current(), beyond)
+    }
+    
+    pub fn cross(&mut self, object: Surface, refractive_index: f64, entering: bool) -> Crossing {
+        if entering {
+            self.entered.push((object, refractive_index));
+            return Crossing::Entered;
+        }
+        match self.innermost(object) {
+            Some(i) => Crossing::Left(i, self.entered.remove(i)),
+            None => Crossing::Unchanged,
+        }
+    }
+    
+    pub fn undo(&mut self, crossing: Crossing) {
+        match crossing {
+            Crossing::Entered => {
+                self.entered.pop();
+            }
+            Crossing::Left(i, entry) => self.entered.insert(i, entry),
+            Crossing::Unchanged => {}
+        }
+    }
+    
+    fn innermost(&self, object: Surface) -> Option<usize> {
+        (0..self.entered.count()).rev().find(|&i| self.entered.get(i).0 == object)
+    }
+}
+
+// Which closed surface a ray crossed: the object, told apart from others by
+// where it lives, which stays put for the length of a render, and the part
+// of it that was hit, since a group can hold several transparent objects
+pub type Surface = (usize, u32);
+
+pub fn surface(object: &dyn Object, hit_record: &HitRecord) -> Surface {
+    (object as *const dyn Object as *const () as usize, hit_record.part)
+}
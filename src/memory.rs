@@ -0,0 +1,184 @@
+use crate::bvh::Bvh;
+use crate::objects::Object;
+use crate::scene::Scene;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::mem;
+
+// Objects named when a scene goes over its budget
+const LISTED: usize = 5;
+
+const UNITS: [(&str, f64); 9] = [
+    ("b", 1.0),
+    ("kb", 1e3),
+    ("mb", 1e6),
+    ("gb", 1e9),
+    ("tb", 1e12),
+    ("kib", 1024.0),
+    ("mib", 1048576.0),
+    ("gib", 1073741824.0),
+    ("tib", 1099511627776.0),
+];
+
+/// A number of bytes as written on the command line: a plain count, or a
+/// number with a decimal (KB, MB, GB, TB) or binary (KiB, MiB, GiB, TiB)
+/// unit in any case.
+///
+/// ```
+/// use rt::memory::parse_size;
+///
+/// assert_eq!(parse_size("4GiB"), Ok(4 << 30));
+/// assert_eq!(parse_size("1.5 mb"), Ok(1_500_000));
+/// assert_eq!(parse_size("512KiB"), Ok(512 << 10));
+/// assert_eq!(parse_size("1000"), Ok(1000));
+/// assert!(parse_size("4 apples").is_err());
+/// assert!(parse_size("-1GB").is_err());
+/// ```
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let split = lower.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+    let scale = match unit {
+        "" => 1.0,
+        unit => match UNITS.iter().find(|(name, _)| *name == unit) {
+            Some(&(_, scale)) => scale,
+            None => return Err(format!("unknown unit in '{}' (expected B, KB, MB, GB, TB, KiB, MiB, GiB or TiB)", s)),
+        },
+    };
+    match number.trim().parse::<f64>() {
+        Ok(number) if number >= 0.0 && (number * scale) < usize::MAX as f64 => Ok((number * scale).round() as usize),
+        _ => Err(format!("expected a size such as 4GiB or 500MB, got '{}'", s)),
+    }
+}
+
+// Bytes in binary units with one decimal, such as "1.5 GiB"
+pub fn format_size(bytes: usize) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB", "GiB"] {
+        if size < 1024.0 {
+            return if unit == "B" { format!("{} B", bytes) } else { format!("{:.1} {}", size, unit) };
+        }
+        size /= 1024.0;
+    }
+    format!("{:.1} TiB", size)
+}
+
+// Roughly how much memory a scene's objects take up, for reports and for
+// refusing scenes too big to render
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryEstimate {
+    // Bytes for the whole scene, counting geometry and textures that
+    // several objects share once
+    pub total: usize,
+    // Each object's estimate, with everything it uses, largest first
+    pub objects: Vec<(String, usize)>,
+}
+
+impl MemoryEstimate {
+    /// Adds up the estimates of the scene's objects. They count what the
+    /// objects hold on the heap, so a mesh's estimate comes close to what
+    /// loading it allocates:
+    ///
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::memory::MemoryEstimate;
+    /// use rt::mesh::Mesh;
+    /// use rt::scene::Scene;
+    /// use std::alloc::{GlobalAlloc, Layout, System};
+    /// use std::fmt::Write;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// // Counts the bytes allocated and not yet freed
+    /// struct Counting;
+    /// static LIVE: AtomicUsize = AtomicUsize::new(0);
+    /// unsafe impl GlobalAlloc for Counting {
+    ///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ///         LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+    ///         System.alloc(layout)
+    ///     }
+    ///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ///         LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    ///         System.dealloc(ptr, layout)
+    ///     }
+    /// }
+    /// #[global_allocator]
+    /// static ALLOCATOR: Counting = Counting;
+    ///
+    /// fn main() {
+    ///     // A 200 x 200 grid of quads, split into 80,000 triangles
+    ///     let n = 200;
+    ///     let mut obj = String::new();
+    ///     for y in 0..=n {
+    ///         for x in 0..=n {
+    ///             writeln!(obj, "v {} {} {}", x, y, (x * y % 7) as f64 * 0.1).unwrap();
+    ///         }
+    ///     }
+    ///     let index = |x: usize, y: usize| y * (n + 1) + x + 1;
+    ///     for y in 0..n {
+    ///         for x in 0..n {
+    ///             writeln!(obj, "f {} {} {}", index(x, y), index(x + 1, y), index(x + 1, y + 1)).unwrap();
+    ///             writeln!(obj, "f {} {} {}", index(x, y), index(x + 1, y + 1), index(x, y + 1)).unwrap();
+    ///         }
+    ///     }
+    ///
+    ///     let before = LIVE.load(Ordering::Relaxed);
+    ///     let mesh = Mesh::parse_obj(&obj, Material::default()).unwrap();
+    ///     let allocated = (LIVE.load(Ordering::Relaxed) - before) as f64;
+    ///     assert_eq!(mesh.triangle_count(), 80_000);
+    ///
+    ///     let mut scene = Scene::new();
+    ///     scene.add_object(Box::new(mesh.clone()));
+    ///     let single = MemoryEstimate::new(&scene);
+    ///     let ratio = single.total as f64 / allocated;
+    ///     assert!(ratio > 0.8 && ratio < 1.25, "estimated {} bytes for {} allocated", single.total, allocated);
+    ///
+    ///     // A second copy shares the geometry, so it adds next to nothing to
+    ///     // the total, though each copy is listed with all it uses
+    ///     scene.add_object(Box::new(mesh));
+    ///     let twice = MemoryEstimate::new(&scene);
+    ///     assert!(twice.total - single.total < 1000);
+    ///     assert_eq!(twice.objects.len(), 2);
+    ///     assert_eq!(twice.objects[0].1, twice.objects[1].1);
+    ///
+    ///     assert!(twice.check(twice.total).is_ok());
+    ///     let message = twice.check(single.total / 2).unwrap_err();
+    ///     assert!(message.contains("over the memory budget") && message.contains("mesh1"), "{}", message);
+    /// }
+    /// ```
+    pub fn new(scene: &Scene) -> Self {
+        // The hierarchy the renderer builds over the objects, then each one
+        // with its place and name in the scene
+        let mut total = Bvh::memory_for(scene.objects().len());
+        let mut shared = HashMap::new();
+        let mut objects = Vec::with_capacity(scene.objects().len());
+        for (index, object) in scene.objects().iter().enumerate() {
+            let name = scene.object_name(index);
+            let estimate = object.memory_estimate() + mem::size_of::<Box<dyn Object>>() + mem::size_of::<String>() + name.len();
+            total += estimate;
+            for (address, bytes) in object.shared_memory() {
+                if shared.insert(address, bytes).is_some() {
+                    total -= bytes;
+                }
+            }
+            objects.push((name.to_string(), estimate));
+        }
+        objects.sort_by_key(|(_, bytes)| Reverse(*bytes));
+        MemoryEstimate { total, objects }
+    }
+    
+    // An error naming the largest objects if the scene needs more than
+    // `budget` bytes
+    pub fn check(&self, budget: usize) -> Result<(), String> {
+        if self.total <= budget {
+            return Ok(());
+        }
+        let largest: Vec<String> = self.objects.iter()
+            .take(LISTED)
+            .map(|(name, bytes)| format!("{} ({})", name, format_size(*bytes)))
+            .collect();
+        Err(format!(
+            "the scene needs about {}, over the memory budget of {}; largest objects: {}",
+            format_size(self.total), format_size(budget), largest.join(", "),
+        ))
+    }
+}
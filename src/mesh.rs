@@ -0,0 +1,679 @@
+use crate::vector::Vec3;
+use crate::ray::{Ray, HitRecord};
+use crate::material::Material;
+use crate::objects::Object;
+use crate::bvh::{Aabb, Bvh};
+use crate::binary::{self, Reader};
+use rayon::prelude::*;
+use std::any::Any;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+// Binary mesh caches written by `rt bake` start with this, followed by the
+// format version. The rest of the header (source hash and source path) has
+// the same layout in every version, so an outdated cache can still name the
+// file to rebuild from.
+const CACHE_MAGIC: &[u8; 8] = b"RTCACHE\0";
+const CACHE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy)]
+struct Triangle {
+    // Indices into `Mesh::positions`
+    vertices: [u32; 3],
+    // Indices into `Mesh::normals` for smooth-shaded triangles
+    normals: Option<[u32; 3]>,
+    // Unit normal of the triangle's plane, facing the side the vertices
+    // wind counter-clockwise around
+    face_normal: Vec3,
+}
+
+// Which axis an OBJ file has pointing up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Up {
+    #[default]
+    Y,
+    Z,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Handedness {
+    #[default]
+    Right,
+    Left,
+}
+
+impl Up {
+    pub fn parse(s: &str) -> Result<Up, String> {
+        match s {
+            "y" => Ok(Up::Y),
+            "z" => Ok(Up::Z),
+            other => Err(format!("unknown up axis '{}' (expected y or z)", other)),
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            Up::Y => "y",
+            Up::Z => "z",
+        }
+    }
+}
+
+impl Handedness {
+    pub fn parse(s: &str) -> Result<Handedness, String> {
+        match s {
+            "right" => Ok(Handedness::Right),
+            "left" => Ok(Handedness::Left),
+            other => Err(format!("unknown handedness '{}' (expected right or left)", other)),
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            Handedness::Right => "right",
+            Handedness::Left => "left",
+        }
+    }
+}
+
+// The coordinate system an OBJ file was written in. The default is the
+// scene's own (see `Vec3::UP`). A left-handed file is taken to be the
+// right-handed one mirrored along its depth axis: z when Y is up, y when Z
+// is up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjConvention {
+    pub up: Up,
+    pub handedness: Handedness,
+}
+
+impl ObjConvention {
+    // A point or direction in the file's coordinates, in the scene's
+    pub fn to_scene(&self, v: Vec3) -> Vec3 {
+        match (self.up, self.handedness) {
+            (Up::Y, Handedness::Right) => v,
+            (Up::Y, Handedness::Left) => Vec3::new(v.x, v.y, -v.z),
+            (Up::Z, Handedness::Right) => Vec3::from_z_up(v),
+            (Up::Z, Handedness::Left) => Vec3::from_z_up(Vec3::new(v.x, -v.y, v.z)),
+        }
+    }
+}
+
+// Triangle mesh with one material, loaded from a Wavefront OBJ file or a
+// baked cache of one. Clones share their geometry, so a loaded mesh can be
+// placed again with another material without copying it.
+#[derive(Clone)]
+pub struct Mesh {
+    // File the mesh was loaded from, kept for scene export
+    pub path: String,
+    // Size relative to the file's coordinates
+    pub scale: f64,
+    // Coordinate system of the file, converted from
+    pub convention: ObjConvention,
+    positions: Arc<[Vec3]>,
+    normals: Arc<[Vec3]>,
+    triangles: Arc<[Triangle]>,
+    bvh: Arc<Bvh>,
+    material: Material,
+}
+
+impl Mesh {
+    // Loads an OBJ file, or a `.rtcache` file made by `rt bake`. A cache
+    // whose version or source hash no longer matches is ignored and the mesh
+    // rebuilt from its source; the second value then says so.
+    pub fn load(path: &str, material: Material) -> Result<(Mesh, Option<String>), String> {
+        let data = fs::read(path).map_err(|e| format!("cannot read mesh file '{}': {}", path, e))?;
+        let (mut mesh, note) = if path.ends_with(".rtcache") {
+            Mesh::from_cache(&data, material).map_err(|e| format!("{}: {}", path, e))?
+        } else {
+            (Mesh::from_obj_bytes(&data, material).map_err(|e| format!("{}: {}", path, e))?, None)
+        };
+        mesh.path = path.to_string();
+        Ok((mesh, note))
+    }
+    
+    pub fn parse_obj(text: &str, material: Material) -> Result<Mesh, String> {
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut faces = Vec::new();
+        
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+            let context = || format!("line {}", number + 1);
+            match fields.next() {
+                Some("v") => positions.push(obj_vec3(fields).ok_or_else(|| format!("{}: bad vertex", context()))?),
+                Some("vn") => normals.push(obj_vec3(fields).ok_or_else(|| format!("{}: bad normal", context()))?.normalize()),
+                Some("f") => {
+                    let corners = fields
+                        .map(|corner| obj_corner(corner, positions.len(), normals.len()))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| format!("{}: bad face", context()))?;
+                    if corners.len() < 3 {
+                        return Err(format!("{}: faces need at least three vertices", context()));
+                    }
+                    // Fan triangulation; fine for the convex polygons OBJ
+                    // exporters write
+                    for i in 1..corners.len() - 1 {
+                        faces.push((corners[0], corners[i], corners[i + 1], number + 1));
+                    }
+                }
+                // Texture coordinates, groups, smoothing groups and
+                // material libraries are not used
+                _ => {}
+            }
+        }
+        
+        // Each face is checked and gets its normal on its own, in parallel;
+        // collecting in file order keeps the first bad face the one reported
+        let triangles: Vec<Result<Option<Triangle>, String>> = faces.par_iter()
+            .map(|&(a, b, c, line)| {
+                let corners = [a, b, c];
+                if corners.iter().any(|&(vertex, normal)| {
+                    vertex >= positions.len() || normal.is_some_and(|n| n >= normals.len())
+                }) {
+                    return Err(format!("line {}: face refers to a missing vertex or normal", line));
+                }
+                let [pa, pb, pc] = corners.map(|(vertex, _)| positions[vertex]);
+                let face_normal = (pb - pa).cross(&(pc - pa)).normalize();
+                if face_normal.length_squared() == 0.0 {
+                    // Degenerate; no ray can hit it
+                    return Ok(None);
+                }
+                let normals = match corners.map(|(_, normal)| normal) {
+                    [Some(na), Some(nb), Some(nc)] => Some([na as u32, nb as u32, nc as u32]),
+                    _ => None,
+                };
+                Ok(Some(Triangle { vertices: corners.map(|(vertex, _)| vertex as u32), normals, face_normal }))
+            })
+            .collect();
+        let triangles = triangles.into_iter().filter_map(Result::transpose).collect::<Result<Vec<_>, _>>()?;
+        if triangles.is_empty() {
+            return Err("no triangles".to_string());
+        }
+        
+        let mut mesh = Mesh {
+            path: String::new(),
+            scale: 1.0,
+            convention: ObjConvention::default(),
+            positions: positions.into(),
+            normals: normals.into(),
+            triangles: triangles.into(),
+            bvh: Arc::new(Bvh::from_bounds([])),
+            material,
+        };
+        mesh.bvh = mesh.build_bvh();
+        Ok(mesh)
+    }
+    
+    // A unit cube around the origin, standing in for the mesh file at
+    // `path`, which is missing; it keeps the path for scene export
+    pub fn placeholder(path: &str, material: Material) -> Mesh {
+        let obj = "v -0.5 -0.5 -0.5\nv 0.5 -0.5 -0.5\nv 0.5 0.5 -0.5\nv -0.5 0.5 -0.5\n\
+            v -0.5 -0.5 0.5\nv 0.5 -0.5 0.5\nv 0.5 0.5 0.5\nv -0.5 0.5 0.5\n\
+            f 1 4 3 2\nf 5 6 7 8\nf 1 2 6 5\nf 4 8 7 3\nf 1 5 8 4\nf 2 3 7 6\n";
+        let mut mesh = Mesh::parse_obj(obj, material).expect("the placeholder cube is valid OBJ");
+        mesh.path = path.to_string();
+        mesh
+    }
+    
+    fn from_obj_bytes(data: &[u8], material: Material) -> Result<Mesh, String> {
+        let text = std::str::from_utf8(data).map_err(|_| "OBJ file is not valid UTF-8".to_string())?;
+        Mesh::parse_obj(text, material)
+    }
+    
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+    
+    // Copy with every vertex moved `factor` times as far from the origin;
+    // normals keep their directions
+    pub fn scaled(&self, factor: f64) -> Mesh {
+        let mut mesh = Mesh {
+            scale: self.scale * factor,
+            positions: self.positions.par_iter().map(|&p| p * factor).collect::<Vec<_>>().into(),
+            ..self.clone()
+        };
+        mesh.bvh = mesh.build_bvh();
+        mesh
+    }
+    
+    /// Copy of a mesh read from a file written in `convention`, turned into
+    /// the scene's coordinates. Mirroring a left-handed file would turn its
+    /// triangles inside out, so their winding is reversed to keep the same
+    /// side facing out.
+    ///
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::mesh::{Handedness, Mesh, ObjConvention, Up};
+    /// use rt::objects::Object;
+    /// use rt::ray::Ray;
+    /// use rt::vector::Vec3;
+    ///
+    /// // A right triangle with legs of 1 along x and 2 along the file's up
+    /// // axis, facing the viewer in each convention: +z for Y up, -y for Z up
+    /// let files = [
+    ///     (Up::Y, Handedness::Right, "v 0 0 0\nv 1 0 0\nv 0 2 0\nf 1 2 3"),
+    ///     (Up::Y, Handedness::Left, "v 0 0 0\nv 1 0 0\nv 0 2 0\nf 1 3 2"),
+    ///     (Up::Z, Handedness::Right, "v 0 0 0\nv 1 0 0\nv 0 0 2\nf 1 2 3"),
+    ///     (Up::Z, Handedness::Left, "v 0 0 0\nv 1 0 0\nv 0 0 2\nf 1 3 2"),
+    /// ];
+    /// for (up, handedness, obj) in files {
+    ///     let mesh = Mesh::parse_obj(obj, Material::default()).unwrap();
+    ///     let mesh = mesh.converted(ObjConvention { up, handedness });
+    ///     assert_eq!(mesh.vertices(), &[Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0)]);
+    ///
+    ///     // Seen from the front, and missed beside the short leg
+    ///     let hit = mesh.hit(&Ray::new(Vec3::new(0.2, 1.0, 5.0), Vec3::FORWARD), 0.001, f64::INFINITY).unwrap();
+    ///     assert!(hit.front_face, "{:?} {:?}", up, handedness);
+    ///     assert_eq!(hit.geometric_normal, Vec3::new(0.0, 0.0, 1.0));
+    ///     assert!(mesh.hit(&Ray::new(Vec3::new(0.8, 1.0, 5.0), Vec3::FORWARD), 0.001, f64::INFINITY).is_none());
+    /// }
+    /// ```
+    pub fn converted(&self, convention: ObjConvention) -> Mesh {
+        let flip = convention.handedness == Handedness::Left;
+        let triangles: Vec<Triangle> = self.triangles.par_iter()
+            .map(|triangle| {
+                let swap = |[a, b, c]: [u32; 3]| if flip { [a, c, b] } else { [a, b, c] };
+                Triangle {
+                    vertices: swap(triangle.vertices),
+                    normals: triangle.normals.map(swap),
+                    face_normal: convention.to_scene(triangle.face_normal),
+                }
+            })
+            .collect();
+        let mut mesh = Mesh {
+            convention,
+            positions: self.positions.par_iter().map(|&p| convention.to_scene(p)).collect::<Vec<_>>().into(),
+            normals: self.normals.par_iter().map(|&n| convention.to_scene(n)).collect::<Vec<_>>().into(),
+            triangles: triangles.into(),
+            ..self.clone()
+        };
+        mesh.bvh = mesh.build_bvh();
+        mesh
+    }
+    
+    // Hierarchy over the triangles, whose bounds are found in parallel
+    fn build_bvh(&self) -> Arc<Bvh> {
+        let bounds: Vec<Option<Aabb>> = (0..self.triangles.len()).into_par_iter().map(|index| Some(self.triangle_bounds(index))).collect();
+        Arc::new(Bvh::from_bounds(bounds))
+    }
+    
+    // Vertex positions in scene coordinates
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.positions
+    }
+    
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+    
+    // Bytes of geometry and hierarchy, which clones share
+    fn geometry_memory(&self) -> usize {
+        mem::size_of_val(&*self.positions)
+            + mem::size_of_val(&*self.normals)
+            + mem::size_of_val(&*self.triangles)
+            + self.bvh.memory_estimate()
+    }
+    
+    fn triangle_bounds(&self, index: usize) -> Aabb {
+        let [a, b, c] = self.triangles[index].vertices.map(|i| self.positions[i as usize]);
+        let bounds = Aabb::new(a, a).union(&Aabb::new(b, b)).union(&Aabb::new(c, c));
+        // Pad so an axis-aligned triangle's box is not flat
+        Aabb::new(bounds.min - Vec3::new(1e-6, 1e-6, 1e-6), bounds.max + Vec3::new(1e-6, 1e-6, 1e-6))
+    }
+    
+    // Moller-Trumbore intersection with one triangle
+    fn hit_triangle(&self, index: usize, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let triangle = &self.triangles[index];
+        let [a, b, c] = triangle.vertices.map(|i| self.positions[i as usize]);
+        let edge1 = b - a;
+        let edge2 = c - a;
+        
+        let p = ray.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+        if det == 0.0 {
+            return None; // Ray is parallel to the triangle
+        }
+        let inv_det = 1.0 / det;
+        
+        let s = ray.origin - a;
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&edge1);
+        let v = ray.direction.dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        
+        let t = edge2.dot(&q) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        
+        let mut hit_record = HitRecord::new(ray.at(t), triangle.face_normal, t, ray);
+        if let Some(normals) = triangle.normals {
+            let [na, nb, nc] = normals.map(|i| self.normals[i as usize]);
+            let normal = (na * (1.0 - u - v) + nb * u + nc * v).normalize();
+            // Opposing vertex normals can cancel out; keep the face normal
+            if normal.length_squared() > 0.0 {
+                hit_record.set_shading_normal(normal);
+            }
+        }
+        Some(hit_record)
+    }
+    
+    // Writes the mesh and its hierarchy as a cache of `source`, the OBJ
+    // data it was built from, found at `source_path`
+    pub fn write_cache(&self, source: &[u8], source_path: &str, output: &str) -> Result<(), String> {
+        let mut out = Vec::new();
+        out.extend_from_slice(CACHE_MAGIC);
+        binary::put_u32(&mut out, CACHE_VERSION);
+        binary::put_u64(&mut out, binary::fnv1a(source));
+        binary::put_bytes(&mut out, source_path.as_bytes());
+        
+        for points in [&self.positions, &self.normals] {
+            binary::put_u32(&mut out, points.len() as u32);
+            for &point in points.iter() {
+                binary::put_vec3(&mut out, point);
+            }
+        }
+        binary::put_u32(&mut out, self.triangles.len() as u32);
+        for triangle in self.triangles.iter() {
+            for index in triangle.vertices {
+                binary::put_u32(&mut out, index);
+            }
+            binary::put_u8(&mut out, triangle.normals.is_some() as u8);
+            for index in triangle.normals.unwrap_or([0; 3]) {
+                binary::put_u32(&mut out, index);
+            }
+            binary::put_vec3(&mut out, triangle.face_normal);
+        }
+        self.bvh.write(&mut out);
+        
+        fs::write(output, out).map_err(|e| format!("cannot write '{}': {}", output, e))
+    }
+    
+    fn from_cache(data: &[u8], material: Material) -> Result<(Mesh, Option<String>), String> {
+        let Some(data) = data.strip_prefix(CACHE_MAGIC) else {
+            return Err("not a mesh cache".to_string());
+        };
+        let mut reader = Reader::new(data);
+        let version = reader.u32()?;
+        let source_hash = reader.u64()?;
+        let source_path = String::from_utf8_lossy(reader.bytes()?).into_owned();
+        
+        // Check the cache against its source when the source is still there
+        let stale = match fs::read(&source_path) {
+            Ok(source) if version != CACHE_VERSION || binary::fnv1a(&source) != source_hash => Some(source),
+            Ok(_) => None,
+            Err(e) if version != CACHE_VERSION => {
+                return Err(format!(
+                    "cache is version {} (expected {}) and its source '{}' cannot be read: {}",
+                    version, CACHE_VERSION, source_path, e
+                ));
+            }
+            Err(_) => None,
+        };
+        if let Some(source) = stale {
+            let reason = if version != CACHE_VERSION { "is from another version" } else { "is out of date" };
+            let mesh = Mesh::from_obj_bytes(&source, material).map_err(|e| format!("{}: {}", source_path, e))?;
+            let note = format!("mesh cache {}, rebuilt from '{}'; run rt bake again to update it", reason, source_path);
+            return Ok((mesh, Some(note)));
+        }
+        
+        let mut points = || -> Result<Vec<Vec3>, String> {
+            let count = reader.count(24)?;
+            (0..count).map(|_| reader.vec3()).collect()
+        };
+        let positions = points()?;
+        let normals = points()?;
+        
+        let triangle_count = reader.count(49)?;
+        let mut triangles = Vec::with_capacity(triangle_count);
+        for _ in 0..triangle_count {
+            let vertices = [reader.u32()?, reader.u32()?, reader.u32()?];
+            let smooth = reader.u8()? != 0;
+            let normal_indices = [reader.u32()?, reader.u32()?, reader.u32()?];
+            let face_normal = reader.vec3()?;
+            if vertices.iter().any(|&i| i as usize >= positions.len())
+                || (smooth && normal_indices.iter().any(|&i| i as usize >= normals.len()))
+            {
+                return Err("triangle refers to a missing vertex or normal".to_string());
+            }
+            triangles.push(Triangle { vertices, normals: smooth.then_some(normal_indices), face_normal });
+        }
+        let bvh = Bvh::read(&mut reader, triangles.len())?;
+        if !reader.is_at_end() {
+            return Err("unexpected data after the mesh".to_string());
+        }
+        
+        let mesh = Mesh {
+            path: String::new(),
+            scale: 1.0,
+            convention: ObjConvention::default(),
+            positions: positions.into(),
+            normals: normals.into(),
+            triangles: triangles.into(),
+            bvh: Arc::new(bvh),
+            material,
+        };
+        Ok((mesh, None))
+    }
+    
+    // Bytes of a mesh of this many vertices, normals and triangles with its
+    // hierarchy, as `memory_estimate` counts them, before any of it is built
+    pub fn memory_for(positions: usize, normals: usize, triangles: usize) -> usize {
+        mem::size_of::<Mesh>()
+            + (positions + normals) * mem::size_of::<Vec3>()
+            + triangles * mem::size_of::<Triangle>()
+            + Bvh::memory_for(triangles)
+    }
+    
+    // What loading the mesh file at `path` would take, without loading it:
+    // an OBJ file is counted a line at a time, and a cache by the counts in
+    // it, skipping the data between them. An outdated cache is counted by
+    // the source it would be rebuilt from.
+    pub fn estimate_file(path: &str) -> Result<usize, String> {
+        let read_error = |e: io::Error| format!("cannot read mesh file '{}': {}", path, e);
+        let mut file = BufReader::new(File::open(path).map_err(read_error)?);
+        if !path.ends_with(".rtcache") {
+            let (positions, normals, triangles) = obj_counts(&mut file).map_err(read_error)?;
+            return Ok(Mesh::memory_for(positions, normals, triangles));
+        }
+        
+        let bad_cache = |e: io::Error| format!("{}: not a mesh cache ({})", path, e);
+        let mut magic = [0; CACHE_MAGIC.len()];
+        file.read_exact(&mut magic).map_err(bad_cache)?;
+        if &magic != CACHE_MAGIC {
+            return Err(format!("{}: not a mesh cache", path));
+        }
+        let version = read_u32(&mut file).map_err(bad_cache)?;
+        file.seek_relative(8).map_err(bad_cache)?;
+        let mut source_path = vec![0; read_u32(&mut file).map_err(bad_cache)? as usize];
+        file.read_exact(&mut source_path).map_err(bad_cache)?;
+        if version != CACHE_VERSION {
+            return Mesh::estimate_file(&String::from_utf8_lossy(&source_path));
+        }
+        let mut points = || -> io::Result<usize> {
+            let count = read_u32(&mut file)? as usize;
+            file.seek_relative(count as i64 * 24)?;
+            Ok(count)
+        };
+        let (positions, normals) = (points().map_err(bad_cache)?, points().map_err(bad_cache)?);
+        let triangles = read_u32(&mut file).map_err(bad_cache)? as usize;
+        Ok(Mesh::memory_for(positions, normals, triangles))
+    }
+}
+
+/// Parses an OBJ file and writes its cache to `output`.
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::material::Material;
+/// use rt::mesh::{self, Mesh};
+/// use rt::scene::{RenderSettings, Scene};
+/// use std::fs;
+///
+/// // A smooth-shaded pyramid: 5 vertices, 5 normals and 6 triangles
+/// let dir = std::env::temp_dir().join(format!("rt-bake-doctest-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// let obj = dir.join("pyramid.obj");
+/// fs::write(&obj, "v -1 0 -1\nv 1 0 -1\nv 1 0 1\nv -1 0 1\nv 0 1.5 0\n\
+///     vn -1 -1 -1\nvn 1 -1 -1\nvn 1 -1 1\nvn -1 -1 1\nvn 0 1 0\n\
+///     f 1//1 2//2 5//5\nf 2//2 3//3 5//5\nf 3//3 4//4 5//5\nf 4//4 1//1 5//5\nf 1//1 3//3 2//2 4//4\n").unwrap();
+/// let cache = dir.join("pyramid.rtcache");
+/// let (obj, cache) = (obj.to_str().unwrap(), cache.to_str().unwrap());
+/// mesh::bake(obj, cache).unwrap();
+/// // Its header tells what loading it will take, as counting the OBJ does
+/// assert_eq!(Mesh::estimate_file(cache), Mesh::estimate_file(obj));
+/// assert_eq!(Mesh::estimate_file(obj), Ok(Mesh::memory_for(5, 5, 6)));
+///
+/// // The cache renders exactly like the OBJ it was baked from
+/// let render = |file: &str| {
+///     let scene: Scene = format!(r#"{{
+///         "camera": {{ "position": [1.5, 1.5, 4], "look_at": [0, 0.5, 0] }},
+///         "lights": [{{ "position": [3, 4, 2] }}],
+///         "objects": [{{ "type": "mesh", "file": "{}" }}]
+///     }}"#, file).parse().unwrap();
+///     let mut image = Image::new(40, 30);
+///     scene.render_into(&mut image, &RenderSettings::default());
+///     image
+/// };
+/// let from_obj = render(obj);
+/// assert!(from_obj.pixels() == render(cache).pixels());
+/// assert!(from_obj.pixels().iter().any(|&pixel| pixel != from_obj.get_pixel(0, 0)));
+///
+/// // Damaged caches are refused rather than read as some other mesh
+/// let baked = fs::read(cache).unwrap();
+/// let damaged = dir.join("damaged.rtcache");
+/// let load = |data: &[u8]| {
+///     fs::write(&damaged, data).unwrap();
+///     Mesh::load(damaged.to_str().unwrap(), Material::default()).map(|_| ()).unwrap_err()
+/// };
+/// for length in 0..baked.len() {
+///     load(&baked[..length]);
+/// }
+/// assert!(load(&[&baked[..], &[0]].concat()).ends_with("unexpected data after the mesh"));
+///
+/// let mut corrupt = baked.clone();
+/// corrupt[0] = b'X';
+/// assert!(load(&corrupt).ends_with("not a mesh cache"));
+///
+/// // Magic, version, source hash and source path come before the counts
+/// let positions = 8 + 4 + 8 + 4 + u32::from_le_bytes(baked[20..24].try_into().unwrap()) as usize;
+/// let mut corrupt = baked.clone();
+/// corrupt[positions..positions + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+/// assert!(load(&corrupt).ends_with("unexpected end of data"));
+///
+/// let first_triangle = positions + 4 + 5 * 24 + 4 + 5 * 24 + 4;
+/// let mut corrupt = baked.clone();
+/// corrupt[first_triangle..first_triangle + 4].copy_from_slice(&99u32.to_le_bytes());
+/// assert!(load(&corrupt).ends_with("triangle refers to a missing vertex or normal"));
+///
+/// fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn bake(obj_path: &str, output: &str) -> Result<Mesh, String> {
+    let source = fs::read(obj_path).map_err(|e| format!("cannot read mesh file '{}': {}", obj_path, e))?;
+    let mut mesh = Mesh::from_obj_bytes(&source, Material::default()).map_err(|e| format!("{}: {}", obj_path, e))?;
+    mesh.path = obj_path.to_string();
+    
+    // Record an absolute path so the cache can find its source from any
+    // working directory
+    let source_path = fs::canonicalize(Path::new(obj_path))
+        .map_err(|e| format!("cannot resolve '{}': {}", obj_path, e))?;
+    mesh.write_cache(&source, &source_path.to_string_lossy(), output)?;
+    Ok(mesh)
+}
+
+// Vertices, normals and triangles (after fan triangulation) in an OBJ file,
+// counted as `parse_obj` would find them
+fn obj_counts(reader: &mut impl BufRead) -> io::Result<(usize, usize, usize)> {
+    let (mut positions, mut normals, mut triangles) = (0, 0, 0);
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        let mut fields = text.split('#').next().unwrap_or("").split_whitespace();
+        match fields.next() {
+            Some("v") => positions += 1,
+            Some("vn") => normals += 1,
+            Some("f") => triangles += fields.count().saturating_sub(2),
+            _ => {}
+        }
+        line.clear();
+    }
+    Ok((positions, normals, triangles))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn obj_vec3<'a>(mut fields: impl Iterator<Item = &'a str>) -> Option<Vec3> {
+    let mut coordinate = || fields.next()?.parse::<f64>().ok().filter(|c| c.is_finite());
+    Some(Vec3::new(coordinate()?, coordinate()?, coordinate()?))
+}
+
+// A face corner "v", "v/vt", "v//vn" or "v/vt/vn" as zero-based vertex and
+// normal indices. Indices count from 1, or back from the latest element
+// when negative.
+fn obj_corner(corner: &str, position_count: usize, normal_count: usize) -> Option<(usize, Option<usize>)> {
+    let resolve = |field: &str, count: usize| -> Option<usize> {
+        let index: i64 = field.parse().ok()?;
+        match index {
+            0 => None,
+            i if i > 0 => Some(i as usize - 1),
+            i => count.checked_sub(i.unsigned_abs() as usize),
+        }
+    };
+    let mut parts = corner.split('/');
+    let vertex = resolve(parts.next()?, position_count)?;
+    let normal = match parts.nth(1) {
+        Some(field) if !field.is_empty() => Some(resolve(field, normal_count)?),
+        _ => None,
+    };
+    Some((vertex, normal))
+}
+
+impl Object for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.bvh
+            .closest_hit(ray, t_min, t_max, |index, closest_t| self.hit_triangle(index, ray, t_min, closest_t))
+            .map(|(hit_record, _)| hit_record)
+    }
+    
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "mesh"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn memory_estimate(&self) -> usize {
+        mem::size_of::<Self>() + self.path.capacity() + self.geometry_memory()
+    }
+    
+    fn shared_memory(&self) -> Vec<(usize, usize)> {
+        vec![(Arc::as_ptr(&self.triangles) as *const u8 as usize, self.geometry_memory())]
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounds()
+    }
+}
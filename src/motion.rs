@@ -0,0 +1,104 @@
+use crate::camera::Camera;
+use crate::scene::AuxBuffers;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+// How far what each pixel sees moved across the image since the previous
+// frame, in pixels with x to the right and y down, so it was last seen at
+// (x - dx, y - dy). Post tools smear each pixel along its vector to add
+// motion blur without rendering extra frames.
+pub struct MotionVectors {
+    pub width: u32,
+    pub height: u32,
+    pub vectors: Vec<(f64, f64)>,
+}
+
+impl MotionVectors {
+    /// Motion of every pixel from `previous` to `camera`, found by carrying
+    /// the point each pixel hit, at the depth in `aux`, back through the
+    /// previous camera. Pixels that hit nothing are treated as infinitely
+    /// far away, so they only move as the view turns or zooms. Points the
+    /// previous camera could not see have no vector and get (0, 0), as do
+    /// images one pixel wide or tall.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::image::Image;
+    /// use rt::motion::MotionVectors;
+    /// use rt::scene::{AuxBuffers, RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// let mut scene: Scene = r#"{
+    ///     "objects": [{ "type": "plane", "point": [0, 0, -4], "normal": [0, 0, 1] }]
+    /// }"#.parse().unwrap();
+    /// let camera = |x: f64| Camera::new(Vec3::new(x, 0.0, 0.0), Vec3::new(x, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 60.0, 1.5).unwrap();
+    /// scene.set_camera(camera(0.1));
+    /// let mut aux = AuxBuffers::new(30, 20);
+    /// scene.render_with_aux(&mut Image::new(30, 20), Some(&mut aux), &RenderSettings::default());
+    ///
+    /// // Moving right slides a wall 4 units away left by 0.1 / 4 of the
+    /// // view's width at unit depth
+    /// let motion = MotionVectors::new(&camera(0.0), &camera(0.1), &aux);
+    /// let expected = -0.1 / 4.0 / camera(0.0).horizontal.length() * 29.0;
+    /// for &(dx, dy) in &motion.vectors {
+    ///     assert!((dx - expected).abs() < 1e-9 && dy.abs() < 1e-9);
+    /// }
+    ///
+    /// // The background is infinitely far away and stays put
+    /// let motion = MotionVectors::new(&camera(0.0), &camera(0.1), &AuxBuffers::new(30, 20));
+    /// assert!(motion.vectors.iter().all(|&(dx, dy)| dx.abs() < 1e-9 && dy.abs() < 1e-9));
+    ///
+    /// let mut bytes = Vec::new();
+    /// motion.write_pfm(&mut bytes).unwrap();
+    /// assert!(bytes.starts_with(b"PF\n30 20\n-1.0\n"));
+    /// assert_eq!(bytes.len(), "PF\n30 20\n-1.0\n".len() + 30 * 20 * 3 * 4);
+    /// ```
+    pub fn new(previous: &Camera, camera: &Camera, aux: &AuxBuffers) -> MotionVectors {
+        let (width, height) = (aux.width, aux.height);
+        let (w, h) = (width as usize, height as usize);
+        let mut vectors = vec![(0.0, 0.0); w * h];
+        if w < 2 || h < 2 {
+            return MotionVectors { width, height, vectors };
+        }
+        
+        // Pixel positions and the (s, t) the camera takes, as the renderer
+        // maps them
+        let to_st = |x: f64, y: f64| (x / (w - 1) as f64, ((h - 1) as f64 - y) / (h - 1) as f64);
+        let to_xy = |s: f64, t: f64| (s * (w - 1) as f64, (h - 1) as f64 - t * (h - 1) as f64);
+        for (index, vector) in vectors.iter_mut().enumerate() {
+            let (x, y) = ((index % w) as f64, (index / w) as f64);
+            let (s, t) = to_st(x, y);
+            let depth = aux.depth[index];
+            let seen = if depth.is_finite() {
+                previous.project(camera.origin(s, t) + camera.direction(s, t) * depth)
+            } else {
+                previous.project(previous.position + camera.direction(s, t))
+            };
+            if let Some((s, t, _)) = seen {
+                let (old_x, old_y) = to_xy(s, t);
+                *vector = (x - old_x, y - old_y);
+            }
+        }
+        MotionVectors { width, height, vectors }
+    }
+    
+    // Writes the vectors as a little-endian PFM image, dx in red and dy in
+    // green, with the bottom row first as the format has it
+    pub fn write_pfm<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        let mut bytes = Vec::with_capacity(self.vectors.len() * 12);
+        for row in self.vectors.chunks(self.width.max(1) as usize).rev() {
+            for &(dx, dy) in row {
+                for value in [dx as f32, dy as f32, 0.0] {
+                    bytes.extend(value.to_le_bytes());
+                }
+            }
+        }
+        out.write_all(&bytes)?;
+        out.flush()
+    }
+    
+    pub fn save_pfm(&self, filename: &str) -> io::Result<()> {
+        self.write_pfm(BufWriter::new(fs::File::create(filename)?))
+    }
+}
@@ -1,12 +1,159 @@
 use crate::vector::Vec3;
-use crate::ray::{Ray, HitRecord};
+use crate::image::color::Color;
+use crate::ray::{Ray, RayPacket4, HitRecord};
 use crate::material::Material;
+use crate::texture::{ImageTexture, Texture, TextureSpace};
+use crate::bvh::Aabb;
+use crate::math;
+use std::any::Any;
+use std::mem;
+use std::sync::Arc;
+
+// Factor pushing the exit of a slab test out past the rounding of the
+// three divisions and comparisons that found it
+const SLAB_ROUNDING: f64 = 1.0 + 6.0 * f64::EPSILON;
 
 pub trait Object: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn material(&self) -> &Material;
+    fn material_mut(&mut self) -> &mut Material;
+    
+    // Materials a material override by the object's name changes
+    fn materials_mut(&mut self) -> Vec<&mut Material> {
+        vec![self.material_mut()]
+    }
+    
+    // Short lowercase type name, used for default object names
+    fn kind(&self) -> &'static str;
+    
+    // For code that needs the concrete type back, such as scene export
+    fn as_any(&self) -> &dyn Any;
+    
+    // Rough number of bytes the object takes up, counting what it holds on
+    // the heap, such as a mesh's triangles or a texture's texels
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self)
+    }
+    
+    // The parts of `memory_estimate` that other objects may share, such as
+    // a loaded mesh's geometry or a material's texture, as (address, bytes)
+    // pairs, so totals over a scene can count each of them once
+    fn shared_memory(&self) -> Vec<(usize, usize)> {
+        Vec::new()
+    }
+    
+    // Box enclosing the whole object, or None for unbounded objects, which
+    // are then tested against every ray
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+    
+    // Material used for shading at a hit; only objects whose material
+    // varies over the surface need to override this
+    fn material_at(&self, _hit: &HitRecord) -> Material {
+        *self.material()
+    }
+    
+    // Surface color at a hit. `footprint` is roughly the world-space width
+    // of one pixel there, for choosing a texture mip level.
+    fn albedo(&self, hit: &HitRecord, _footprint: f64) -> Color {
+        self.material_at(hit).color
+    }
+    
+    // Light the surface gives off at a hit, with `footprint` as for
+    // `albedo`
+    fn emission(&self, hit: &HitRecord, _footprint: f64) -> Color {
+        self.material_at(hit).emission
+    }
+    
+    // Packet intersection with a per-lane t_min and t_max. Objects without a
+    // dedicated packet routine fall back to four scalar tests.
+    fn hit4(&self, packet: &RayPacket4, t_min: [f64; 4], t_max: [f64; 4]) -> [Option<HitRecord>; 4] {
+        let mut hits = [None; 4];
+        for (lane, hit) in hits.iter_mut().enumerate() {
+            *hit = self.hit(&packet.rays[lane], t_min[lane], t_max[lane]);
+        }
+        hits
+    }
+    
+    // Parameter intervals along the ray that lie inside the object, clipped
+    // to [t_min, t_max] and sorted. A ray starting inside gets an interval
+    // beginning at t_min; a tangential graze gives a zero-length interval.
+    //
+    // The default walks successive `hit`s, pairing front-face entries with
+    // back-face exits. An entry with no matching exit (open surfaces) runs
+    // to t_max.
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        const MAX_CROSSINGS: usize = 16;
+        const STEP: f64 = 1e-7;
+        
+        let mut intervals = Vec::new();
+        let mut entry = None;
+        let mut t = t_min;
+        for _ in 0..MAX_CROSSINGS {
+            let Some(hit_record) = self.hit(ray, t, t_max) else {
+                break;
+            };
+            if hit_record.front_face {
+                entry = Some(hit_record.t);
+            } else {
+                intervals.push((entry.take().unwrap_or(t_min), hit_record.t));
+            }
+            t = hit_record.t + STEP;
+        }
+        if let Some(entry) = entry {
+            intervals.push((entry, t_max));
+        }
+        intervals
+    }
 }
 
+// The part of [lo, hi] inside [t_min, t_max], as a zero- or one-element list
+fn clip_interval(lo: f64, hi: f64, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+    let lo = lo.max(t_min);
+    let hi = hi.min(t_max);
+    if lo <= hi {
+        vec![(lo, hi)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// A sphere. Tiny spheres far away stay round when zoomed in on:
+///
+/// ```
+/// use rt::camera::Camera;
+/// use rt::image::color::Color;
+/// use rt::image::Image;
+/// use rt::material::Material;
+/// use rt::objects::Sphere;
+/// use rt::scene::{RenderSettings, Scene};
+/// use rt::vector::Vec3;
+///
+/// // A 1 mm sphere 100 km away, framed by a view three radii wide each
+/// // way; narrower than any field of view, so the rays are parallel
+/// let (distance, radius) = (1.0e5, 1.0e-3);
+/// let mut scene = Scene::new();
+/// scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -distance), radius, Material::emissive(Color::WHITE))));
+/// scene.set_background(rt::background::Background::Solid(Color::BLACK));
+/// scene.set_camera(Camera::orthographic(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 1.0, 0.0), 6.0 * radius, 1.0));
+/// let mut image = Image::new(33, 33);
+/// scene.render_into(&mut image, &RenderSettings::default());
+///
+/// let lit = |x: usize, y: usize| image.get_pixel(x, y).r > 0.5;
+/// // Pixel centers are 6/32 of a radius apart, so the disk covers about
+/// // pi (32/6)^2 of them; the naive intersection drew an 11 x 11 square
+/// let count = (0..33).flat_map(|y| (0..33).map(move |x| (x, y))).filter(|&(x, y)| lit(x, y)).count();
+/// let expected = std::f64::consts::PI * (32.0 / 6.0_f64).powi(2);
+/// assert!((count as f64 - expected).abs() < 0.1 * expected, "{} pixels lit", count);
+/// assert!(!lit(11, 11) && !lit(21, 21));
+/// for y in 0..33 {
+///     for x in 0..33 {
+///         assert_eq!(lit(x, y), lit(32 - x, y));
+///         assert_eq!(lit(x, y), lit(y, x));
+///     }
+/// }
+/// ```
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f64,
@@ -17,14 +164,164 @@ impl Sphere {
     pub fn new(center: Vec3, radius: f64, material: Material) -> Self {
         Sphere { center, radius, material }
     }
+    
+    // Both ray parameters where the ray crosses the surface, nearest first.
+    // The discriminant comes from how close the ray passes to the center
+    // rather than from the coefficients, which lose the radius entirely
+    // for small spheres far from the ray's origin.
+    fn roots(&self, origin: Vec3, direction: Vec3) -> Option<(f64, f64)> {
+        let oc = origin - self.center;
+        let a = direction.length_squared();
+        let b = 2.0 * oc.dot(&direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let closest = oc - direction * (0.5 * b / a);
+        let discriminant = 4.0 * a * (self.radius * self.radius - closest.length_squared());
+        math::quadratic_roots(a, b, c, discriminant)
+    }
 }
 
 impl Object for Sphere {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        let a = ray.direction.length_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
+        let (near, far) = self.roots(ray.origin, ray.direction)?;
+        let mut root = near;
+        if root < t_min || t_max < root {
+            root = far;
+            if root < t_min || t_max < root {
+                return None;
+            }
+        }
+        
+        let point = ray.at(root);
+        let outward_normal = (point - self.center) / self.radius;
+        
+        Some(HitRecord::new(point, outward_normal, root, ray))
+    }
+    
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "sphere"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, Vec3::new(self.radius, self.radius, self.radius)))
+    }
+    
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::objects::{Object, Sphere};
+    /// use rt::ray::Ray;
+    /// use rt::vector::Vec3;
+    ///
+    /// let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.0, Material::default());
+    /// let range = |origin: Vec3| sphere.hit_range(&Ray::new(origin, Vec3::new(0.0, 0.0, -1.0)), 0.001, 100.0);
+    ///
+    /// assert_eq!(range(Vec3::zero()), [(4.0, 6.0)]);
+    /// // From the center, the interval starts at t_min
+    /// assert_eq!(range(Vec3::new(0.0, 0.0, -5.0)), [(0.001, 1.0)]);
+    /// // Touching the side, it has no length
+    /// assert_eq!(range(Vec3::new(1.0, 0.0, 0.0)), [(5.0, 5.0)]);
+    /// assert!(range(Vec3::new(1.001, 0.0, 0.0)).is_empty());
+    /// ```
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        match self.roots(ray.origin, ray.direction) {
+            Some((near, far)) => clip_interval(near, far, t_min, t_max),
+            None => Vec::new(),
+        }
+    }
+    
+    fn hit4(&self, packet: &RayPacket4, t_min: [f64; 4], t_max: [f64; 4]) -> [Option<HitRecord>; 4] {
+        // Same arithmetic as the scalar path, one lane at a time, so the
+        // results are bit-identical
+        let mut hits = [None; 4];
+        for (lane, hit) in hits.iter_mut().enumerate() {
+            let origin = Vec3::new(packet.origin_x[lane], packet.origin_y[lane], packet.origin_z[lane]);
+            let direction = Vec3::new(packet.dir_x[lane], packet.dir_y[lane], packet.dir_z[lane]);
+            let Some((near, far)) = self.roots(origin, direction) else {
+                continue;
+            };
+            let mut root = near;
+            if root < t_min[lane] || t_max[lane] < root {
+                root = far;
+                if root < t_min[lane] || t_max[lane] < root {
+                    continue;
+                }
+            }
+            
+            let ray = &packet.rays[lane];
+            let point = ray.at(root);
+            let outward_normal = (point - self.center) / self.radius;
+            *hit = Some(HitRecord::new(point, outward_normal, root, ray));
+        }
+        hits
+    }
+}
+
+/// An axis-aligned ellipsoid: a unit sphere stretched by `radii`.
+///
+/// ```
+/// use rt::material::Material;
+/// use rt::objects::{Ellipsoid, Object};
+/// use rt::ray::Ray;
+/// use rt::vector::Vec3;
+///
+/// // Long along x, short along y
+/// let ellipsoid = Ellipsoid::new(Vec3::zero(), Vec3::new(3.0, 1.0, 2.0), Material::default());
+/// let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-9;
+///
+/// // Straight down each semi-axis, meeting the surface head on
+/// for (axis, t) in [(Vec3::new(1.0, 0.0, 0.0), 7.0), (Vec3::new(0.0, 1.0, 0.0), 9.0), (Vec3::new(0.0, 0.0, 1.0), 8.0)] {
+///     let hit = ellipsoid.hit(&Ray::new(axis * 10.0, -axis), 0.001, 100.0).unwrap();
+///     assert!((hit.t - t).abs() < 1e-9 && hit.front_face);
+///     assert!(close(hit.geometric_normal, axis) && close(hit.point, axis * (10.0 - t)));
+/// }
+///
+/// // Along the long axis, touching the top in a single point (which side
+/// // it faces is down to rounding), and just passing over it
+/// let graze = Ray::new(Vec3::new(-10.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+/// let hit = ellipsoid.hit(&graze, 0.001, 100.0).unwrap();
+/// assert!((hit.t - 10.0).abs() < 1e-9 && hit.geometric_normal.y.abs() > 1.0 - 1e-9);
+/// let range = ellipsoid.hit_range(&graze, 0.001, 100.0);
+/// assert!(range.len() == 1 && (range[0].0 - 10.0).abs() < 1e-9 && range[0].0 == range[0].1);
+/// assert!(ellipsoid.hit(&Ray::new(Vec3::new(-10.0, 1.001, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.001, 100.0).is_none());
+///
+/// // From the center, the far side is hit from within
+/// let inside = ellipsoid.hit(&Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0)), 0.001, 100.0).unwrap();
+/// assert!((inside.t - 3.0).abs() < 1e-9 && !inside.front_face);
+/// assert!(close(inside.geometric_normal, Vec3::new(-1.0, 0.0, 0.0)));
+/// assert_eq!(ellipsoid.hit_range(&Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, 1.0)), 0.001, 100.0), [(0.001, 2.0)]);
+/// ```
+pub struct Ellipsoid {
+    pub center: Vec3,
+    pub radii: Vec3,
+    pub material: Material,
+}
+
+impl Ellipsoid {
+    pub fn new(center: Vec3, radii: Vec3, material: Material) -> Self {
+        Ellipsoid { center, radii, material }
+    }
+    
+    // Both ray parameters where the ray crosses the surface, nearest first.
+    // Scaling the ray into the unit sphere's space leaves t unchanged.
+    fn roots(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let scale = |v: Vec3| Vec3::new(v.x / self.radii.x, v.y / self.radii.y, v.z / self.radii.z);
+        let oc = scale(ray.origin - self.center);
+        let direction = scale(ray.direction);
+        
+        let a = direction.length_squared();
+        let half_b = oc.dot(&direction);
+        let c = oc.length_squared() - 1.0;
         
         let discriminant = half_b * half_b - a * c;
         if discriminant < 0.0 {
@@ -32,16 +329,221 @@ impl Object for Sphere {
         }
         
         let sqrtd = discriminant.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
+        Some(((-half_b - sqrtd) / a, (-half_b + sqrtd) / a))
+    }
+}
+
+impl Object for Ellipsoid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (near, far) = self.roots(ray)?;
+        let root = if near >= t_min && near <= t_max {
+            near
+        } else if far >= t_min && far <= t_max {
+            far
+        } else {
+            return None;
+        };
+        
+        // Gradient of the implicit surface
+        let point = ray.at(root);
+        let offset = point - self.center;
+        let outward_normal = Vec3::new(
+            offset.x / (self.radii.x * self.radii.x),
+            offset.y / (self.radii.y * self.radii.y),
+            offset.z / (self.radii.z * self.radii.z),
+        ).normalize();
+        
+        Some(HitRecord::new(point, outward_normal, root, ray))
+    }
+    
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "ellipsoid"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, self.radii))
+    }
+    
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        match self.roots(ray) {
+            Some((near, far)) => clip_interval(near, far, t_min, t_max),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// A cylinder from `a` to `b` capped with hemispheres: every point within
+/// `radius` of the segment ab.
+///
+/// ```
+/// use rt::material::Material;
+/// use rt::objects::{Capsule, Object, Sphere};
+/// use rt::ray::Ray;
+/// use rt::vector::Vec3;
+///
+/// // Upright, with its caps centered at y = -1 and y = 1
+/// let capsule = Capsule::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.5, Material::default());
+/// let shoot = |object: &dyn Object, x: f64, y: f64| {
+///     object.hit(&Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, 100.0)
+/// };
+///
+/// // A grid of rays over the body, both caps and the junctions between
+/// // them, each checked against the closest point on the segment
+/// for i in -9..=9 {
+///     for j in -29..=29 {
+///         let (x, y) = (i as f64 * 0.05 + 0.001, j as f64 * 0.05 + 0.001);
+///         let axis_point = Vec3::new(0.0, y.clamp(-1.0, 1.0), 0.0);
+///         let distance = (Vec3::new(x, y, 0.0) - axis_point).length();
+///         match shoot(&capsule, x, y) {
+///             Some(hit) => {
+///                 let depth = (0.25 - distance * distance).sqrt();
+///                 assert!((hit.t - (5.0 - depth)).abs() < 1e-9, "({}, {})", x, y);
+///                 assert!((hit.geometric_normal - (hit.point - axis_point) / 0.5).length() < 1e-9);
+///             }
+///             None => assert!(distance > 0.5, "({}, {}) missed", x, y),
+///         }
+///     }
+/// }
+///
+/// // Normals turn smoothly from the body onto the cap
+/// let normal = |y: f64| shoot(&capsule, 0.3, y).unwrap().geometric_normal;
+/// assert!((normal(1.0 - 1e-6) - normal(1.0 + 1e-6)).length() < 1e-5);
+/// assert!((normal(-1.0 - 1e-6) - normal(-1.0 + 1e-6)).length() < 1e-5);
+///
+/// // With both ends together it is a sphere
+/// let ball = Capsule::new(Vec3::zero(), Vec3::zero(), 1.0, Material::default());
+/// let sphere = Sphere::new(Vec3::zero(), 1.0, Material::default());
+/// for i in -12..=12 {
+///     let (x, y) = (i as f64 * 0.09, i as f64 * -0.04);
+///     match (shoot(&ball, x, y), shoot(&sphere, x, y)) {
+///         (Some(a), Some(b)) => assert!((a.t - b.t).abs() < 1e-9 && (a.geometric_normal - b.geometric_normal).length() < 1e-9),
+///         (a, b) => assert_eq!(a.is_some(), b.is_some()),
+///     }
+/// }
+/// ```
+pub struct Capsule {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl Capsule {
+    pub fn new(a: Vec3, b: Vec3, radius: f64, material: Material) -> Self {
+        Capsule { a, b, radius, material }
+    }
+    
+    // Entry and exit parameters along the ray. The capsule is convex and is
+    // the union of the two end spheres and the cylindrical body, so the
+    // span covers the spans of all three.
+    fn span(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let mut span: Option<(f64, f64)> = None;
+        let mut add = |interval: Option<(f64, f64)>| {
+            if let Some((lo, hi)) = interval {
+                span = Some(span.map_or((lo, hi), |(l, h)| (l.min(lo), h.max(hi))));
+            }
+        };
+        
+        add(self.sphere_span(ray, self.a));
+        add(self.sphere_span(ray, self.b));
+        add(self.body_span(ray));
+        span
+    }
+    
+    fn sphere_span(&self, ray: &Ray, center: Vec3) -> Option<(f64, f64)> {
+        let oc = ray.origin - center;
+        let half_b = oc.dot(&ray.direction);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = half_b * half_b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+        Some((-half_b - sqrtd, -half_b + sqrtd))
+    }
+    
+    // The infinite cylinder around the axis, cut to the slab between the
+    // end points. Zero-length capsules have no body.
+    fn body_span(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let axis = self.b - self.a;
+        let length = axis.length();
+        if length < 1e-12 {
+            return None;
+        }
+        let axis = axis / length;
+        
+        let oc = ray.origin - self.a;
+        let along_origin = oc.dot(&axis);
+        let along_direction = ray.direction.dot(&axis);
+        let oc_perp = oc - axis * along_origin;
+        let direction_perp = ray.direction - axis * along_direction;
+        
+        let a = direction_perp.length_squared();
+        let c = oc_perp.length_squared() - self.radius * self.radius;
+        let (mut lo, mut hi) = if a < 1e-12 {
+            // Parallel to the axis: inside the tube everywhere or nowhere
+            if c > 0.0 {
                 return None;
             }
+            (f64::NEG_INFINITY, f64::INFINITY)
+        } else {
+            let half_b = oc_perp.dot(&direction_perp);
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrtd = discriminant.sqrt();
+            ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a)
+        };
+        
+        if along_direction.abs() < 1e-12 {
+            if along_origin < 0.0 || along_origin > length {
+                return None;
+            }
+        } else {
+            let t0 = -along_origin / along_direction;
+            let t1 = (length - along_origin) / along_direction;
+            lo = lo.max(t0.min(t1));
+            hi = hi.min(t0.max(t1));
         }
         
+        (lo <= hi).then_some((lo, hi))
+    }
+}
+
+impl Object for Capsule {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (near, far) = self.span(ray)?;
+        let root = if near >= t_min && near <= t_max {
+            near
+        } else if far >= t_min && far <= t_max {
+            far
+        } else {
+            return None;
+        };
+        
+        // Away from the closest point on the segment, which keeps the normal
+        // continuous where the body meets the caps
         let point = ray.at(root);
-        let outward_normal = (point - self.center) / self.radius;
+        let axis = self.b - self.a;
+        let along = if axis.length_squared() > 0.0 {
+            ((point - self.a).dot(&axis) / axis.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let outward_normal = (point - (self.a + axis * along)).normalize();
         
         Some(HitRecord::new(point, outward_normal, root, ray))
     }
@@ -49,12 +551,41 @@ impl Object for Sphere {
     fn material(&self) -> &Material {
         &self.material
     }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "capsule"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::around(self.a, r).union(&Aabb::around(self.b, r)))
+    }
+    
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        match self.span(ray) {
+            Some((near, far)) => clip_interval(near, far, t_min, t_max),
+            None => Vec::new(),
+        }
+    }
 }
 
 pub struct Plane {
     pub point: Vec3,
     pub normal: Vec3,
     pub material: Material,
+    // Replaces the material color; texture coordinates count in units of
+    // `texture_scale` world units, so an image repeats every unit
+    pub texture: Option<Arc<Texture>>,
+    pub texture_scale: f64,
+    pub texture_space: TextureSpace,
 }
 
 impl Plane {
@@ -63,31 +594,273 @@ impl Plane {
             point,
             normal: normal.normalize(),
             material,
+            texture: None,
+            texture_scale: 1.0,
+            texture_space: TextureSpace::Object,
         }
     }
+    
+    pub fn with_texture(mut self, texture: Arc<Texture>, scale: f64) -> Self {
+        self.texture = Some(texture);
+        self.texture_scale = scale;
+        self
+    }
+    
+    /// In object space the texture moves with `point`; in world space the
+    /// plane slides under a texture that stays put.
+    ///
+    /// ```
+    /// use rt::image::color::Color;
+    /// use rt::material::Material;
+    /// use rt::objects::{Object, Plane};
+    /// use rt::ray::Ray;
+    /// use rt::texture::{ImageTexture, Texture, TextureFilter, TextureSpace};
+    /// use rt::vector::Vec3;
+    /// use std::sync::Arc;
+    ///
+    /// // Black and white squares half a unit wide, on the floor and on the
+    /// // floor moved within its own plane
+    /// let checker = ImageTexture::checker(8, 2, Color::WHITE, Color::BLACK).with_filter(TextureFilter::Nearest);
+    /// let checker = Arc::new(Texture::Image(checker));
+    /// let floor = |point: Vec3, space: TextureSpace| {
+    ///     Plane::new(point, Vec3::new(0.0, 1.0, 0.0), Material::default())
+    ///         .with_texture(Arc::clone(&checker), 1.0)
+    ///         .with_texture_space(space)
+    /// };
+    /// let color_at = |plane: &Plane, point: Vec3| {
+    ///     let hit = plane.hit(&Ray::new(point + Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), 0.001, 10.0).unwrap();
+    ///     plane.albedo(&hit, 0.0)
+    /// };
+    /// let delta = Vec3::new(0.3, 0.0, 0.45);
+    /// let points: Vec<Vec3> = (0..100).map(|i| Vec3::new((i % 10) as f64 * 0.1 + 0.03, 0.0, (i / 10) as f64 * 0.1 + 0.07)).collect();
+    ///
+    /// let (still, moved) = (floor(Vec3::zero(), TextureSpace::Object), floor(delta, TextureSpace::Object));
+    /// assert!(points.iter().all(|&p| color_at(&moved, p + delta) == color_at(&still, p)));
+    /// assert!(points.iter().any(|&p| color_at(&moved, p) != color_at(&still, p)));
+    ///
+    /// let (still, moved) = (floor(Vec3::zero(), TextureSpace::World), floor(delta, TextureSpace::World));
+    /// assert!(points.iter().all(|&p| color_at(&moved, p) == color_at(&still, p)));
+    /// assert!(points.iter().any(|&p| color_at(&moved, p + delta) != color_at(&still, p)));
+    /// ```
+    pub fn with_texture_space(mut self, space: TextureSpace) -> Self {
+        self.texture_space = space;
+        self
+    }
+    
+    // Origin and in-plane axes of the plane's own frame. The axes follow the
+    // normal; the origin is `point`.
+    pub fn local_frame(&self) -> (Vec3, Vec3, Vec3) {
+        let (tangent, bitangent) = plane_axes(self.normal);
+        (self.point, tangent, bitangent)
+    }
+    
+}
+
+// Parameter where a ray crosses the plane through `point`, if it is in
+// [t_min, t_max] and the ray is not parallel to the plane
+fn plane_hit_t(point: Vec3, normal: Vec3, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+    let denom = normal.dot(&ray.direction);
+    if denom.abs() < 1e-8 {
+        return None; // Ray is parallel to plane
+    }
+    
+    let t = (point - ray.origin).dot(&normal) / denom;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some(t)
+}
+
+// A plane bounds the half-space behind its normal
+fn plane_hit_range(point: Vec3, normal: Vec3, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+    let denom = normal.dot(&ray.direction);
+    let distance = (point - ray.origin).dot(&normal);
+    if denom.abs() < 1e-8 {
+        // Parallel: either entirely inside or entirely outside
+        return if distance >= 0.0 { clip_interval(t_min, t_max, t_min, t_max) } else { Vec::new() };
+    }
+    
+    let t = distance / denom;
+    if denom < 0.0 {
+        clip_interval(t, f64::INFINITY, t_min, t_max)
+    } else {
+        clip_interval(f64::NEG_INFINITY, t, t_min, t_max)
+    }
+}
+
+// Two unit axes spanning the plane with this normal, for texture and tile
+// coordinates
+fn plane_axes(normal: Vec3) -> (Vec3, Vec3) {
+    let helper = if normal.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+    let tangent = (helper - normal * normal.dot(&helper)).normalize();
+    (tangent, normal.cross(&tangent))
 }
 
 impl Object for Plane {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let denom = self.normal.dot(&ray.direction);
-        if denom.abs() < 1e-8 {
-            return None; // Ray is parallel to plane
+        let t = plane_hit_t(self.point, self.normal, ray, t_min, t_max)?;
+        Some(HitRecord::new(ray.at(t), self.normal, t, ray))
+    }
+    
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "plane"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self) + self.texture.as_ref().map_or(0, |texture| texture.memory_estimate())
+    }
+    
+    fn shared_memory(&self) -> Vec<(usize, usize)> {
+        self.texture.iter().map(|texture| texture.shared_memory()).collect()
+    }
+    
+    fn albedo(&self, hit: &HitRecord, footprint: f64) -> Color {
+        let Some(texture) = &self.texture else {
+            return self.material.color;
+        };
+        let (origin, tangent, bitangent) = self.local_frame();
+        let origin = match self.texture_space {
+            TextureSpace::Object => origin,
+            TextureSpace::World => Vec3::zero(),
+        };
+        let offset = (hit.point - origin) / self.texture_scale;
+        texture.sample(offset, offset.dot(&tangent), offset.dot(&bitangent), footprint / self.texture_scale)
+    }
+    
+    /// Everything behind the plane is inside it, so intervals run on to
+    /// t_max, or start at t_min for rays starting behind it. A ray along
+    /// the plane is inside everywhere if it lies in the plane and nowhere
+    /// otherwise.
+    ///
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::objects::{Object, Plane};
+    /// use rt::ray::Ray;
+    /// use rt::vector::Vec3;
+    ///
+    /// let floor = Plane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Material::default());
+    /// let range = |origin: Vec3, direction: Vec3| floor.hit_range(&Ray::new(origin, direction), 0.001, 100.0);
+    /// let (up, down, along) = (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    ///
+    /// assert_eq!(range(Vec3::new(0.0, 2.0, 0.0), down), [(2.0, 100.0)]);
+    /// assert_eq!(range(Vec3::new(0.0, -2.0, 0.0), up), [(0.001, 2.0)]);
+    /// assert!(range(Vec3::new(0.0, 2.0, 0.0), up).is_empty());
+    /// assert!(range(Vec3::new(0.0, 1.0, 0.0), along).is_empty());
+    /// assert_eq!(range(Vec3::zero(), along), [(0.001, 100.0)]);
+    /// ```
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        plane_hit_range(self.point, self.normal, ray, t_min, t_max)
+    }
+    
+    fn hit4(&self, packet: &RayPacket4, t_min: [f64; 4], t_max: [f64; 4]) -> [Option<HitRecord>; 4] {
+        let mut denom = [0.0; 4];
+        let mut t = [0.0; 4];
+        for lane in 0..4 {
+            denom[lane] = self.normal.x * packet.dir_x[lane]
+                + self.normal.y * packet.dir_y[lane]
+                + self.normal.z * packet.dir_z[lane];
+            t[lane] = ((self.point.x - packet.origin_x[lane]) * self.normal.x
+                + (self.point.y - packet.origin_y[lane]) * self.normal.y
+                + (self.point.z - packet.origin_z[lane]) * self.normal.z)
+                / denom[lane];
         }
         
-        let t = (self.point - ray.origin).dot(&self.normal) / denom;
-        if t < t_min || t > t_max {
-            return None;
+        let mut hits = [None; 4];
+        for (lane, hit) in hits.iter_mut().enumerate() {
+            if denom[lane].abs() < 1e-8 || t[lane] < t_min[lane] || t[lane] > t_max[lane] {
+                continue;
+            }
+            let ray = &packet.rays[lane];
+            *hit = Some(HitRecord::new(ray.at(t[lane]), self.normal, t[lane], ray));
+        }
+        hits
+    }
+}
+
+// A plane tiled with two alternating materials, which may differ in any
+// property, not just color. `material()` is the first tile's material.
+pub struct CheckerPlane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub materials: [Material; 2],
+    pub tile_size: f64,
+}
+
+impl CheckerPlane {
+    pub fn new(point: Vec3, normal: Vec3, first: Material, second: Material, tile_size: f64) -> Self {
+        CheckerPlane {
+            point,
+            normal: normal.normalize(),
+            materials: [first, second],
+            tile_size,
         }
-        
-        let point = ray.at(t);
-        Some(HitRecord::new(point, self.normal, t, ray))
+    }
+}
+
+impl Object for CheckerPlane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let t = plane_hit_t(self.point, self.normal, ray, t_min, t_max)?;
+        Some(HitRecord::new(ray.at(t), self.normal, t, ray))
     }
     
     fn material(&self) -> &Material {
-        &self.material
+        &self.materials[0]
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.materials[0]
+    }
+    
+    fn material_at(&self, hit: &HitRecord) -> Material {
+        let (tangent, bitangent) = plane_axes(self.normal);
+        let offset = hit.point - self.point;
+        let u = (offset.dot(&tangent) / self.tile_size).floor() as i64;
+        let v = (offset.dot(&bitangent) / self.tile_size).floor() as i64;
+        self.materials[(u + v).rem_euclid(2) as usize]
+    }
+    
+    fn kind(&self) -> &'static str {
+        "checker_plane"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        plane_hit_range(self.point, self.normal, ray, t_min, t_max)
     }
 }
 
+/// An axis-aligned cube. Touching cubes leave no gap between them: rays
+/// down the seams, which `scenes/cube_grid.json` lines up with the middle
+/// column of pixels, hit the top faces on either side.
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::scene::{RenderSettings, Scene};
+///
+/// let scene: Scene = std::fs::read_to_string("scenes/cube_grid.json").unwrap().parse().unwrap();
+/// for settings in [RenderSettings::default(), RenderSettings { packets: true, ..RenderSettings::default() }] {
+///     let mut image = Image::new(41, 41);
+///     scene.render_into(&mut image, &settings);
+///     // Only top faces, all lit alike; the background is magenta
+///     assert!(image.pixels.iter().all(|&pixel| pixel == image.pixels[0]));
+///     assert!(image.pixels[0].g > 0.1);
+/// }
+/// ```
 pub struct Cube {
     pub center: Vec3,
     pub size: f64,
@@ -98,25 +871,59 @@ impl Cube {
     pub fn new(center: Vec3, size: f64, material: Material) -> Self {
         Cube { center, size, material }
     }
+    
+    // Where a ray enters and leaves the cube, an empty span if it misses. A
+    // ray lying in the plane of a face is inside that slab everywhere or
+    // nowhere, which is tested directly rather than through 0 * inf, and
+    // faces count as inside, so rays along the seam between touching cubes
+    // hit both rather than slipping between them. The exit is pushed out
+    // by a few rounding errors for the same reason.
+    fn span(&self, origin: Vec3, direction: Vec3) -> (f64, f64) {
+        let half_size = self.size / 2.0;
+        let (min, max) = (self.center - Vec3::splat(half_size), self.center + Vec3::splat(half_size));
+        let (mut near, mut far) = (f64::NEG_INFINITY, f64::INFINITY);
+        for (min, max, o, d) in [
+            (min.x, max.x, origin.x, direction.x),
+            (min.y, max.y, origin.y, direction.y),
+            (min.z, max.z, origin.z, direction.z),
+        ] {
+            if d == 0.0 {
+                if o < min || o > max {
+                    return (f64::INFINITY, f64::NEG_INFINITY);
+                }
+                continue;
+            }
+            let t0 = (min - o) / d;
+            let t1 = (max - o) / d;
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        (near, far * SLAB_ROUNDING)
+    }
+    
+    // Normal of the face a ray along `direction` hits at `point`. Points on
+    // an edge or corner, within rounding, are on several faces; of those the
+    // one the ray meets most squarely is picked, so the seams between
+    // touching cubes shade like the faces on either side.
+    fn face_normal(&self, point: Vec3, direction: Vec3) -> Vec3 {
+        let center_to_point = point - self.center;
+        let extent = center_to_point.x.abs().max(center_to_point.y.abs()).max(center_to_point.z.abs());
+        let on_face = |offset: f64| offset.abs() >= extent - self.size * 1e-9;
+        [
+            (center_to_point.x, direction.x, Vec3::new(1.0, 0.0, 0.0)),
+            (center_to_point.y, direction.y, Vec3::new(0.0, 1.0, 0.0)),
+            (center_to_point.z, direction.z, Vec3::new(0.0, 0.0, 1.0)),
+        ]
+        .into_iter()
+        .filter(|&(offset, _, _)| on_face(offset))
+        .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))
+        .map_or(Vec3::new(0.0, 0.0, 1.0), |(offset, _, axis)| axis * offset.signum())
+    }
 }
 
 impl Object for Cube {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let half_size = self.size / 2.0;
-        let min = self.center - Vec3::new(half_size, half_size, half_size);
-        let max = self.center + Vec3::new(half_size, half_size, half_size);
-        
-        let inv_dir = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
-        
-        let t1 = (min.x - ray.origin.x) * inv_dir.x;
-        let t2 = (max.x - ray.origin.x) * inv_dir.x;
-        let t3 = (min.y - ray.origin.y) * inv_dir.y;
-        let t4 = (max.y - ray.origin.y) * inv_dir.y;
-        let t5 = (min.z - ray.origin.z) * inv_dir.z;
-        let t6 = (max.z - ray.origin.z) * inv_dir.z;
-        
-        let tmin = t1.min(t2).max(t3.min(t4)).max(t5.min(t6));
-        let tmax = t1.max(t2).min(t3.max(t4)).min(t5.max(t6));
+        let (tmin, tmax) = self.span(ray.origin, ray.direction);
         
         if tmax < 0.0 || tmin > tmax {
             return None;
@@ -128,27 +935,82 @@ impl Object for Cube {
         }
         
         let point = ray.at(t);
-        let center_to_point = point - self.center;
-        
-        // Determine which face was hit
-        let abs_x = center_to_point.x.abs();
-        let abs_y = center_to_point.y.abs();
-        let abs_z = center_to_point.z.abs();
-        
-        let normal = if abs_x > abs_y && abs_x > abs_z {
-            Vec3::new(center_to_point.x.signum(), 0.0, 0.0)
-        } else if abs_y > abs_z {
-            Vec3::new(0.0, center_to_point.y.signum(), 0.0)
-        } else {
-            Vec3::new(0.0, 0.0, center_to_point.z.signum())
-        };
-        
-        Some(HitRecord::new(point, normal, t, ray))
+        Some(HitRecord::new(point, self.face_normal(point, ray.direction), t, ray))
     }
     
     fn material(&self) -> &Material {
         &self.material
     }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "cube"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        let half_size = self.size / 2.0;
+        Some(Aabb::around(self.center, Vec3::new(half_size, half_size, half_size)))
+    }
+    
+    /// Faces count as inside, and exits are pushed out by a few rounding
+    /// errors (see `span`), so the far ends below are a hair past the face.
+    ///
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::objects::{Cube, Object};
+    /// use rt::ray::Ray;
+    /// use rt::vector::Vec3;
+    ///
+    /// let cube = Cube::new(Vec3::new(0.0, 0.0, -5.0), 2.0, Material::default());
+    /// let range = |origin: Vec3, direction: Vec3| cube.hit_range(&Ray::new(origin, direction), 0.001, 100.0);
+    /// let close = |range: &[(f64, f64)], (near, far): (f64, f64)| {
+    ///     range.len() == 1 && (range[0].0 - near).abs() < 1e-9 && (range[0].1 - far).abs() < 1e-9
+    /// };
+    /// let forward = Vec3::new(0.0, 0.0, -1.0);
+    ///
+    /// assert!(close(&range(Vec3::zero(), forward), (4.0, 6.0)));
+    /// assert!(close(&range(Vec3::new(0.0, 0.0, -5.0), forward), (0.001, 1.0)));
+    /// // Grazing the edge at x = 1, z = -4 crosses it in a single point
+    /// let graze = range(Vec3::new(0.0, 0.0, -3.0), Vec3::new(1.0, 0.0, -1.0));
+    /// assert!(close(&graze, (2f64.sqrt(), 2f64.sqrt())));
+    /// assert!(range(Vec3::new(0.0, 0.0, -2.999), Vec3::new(1.0, 0.0, -1.0)).is_empty());
+    /// ```
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        let (near, far) = self.span(ray.origin, ray.direction);
+        clip_interval(near, far, t_min, t_max)
+    }
+    
+    fn hit4(&self, packet: &RayPacket4, t_min: [f64; 4], t_max: [f64; 4]) -> [Option<HitRecord>; 4] {
+        let mut near = [0.0; 4];
+        let mut far = [0.0; 4];
+        for lane in 0..4 {
+            let origin = Vec3::new(packet.origin_x[lane], packet.origin_y[lane], packet.origin_z[lane]);
+            let direction = Vec3::new(packet.dir_x[lane], packet.dir_y[lane], packet.dir_z[lane]);
+            (near[lane], far[lane]) = self.span(origin, direction);
+        }
+        
+        let mut hits = [None; 4];
+        for (lane, hit) in hits.iter_mut().enumerate() {
+            if far[lane] < 0.0 || near[lane] > far[lane] {
+                continue;
+            }
+            let t = if near[lane] < t_min[lane] { far[lane] } else { near[lane] };
+            if t < t_min[lane] || t > t_max[lane] {
+                continue;
+            }
+            let ray = &packet.rays[lane];
+            let point = ray.at(t);
+            *hit = Some(HitRecord::new(point, self.face_normal(point, ray.direction), t, ray));
+        }
+        hits
+    }
 }
 
 pub struct Cylinder {
@@ -162,30 +1024,45 @@ impl Cylinder {
     pub fn new(center: Vec3, radius: f64, height: f64, material: Material) -> Self {
         Cylinder { center, radius, height, material }
     }
+    
+    // Where the ray crosses the infinite tube around the axis, nearest
+    // first, with the discriminant from the ray's closest approach to the
+    // axis as for spheres. None for rays parallel to the axis, which are
+    // inside the tube everywhere or nowhere.
+    fn tube_roots(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let oc = ray.origin - self.center;
+        let (ox, oz, dx, dz) = (oc.x, oc.z, ray.direction.x, ray.direction.z);
+        let a = dx * dx + dz * dz;
+        if a < 1e-12 {
+            return None;
+        }
+        let b = 2.0 * (ox * dx + oz * dz);
+        let c = ox * ox + oz * oz - self.radius * self.radius;
+        let along = 0.5 * b / a;
+        let (closest_x, closest_z) = (ox - dx * along, oz - dz * along);
+        let discriminant = 4.0 * a * (self.radius * self.radius - (closest_x * closest_x + closest_z * closest_z));
+        math::quadratic_roots(a, b, c, discriminant)
+    }
 }
 
 impl Object for Cylinder {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
-        
-        // Check intersection with infinite cylinder (ignoring y)
-        let a = ray.direction.x * ray.direction.x + ray.direction.z * ray.direction.z;
-        let b = 2.0 * (oc.x * ray.direction.x + oc.z * ray.direction.z);
-        let c = oc.x * oc.x + oc.z * oc.z - self.radius * self.radius;
-        
-        let discriminant = b * b - 4.0 * a * c;
-        if discriminant < 0.0 {
+        // Check intersection with infinite cylinder (ignoring y); rays along
+        // the axis can still hit the caps
+        let tube = self.tube_roots(ray);
+        let along_axis = ray.direction.x * ray.direction.x + ray.direction.z * ray.direction.z < 1e-12;
+        if tube.is_none() && !along_axis {
             return None;
         }
         
-        let sqrt_discriminant = discriminant.sqrt();
-        let t1 = (-b - sqrt_discriminant) / (2.0 * a);
-        let t2 = (-b + sqrt_discriminant) / (2.0 * a);
-        
         let half_height = self.height / 2.0;
+        // The nearest of the side and cap hits, so a ray entering through a
+        // cap and leaving through the side sees the cap first
+        let mut closest: Option<HitRecord> = None;
+        let mut t_max = t_max;
         
         // Check both intersection points
-        for &t in &[t1, t2] {
+        for t in tube.into_iter().flat_map(|(t1, t2)| [t1, t2]) {
             if t >= t_min && t <= t_max {
                 let point = ray.at(t);
                 let y = point.y - self.center.y;
@@ -197,45 +1074,439 @@ impl Object for Cylinder {
                         0.0,
                         (point.z - self.center.z) / self.radius,
                     );
-                    return Some(HitRecord::new(point, normal, t, ray));
+                    closest = Some(HitRecord::new(point, normal, t, ray));
+                    t_max = t;
+                    break;
                 }
             }
         }
         
-        // Check intersection with top and bottom caps
-        let y_min = self.center.y - half_height;
-        let y_max = self.center.y + half_height;
-        
+        // Check intersection with bottom and top caps
         if ray.direction.y.abs() > 1e-8 {
-            // Bottom cap
-            let t = (y_min - ray.origin.y) / ray.direction.y;
-            if t >= t_min && t <= t_max {
-                let point = ray.at(t);
-                let dx = point.x - self.center.x;
-                let dz = point.z - self.center.z;
-                if dx * dx + dz * dz <= self.radius * self.radius {
-                    let normal = Vec3::new(0.0, -1.0, 0.0);
-                    return Some(HitRecord::new(point, normal, t, ray));
+            for (y, normal_y) in [(self.center.y - half_height, -1.0), (self.center.y + half_height, 1.0)] {
+                let t = (y - ray.origin.y) / ray.direction.y;
+                if t >= t_min && t <= t_max {
+                    let point = ray.at(t);
+                    let dx = point.x - self.center.x;
+                    let dz = point.z - self.center.z;
+                    if dx * dx + dz * dz <= self.radius * self.radius {
+                        closest = Some(HitRecord::new(point, Vec3::new(0.0, normal_y, 0.0), t, ray));
+                        t_max = t;
+                    }
                 }
             }
-            
-            // Top cap
-            let t = (y_max - ray.origin.y) / ray.direction.y;
-            if t >= t_min && t <= t_max {
-                let point = ray.at(t);
-                let dx = point.x - self.center.x;
-                let dz = point.z - self.center.z;
-                if dx * dx + dz * dz <= self.radius * self.radius {
-                    let normal = Vec3::new(0.0, 1.0, 0.0);
-                    return Some(HitRecord::new(point, normal, t, ray));
-                }
+        }
+        
+        closest
+    }
+    
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "cylinder"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, Vec3::new(self.radius, self.height / 2.0, self.radius)))
+    }
+    
+    /// Intersection of the infinite tube's interval with the slab between
+    /// the caps.
+    ///
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::objects::{Cylinder, Object};
+    /// use rt::ray::Ray;
+    /// use rt::vector::Vec3;
+    ///
+    /// // Upright, 2 tall, around the y axis at z = -5
+    /// let cylinder = Cylinder::new(Vec3::new(0.0, 0.0, -5.0), 1.0, 2.0, Material::default());
+    /// let range = |origin: Vec3, direction: Vec3| cylinder.hit_range(&Ray::new(origin, direction), 0.001, 100.0);
+    /// let (forward, down) = (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, -1.0, 0.0));
+    ///
+    /// assert_eq!(range(Vec3::zero(), forward), [(4.0, 6.0)]);
+    /// assert_eq!(range(Vec3::new(0.0, 0.0, -5.0), forward), [(0.001, 1.0)]);
+    /// // Through both caps along the axis
+    /// assert_eq!(range(Vec3::new(0.0, 3.0, -5.0), down), [(2.0, 4.0)]);
+    /// // Touching the side, and just missing it
+    /// assert_eq!(range(Vec3::new(1.0, 0.0, 0.0), forward), [(5.0, 5.0)]);
+    /// assert!(range(Vec3::new(1.001, 0.0, 0.0), forward).is_empty());
+    /// // Passing just over the top
+    /// assert!(range(Vec3::new(0.0, 1.001, 0.0), forward).is_empty());
+    /// ```
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        let oc = ray.origin - self.center;
+        let a = ray.direction.x * ray.direction.x + ray.direction.z * ray.direction.z;
+        
+        let (tube_near, tube_far) = if a < 1e-12 {
+            // Parallel to the axis: inside the tube everywhere or nowhere
+            if oc.x * oc.x + oc.z * oc.z > self.radius * self.radius {
+                return Vec::new();
+            }
+            (f64::NEG_INFINITY, f64::INFINITY)
+        } else {
+            match self.tube_roots(ray) {
+                Some(roots) => roots,
+                None => return Vec::new(),
+            }
+        };
+        
+        let half_height = self.height / 2.0;
+        let (slab_near, slab_far) = if ray.direction.y.abs() < 1e-12 {
+            if oc.y.abs() > half_height {
+                return Vec::new();
             }
+            (f64::NEG_INFINITY, f64::INFINITY)
+        } else {
+            let t1 = (-half_height - oc.y) / ray.direction.y;
+            let t2 = (half_height - oc.y) / ray.direction.y;
+            (t1.min(t2), t1.max(t2))
+        };
+        
+        clip_interval(tube_near.max(slab_near), tube_far.min(slab_far), t_min, t_max)
+    }
+}
+
+// Flat parallelogram spanned by two edges from a corner
+pub struct Quad {
+    pub corner: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+    pub material: Material,
+    // Multiplies the material's emission, stretched once over the quad with
+    // (0, 0) at the corner, u along edge_u and v along edge_v
+    pub emission_texture: Option<Arc<ImageTexture>>,
+}
+
+impl Quad {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3, material: Material) -> Self {
+        Quad { corner, edge_u, edge_v, material, emission_texture: None }
+    }
+    
+    pub fn with_emission_texture(mut self, texture: Arc<ImageTexture>) -> Self {
+        self.emission_texture = Some(texture);
+        self
+    }
+    
+    // Coordinates of a point in the quad's plane along each edge, valid for
+    // any parallelogram; the quad covers 0..1 in both
+    fn coordinates(&self, point: Vec3) -> (f64, f64) {
+        let n = self.edge_u.cross(&self.edge_v);
+        let local = point - self.corner;
+        let w = n / n.dot(&n);
+        (w.dot(&local.cross(&self.edge_v)), w.dot(&self.edge_u.cross(&local)))
+    }
+}
+
+impl Object for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let n = self.edge_u.cross(&self.edge_v);
+        let normal = n.normalize();
+        let denom = normal.dot(&ray.direction);
+        if denom.abs() < 1e-8 {
+            return None; // Ray is parallel to the quad
         }
         
-        None
+        let t = (self.corner - ray.origin).dot(&normal) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        
+        let point = ray.at(t);
+        let (alpha, beta) = self.coordinates(point);
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        
+        Some(HitRecord::new(point, normal, t, ray))
+    }
+    
+    fn material(&self) -> &Material {
+        &self.material
+    }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "quad"
+    }
+    
+    fn emission(&self, hit: &HitRecord, footprint: f64) -> Color {
+        let Some(texture) = &self.emission_texture else {
+            return self.material.emission;
+        };
+        let (u, v) = self.coordinates(hit.point);
+        let size = self.edge_u.length().max(self.edge_v.length());
+        self.material.emission * texture.sample(u, v, footprint / size)
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [self.edge_u, self.edge_v, self.edge_u + self.edge_v]
+            .map(|offset| self.corner + offset);
+        let bounds = corners.iter().fold(Aabb::new(self.corner, self.corner), |acc, &c| acc.union(&Aabb::new(c, c)));
+        // Pad so an axis-aligned quad's box is not flat
+        Some(Aabb::new(bounds.min - Vec3::new(1e-6, 1e-6, 1e-6), bounds.max + Vec3::new(1e-6, 1e-6, 1e-6)))
+    }
+    
+    /// A quad has no volume; crossing it is a zero-length interval, and
+    /// there is no starting inside it.
+    ///
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::objects::{Object, Quad};
+    /// use rt::ray::Ray;
+    /// use rt::vector::Vec3;
+    ///
+    /// let quad = Quad::new(Vec3::new(-1.0, -1.0, -5.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), Material::default());
+    /// let range = |origin: Vec3, direction: Vec3| quad.hit_range(&Ray::new(origin, direction), 0.001, 100.0);
+    /// let forward = Vec3::new(0.0, 0.0, -1.0);
+    ///
+    /// assert_eq!(range(Vec3::zero(), forward), [(5.0, 5.0)]);
+    /// // On its edge, and just past it
+    /// assert_eq!(range(Vec3::new(1.0, 0.0, 0.0), forward), [(5.0, 5.0)]);
+    /// assert!(range(Vec3::new(1.001, 0.0, 0.0), forward).is_empty());
+    /// // Starting in its plane, or sliding along it
+    /// assert!(range(Vec3::new(0.0, 0.0, -5.0), forward).is_empty());
+    /// assert!(range(Vec3::new(-2.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0)).is_empty());
+    /// ```
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        match self.hit(ray, t_min, t_max) {
+            Some(hit_record) => vec![(hit_record.t, hit_record.t)],
+            None => Vec::new(),
+        }
+    }
+}
+
+// A rectangle with thickness: a box centered on `center` whose two large
+// faces span the perpendicular edges `edge_u` and `edge_v`, `thickness`
+// apart along their normal. For walls, floors and tabletops, which as
+// planes or quads would be infinite or leak light through their edges.
+pub struct Slab {
+    pub center: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+    pub thickness: f64,
+    pub material: Material,
+    // Replaces the material color on the two large faces, repeating every
+    // `texture_scale` world units along the edges
+    pub texture: Option<Arc<Texture>>,
+    pub texture_scale: f64,
+    pub texture_space: TextureSpace,
+}
+
+impl Slab {
+    /// The edges must be perpendicular and the thickness positive.
+    ///
+    /// ```
+    /// use rt::material::Material;
+    /// use rt::objects::{Object, Slab};
+    /// use rt::ray::Ray;
+    /// use rt::vector::Vec3;
+    ///
+    /// // A 4 x 2 tabletop 0.1 thick, turned 45 degrees about y
+    /// let (c, s) = (0.5f64.sqrt(), 0.5f64.sqrt());
+    /// let slab = Slab::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(4.0 * c, 0.0, -4.0 * s), Vec3::new(2.0 * s, 0.0, 2.0 * c), 0.1, Material::default()).unwrap();
+    ///
+    /// // Every face is hit from outside with its own outward normal
+    /// let u = slab.edge_u.normalize();
+    /// let v = slab.edge_v.normalize();
+    /// let n = Vec3::new(0.0, 1.0, 0.0);
+    /// for (normal, distance) in [(n, 0.05), (-n, 0.05), (u, 2.0), (-u, 2.0), (v, 1.0), (-v, 1.0)] {
+    ///     let ray = Ray::new(slab.center + normal * 10.0, -normal);
+    ///     let hit = slab.hit(&ray, 1e-9, f64::INFINITY).unwrap();
+    ///     assert!(hit.front_face);
+    ///     assert!((hit.geometric_normal - normal).length() < 1e-9);
+    ///     assert!((hit.t - (10.0 - distance)).abs() < 1e-9);
+    ///     assert_eq!(slab.uv(hit.point).is_some(), normal == n || normal == -n);
+    /// }
+    ///
+    /// // From inside, the exit is the back face; a grazing ray along the
+    /// // top still sees the slab's full width
+    /// let up = Ray::new(slab.center, n);
+    /// let exit = slab.hit(&up, 1e-9, f64::INFINITY).unwrap();
+    /// assert!(!exit.front_face && (exit.t - 0.05).abs() < 1e-9);
+    /// let along = Ray::new(slab.center - u * 3.0, u);
+    /// let range = slab.hit_range(&along, 0.0, f64::INFINITY);
+    /// assert_eq!(range.len(), 1);
+    /// assert!((range[0].0 - 1.0).abs() < 1e-9 && (range[0].1 - 5.0).abs() < 1e-9);
+    ///
+    /// // UVs run from 0 to 1 across the top from the corner at -u, -v
+    /// let corner = slab.center - slab.edge_u * 0.5 - slab.edge_v * 0.5 + n * 0.05;
+    /// let (a, b) = slab.uv(corner + slab.edge_u * 0.25 + slab.edge_v * 0.75).unwrap();
+    /// assert!((a - 0.25).abs() < 1e-9 && (b - 0.75).abs() < 1e-9);
+    ///
+    /// assert!(Slab::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 1.0), 0.1, Material::default()).is_err());
+    /// assert!(Slab::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.0, Material::default()).is_err());
+    /// ```
+    pub fn new(center: Vec3, edge_u: Vec3, edge_v: Vec3, thickness: f64, material: Material) -> Result<Self, String> {
+        let (length_u, length_v) = (edge_u.length(), edge_v.length());
+        if length_u <= 0.0 || length_v <= 0.0 {
+            return Err("a slab needs two edges of nonzero length".to_string());
+        }
+        if edge_u.dot(&edge_v).abs() > 1e-6 * length_u * length_v {
+            return Err("a slab's edges must be perpendicular".to_string());
+        }
+        if thickness <= 0.0 || thickness.is_nan() {
+            return Err(format!("a slab's thickness must be positive (got {})", thickness));
+        }
+        Ok(Slab {
+            center,
+            edge_u,
+            edge_v,
+            thickness,
+            material,
+            texture: None,
+            texture_scale: 1.0,
+            texture_space: TextureSpace::Object,
+        })
+    }
+    
+    pub fn with_texture(mut self, texture: Arc<Texture>, scale: f64) -> Self {
+        self.texture = Some(texture);
+        self.texture_scale = scale;
+        self
+    }
+    
+    pub fn with_texture_space(mut self, space: TextureSpace) -> Self {
+        self.texture_space = space;
+        self
+    }
+    
+    // Unit axes along each edge and the large faces' normal, with the half
+    // size of the slab along each
+    fn axes(&self) -> ([Vec3; 3], [f64; 3]) {
+        let u = self.edge_u.normalize();
+        let v = self.edge_v.normalize();
+        let axes = [u, v, u.cross(&v)];
+        (axes, [self.edge_u.length() / 2.0, self.edge_v.length() / 2.0, self.thickness / 2.0])
+    }
+    
+    // Where the ray enters and leaves the slab, each with the outward normal
+    // of the face it crosses there
+    fn crossings(&self, ray: &Ray) -> Option<((f64, Vec3), (f64, Vec3))> {
+        let (axes, half) = self.axes();
+        let offset = ray.origin - self.center;
+        let mut near = (f64::NEG_INFINITY, Vec3::zero());
+        let mut far = (f64::INFINITY, Vec3::zero());
+        for (axis, half) in axes.into_iter().zip(half) {
+            let (origin, direction) = (offset.dot(&axis), ray.direction.dot(&axis));
+            if direction == 0.0 {
+                if origin.abs() > half {
+                    return None;
+                }
+                continue;
+            }
+            let mut t0 = (-half - origin) / direction;
+            let mut t1 = (half - origin) / direction;
+            let mut normal = -axis;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+                normal = axis;
+            }
+            if t0 > near.0 {
+                near = (t0, normal);
+            }
+            if t1 < far.0 {
+                far = (t1, -normal);
+            }
+        }
+        if near.0 > far.0 {
+            return None;
+        }
+        Some((near, far))
+    }
+    
+    // Coordinates of a point on either large face, from 0 to 1 along each
+    // edge starting at the corner `center - edge_u / 2 - edge_v / 2`; None
+    // on the four narrow sides
+    pub fn uv(&self, point: Vec3) -> Option<(f64, f64)> {
+        let ([u, v, n], [half_u, half_v, half_n]) = self.axes();
+        let offset = point - self.center;
+        let (along_u, along_v) = (offset.dot(&u), offset.dot(&v));
+        let tolerance = 1e-9 * (half_u + half_v + half_n);
+        if (offset.dot(&n).abs() - half_n).abs() > tolerance || along_u.abs() > half_u + tolerance || along_v.abs() > half_v + tolerance {
+            return None;
+        }
+        Some((0.5 + along_u / (2.0 * half_u), 0.5 + along_v / (2.0 * half_v)))
+    }
+}
+
+impl Object for Slab {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let ((near, near_normal), (far, far_normal)) = self.crossings(ray)?;
+        let (t, normal) = if near >= t_min { (near, near_normal) } else { (far, far_normal) };
+        if t < t_min || t > t_max {
+            return None;
+        }
+        Some(HitRecord::new(ray.at(t), normal, t, ray))
     }
     
     fn material(&self) -> &Material {
         &self.material
     }
+    
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+    
+    fn kind(&self) -> &'static str {
+        "slab"
+    }
+    
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    
+    fn memory_estimate(&self) -> usize {
+        mem::size_of_val(self) + self.texture.as_ref().map_or(0, |texture| texture.memory_estimate())
+    }
+    
+    fn shared_memory(&self) -> Vec<(usize, usize)> {
+        self.texture.iter().map(|texture| texture.shared_memory()).collect()
+    }
+    
+    fn bounding_box(&self) -> Option<Aabb> {
+        let ([u, v, n], [half_u, half_v, half_n]) = self.axes();
+        let extent = |axis: fn(&Vec3) -> f64| half_u * axis(&u).abs() + half_v * axis(&v).abs() + half_n * axis(&n).abs();
+        Some(Aabb::around(self.center, Vec3::new(extent(|a| a.x), extent(|a| a.y), extent(|a| a.z))))
+    }
+    
+    fn albedo(&self, hit: &HitRecord, footprint: f64) -> Color {
+        let Some(texture) = &self.texture else {
+            return self.material.color;
+        };
+        if self.uv(hit.point).is_none() {
+            return self.material.color;
+        }
+        let ([u, v, _], [half_u, half_v, _]) = self.axes();
+        let origin = match self.texture_space {
+            TextureSpace::Object => self.center - u * half_u - v * half_v,
+            TextureSpace::World => Vec3::zero(),
+        };
+        let offset = (hit.point - origin) / self.texture_scale;
+        texture.sample(offset, offset.dot(&u), offset.dot(&v), footprint / self.texture_scale)
+    }
+    
+    fn hit_range(&self, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+        match self.crossings(ray) {
+            Some(((near, _), (far, _))) => clip_interval(near, far, t_min, t_max),
+            None => Vec::new(),
+        }
+    }
 }
@@ -0,0 +1,122 @@
+use crate::image::color::Color;
+use crate::image::Image;
+use crate::scene::AuxBuffers;
+
+// Toon-style outlines drawn from the depth and normal buffers. Neighbouring
+// pixels are split by an edge where one sees the background and the other
+// does not, where their depths jump, or where their normals turn sharply.
+// The line goes on the nearer pixel, so silhouettes are outlined on the
+// object in front.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outline {
+    pub color: Color,
+    // Depth jump that counts as an edge, relative to the nearer depth. The
+    // jump is measured against how fast depth already changes on either
+    // side, so surfaces seen at a grazing angle are not outlined.
+    pub depth_threshold: f64,
+    // Angle in degrees between neighbouring normals that counts as an edge
+    pub angle_threshold: f64,
+    // Line width in pixels
+    pub thickness: u32,
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Outline {
+            color: Color::BLACK,
+            depth_threshold: 0.1,
+            angle_threshold: 30.0,
+            thickness: 1,
+        }
+    }
+}
+
+impl Outline {
+    pub fn apply(&self, image: &mut Image, aux: &AuxBuffers) {
+        let (width, height) = (image.width as usize, image.height as usize);
+        let mut near = vec![false; width * height];
+        let mut far = vec![false; width * height];
+        
+        // Each pair of horizontal and vertical neighbours, with the pixels
+        // beyond them on either side for the depth slope
+        let mut check = |before: Option<usize>, p: usize, q: usize, after: Option<usize>| {
+            if self.is_edge(aux, before, p, q, after) {
+                let (front, back) = if aux.depth[p] <= aux.depth[q] { (p, q) } else { (q, p) };
+                near[front] = true;
+                far[back] = true;
+            }
+        };
+        for y in 0..height {
+            for x in 0..width.saturating_sub(1) {
+                let p = y * width + x;
+                check((x > 0).then(|| p - 1), p, p + 1, (x + 2 < width).then_some(p + 2));
+            }
+        }
+        for y in 0..height.saturating_sub(1) {
+            for x in 0..width {
+                let p = y * width + x;
+                check((y > 0).then(|| p - width), p, p + width, (y + 2 < height).then_some(p + 2 * width));
+            }
+        }
+        
+        // Even widths also take the pixel behind the edge, then both grow
+        // by the same radius
+        let mut lines = near;
+        if self.thickness.is_multiple_of(2) {
+            lines.iter_mut().zip(&far).for_each(|(line, &far)| *line |= far);
+        }
+        let radius = (self.thickness.max(1) as i64 - 1) / 2;
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let covered = (-radius..=radius).any(|dy| {
+                    (-radius..=radius).any(|dx| {
+                        let (nx, ny) = (x + dx, y + dy);
+                        dx * dx + dy * dy <= radius * radius
+                            && (0..width as i64).contains(&nx)
+                            && (0..height as i64).contains(&ny)
+                            && lines[ny as usize * width + nx as usize]
+                    })
+                });
+                if covered {
+                    image.set_pixel(x as usize, y as usize, self.color);
+                }
+            }
+        }
+    }
+    
+    fn is_edge(&self, aux: &AuxBuffers, before: Option<usize>, p: usize, q: usize, after: Option<usize>) -> bool {
+        let (depth_p, depth_q) = (aux.depth[p], aux.depth[q]);
+        if depth_p.is_finite() != depth_q.is_finite() {
+            return true;
+        }
+        if !depth_p.is_finite() {
+            return false;
+        }
+        
+        let cos_angle = aux.normal[p].dot(&aux.normal[q]);
+        if cos_angle < self.angle_threshold.to_radians().cos() {
+            return true;
+        }
+        
+        // Inverse depth changes linearly across the image on a flat
+        // surface, so a jump is compared with the slopes just outside the
+        // pair; where there is no usable slope, it is compared with zero
+        let inverse = |i: usize| 1.0 / aux.depth[i];
+        let jump = inverse(q) - inverse(p);
+        let slope = |from: Option<usize>, to: usize, forward: bool| {
+            from.filter(|&i| aux.depth[i].is_finite()).map(|i| {
+                if forward { inverse(i) - inverse(to) } else { inverse(to) - inverse(i) }
+            })
+        };
+        let slope_before = slope(before, p, false);
+        let slope_after = slope(after, q, true);
+        let tolerance = self.depth_threshold * inverse(p).max(inverse(q));
+        let differs = |slope: Option<f64>| (jump - slope.unwrap_or(0.0)).abs() > tolerance;
+        match (slope_before, slope_after) {
+            (None, None) => differs(None),
+            (Some(_), None) => differs(slope_before),
+            (None, Some(_)) => differs(slope_after),
+            (Some(_), Some(_)) => differs(slope_before) && differs(slope_after),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::vector::Vec3;
+use crate::objects::{Object, Sphere, Cube, Plane, CheckerPlane};
+use crate::bvh::Aabb;
+
+// Checks for objects placed into each other by mistake, such as a cube
+// sunk halfway into the floor, which renders with odd shadows along the
+// seam. Spheres, cubes and planes are tested exactly; anything else only
+// by its bounding box.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overlap {
+    // The shapes intersect, this far at the deepest
+    Penetrating(f64),
+    // The bounding boxes intersect, but the shapes are not tested exactly
+    Possible,
+}
+
+// What an object looks like to the overlap tests
+enum Shape {
+    Sphere { center: Vec3, radius: f64 },
+    Cube { center: Vec3, half_size: f64 },
+    Plane { point: Vec3, normal: Vec3 },
+    Other,
+    Unbounded,
+}
+
+fn shape(object: &dyn Object) -> Shape {
+    let any = object.as_any();
+    if let Some(sphere) = any.downcast_ref::<Sphere>() {
+        Shape::Sphere { center: sphere.center, radius: sphere.radius }
+    } else if let Some(cube) = any.downcast_ref::<Cube>() {
+        Shape::Cube { center: cube.center, half_size: cube.size / 2.0 }
+    } else if let Some(plane) = any.downcast_ref::<Plane>() {
+        Shape::Plane { point: plane.point, normal: plane.normal }
+    } else if let Some(plane) = any.downcast_ref::<CheckerPlane>() {
+        Shape::Plane { point: plane.point, normal: plane.normal }
+    } else if object.bounding_box().is_some() {
+        Shape::Other
+    } else {
+        Shape::Unbounded
+    }
+}
+
+/// How two objects overlap, or None when they are apart, touch, or
+/// intersect by no more than `tolerance`, which lets objects resting on
+/// each other sit a hair too low. Planes never overlap each other, since
+/// walls meeting a floor are what planes are for.
+///
+/// ```
+/// use rt::material::Material;
+/// use rt::objects::{Cube, Cylinder, Plane, Sphere};
+/// use rt::overlap::{between, Overlap};
+/// use rt::vector::Vec3;
+///
+/// let m = Material::default();
+/// let floor = Plane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), m);
+/// let sphere = |x: f64, y: f64| Sphere::new(Vec3::new(x, y, 0.0), 1.0, m);
+/// let cube = |x: f64, y: f64| Cube::new(Vec3::new(x, y, 0.0), 2.0, m);
+/// let depth = |overlap: Option<Overlap>| match overlap {
+///     Some(Overlap::Penetrating(depth)) => (depth * 1e9).round() / 1e9,
+///     other => panic!("expected an exact depth, got {:?}", other),
+/// };
+///
+/// // Sphere and sphere: touching, apart, sunk 0.5 into each other
+/// assert_eq!(between(&sphere(0.0, 0.0), &sphere(2.0, 0.0), 0.001), None);
+/// assert_eq!(between(&sphere(0.0, 0.0), &sphere(3.0, 0.0), 0.001), None);
+/// assert_eq!(depth(between(&sphere(0.0, 0.0), &sphere(1.5, 0.0), 0.001)), 0.5);
+/// // Sphere and plane
+/// assert_eq!(between(&sphere(0.0, 1.0), &floor, 0.001), None);
+/// assert_eq!(between(&floor, &sphere(0.0, 5.0), 0.001), None);
+/// assert_eq!(depth(between(&sphere(0.0, 0.25), &floor, 0.001)), 0.75);
+/// // Cube and cube
+/// assert_eq!(between(&cube(0.0, 0.0), &cube(2.0, 1.0), 0.001), None);
+/// assert_eq!(between(&cube(0.0, 0.0), &cube(2.5, 0.0), 0.001), None);
+/// assert_eq!(depth(between(&cube(0.0, 0.0), &cube(1.75, 0.5), 0.001)), 0.25);
+/// // Cube and plane, the cube half sunk into the floor
+/// assert_eq!(between(&cube(0.0, 1.0), &floor, 0.001), None);
+/// assert_eq!(between(&cube(0.0, 3.0), &floor, 0.001), None);
+/// assert_eq!(depth(between(&floor, &cube(0.0, 0.0), 0.001)), 1.0);
+///
+/// // Resting a fraction of the tolerance too low is not flagged
+/// assert_eq!(between(&sphere(0.0, 0.9995), &floor, 0.001), None);
+/// assert!(between(&sphere(0.0, 0.9995), &floor, 0.0).is_some());
+///
+/// // Other shapes only get a bounding box test, which flags this post
+/// // standing just clear of the sphere
+/// let post = Cylinder::new(Vec3::new(0.9, 1.0, 0.9), 0.2, 2.0, m);
+/// assert_eq!(between(&post, &sphere(0.0, 1.0), 0.001), Some(Overlap::Possible));
+/// assert_eq!(between(&post, &floor, 0.001), None);
+/// ```
+pub fn between(a: &dyn Object, b: &dyn Object, tolerance: f64) -> Option<Overlap> {
+    // Depth of the overlap, and whether it is exact or from bounding boxes
+    let (depth, exact) = match (shape(a), shape(b)) {
+        (Shape::Unbounded, _) | (_, Shape::Unbounded) | (Shape::Plane { .. }, Shape::Plane { .. }) => return None,
+        (Shape::Sphere { center: a, radius: ra }, Shape::Sphere { center: b, radius: rb }) => {
+            (ra + rb - (a - b).length(), true)
+        }
+        (Shape::Sphere { center, radius }, Shape::Plane { point, normal })
+        | (Shape::Plane { point, normal }, Shape::Sphere { center, radius }) => {
+            (radius - (center - point).dot(&normal).abs(), true)
+        }
+        (Shape::Cube { center: a, half_size: ha }, Shape::Cube { center: b, half_size: hb }) => {
+            (box_depth(a, Vec3::splat(ha), b, Vec3::splat(hb)), true)
+        }
+        (Shape::Cube { center, half_size }, Shape::Plane { point, normal })
+        | (Shape::Plane { point, normal }, Shape::Cube { center, half_size }) => {
+            (plane_depth(center, Vec3::splat(half_size), point, normal), true)
+        }
+        (Shape::Sphere { center, radius }, Shape::Cube { center: box_center, half_size })
+        | (Shape::Cube { center: box_center, half_size }, Shape::Sphere { center, radius }) => {
+            (sphere_box_depth(center, radius, box_center, Vec3::splat(half_size)), true)
+        }
+        (Shape::Plane { point, normal }, _) => {
+            let bounds = b.bounding_box()?;
+            (plane_depth(bounds.centroid(), half_extents(&bounds), point, normal), false)
+        }
+        (_, Shape::Plane { point, normal }) => {
+            let bounds = a.bounding_box()?;
+            (plane_depth(bounds.centroid(), half_extents(&bounds), point, normal), false)
+        }
+        _ => {
+            let (a, b) = (a.bounding_box()?, b.bounding_box()?);
+            (box_depth(a.centroid(), half_extents(&a), b.centroid(), half_extents(&b)), false)
+        }
+    };
+    match (depth > tolerance, exact) {
+        (false, _) => None,
+        (true, true) => Some(Overlap::Penetrating(depth)),
+        (true, false) => Some(Overlap::Possible),
+    }
+}
+
+fn half_extents(bounds: &Aabb) -> Vec3 {
+    (bounds.max - bounds.min) * 0.5
+}
+
+// How far two axis-aligned boxes would have to move apart to only touch,
+// negative when there is a gap
+fn box_depth(a: Vec3, half_a: Vec3, b: Vec3, half_b: Vec3) -> f64 {
+    let reach = half_a + half_b;
+    (reach.x - (a.x - b.x).abs())
+        .min(reach.y - (a.y - b.y).abs())
+        .min(reach.z - (a.z - b.z).abs())
+}
+
+// How far a box pokes through to the far side of a plane, from whichever
+// side its center is on
+fn plane_depth(center: Vec3, half: Vec3, point: Vec3, normal: Vec3) -> f64 {
+    let reach = half.x * normal.x.abs() + half.y * normal.y.abs() + half.z * normal.z.abs();
+    reach - (center - point).dot(&normal).abs()
+}
+
+fn sphere_box_depth(center: Vec3, radius: f64, box_center: Vec3, half: Vec3) -> f64 {
+    let offset = center - box_center;
+    let outside = Vec3::new(
+        (offset.x.abs() - half.x).max(0.0),
+        (offset.y.abs() - half.y).max(0.0),
+        (offset.z.abs() - half.z).max(0.0),
+    );
+    if outside.length() > 0.0 {
+        radius - outside.length()
+    } else {
+        // Center inside: the sphere would have to leave by the nearest face
+        let inside = (half.x - offset.x.abs()).min(half.y - offset.y.abs()).min(half.z - offset.z.abs());
+        radius + inside
+    }
+}
@@ -0,0 +1,216 @@
+use crate::vector::Vec3;
+use crate::image::color::Color;
+use crate::ray::Ray;
+use crate::scene::Scene;
+use crate::light::{Light, LightShape};
+use crate::rng::Rng;
+use crate::medium::{self, MediumStack};
+use crate::bvh::Aabb;
+use crate::sampling;
+use crate::vector::Onb;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+// Photons are traced in independently seeded batches so the map does not
+// depend on how rayon splits the work
+const BATCH_SIZE: u32 = 4096;
+const MAX_BOUNCES: u32 = 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+    pub position: Vec3,
+    pub power: Color,
+}
+
+// Caustic photon map: photons that reached a diffuse surface after at least
+// one specular (mirror or glass) bounce, bucketed in a uniform grid whose
+// cells are as large as the gather radius
+pub struct PhotonMap {
+    radius: f64,
+    cells: HashMap<(i64, i64, i64), Vec<Photon>>,
+}
+
+impl PhotonMap {
+    /// Traces `photons_per_light` photons from every light. Directional
+    /// lights send a parallel beam over the bounded objects, so a glass
+    /// ball under the sun focuses a spot on the floor below it:
+    ///
+    /// ```
+    /// use rt::photon::PhotonMap;
+    /// use rt::scene::Scene;
+    /// use rt::vector::Vec3;
+    ///
+    /// let scene: Scene = r#"{
+    ///     "lights": [{ "type": "directional", "direction": [0, -1, 0], "intensity": 1 }],
+    ///     "objects": [
+    ///         { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0] },
+    ///         { "type": "sphere", "center": [0, 1.6, 0], "radius": 1, "material": { "preset": "glass" } }
+    ///     ]
+    /// }"#.parse().unwrap();
+    /// let map = PhotonMap::build(&scene, 20_000, 0.1, 1);
+    /// assert!(!map.is_empty());
+    /// // Brighter than the sunlight itself under the ball, and dark outside
+    /// // its shadow
+    /// let focus = map.irradiance(Vec3::zero()).r;
+    /// assert!(focus > 2.0, "{}", focus);
+    /// assert_eq!(map.irradiance(Vec3::new(3.0, 0.0, 0.0)).r, 0.0);
+    /// ```
+    pub fn build(scene: &Scene, photons_per_light: u32, radius: f64, seed: u64) -> Self {
+        let mut map = PhotonMap { radius, cells: HashMap::new() };
+        // Directional light is aimed at the bounded objects; whatever it
+        // reaches of an unbounded one directly is never a caustic
+        let bounds = scene.objects().iter()
+            .filter_map(|object| object.bounding_box())
+            .reduce(|total, bounds| total.union(&bounds));
+        
+        for (light_index, light) in scene.lights.iter().enumerate() {
+            if matches!(light.shape, LightShape::Directional { .. }) && bounds.is_none() {
+                continue;
+            }
+            let batches = photons_per_light.div_ceil(BATCH_SIZE);
+            let photons: Vec<Photon> = (0..batches).into_par_iter().flat_map_iter(|batch| {
+                let mut rng = Rng::keyed(seed, &[light_index as u64, batch as u64]);
+                let count = BATCH_SIZE.min(photons_per_light - batch * BATCH_SIZE);
+                let mut stored = Vec::new();
+                for _ in 0..count {
+                    let (ray, power) = emit(light, photons_per_light, bounds, &mut rng);
+                    trace_photon(scene, ray, power, &mut rng, &mut stored);
+                }
+                stored
+            }).collect();
+            
+            for photon in photons {
+                map.cells.entry(map.cell(photon.position)).or_default().push(photon);
+            }
+        }
+        
+        map
+    }
+    
+    pub fn len(&self) -> usize {
+        self.cells.values().map(Vec::len).sum()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+    
+    fn cell(&self, point: Vec3) -> (i64, i64, i64) {
+        (
+            (point.x / self.radius).floor() as i64,
+            (point.y / self.radius).floor() as i64,
+            (point.z / self.radius).floor() as i64,
+        )
+    }
+    
+    // Caustic irradiance at a point: photon power within the gather radius
+    // divided by the disc area
+    pub fn irradiance(&self, point: Vec3) -> Color {
+        let (cx, cy, cz) = self.cell(point);
+        let radius_squared = self.radius * self.radius;
+        let mut total = Color::BLACK;
+        
+        for x in cx - 1..=cx + 1 {
+            for y in cy - 1..=cy + 1 {
+                for z in cz - 1..=cz + 1 {
+                    let Some(photons) = self.cells.get(&(x, y, z)) else {
+                        continue;
+                    };
+                    for photon in photons {
+                        if (photon.position - point).length_squared() <= radius_squared {
+                            total = total + photon.power;
+                        }
+                    }
+                }
+            }
+        }
+        
+        total / (PI * radius_squared)
+    }
+}
+
+// Power is split evenly over the photons of a light, scaled so the photon
+// density at distance d matches intensity / d^2
+fn emit(light: &Light, photon_count: u32, bounds: Option<Aabb>, rng: &mut Rng) -> (Ray, Color) {
+    let power = light.color * (light.intensity * 4.0 * PI / photon_count as f64);
+    match light.shape {
+        LightShape::Point => {
+            let direction = Vec3::random_unit_vector(rng);
+            (Ray::new(light.position, direction), power * light.emission_factor(direction))
+        }
+        LightShape::Directional { angular_radius } => {
+            // A parallel beam from a disk across `bounds`, set back outside
+            // them. Its intensity is irradiance, so the photons share it
+            // times the disk's area.
+            let bounds = bounds.expect("directional photons need bounded objects");
+            let radius = (bounds.max - bounds.min).length() * 0.5;
+            let disk = Vec3::random_in_unit_disk(rng) * radius;
+            let basis = Onb::from_w(light.direction);
+            let origin = bounds.centroid() - light.direction * (radius + 1.0) + basis.local(disk);
+            let direction = -sampling::cone_direction(-light.direction, angular_radius, rng.next_f64(), rng.next_f64());
+            let area = PI * radius * radius;
+            (Ray::new(origin, direction), light.color * (light.intensity * area / photon_count as f64))
+        }
+        LightShape::Quad { edge_u, edge_v } => {
+            let origin = light.position + edge_u * rng.next_f64() + edge_v * rng.next_f64();
+            let area_normal = edge_u.cross(&edge_v);
+            let normal = area_normal.normalize();
+            let mut direction = Vec3::random_unit_vector(rng);
+            if direction.dot(&normal) < 0.0 {
+                direction = -direction;
+            }
+            // Weighted by the emission cosine and the panel area, as in
+            // direct lighting
+            let weight = direction.dot(&normal) * area_normal.length();
+            (Ray::new(origin, direction), power * weight)
+        }
+    }
+}
+
+fn trace_photon(scene: &Scene, mut ray: Ray, mut power: Color, rng: &mut Rng, stored: &mut Vec<Photon>) {
+    let mut specular_path = false;
+    let mut media = MediumStack::new();
+    
+    for _ in 0..MAX_BOUNCES {
+        let Some((hit_record, object)) = scene.hit(&ray, 0.001, f64::INFINITY) else {
+            return;
+        };
+        let material = object.material_at(&hit_record);
+        
+        // Pick what happens at this surface in proportion to the material's
+        // transparency and reflectivity. Reflection is picked with the
+        // strongest channel's probability, and the photon is tinted by how
+        // the channels compare to it.
+        let reflect_probability = material.reflectivity.max_component();
+        let choice = rng.next_f64();
+        if choice < material.transparency {
+            let id = medium::surface(object, &hit_record);
+            let (from, to) = media.indices(id, material.refractive_index, hit_record.front_face);
+            let normal = hit_record.facing_shading_normal(&ray);
+            let direction = match ray.direction.refract(&normal, from / to) {
+                Some(direction) => {
+                    media.cross(id, material.refractive_index, hit_record.front_face);
+                    direction
+                }
+                None => ray.direction.reflect(&normal),
+            };
+            let offset = if direction.dot(&hit_record.geometric_normal) < 0.0 { -0.001 } else { 0.001 };
+            ray = Ray::new(hit_record.point + hit_record.geometric_normal * offset, direction);
+            power = power * material.color;
+            specular_path = true;
+        } else if choice < material.transparency + reflect_probability {
+            let direction = ray.direction.reflect(&hit_record.facing_shading_normal(&ray));
+            ray = Ray::new(hit_record.point + hit_record.geometric_normal * 0.001, direction);
+            power = power * material.color * (material.reflectivity / reflect_probability);
+            specular_path = true;
+        } else {
+            // Diffuse surface: only photons that came through a mirror or
+            // glass are caustics; direct light is handled by regular shading
+            if specular_path && material.diffuse > 0.0 {
+                stored.push(Photon { position: hit_record.point, power });
+            }
+            return;
+        }
+    }
+}
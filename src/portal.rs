@@ -0,0 +1,162 @@
+use crate::vector::Vec3;
+use crate::ray::Ray;
+use crate::rng::Rng;
+use std::f64::consts::PI;
+
+// A rectangle marking an opening, such as a window, that most of the light
+// from outside comes in through. Hemisphere sampling sends part of its rays
+// straight at portals instead of spending them on walls. The edges must be
+// perpendicular.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Portal {
+    pub corner: Vec3,
+    pub edge_u: Vec3,
+    pub edge_v: Vec3,
+}
+
+// The portal as seen from one point, in a frame where the rectangle lies in
+// the plane z = z0 < 0 and spans x0..x1 by y0..y1
+pub struct SphericalRectangle {
+    origin: Vec3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    x0: f64,
+    x1: f64,
+    y0: f64,
+    y1: f64,
+    z0: f64,
+    b0: f64,
+    b1: f64,
+    k: f64,
+    pub solid_angle: f64,
+}
+
+impl Portal {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3) -> Result<Self, String> {
+        let (length_u, length_v) = (edge_u.length(), edge_v.length());
+        if length_u <= 0.0 || length_v <= 0.0 {
+            return Err("a portal needs two edges of nonzero length".to_string());
+        }
+        if edge_u.dot(&edge_v).abs() > 1e-6 * length_u * length_v {
+            return Err("a portal's edges must be perpendicular".to_string());
+        }
+        Ok(Portal { corner, edge_u, edge_v })
+    }
+    
+    pub fn corners(&self) -> [Vec3; 4] {
+        [self.corner, self.corner + self.edge_u, self.corner + self.edge_u + self.edge_v, self.corner + self.edge_v]
+    }
+    
+    // Whether the ray passes through the portal
+    pub fn admits(&self, ray: &Ray) -> bool {
+        let n = self.edge_u.cross(&self.edge_v);
+        let denom = n.dot(&ray.direction);
+        if denom == 0.0 {
+            return false;
+        }
+        let t = n.dot(&(self.corner - ray.origin)) / denom;
+        if t <= 0.0 {
+            return false;
+        }
+        let local = ray.at(t) - self.corner;
+        let u = local.dot(&self.edge_u) / self.edge_u.dot(&self.edge_u);
+        let v = local.dot(&self.edge_v) / self.edge_v.dot(&self.edge_v);
+        (0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v)
+    }
+    
+    /// The portal as seen from `origin`, for sampling directions uniformly
+    /// over its solid angle (Ureña et al., "An Area-Preserving
+    /// Parametrization for Spherical Rectangles"). None from points in the
+    /// portal's plane, which see no solid angle.
+    ///
+    /// ```
+    /// use rt::portal::Portal;
+    /// use rt::ray::Ray;
+    /// use rt::rng::Rng;
+    /// use rt::vector::Vec3;
+    ///
+    /// // A 2 x 1 window 3 units in front of the origin, centered on the axis
+    /// let portal = Portal::new(Vec3::new(-1.0, -0.5, -3.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).unwrap();
+    /// let seen = portal.seen_from(Vec3::zero()).unwrap();
+    /// let (a, b, d) = (1.0f64, 0.5f64, 3.0f64);
+    /// let exact = 4.0 * (a * b / ((a * a + d * d) * (b * b + d * d)).sqrt()).asin();
+    /// assert!((seen.solid_angle - exact).abs() < 1e-12);
+    ///
+    /// // Every sampled direction goes through the window, and the samples
+    /// // fall evenly on either side of it
+    /// let mut rng = Rng::new(7);
+    /// let mut left = 0;
+    /// for _ in 0..1000 {
+    ///     let direction = seen.sample(&mut rng);
+    ///     assert!((direction.length() - 1.0).abs() < 1e-9);
+    ///     assert!(portal.admits(&Ray::new(Vec3::zero(), direction)));
+    ///     if direction.x < 0.0 {
+    ///         left += 1;
+    ///     }
+    /// }
+    /// assert!((450..550).contains(&left));
+    /// assert!(portal.seen_from(Vec3::new(5.0, 0.0, -3.0)).is_none());
+    /// ```
+    pub fn seen_from(&self, origin: Vec3) -> Option<SphericalRectangle> {
+        let (length_u, length_v) = (self.edge_u.length(), self.edge_v.length());
+        let x = self.edge_u / length_u;
+        let y = self.edge_v / length_v;
+        let mut z = x.cross(&y);
+        let d = self.corner - origin;
+        let (x0, y0) = (d.dot(&x), d.dot(&y));
+        let mut z0 = d.dot(&z);
+        if z0.abs() < 1e-9 {
+            return None;
+        }
+        if z0 > 0.0 {
+            z0 = -z0;
+            z = -z;
+        }
+        let (x1, y1) = (x0 + length_u, y0 + length_v);
+        
+        // Normals of the planes through the origin and each edge, and the
+        // angles between them
+        let v00 = Vec3::new(x0, y0, z0);
+        let v01 = Vec3::new(x0, y1, z0);
+        let v10 = Vec3::new(x1, y0, z0);
+        let v11 = Vec3::new(x1, y1, z0);
+        let n0 = v00.cross(&v10).normalize();
+        let n1 = v10.cross(&v11).normalize();
+        let n2 = v11.cross(&v01).normalize();
+        let n3 = v01.cross(&v00).normalize();
+        let angle = |a: Vec3, b: Vec3| (-a.dot(&b)).clamp(-1.0, 1.0).acos();
+        let (g0, g1, g2, g3) = (angle(n0, n1), angle(n1, n2), angle(n2, n3), angle(n3, n0));
+        let k = 2.0 * PI - g2 - g3;
+        let solid_angle = g0 + g1 - k;
+        if solid_angle.is_nan() || solid_angle <= 0.0 {
+            return None;
+        }
+        Some(SphericalRectangle { origin, x, y, z, x0, x1, y0, y1, z0, b0: n0.z, b1: n2.z, k, solid_angle })
+    }
+}
+
+impl SphericalRectangle {
+    // A unit direction from the origin through the portal, uniform over its
+    // solid angle
+    pub fn sample(&self, rng: &mut Rng) -> Vec3 {
+        let (u, v) = (rng.next_f64(), rng.next_f64());
+        
+        // Pick the x coordinate so the strip left of it covers u of the
+        // solid angle
+        let au = u * self.solid_angle + self.k;
+        let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+        let cu = (fu.signum() / (fu * fu + self.b0 * self.b0).sqrt()).clamp(-1.0, 1.0);
+        let xu = (-(cu * self.z0) / (1.0 - cu * cu).sqrt()).clamp(self.x0, self.x1);
+        
+        // Then y, uniform in solid angle along that line
+        let d = (xu * xu + self.z0 * self.z0).sqrt();
+        let h0 = self.y0 / (d * d + self.y0 * self.y0).sqrt();
+        let h1 = self.y1 / (d * d + self.y1 * self.y1).sqrt();
+        let hv = h0 + v * (h1 - h0);
+        let yv = if hv * hv < 1.0 - 1e-12 { hv * d / (1.0 - hv * hv).sqrt() } else { self.y1 };
+        
+        let point = self.origin + self.x * xu + self.y * yv + self.z * self.z0;
+        (point - self.origin).normalize()
+    }
+}
@@ -1,4 +1,5 @@
 use crate::vector::Vec3;
+use std::sync::Once;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
@@ -7,11 +8,21 @@ pub struct Ray {
 }
 
 impl Ray {
+    // A direction that cannot be normalized (zero length or not finite)
+    // would turn every intersection into NaNs, and always means a bug in the
+    // caller. Debug builds panic; release builds warn once and trace along
+    // +Z instead so the render can finish.
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Ray {
-            origin,
-            direction: direction.normalize(),
+        let mut direction = direction.normalize();
+        if !direction.is_finite() || direction.length_squared() == 0.0 {
+            debug_assert!(false, "degenerate ray direction {:?} from {:?}", direction, origin);
+            static WARNING: Once = Once::new();
+            WARNING.call_once(|| {
+                crate::warn!("degenerate ray direction {:?} from {:?}; using +Z", direction, origin);
+            });
+            direction = Vec3::new(0.0, 0.0, 1.0);
         }
+        Ray { origin, direction }
     }
     
     pub fn at(&self, t: f64) -> Vec3 {
@@ -22,9 +33,18 @@ impl Ray {
 #[derive(Debug, Clone, Copy)]
 pub struct HitRecord {
     pub point: Vec3,
-    pub normal: Vec3,
+    // True surface orientation, facing the incoming ray. Used for ray
+    // origin offsets and inside/outside decisions.
+    pub geometric_normal: Vec3,
+    // Normal used for lighting and reflection/refraction directions. Equal
+    // to the geometric normal unless a primitive perturbs it (smooth
+    // shading, bump or normal maps).
+    pub shading_normal: Vec3,
     pub t: f64,
     pub front_face: bool,
+    // Which part of a compound object was hit, for looking up its
+    // material; always 0 for simple objects
+    pub part: u32,
 }
 
 impl HitRecord {
@@ -34,9 +54,55 @@ impl HitRecord {
         
         HitRecord {
             point,
-            normal,
+            geometric_normal: normal,
+            shading_normal: normal,
             t,
             front_face,
+            part: 0,
+        }
+    }
+    
+    // Sets an outward-facing shading normal, flipped to the same side as the
+    // geometric normal
+    pub fn set_shading_normal(&mut self, outward_normal: Vec3) {
+        self.shading_normal = if self.front_face { outward_normal } else { -outward_normal };
+    }
+    
+    // Shading normal to light with when viewed along `ray`. Interpolated
+    // normals can face away from the viewer near silhouettes, which would
+    // light the back side; fall back to the geometric normal there.
+    pub fn facing_shading_normal(&self, ray: &Ray) -> Vec3 {
+        if self.shading_normal.dot(&ray.direction) < 0.0 {
+            self.shading_normal
+        } else {
+            self.geometric_normal
+        }
+    }
+}
+
+// Four rays laid out structure-of-arrays so the packet intersection
+// routines can process all lanes with straight-line arithmetic.
+#[derive(Debug, Clone, Copy)]
+pub struct RayPacket4 {
+    pub rays: [Ray; 4],
+    pub origin_x: [f64; 4],
+    pub origin_y: [f64; 4],
+    pub origin_z: [f64; 4],
+    pub dir_x: [f64; 4],
+    pub dir_y: [f64; 4],
+    pub dir_z: [f64; 4],
+}
+
+impl RayPacket4 {
+    pub fn new(rays: [Ray; 4]) -> Self {
+        RayPacket4 {
+            rays,
+            origin_x: rays.map(|r| r.origin.x),
+            origin_y: rays.map(|r| r.origin.y),
+            origin_z: rays.map(|r| r.origin.z),
+            dir_x: rays.map(|r| r.direction.x),
+            dir_y: rays.map(|r| r.direction.y),
+            dir_z: rays.map(|r| r.direction.z),
         }
     }
 }
@@ -0,0 +1,201 @@
+use crate::vector::Vec3;
+use crate::bvh::Aabb;
+use std::io::{self, Write};
+
+// How far rays that hit nothing are drawn, in scene units
+pub const MISS_LENGTH: f64 = 10.0;
+
+// Markers at hit points are this share of the size of all the segments
+// together, so they show at any scene scale without hiding the paths
+const MARKER_SHARE: f64 = 0.004;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RayKind {
+    Primary,
+    Shadow,
+    Reflect,
+    Refract,
+}
+
+impl RayKind {
+    pub const ALL: [RayKind; 4] = [RayKind::Primary, RayKind::Shadow, RayKind::Reflect, RayKind::Refract];
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            RayKind::Primary => "primary",
+            RayKind::Shadow => "shadow",
+            RayKind::Reflect => "reflect",
+            RayKind::Refract => "refract",
+        }
+    }
+    
+    // Vertex color in PLY files
+    fn color(&self) -> [u8; 3] {
+        match self {
+            RayKind::Primary => [255, 255, 255],
+            RayKind::Shadow => [255, 200, 0],
+            RayKind::Reflect => [0, 160, 255],
+            RayKind::Refract => [255, 0, 160],
+        }
+    }
+}
+
+// One ray of a traced pixel, from its origin to what it hit, or
+// MISS_LENGTH along it when it hit nothing. Shadow rays end at whatever
+// blocks them, or at the light.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaySegment {
+    pub pixel: (u32, u32),
+    pub kind: RayKind,
+    // Bounces before this ray; 0 for the camera ray and the shadow rays
+    // from its hit
+    pub bounce: i32,
+    pub from: Vec3,
+    pub to: Vec3,
+    // Whether the ray stopped at a surface
+    pub hit: bool,
+}
+
+// Side of a marker, from the size of the box around all the segments
+fn marker_size(segments: &[RaySegment]) -> f64 {
+    let bounds = segments.iter()
+        .flat_map(|segment| [segment.from, segment.to])
+        .map(|point| Aabb::new(point, point))
+        .reduce(|a, b| a.union(&b));
+    bounds.map_or(0.0, |bounds| (bounds.max - bounds.min).length() * MARKER_SHARE)
+}
+
+// Corners of a small octahedron around `point`, and its faces as indices
+// into them
+fn marker(point: Vec3, size: f64) -> ([Vec3; 6], [[usize; 3]; 8]) {
+    let h = size / 2.0;
+    let corners = [
+        point + Vec3::new(h, 0.0, 0.0),
+        point - Vec3::new(h, 0.0, 0.0),
+        point + Vec3::new(0.0, h, 0.0),
+        point - Vec3::new(0.0, h, 0.0),
+        point + Vec3::new(0.0, 0.0, h),
+        point - Vec3::new(0.0, 0.0, h),
+    ];
+    let faces = [[0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4], [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5]];
+    (corners, faces)
+}
+
+/// Writes segments as an OBJ file of lines, one object per kind of ray, plus
+/// an object of small octahedra marking where rays hit something, for
+/// viewing the paths in a 3D program next to the scene.
+///
+/// ```
+/// use rt::ray_paths::{write_obj, RayKind, RaySegment};
+/// use rt::vector::Vec3;
+///
+/// let segment = |kind, from, to| RaySegment { pixel: (0, 0), kind, bounce: 0, from, to, hit: true };
+/// let segments = [
+///     segment(RayKind::Primary, Vec3::zero(), Vec3::new(0.0, 0.0, -2.0)),
+///     segment(RayKind::Refract, Vec3::new(0.0, 0.0, -2.0), Vec3::new(0.1, 0.0, -4.0)),
+/// ];
+/// let mut out = Vec::new();
+/// write_obj(&segments, &mut out).unwrap();
+/// let text = String::from_utf8(out).unwrap();
+/// assert!(text.contains("o primary\n") && text.contains("o refract\n") && !text.contains("o shadow"));
+/// assert_eq!(text.lines().filter(|line| line.starts_with("l ")).count(), 2);
+/// assert_eq!(text.lines().filter(|line| line.starts_with("f ")).count(), 16);
+/// ```
+pub fn write_obj<W: Write>(segments: &[RaySegment], mut out: W) -> io::Result<()> {
+    writeln!(out, "# {} ray segments", segments.len())?;
+    // OBJ indices count from 1 across the whole file
+    let mut next = 1;
+    for kind in RayKind::ALL {
+        let of_kind: Vec<&RaySegment> = segments.iter().filter(|segment| segment.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        writeln!(out, "o {}", kind.name())?;
+        for segment in &of_kind {
+            writeln!(out, "v {} {} {}", segment.from.x, segment.from.y, segment.from.z)?;
+            writeln!(out, "v {} {} {}", segment.to.x, segment.to.y, segment.to.z)?;
+            writeln!(out, "l {} {}", next, next + 1)?;
+            next += 2;
+        }
+    }
+    
+    let size = marker_size(segments);
+    let hits: Vec<&RaySegment> = segments.iter().filter(|segment| segment.hit).collect();
+    if !hits.is_empty() {
+        writeln!(out, "o hits")?;
+    }
+    for segment in hits {
+        let (corners, faces) = marker(segment.to, size);
+        for corner in corners {
+            writeln!(out, "v {} {} {}", corner.x, corner.y, corner.z)?;
+        }
+        for [a, b, c] in faces {
+            writeln!(out, "f {} {} {}", next + a, next + b, next + c)?;
+        }
+        next += corners.len();
+    }
+    out.flush()
+}
+
+/// Writes the same as `write_obj` as an ASCII PLY file, with the kinds of
+/// rays told apart by vertex color: white camera rays, yellow shadow rays,
+/// blue reflections and pink refractions.
+///
+/// ```
+/// use rt::ray_paths::{write_ply, RayKind, RaySegment};
+/// use rt::vector::Vec3;
+///
+/// let segments = [RaySegment { pixel: (0, 0), kind: RayKind::Shadow, bounce: 0, from: Vec3::zero(), to: Vec3::one(), hit: false }];
+/// let mut out = Vec::new();
+/// write_ply(&segments, &mut out).unwrap();
+/// let text = String::from_utf8(out).unwrap();
+/// assert!(text.contains("element vertex 2\n") && text.contains("element edge 1\n") && text.contains("element face 0\n"));
+/// assert!(text.ends_with("0 1\n"));
+/// ```
+pub fn write_ply<W: Write>(segments: &[RaySegment], mut out: W) -> io::Result<()> {
+    let size = marker_size(segments);
+    let markers: Vec<_> = segments.iter()
+        .filter(|segment| segment.hit)
+        .map(|segment| (marker(segment.to, size), segment.kind.color()))
+        .collect();
+    
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    writeln!(out, "comment {} ray segments", segments.len())?;
+    writeln!(out, "element vertex {}", segments.len() * 2 + markers.len() * 6)?;
+    writeln!(out, "property float x")?;
+    writeln!(out, "property float y")?;
+    writeln!(out, "property float z")?;
+    writeln!(out, "property uchar red")?;
+    writeln!(out, "property uchar green")?;
+    writeln!(out, "property uchar blue")?;
+    writeln!(out, "element face {}", markers.len() * 8)?;
+    writeln!(out, "property list uchar int vertex_indices")?;
+    writeln!(out, "element edge {}", segments.len())?;
+    writeln!(out, "property int vertex1")?;
+    writeln!(out, "property int vertex2")?;
+    writeln!(out, "end_header")?;
+    
+    for segment in segments {
+        let [r, g, b] = segment.kind.color();
+        for point in [segment.from, segment.to] {
+            writeln!(out, "{} {} {} {} {} {}", point.x, point.y, point.z, r, g, b)?;
+        }
+    }
+    for ((corners, _), [r, g, b]) in &markers {
+        for corner in corners {
+            writeln!(out, "{} {} {} {} {} {}", corner.x, corner.y, corner.z, r, g, b)?;
+        }
+    }
+    let first_marker = segments.len() * 2;
+    for (index, ((_, faces), _)) in markers.iter().enumerate() {
+        let base = first_marker + index * 6;
+        for [a, b, c] in faces {
+            writeln!(out, "3 {} {} {}", base + a, base + b, base + c)?;
+        }
+    }
+    for index in 0..segments.len() {
+        writeln!(out, "{} {}", index * 2, index * 2 + 1)?;
+    }
+    out.flush()
+}
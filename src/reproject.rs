@@ -0,0 +1,199 @@
+use crate::camera::Camera;
+use crate::image::Image;
+use crate::scene::AuxBuffers;
+use crate::vector::Vec3;
+use crate::image::color::Color;
+
+// How far a point may be from the depth the previous frame saw at its
+// position, relative to that depth, and still count as the same surface.
+// Surfaces seen at a slant change depth from one pixel to the next, so
+// they are allowed that much more, up to the slope of a surface at about
+// 87 degrees.
+const DEPTH_TOLERANCE: f64 = 0.02;
+const MAX_SLOPE: f64 = 20.0;
+
+// Neighbours out of 8 that need a depth for a gap to be filled from them;
+// fewer means the gap is more likely the edge of a surface than a hole in
+// it
+const MIN_NEIGHBOURS: usize = 5;
+
+// A finished frame of an animation, with what its pixels hit, kept to
+// predict the next frame from
+pub struct Frame {
+    pub camera: Camera,
+    pub image: Image,
+    pub aux: AuxBuffers,
+}
+
+// The next frame as far as the previous one could predict it, and the
+// pixels it could not, which still have to be traced
+pub struct Reprojection {
+    pub image: Image,
+    pub aux: AuxBuffers,
+    pub retrace: Vec<(u32, u32)>,
+}
+
+/// Predicts what `camera` sees from the previous frame, for animation
+/// previews where consecutive frames barely differ. Surfaces seen last
+/// time are carried to where they land now using their depth; every pixel
+/// that gets one is then looked up in the previous frame and keeps the
+/// color found there if the surface is still the one seen. Pixels that
+/// come out from behind something, enter from off-screen, show the
+/// background or sit on a depth jump are left for `Scene::retrace`.
+/// Highlights and reflections stay where they were, so the result is only
+/// a preview and drifts the more frames are predicted in a row.
+///
+/// ```
+/// use rt::camera::Camera;
+/// use rt::image::Image;
+/// use rt::reproject::{reproject, Frame, Reprojection};
+/// use rt::scene::{AuxBuffers, RenderSettings, Scene};
+/// use rt::vector::Vec3;
+///
+/// let mut scene: Scene = r#"{
+///     "lights": [{ "type": "point", "position": [2, 4, 1] }],
+///     "objects": [
+///         { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] },
+///         { "type": "sphere", "center": [0, 0, -4], "radius": 1 },
+///         { "type": "sphere", "center": [1.5, -0.5, -2.5], "radius": 0.5 }
+///     ]
+/// }"#.parse().unwrap();
+/// let settings = RenderSettings::default();
+/// let camera = |z: f64| Camera::new(Vec3::new(0.0, 0.5, z), Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 1.0, 0.0), 60.0, 1.5).unwrap();
+/// let render = |scene: &mut Scene, camera: Camera| {
+///     scene.set_camera(camera);
+///     let mut image = Image::new(60, 40);
+///     let mut aux = AuxBuffers::new(60, 40);
+///     scene.render_with_progress(&mut image, Some(&mut aux), &settings, |_| {});
+///     (image, aux)
+/// };
+///
+/// let (image, aux) = render(&mut scene, camera(0.0));
+/// let previous = Frame { camera: camera(0.0), image, aux };
+/// let (full, _) = render(&mut scene, camera(-0.1));
+///
+/// let Reprojection { mut image, mut aux, retrace } = reproject(&previous, &camera(-0.1));
+/// assert!(retrace.len() < full.pixels.len() / 2);
+/// let report = scene.retrace(&mut image, Some(&mut aux), &settings, &retrace).unwrap();
+/// assert_eq!(report.stats.primary_rays, retrace.len() as u64);
+///
+/// // Traced pixels are exact and the predicted ones close
+/// for &(x, y) in &retrace {
+///     assert_eq!(image.get_pixel(x as usize, y as usize), full.get_pixel(x as usize, y as usize));
+/// }
+/// let error: f64 = image.pixels.iter().zip(&full.pixels).map(|(a, b)| Vec3::from(*a - *b).length()).sum();
+/// assert!(error / (full.pixels.len() as f64) < 0.01);
+/// ```
+pub fn reproject(previous: &Frame, camera: &Camera) -> Reprojection {
+    let (width, height) = (previous.image.width, previous.image.height);
+    let mut image = Image::new(width, height);
+    let mut aux = AuxBuffers::new(width, height);
+    let (w, h) = (width as usize, height as usize);
+    if w < 2 || h < 2 {
+        let retrace = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).collect();
+        return Reprojection { image, aux, retrace };
+    }
+    
+    // Pixel positions and the (s, t) the camera takes, as the renderer
+    // maps them
+    let to_st = |x: f64, y: f64| (x / (w - 1) as f64, ((h - 1) as f64 - y) / (h - 1) as f64);
+    let to_xy = |s: f64, t: f64| (s * (w - 1) as f64, (h - 1) as f64 - t * (h - 1) as f64);
+    let old = &previous.camera;
+    let pixel_size = old.vertical.length() / (h - 1) as f64;
+    
+    // Depth of the nearest surface seen last time that lands in each pixel
+    let mut splatted = vec![f64::INFINITY; w * h];
+    for (index, &depth) in previous.aux.depth.iter().enumerate() {
+        if !depth.is_finite() {
+            continue;
+        }
+        let (s, t) = to_st((index % w) as f64, (index / w) as f64);
+        let Some((s, t, depth)) = camera.project(old.origin(s, t) + old.direction(s, t) * depth) else {
+            continue;
+        };
+        let (x, y) = to_xy(s, t);
+        let (x, y) = (x.round(), y.round());
+        if x >= 0.0 && y >= 0.0 && x < w as f64 && y < h as f64 {
+            let target = &mut splatted[y as usize * w + x as usize];
+            *target = target.min(depth);
+        }
+    }
+    
+    // Where the view zooms in, surfaces spread over more pixels than they
+    // came from and leave gaps, which take the nearest depth around them
+    let estimate = |x: usize, y: usize| {
+        let own = splatted[y * w + x];
+        if own.is_finite() {
+            return own;
+        }
+        let mut nearest = f64::INFINITY;
+        let mut neighbours = 0;
+        for ny in y.saturating_sub(1)..(y + 2).min(h) {
+            for nx in x.saturating_sub(1)..(x + 2).min(w) {
+                let depth = splatted[ny * w + nx];
+                if depth.is_finite() {
+                    nearest = nearest.min(depth);
+                    neighbours += 1;
+                }
+            }
+        }
+        if neighbours >= MIN_NEIGHBOURS { nearest } else { f64::INFINITY }
+    };
+    
+    // Color, normal and depth for a pixel, if the previous frame saw the
+    // same surface all around where it lands; pixels next to an edge are
+    // traced rather than blended across it
+    let predict = |x: usize, y: usize| -> Option<(Color, Vec3, f64)> {
+        let depth = estimate(x, y);
+        if !depth.is_finite() {
+            return None;
+        }
+        let (s, t) = to_st(x as f64, y as f64);
+        let (s, t, old_depth) = old.project(camera.origin(s, t) + camera.direction(s, t) * depth)?;
+        let (fx, fy) = to_xy(s, t);
+        if !(0.0..=(w - 1) as f64).contains(&fx) || !(0.0..=(h - 1) as f64).contains(&fy) {
+            return None;
+        }
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+        let (ax, ay) = (fx - x0 as f64, fy - y0 as f64);
+        
+        let mut color = Color::BLACK;
+        let mut normal = Vec3::zero();
+        let mut heaviest = 0.0;
+        for (nx, ny, weight) in [(x0, y0, (1.0 - ax) * (1.0 - ay)), (x1, y0, ax * (1.0 - ay)), (x0, y1, (1.0 - ax) * ay), (x1, y1, ax * ay)] {
+            if weight <= 0.0 {
+                continue;
+            }
+            let index = ny * w + nx;
+            let (s, t) = to_st(nx as f64, ny as f64);
+            let cosine = previous.aux.normal[index].dot(&old.direction(s, t).normalize()).abs();
+            let slope = ((1.0 - cosine * cosine).sqrt() / cosine).min(MAX_SLOPE);
+            if (previous.aux.depth[index] - old_depth).abs() > (DEPTH_TOLERANCE + pixel_size * slope) * old_depth {
+                return None;
+            }
+            color = color + previous.image.get_pixel(nx, ny) * weight;
+            if weight > heaviest {
+                heaviest = weight;
+                normal = previous.aux.normal[index];
+            }
+        }
+        Some((color, normal, depth))
+    };
+    
+    let mut retrace = Vec::new();
+    for y in 0..h {
+        for x in 0..w {
+            let index = y * w + x;
+            match predict(x, y) {
+                Some((color, normal, depth)) => {
+                    image.set_pixel(x, y, color);
+                    aux.normal[index] = normal;
+                    aux.depth[index] = depth;
+                }
+                None => retrace.push((x as u32, y as u32)),
+            }
+        }
+    }
+    Reprojection { image, aux, retrace }
+}
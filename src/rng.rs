@@ -0,0 +1,65 @@
+// Small deterministic generator (SplitMix64), the only source of random
+// numbers for rendering.
+//
+// Renders are reproducible: the image depends on the scene, the settings
+// and `RenderSettings::seed`, never on the number of threads or the order
+// they run in. To keep it that way, every piece of work that may run on its
+// own thread draws from its own stream, made with `Rng::keyed` from the seed
+// and what identifies the work: the pixel and sample for a camera path
+// (`Rng::for_sample`), the light and batch for caustic photons. Streams are
+// never shared or carried from one piece of work to the next, and nothing
+// in the render path may use another source of randomness. A sample draws
+// its numbers in a fixed order, so the same sample always sees the same
+// values. Renders cut short by a time budget are the exception, since where
+// they stop depends on the clock.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+    
+    /// Stream for one piece of work, from the render's seed and keys that
+    /// tell it apart from every other piece. Each key is mixed in fully, so
+    /// neighbouring pixels or seeds give unrelated streams.
+    ///
+    /// ```
+    /// use rt::rng::Rng;
+    ///
+    /// let first = |mut rng: Rng| rng.next_u64();
+    /// assert_eq!(first(Rng::keyed(1, &[4, 2])), first(Rng::keyed(1, &[4, 2])));
+    /// assert_ne!(first(Rng::keyed(1, &[4, 2])), first(Rng::keyed(1, &[2, 4])));
+    /// assert_ne!(first(Rng::keyed(1, &[4, 2])), first(Rng::keyed(2, &[4, 2])));
+    /// ```
+    pub fn keyed(seed: u64, keys: &[u64]) -> Self {
+        Rng::new(keys.iter().fold(mix(seed), |hash, &key| mix(hash.wrapping_add(GAMMA) ^ key)))
+    }
+    
+    // Stream for one camera sample of a pixel
+    pub fn for_sample(seed: u64, x: u32, y: u32, sample: u32) -> Self {
+        Rng::keyed(seed, &[x as u64, y as u64, sample as u64])
+    }
+    
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GAMMA);
+        mix(self.state)
+    }
+    
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// SplitMix64's output function, which scrambles every bit of the input
+// into every bit of the result
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
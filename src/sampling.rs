@@ -0,0 +1,119 @@
+use crate::vector::{Onb, Vec3};
+use std::f64::consts::PI;
+
+/// The direction at (u, v) of the unit square mapped onto the cone of
+/// directions within `half_angle` radians of `axis` (a unit vector), with
+/// equal areas of the square covering equal solid angles. Jittered points
+/// of the square make jittered directions, as for shadow rays toward a
+/// light of some angular size like the sun. u runs from the axis (0) to
+/// the rim (1) and v once around it.
+///
+/// ```
+/// use rt::rng::Rng;
+/// use rt::sampling::cone_direction;
+/// use rt::vector::Vec3;
+///
+/// let axis = Vec3::new(1.0, 2.0, -2.0).normalize();
+/// let half_angle = 0.26_f64.to_radians();
+/// assert!((cone_direction(axis, half_angle, 0.0, 0.3) - axis).length() < 1e-12);
+///
+/// // The rim is at the half angle whatever v is, and u = 0.5 halves the
+/// // cone's solid angle
+/// let angle = |direction: Vec3| direction.dot(&axis).clamp(-1.0, 1.0).acos();
+/// for v in [0.0, 0.25, 0.7] {
+///     assert!((cone_direction(axis, half_angle, 1.0, v).length() - 1.0).abs() < 1e-12);
+///     assert!((angle(cone_direction(axis, half_angle, 1.0, v)) - half_angle).abs() < 1e-9);
+///     let half = angle(cone_direction(axis, half_angle, 0.5, v));
+///     assert!(((1.0 - half.cos()) / (1.0 - half_angle.cos()) - 0.5).abs() < 1e-6);
+/// }
+///
+/// // Random points fill the cone, spread evenly about the axis
+/// let mut rng = Rng::new(7);
+/// let mut sum = Vec3::zero();
+/// for _ in 0..10_000 {
+///     let direction = cone_direction(axis, 0.5, rng.next_f64(), rng.next_f64());
+///     assert!(angle(direction) <= 0.5 + 1e-12);
+///     sum = sum + direction;
+/// }
+/// assert!((sum.normalize() - axis).length() < 0.01);
+/// ```
+pub fn cone_direction(axis: Vec3, half_angle: f64, u: f64, v: f64) -> Vec3 {
+    // 1 - cos, written so it keeps its precision for cones as narrow as
+    // the sun's
+    let rim = 2.0 * (half_angle / 2.0).sin().powi(2);
+    let cos_theta = 1.0 - u * rim;
+    let sin_theta = (u * rim * (2.0 - u * rim)).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Onb::from_w(axis).local(Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+}
+
+/// The point at (u, v) of the unit square mapped onto the regular polygon
+/// with `sides` corners on the unit circle, the first `rotation` radians
+/// round from the x axis, with equal areas of the square covering equal
+/// areas of the polygon. Polygons of fewer than 3 sides are the unit disk.
+/// Lens samples use it for the shape of a bladed aperture.
+///
+/// ```
+/// use rt::rng::Rng;
+/// use rt::sampling::polygon_point;
+/// use std::f64::consts::TAU;
+///
+/// let (sides, rotation) = (6, 0.3);
+/// // Distance inward from each edge, scaled so the center is at 1
+/// let apothem = (TAU / 12.0).cos();
+/// let inside = |(x, y): (f64, f64)| {
+///     (0..sides)
+///         .map(|k| {
+///             let normal = rotation + (k as f64 + 0.5) * TAU / sides as f64;
+///             1.0 - (x * normal.cos() + y * normal.sin()) / apothem
+///         })
+///         .fold(f64::INFINITY, f64::min)
+/// };
+/// assert_eq!(polygon_point(sides, rotation, 0.0, 0.4), (0.0, 0.0));
+///
+/// // Every point is inside, half-size copies of the polygon get a quarter
+/// // of them, and equal cells that fit inside get equal shares
+/// let mut rng = Rng::new(7);
+/// let (count, mut half) = (200_000, 0);
+/// let mut cells = [[0; 10]; 10];
+/// for _ in 0..count {
+///     let point = polygon_point(sides, rotation, rng.next_f64(), rng.next_f64());
+///     assert!(inside(point) >= -1e-12);
+///     if inside(point) >= 0.5 {
+///         half += 1;
+///     }
+///     cells[((point.0 + 1.0) * 5.0) as usize][((point.1 + 1.0) * 5.0) as usize] += 1;
+/// }
+/// assert!((half as f64 / count as f64 - 0.25).abs() < 0.005);
+/// let area = 0.5 * sides as f64 * (TAU / sides as f64).sin();
+/// let expected = count as f64 * 0.04 / area;
+/// let mut checked = 0;
+/// for i in 0..10 {
+///     for j in 0..10 {
+///         let corner = |a: usize, b: usize| (a as f64 / 5.0 - 1.0, b as f64 / 5.0 - 1.0);
+///         if [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)].iter().all(|&(a, b)| inside(corner(a, b)) >= 0.0) {
+///             assert!((cells[i][j] as f64 / expected - 1.0).abs() < 0.1);
+///             checked += 1;
+///         }
+///     }
+/// }
+/// assert!(checked >= 40);
+///
+/// // The disk for round apertures
+/// let (x, y) = polygon_point(0, 0.0, 0.25, 0.5);
+/// assert!((x + 0.5).abs() < 1e-12 && y.abs() < 1e-12);
+/// ```
+pub fn polygon_point(sides: u32, rotation: f64, u: f64, v: f64) -> (f64, f64) {
+    if sides < 3 {
+        let (r, phi) = (u.sqrt(), 2.0 * PI * v);
+        return (r * phi.cos(), r * phi.sin());
+    }
+    // u picks one of the triangles fanned out from the center, and what is
+    // left of it places the point across that triangle
+    let scaled = u * sides as f64;
+    let k = scaled.floor().min(sides as f64 - 1.0);
+    let r = (scaled - k).sqrt();
+    let step = 2.0 * PI / sides as f64;
+    let (a, b) = (rotation + k * step, rotation + (k + 1.0) * step);
+    (r * ((1.0 - v) * a.cos() + v * b.cos()), r * ((1.0 - v) * a.sin() + v * b.sin()))
+}
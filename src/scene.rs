@@ -1,145 +1,3634 @@
-use crate::vector::Vec3;
-use crate::ray::{Ray, HitRecord};
-use crate::objects::Object;
-use crate::camera::Camera;
-use crate::light::Light;
-use crate::image::Image;
+use crate::vector::{Onb, Vec3};
+use crate::ray::{Ray, RayPacket4, HitRecord};
+use crate::objects::{Object, Plane, Sphere, Quad};
+use crate::camera::{Camera, Shake};
+use crate::framing::{same_aspect, Framing};
+use crate::light::{Light, LightLinking, LightShape};
+use crate::rng::Rng;
+use crate::photon::PhotonMap;
+use crate::filter::Filter;
+use crate::image::{Image, heat_color, viridis_color};
+use crate::image::color::Color;
+use crate::background::{Backdrop, Background};
+use crate::material::{Material, MaterialOverride};
+use crate::texture::ImageTexture;
+use crate::bvh::Bvh;
+use crate::clip::{self, ClipPlane, KeptRange};
+use crate::summary::SceneSummary;
+use crate::overlap;
+use crate::medium::{self, MediumStack};
+use crate::portal::Portal;
+use crate::ray_paths::{RayKind, RaySegment, MISS_LENGTH};
+use crate::interlace;
+use crate::lod;
+use crate::{debug, info, warn};
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::cmp::Reverse;
+use std::ops::{Add, AddAssign};
+use std::time::{Duration, Instant};
+
+pub mod scatter;
+
+// What each pixel of the output shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPass {
+    // Regular shaded image
+    Beauty,
+    // False color of the total unshadowed light arriving at the first hit
+    Irradiance,
+    // False color of the work spent on each pixel, measured as ray-object
+    // intersection tests over all of its rays, relative to the costliest
+    // pixel
+    Cost,
+    // White where the camera sees the scene, or with a render layer the
+    // layer's own objects, and black where it holds out; averaged over a
+    // pixel's samples, how much of the pixel is covered
+    Alpha,
+}
+
+// Most layer names a scene can have, one bit each in `RenderLayer`
+const MAX_LAYERS: usize = 64;
+
+// Which objects camera rays see when rendering one layer of a scene for
+// compositing; see `Scene::render_layers`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderLayer {
+    // Bit i stands for the scene's i-th layer name
+    layers: u64,
+    // Objects in no layer, light gizmos, section caps and the background
+    rest: bool,
+}
+
+// Order in which the pixels of an image are traced. The finished image is
+// the same either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelOrder {
+    // Row by row, left to right
+    Scanline,
+    // Square tiles, each walked along a Z-order (Morton) curve
+    Morton,
+    // Coarse to fine: every 8th pixel of every 8th row, then every 4th,
+    // 2nd and the rest, filling the blocks between traced pixels after
+    // each level so the image can be previewed as it sharpens
+    Interlaced,
+}
+
+/// Order in which 16x16 tiles are handed to the render threads. The
+/// finished image is the same either way.
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::scene::{AuxBuffers, PixelOrder, RenderSettings, Scene, TileOrder};
+///
+/// let scene: Scene = r#"{
+///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+///     "lights": [{ "type": "point", "position": [2, 3, 0] }],
+///     "objects": [
+///         { "type": "sphere", "center": [0.5, 0, -3], "radius": 0.6, "material": { "transparency": 0.9, "refractive_index": 1.5 } },
+///         { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] }
+///     ]
+/// }"#.parse().unwrap();
+/// let mut expected = Image::new(37, 21);
+/// let mut expected_aux = AuxBuffers::new(37, 21);
+/// let report = scene.render_with_aux(&mut expected, Some(&mut expected_aux), &RenderSettings::default());
+/// assert!(report.tiles.is_none());
+///
+/// // Cost order twice: the second render goes by what the first measured
+/// for tile_order in [TileOrder::Hilbert, TileOrder::Cost, TileOrder::Cost] {
+///     for pixel_order in [PixelOrder::Scanline, PixelOrder::Morton] {
+///         let settings = RenderSettings { tile_order, pixel_order, ..RenderSettings::default() };
+///         let mut image = Image::new(37, 21);
+///         let mut aux = AuxBuffers::new(37, 21);
+///         let report = scene.render_with_aux(&mut image, Some(&mut aux), &settings);
+///         assert_eq!(image.pixels(), expected.pixels());
+///         assert_eq!(aux.depth, expected_aux.depth);
+///         // Three tiles across and two down, those on the edges cut short
+///         assert_eq!(report.tiles.unwrap().count, 6);
+///         assert_eq!(report.stats.primary_rays, 37 * 21);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    // No queue: rayon splits the rows (bands of tiles with Morton pixel
+    // order) between the threads up front
+    Scanline,
+    // Tiles one at a time from a shared queue, along a Hilbert curve over
+    // the image
+    Hilbert,
+    // Tiles one at a time from a shared queue, those predicted to cost the
+    // most first, so no expensive tile is left to start once the others
+    // are done. The prediction is what each tile cost in the last render
+    // of the same size, or failing that a one-sample probe of each tile.
+    Cost,
+}
+
+// How reflections mix with the color of the surface under them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReflectionBlend {
+    // The reflection covers `reflectivity` of the surface color at every
+    // angle
+    Linear,
+    // As on glazed or varnished surfaces: head-on the reflection is added
+    // over the full surface color with weight `reflectivity`, and towards
+    // grazing angles it takes over along Schlick's Fresnel curve
+    Fresnel,
+}
+
+// Highlights in toon shading are either fully on or off, switching where
+// the Phong highlight would reach this strength
+const TOON_SPECULAR_THRESHOLD: f64 = 0.5;
+
+// Shading model used for direct light at each hit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shading {
+    Phong,
+    // Cel shading: diffuse light is snapped to `bands` flat levels from
+    // unlit to fully lit, blended over `smoothing` (in units of the cosine
+    // to the light) at each step. Highlights have hard edges and nothing
+    // is reflected.
+    Toon { bands: u32, smoothing: f64 },
+}
+
+impl Shading {
+    // Diffuse strength for a cosine between the normal and the light
+    fn diffuse(&self, cosine: f64) -> f64 {
+        match *self {
+            Shading::Phong => cosine,
+            Shading::Toon { bands, smoothing } => {
+                if bands < 2 {
+                    return if cosine > 0.0 { 1.0 } else { 0.0 };
+                }
+                // A step up at each band boundary, each step the same height
+                let half_width = smoothing.max(0.0) / 2.0;
+                let steps: f64 = (1..bands)
+                    .map(|band| {
+                        let boundary = band as f64 / bands as f64;
+                        if half_width == 0.0 {
+                            return if cosine >= boundary { 1.0 } else { 0.0 };
+                        }
+                        let t = ((cosine - boundary + half_width) / (2.0 * half_width)).clamp(0.0, 1.0);
+                        t * t * (3.0 - 2.0 * t)
+                    })
+                    .sum();
+                steps / (bands - 1) as f64
+            }
+        }
+    }
+    
+    fn specular(&self, strength: f64) -> f64 {
+        match self {
+            Shading::Phong => strength,
+            Shading::Toon { .. } => if strength > TOON_SPECULAR_THRESHOLD { 1.0 } else { 0.0 },
+        }
+    }
+    
+    fn reflects(&self) -> bool {
+        matches!(self, Shading::Phong)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub reflection: bool,
+    pub max_depth: i32,
+    /// Reflection and refraction rays whose share of the pixel, the product
+    /// of the blend weights along their chain, is below this are not traced.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// // A corridor of faintly reflective walls, which bounce most rays
+    /// // back and forth until the depth limit
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1], "fov": 90 },
+    ///     "materials": { "wall": { "reflectivity": 0.04 } },
+    ///     "lights": [{ "position": [0, 0.5, -3], "intensity": 1 }],
+    ///     "objects": [
+    ///         { "type": "plane", "point": [-1, 0, 0], "normal": [1, 0, 0], "material": "wall" },
+    ///         { "type": "plane", "point": [1, 0, 0], "normal": [-1, 0, 0], "material": "wall" },
+    ///         { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0], "material": "wall" }
+    ///     ]
+    /// }"#.parse().unwrap();
+    ///
+    /// let render = |min_contribution| {
+    ///     let settings = RenderSettings { reflection: true, min_contribution, ..RenderSettings::default() };
+    ///     let mut image = Image::new(32, 32);
+    ///     let report = scene.render_into(&mut image, &settings);
+    ///     (image, report.stats.secondary_rays)
+    /// };
+    /// let (every_ray, all_secondary) = render(0.0);
+    /// let (cut_off, secondary) = render(RenderSettings::default().min_contribution);
+    ///
+    /// assert!(secondary * 2 < all_secondary, "{} of {} rays traced", secondary, all_secondary);
+    /// for (a, b) in every_ray.pixels.iter().zip(&cut_off.pixels) {
+    ///     let difference = a.clamp(0.0, 1.0) - b.clamp(0.0, 1.0);
+    ///     assert!(Vec3::from(difference).length() < 1.0 / 255.0, "{} apart", difference);
+    /// }
+    /// ```
+    pub min_contribution: f64,
+    /// How reflections mix with the surface color. `Fresnel` keeps a
+    /// faintly reflective sphere's color head-on and mirrors strongly only
+    /// near its outline.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{ReflectionBlend, RenderSettings, Scene};
+    ///
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1], "fov": 40 },
+    ///     "background": [0.1, 0.3, 1],
+    ///     "lights": [{ "position": [2, 2, 0], "intensity": 0.8 }],
+    ///     "objects": [{
+    ///         "type": "sphere", "center": [0, 0, -3], "radius": 1,
+    ///         "material": { "color": [0.8, 0.1, 0.1], "reflectivity": 0.1 }
+    ///     }]
+    /// }"#.parse().unwrap();
+    /// let render = |reflection, reflection_blend| {
+    ///     let mut image = Image::new(33, 33);
+    ///     scene.render_into(&mut image, &RenderSettings { reflection, reflection_blend, ..RenderSettings::default() });
+    ///     image
+    /// };
+    /// let plain = render(false, ReflectionBlend::Linear);
+    /// let linear = render(true, ReflectionBlend::Linear);
+    /// let fresnel = render(true, ReflectionBlend::Fresnel);
+    ///
+    /// // Head-on the linear blend dims the red, the Fresnel blend does not
+    /// let center = |image: &Image| image.get_pixel(16, 16);
+    /// assert!(center(&linear).r < 0.95 * center(&plain).r);
+    /// assert!(center(&fresnel).r >= center(&plain).r);
+    /// // At the outline the blue background shows strongly in it
+    /// let x = (0..16).find(|&x| plain.get_pixel(x, 16) != plain.get_pixel(0, 16)).unwrap();
+    /// let rim = |image: &Image| image.get_pixel(x, 16);
+    /// assert!(rim(&fresnel).b > rim(&linear).b + 0.15, "{} against {}", rim(&fresnel), rim(&linear));
+    /// ```
+    pub reflection_blend: ReflectionBlend,
+    // Trace primary rays four at a time
+    pub packets: bool,
+    pub pass: RenderPass,
+    // Draw a small glowing sphere at every light (never casts shadows)
+    pub show_lights: bool,
+    // Area lights are sampled on an N x N jittered grid
+    pub area_samples: u32,
+    // Trace a caustic photon map before rendering and add its estimate at
+    // diffuse surfaces
+    pub caustics: bool,
+    pub photons_per_light: u32,
+    pub photon_radius: f64,
+    // Darken the ambient term at camera ray hits by the share of `ao_samples`
+    // rays over the hemisphere that hit something within `ao_distance`
+    pub ambient_occlusion: bool,
+    pub ao_samples: u32,
+    pub ao_distance: f64,
+    /// Share of occlusion rays aimed straight at the scene's portals, such
+    /// as the windows of a room lit from outside, instead of spread over
+    /// the hemisphere. Every ray is weighted by how likely either way was
+    /// to pick it, so the result converges to the same image, only faster
+    /// when most of the hemisphere is walls.
+    ///
+    /// ```
+    /// use rt::scene::{Scene, RenderSettings};
+    /// use rt::objects::Quad;
+    /// use rt::material::Material;
+    /// use rt::camera::Camera;
+    /// use rt::image::color::Color;
+    /// use rt::image::Image;
+    /// use rt::portal::Portal;
+    /// use rt::vector::Vec3;
+    ///
+    /// // A closed room, 2 wide, 2 high and 3 deep, with a window in the
+    /// // left wall; the only light is ambient light coming in through it
+    /// let mut scene = Scene::new();
+    /// let white = Material::new(Color::WHITE, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0);
+    /// let mut wall = |corner: [f64; 3], u: [f64; 3], v: [f64; 3]| {
+    ///     let vec = |a: [f64; 3]| Vec3::new(a[0], a[1], a[2]);
+    ///     scene.add_object(Box::new(Quad::new(vec(corner), vec(u), vec(v), white)));
+    /// };
+    /// wall([-1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, -3.0]);
+    /// wall([-1.0, 2.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, -3.0]);
+    /// wall([-1.0, 0.0, -3.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]);
+    /// wall([-1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]);
+    /// wall([1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, -3.0]);
+    /// wall([-1.0, 0.0, 0.0], [0.0, 0.8, 0.0], [0.0, 0.0, -3.0]);
+    /// wall([-1.0, 1.6, 0.0], [0.0, 0.4, 0.0], [0.0, 0.0, -3.0]);
+    /// wall([-1.0, 0.8, 0.0], [0.0, 0.8, 0.0], [0.0, 0.0, -1.0]);
+    /// wall([-1.0, 0.8, -2.0], [0.0, 0.8, 0.0], [0.0, 0.0, -1.0]);
+    /// scene.set_camera(Camera::new(Vec3::new(0.6, 1.0, -0.2), Vec3::new(-0.4, 0.7, -2.6), Vec3::new(0.0, 1.0, 0.0), 70.0, 4.0 / 3.0).unwrap());
+    ///
+    /// let render = |scene: &Scene, ao_samples, portal_fraction| {
+    ///     let settings = RenderSettings {
+    ///         ambient_occlusion: true,
+    ///         ao_samples,
+    ///         ao_distance: 10.0,
+    ///         portal_fraction,
+    ///         ..RenderSettings::default()
+    ///     };
+    ///     let mut image = Image::new(32, 24);
+    ///     scene.render_into(&mut image, &settings);
+    ///     image.pixels
+    /// };
+    /// let rms = |a: &[Color], b: &[Color]| {
+    ///     let sum: f64 = a.iter().zip(b).map(|(a, b)| Vec3::from(*a - *b).length_squared()).sum();
+    ///     (sum / a.len() as f64).sqrt()
+    /// };
+    /// let mean = |a: &[Color]| a.iter().map(|p| p.r).sum::<f64>() / a.len() as f64;
+    ///
+    /// let plain = render(&scene, 8, 0.5);
+    /// scene.add_portal(Portal::new(Vec3::new(-1.0, 0.8, -1.0), Vec3::new(0.0, 0.8, 0.0), Vec3::new(0.0, 0.0, -1.0)).unwrap());
+    /// assert_eq!(render(&scene, 8, 0.0), plain);
+    /// let reference = render(&scene, 512, 0.5);
+    /// let portaled = render(&scene, 8, 0.5);
+    ///
+    /// // Same answer on average, with much less noise at equal samples
+    /// assert!((mean(&plain) - mean(&reference)).abs() < 0.01);
+    /// assert!((mean(&portaled) - mean(&reference)).abs() < 0.01);
+    /// assert!(rms(&portaled, &reference) < 0.5 * rms(&plain, &reference));
+    /// ```
+    pub portal_fraction: f64,
+    // Rows started after this instant are not traced; their pixels are
+    // filled with the background seen by their primary rays
+    pub deadline: Option<Instant>,
+    // Shade with this many lights per hit, chosen by importance, instead of
+    // all of them
+    pub light_samples: Option<u32>,
+    // Jittered camera rays per pixel, combined by `filter`
+    pub samples_per_pixel: u32,
+    pub filter: Filter,
+    pub pixel_order: PixelOrder,
+    // Ignored by interlaced renders and filters wider than a pixel
+    pub tile_order: TileOrder,
+    // Test the last object that shadowed each light before searching the
+    // whole scene
+    pub shadow_cache: bool,
+    // Trace camera rays that miss between hits again a hair over
+    pub seal_pinholes: bool,
+    // Log every ray traced for this pixel (x, y from the top left) to
+    // stderr
+    pub debug_pixel: Option<(u32, u32)>,
+    pub shading: Shading,
+    /// Renders in scanline order needing at most this many camera samples
+    /// (pixels times samples per pixel) trace their rows one after another
+    /// on the calling thread, as do all renders when rayon has a single
+    /// thread: for thumbnails, handing rows to the pool costs more than it
+    /// saves. The image is the same either way; 0 always uses the pool.
+    /// The default is 64x64. On one core, handing rows to a two-thread pool
+    /// costs about 7µs, a third of an 8x8 render. Where the pool pays off
+    /// on several cores is still to be measured;
+    /// `cargo bench --bench sequential_crossover` times both paths.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{RenderSettings, Scene};
+    ///
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+    ///     "lights": [{ "type": "point", "position": [2, 3, 0] }],
+    ///     "objects": [
+    ///         { "type": "sphere", "center": [0, 0, -3], "radius": 1, "material": { "reflectivity": 0.3 } },
+    ///         { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] }
+    ///     ]
+    /// }"#.parse().unwrap();
+    /// let render = |sequential_samples| {
+    ///     let settings = RenderSettings { reflection: true, samples_per_pixel: 2, sequential_samples, ..RenderSettings::default() };
+    ///     let mut image = Image::new(48, 32);
+    ///     let report = scene.render_into(&mut image, &settings);
+    ///     (image.pixels, report.stats)
+    /// };
+    /// assert!(48 * 32 * 2 <= RenderSettings::default().sequential_samples);
+    /// assert_eq!(render(RenderSettings::default().sequential_samples), render(0));
+    /// ```
+    pub sequential_samples: u64,
+    // Camera rays only see this layer's objects; the others hold out,
+    // coming out black while still casting shadows and showing in
+    // reflections
+    pub layer: Option<RenderLayer>,
+    /// Picks the random numbers for jittered samples, soft shadows, ambient
+    /// occlusion and caustics. Renders with the same seed are identical
+    /// however many threads they run on (see `rng`).
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::filter::Filter;
+    /// use rt::image::color::Color;
+    /// use rt::image::Image;
+    /// use rt::light::Light;
+    /// use rt::material::Material;
+    /// use rt::objects::{Plane, Sphere};
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// // Soft shadows from an area light, ambient occlusion and jittered
+    /// // samples, all of them noisy
+    /// let mut scene = Scene::new();
+    /// let m = Material::default();
+    /// scene.add_object(Box::new(Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), m)));
+    /// scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -4.0), 1.0, m)));
+    /// scene.add_light(Light::quad(Vec3::new(-1.0, 3.0, -5.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), Color::WHITE, 1.0));
+    /// scene.set_camera(Camera::new(Vec3::zero(), Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 1.0, 0.0), 60.0, 1.0).unwrap());
+    ///
+    /// let render = |threads, seed, filter| {
+    ///     let settings = RenderSettings {
+    ///         seed,
+    ///         filter,
+    ///         samples_per_pixel: 4,
+    ///         area_samples: 2,
+    ///         ambient_occlusion: true,
+    ///         // Split between the threads however small the image
+    ///         sequential_samples: 0,
+    ///         ..RenderSettings::default()
+    ///     };
+    ///     let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+    ///     pool.install(|| {
+    ///         let mut image = Image::new(24, 24);
+    ///         scene.render_into(&mut image, &settings);
+    ///         image.pixels
+    ///     })
+    /// };
+    ///
+    /// for filter in [RenderSettings::default().filter, Filter::Gaussian { radius: 1.5 }] {
+    ///     let one = render(1, 7, filter);
+    ///     assert_eq!(one, render(4, 7, filter));
+    ///     assert_eq!(one, render(16, 7, filter));
+    ///     assert_ne!(one, render(1, 8, filter));
+    /// }
+    /// ```
+    pub seed: u64,
+}
+
+impl RenderSettings {
+    // Small renders, and any render when rayon has a single thread, skip
+    // the parallel iterators
+    fn renders_sequentially(&self, width: u32, height: u32) -> bool {
+        let samples = width as u64 * height as u64 * self.samples_per_pixel.max(1) as u64;
+        samples <= self.sequential_samples || rayon::current_num_threads() == 1
+    }
+    
+    // Packets are only traced with one sample per pixel, and not for the
+    // cost pass, which needs the tests made for each pixel on its own, or
+    // through a lens, whose rays do not share an origin
+    fn traces_packets(&self, camera: &Camera) -> bool {
+        self.packets && self.samples_per_pixel <= 1 && self.pass != RenderPass::Cost && camera.lens.is_none()
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            reflection: false,
+            max_depth: 5,
+            min_contribution: 1.0 / 512.0,
+            packets: false,
+            pass: RenderPass::Beauty,
+            show_lights: false,
+            area_samples: 4,
+            caustics: false,
+            photons_per_light: 200_000,
+            photon_radius: 0.1,
+            ambient_occlusion: false,
+            ao_samples: 8,
+            ao_distance: 1.5,
+            portal_fraction: 0.5,
+            deadline: None,
+            light_samples: None,
+            samples_per_pixel: 1,
+            filter: Filter::default(),
+            pixel_order: PixelOrder::Scanline,
+            tile_order: TileOrder::Scanline,
+            reflection_blend: ReflectionBlend::Linear,
+            shadow_cache: false,
+            seal_pinholes: false,
+            debug_pixel: None,
+            shading: Shading::Phong,
+            sequential_samples: 64 * 64,
+            layer: None,
+            seed: 0,
+        }
+    }
+}
+
+// Counts of rays traced during a render, by purpose
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RayStats {
+    pub primary_rays: u64,
+    pub shadow_rays: u64,
+    // Reflection and refraction rays
+    pub secondary_rays: u64,
+    // Shadow rays first tested against the last occluder seen for their
+    // light, and how many of those it still blocked
+    pub shadow_cache_tests: u64,
+    pub shadow_cache_hits: u64,
+    // Ray-object intersection tests, not counting packet traversal
+    pub intersection_tests: u64,
+    // Camera rays that missed between hits and hit when traced again, with
+    // `RenderSettings::seal_pinholes`
+    pub pinholes_sealed: u64,
+}
+
+impl AddAssign for RayStats {
+    fn add_assign(&mut self, other: RayStats) {
+        self.primary_rays += other.primary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.shadow_cache_tests += other.shadow_cache_tests;
+        self.shadow_cache_hits += other.shadow_cache_hits;
+        self.intersection_tests += other.intersection_tests;
+        self.pinholes_sealed += other.pinholes_sealed;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderReport {
+    pub elapsed: Duration,
+    pub stats: RayStats,
+    // Some rows were skipped because the deadline passed
+    pub truncated: bool,
+    // Intersection tests of the costliest pixel, which the cost pass maps
+    // to the top of its ramp; None for other passes
+    pub peak_cost: Option<f64>,
+    // None unless the tiles were queued (see `TileOrder`)
+    pub tiles: Option<TileReport>,
+}
+
+// How long the tiles of a queued render took
+#[derive(Debug, Clone, Copy)]
+pub struct TileReport {
+    pub count: usize,
+    pub mean: Duration,
+    pub slowest: Duration,
+    // Top left pixel of the slowest tile
+    pub slowest_at: (u32, u32),
+    // From when the last tile was taken from the queue until the last one
+    // finished, during which threads run out of work
+    pub tail: Duration,
+}
+
+impl TileReport {
+    // `first_row` is where the band the tiles were in starts in the image
+    fn of(tiles: &[TracedTile], first_row: u32) -> Option<Self> {
+        let slowest = tiles.iter().max_by_key(|tile| tile.finished - tile.started)?;
+        let last_start = tiles.iter().map(|tile| tile.started).max()?;
+        let end = tiles.iter().map(|tile| tile.finished).max()?;
+        let total: Duration = tiles.iter().map(|tile| tile.finished - tile.started).sum();
+        Some(TileReport {
+            count: tiles.len(),
+            mean: total / tiles.len() as u32,
+            slowest: slowest.finished - slowest.started,
+            slowest_at: (slowest.corner.0, first_row + slowest.corner.1),
+            tail: end - last_start,
+        })
+    }
+    
+    // Bands are rendered one after another, so their tails add up
+    fn combine(self, other: TileReport) -> TileReport {
+        let count = self.count + other.count;
+        let (slowest, slowest_at) = match other.slowest > self.slowest {
+            true => (other.slowest, other.slowest_at),
+            false => (self.slowest, self.slowest_at),
+        };
+        TileReport {
+            count,
+            mean: (self.mean * self.count as u32 + other.mean * other.count as u32) / count as u32,
+            slowest,
+            slowest_at,
+            tail: self.tail + other.tail,
+        }
+    }
+}
+
+// Per-pixel data about what each pixel's camera ray hit first, filled in
+// alongside the color by `render_with_aux`. With several samples per
+// pixel, the first sample's ray is used.
+#[derive(Debug, Clone)]
+pub struct AuxBuffers {
+    pub width: u32,
+    pub height: u32,
+    // Distance along the view direction, as the clipping planes measure
+    // it; infinite where the ray hit nothing
+    pub depth: Vec<f64>,
+    // Shading normal facing the camera; zero where the ray hit nothing
+    pub normal: Vec<Vec3>,
+    // What each pixel's color is made of, averaged over all its samples
+    // like the color; None unless asked for with `with_layers`
+    pub layers: Option<Vec<Layers>>,
+}
+
+impl AuxBuffers {
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = Image::pixel_count(width, height);
+        AuxBuffers {
+            width,
+            height,
+            depth: vec![f64::INFINITY; size],
+            normal: vec![Vec3::zero(); size],
+            layers: None,
+        }
+    }
+    
+    pub fn with_layers(mut self) -> Self {
+        self.layers = Some(vec![Layers::default(); self.depth.len()]);
+        self
+    }
+    
+    // Consecutive runs of `len` pixels, one per parallel task, or a None per
+    // task when there are no buffers to fill
+    fn chunks(aux: Option<&mut AuxBuffers>, len: usize, count: usize) -> Vec<Option<AuxChunk<'_>>> {
+        match aux {
+            Some(aux) => {
+                let mut layers = aux.layers.as_mut().map(|layers| layers.chunks_mut(len));
+                aux.depth.chunks_mut(len).zip(aux.normal.chunks_mut(len))
+                    .map(|(depth, normal)| Some(AuxChunk { depth, normal, layers: layers.as_mut().and_then(Iterator::next) }))
+                    .collect()
+            }
+            None => (0..count).map(|_| None).collect(),
+        }
+    }
+}
+
+struct AuxChunk<'a> {
+    depth: &'a mut [f64],
+    normal: &'a mut [Vec3],
+    layers: Option<&'a mut [Layers]>,
+}
+
+/// A pixel's color split into parts a compositor can adjust on their own:
+/// the direct light at the first hit with and without shadows, the mirror
+/// reflection there, and everything else. Blends further down, such as
+/// transparency or a clear coat over the reflection, are already applied
+/// to each part, and `rest` also takes off whatever clipping to 0..1
+/// removed, so `composite` gives back the rendered color.
+///
+/// ```
+/// use rt::filter::Filter;
+/// use rt::image::Image;
+/// use rt::scene::{AuxBuffers, RenderSettings, Scene};
+/// use rt::vector::Vec3;
+///
+/// let scene: Scene = r#"{
+///     "camera": { "position": [0, 1, 3], "look_at": [0, 0, -3] },
+///     "lights": [{ "position": [3, 4, 0] }, { "type": "quad", "corner": [-3, 3, -1], "edge_u": [1, 0, 0], "edge_v": [0, 0, 1] }],
+///     "objects": [
+///         { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] },
+///         { "type": "sphere", "center": [-1, 0, -3], "radius": 1, "material": { "preset": "mirror" } },
+///         { "type": "sphere", "center": [1.2, 0, -3], "radius": 1, "material": { "preset": "glass" } }
+///     ]
+/// }"#.parse().unwrap();
+///
+/// let filters = [RenderSettings::default().filter, Filter::Gaussian { radius: 1.5 }];
+/// for (filter, samples) in filters.into_iter().zip([4, 2]) {
+///     let settings = RenderSettings { reflection: true, filter, samples_per_pixel: samples, ..RenderSettings::default() };
+///     let mut image = Image::new(40, 30);
+///     let mut aux = AuxBuffers::new(40, 30).with_layers();
+///     scene.render_with_aux(&mut image, Some(&mut aux), &settings);
+///
+///     let layers = aux.layers.unwrap();
+///     for (pixel, layers) in image.pixels.iter().zip(&layers) {
+///         assert!(Vec3::from(*pixel - layers.composite()).length() < 1e-9);
+///     }
+///     // The spheres shade the floor, and the mirror shows its surroundings
+///     assert!(layers.iter().any(|layers| layers.shadow().max_component() < 0.5));
+///     assert!(layers.iter().any(|layers| layers.reflection.max_component() > 0.1));
+/// }
+///
+/// // Nothing changes in the image itself for collecting the layers
+/// let mut plain = Image::new(40, 30);
+/// let mut layered = Image::new(40, 30);
+/// let settings = RenderSettings { reflection: true, ..RenderSettings::default() };
+/// scene.render_into(&mut plain, &settings);
+/// scene.render_with_aux(&mut layered, Some(&mut AuxBuffers::new(40, 30).with_layers()), &settings);
+/// assert_eq!(plain.pixels, layered.pixels);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Layers {
+    // Direct light as if nothing cast shadows
+    pub unshadowed: Color,
+    // Direct light that gets through
+    pub direct: Color,
+    pub reflection: Color,
+    pub rest: Color,
+}
+
+impl Layers {
+    // Share of the direct light that gets through, per channel; 1 where
+    // there is no direct light to block
+    pub fn shadow(&self) -> Color {
+        let through = |direct: f64, unshadowed: f64| if unshadowed > 0.0 { direct / unshadowed } else { 1.0 };
+        Color::new(
+            through(self.direct.r, self.unshadowed.r),
+            through(self.direct.g, self.unshadowed.g),
+            through(self.direct.b, self.unshadowed.b),
+        )
+    }
+    
+    pub fn composite(&self) -> Color {
+        self.rest + self.shadow() * self.unshadowed + self.reflection
+    }
+    
+    fn scaled(self, factor: f64) -> Self {
+        Layers {
+            unshadowed: self.unshadowed * factor,
+            direct: self.direct * factor,
+            reflection: self.reflection * factor,
+            rest: self.rest * factor,
+        }
+    }
+}
+
+impl Add for Layers {
+    type Output = Layers;
+    
+    fn add(self, other: Layers) -> Layers {
+        Layers {
+            unshadowed: self.unshadowed + other.unshadowed,
+            direct: self.direct + other.direct,
+            reflection: self.reflection + other.reflection,
+            rest: self.rest + other.rest,
+        }
+    }
+}
+
+// First hit of a pixel's camera ray, for the auxiliary buffers
+#[derive(Debug, Clone, Copy)]
+struct Surface {
+    depth: f64,
+    normal: Vec3,
+    // Unlike the rest, averaged over all the pixel's samples
+    layers: Layers,
+}
+
+impl Surface {
+    fn miss() -> Self {
+        Surface { depth: f64::INFINITY, normal: Vec3::zero(), layers: Layers::default() }
+    }
+    
+    fn of(ray: &Ray, hit: Option<&(HitRecord, &dyn Object)>, camera: &Camera) -> Self {
+        match hit {
+            Some((hit_record, _)) => Surface {
+                depth: hit_record.t * -ray.direction.dot(&camera.w),
+                normal: hit_record.facing_shading_normal(ray),
+                layers: Layers::default(),
+            },
+            None => Surface::miss(),
+        }
+    }
+    
+    fn store(self, chunk: &mut Option<AuxChunk>, index: usize) {
+        if let Some(chunk) = chunk {
+            chunk.depth[index] = self.depth;
+            chunk.normal[index] = self.normal;
+            if let Some(layers) = &mut chunk.layers {
+                layers[index] = self.layers;
+            }
+        }
+    }
+}
+
+// Rows per parallel task when splatting samples through a wide filter
+const SPLAT_BAND_ROWS: usize = 16;
+
+// Edge length of the tiles used by `PixelOrder::Morton`; a power of two so
+// the Z-order curve covers each tile exactly
+const TILE_SIZE: u32 = 16;
+
+const LIGHT_GIZMO_RADIUS: f64 = 0.1;
+
+// Keeps shadow rays toward area light samples from hitting the light's own
+// emitter geometry
+const AREA_SHADOW_EPSILON: f64 = 0.001;
+
+// Thickness probes per light sample on translucent materials when rendering
+// more than one sample per pixel
+const SUBSURFACE_PROBES: u32 = 4;
+
+// How far over, in pixels, a camera ray that slipped through a seam is
+// traced again
+const PINHOLE_NUDGE: f64 = 0.001;
+
+// Highlights on clear coats are much tighter than those of the layer below
+const COAT_SHININESS: f64 = 1000.0;
+
+// How closely sheen hugs the silhouette
+const SHEEN_EXPONENT: f64 = 2.0;
+
+// Object that blocked the last shadow ray toward each light, by light
+// index. Neighbouring shadow rays tend to be blocked by the same object, so
+// it is worth testing first. The entry is cleared once a ray gets through,
+// so lit areas do not pay for a test that keeps failing.
+#[derive(Default)]
+struct ShadowCache {
+    occluders: Vec<Option<usize>>,
+}
+
+impl ShadowCache {
+    fn occluder(&self, light_index: usize) -> Option<usize> {
+        self.occluders.get(light_index).copied().flatten()
+    }
+    
+    fn remember(&mut self, light_index: usize, object_index: usize) {
+        if self.occluders.len() <= light_index {
+            self.occluders.resize(light_index + 1, None);
+        }
+        self.occluders[light_index] = Some(object_index);
+    }
+    
+    fn forget(&mut self, light_index: usize) {
+        if let Some(occluder) = self.occluders.get_mut(light_index) {
+            *occluder = None;
+        }
+    }
+}
+
+// Mutable state carried along all rays of one pixel
+struct PathState<'c> {
+    pixel: (u32, u32),
+    stats: RayStats,
+    rng: Rng,
+    // Shared by the pixels of one render task, so it carries over from
+    // pixel to pixel; None unless `RenderSettings::shadow_cache` is set
+    shadow_cache: Option<&'c mut ShadowCache>,
+    // Set for the pixel picked by `RenderSettings::debug_pixel`
+    log: bool,
+    // Where the rays traced are recorded, when `Scene::trace_paths` asks
+    paths: Option<&'c Mutex<Vec<RaySegment>>>,
+    // Share of the pixel's color that the ray being traced makes up, the
+    // product of the blend weights down the chain of bounces
+    throughput: f64,
+    // Transparent objects the ray being traced is inside
+    media: MediumStack,
+    // Parts of the current sample's color, filled in when
+    // `TraceContext::layers` is set
+    layers: Layers,
+}
+
+impl<'c> PathState<'c> {
+    fn for_pixel(x: u32, y: u32, ctx: &'c TraceContext, shadow_cache: &'c mut ShadowCache) -> Self {
+        let settings = ctx.settings;
+        PathState {
+            pixel: (x, y),
+            stats: RayStats::default(),
+            rng: Rng::for_sample(settings.seed, x, y, 0),
+            shadow_cache: settings.shadow_cache.then_some(shadow_cache),
+            log: settings.debug_pixel == Some((x, y)),
+            paths: ctx.paths.as_ref(),
+            throughput: 1.0,
+            media: MediumStack::new(),
+            layers: Layers::default(),
+        }
+    }
+    
+    // Switches to the random numbers of another sample of the same pixel
+    fn begin_sample(&mut self, seed: u64, sample: u32) {
+        self.rng = Rng::for_sample(seed, self.pixel.0, self.pixel.1, sample);
+    }
+    
+    // Writes a line of the debug pixel's trace, indented by bounce. The
+    // message is only built when this pixel is being logged.
+    fn log(&self, bounce: i32, message: impl FnOnce() -> String) {
+        if self.log {
+            info!("pixel ({}, {}) {}{}", self.pixel.0, self.pixel.1, "  ".repeat(bounce.max(0) as usize), message());
+        }
+    }
+    
+    // Notes a ray running from `from` to `to` for `Scene::trace_paths`;
+    // `hit` tells whether it ended at a surface
+    fn record(&self, kind: RayKind, bounce: i32, from: Vec3, to: Vec3, hit: bool) {
+        if let Some(paths) = self.paths {
+            let segment = RaySegment { pixel: self.pixel, kind, bounce, from, to, hit };
+            paths.lock().unwrap().push(segment);
+        }
+    }
+}
+
+// Per-render state shared by every ray. Debug-only objects live here rather
+// than in the scene so they are never seen by shadow rays and never outlive
+// the render.
+struct TraceContext<'a> {
+    settings: &'a RenderSettings,
+    gizmos: Vec<Sphere>,
+    photon_map: Option<PhotonMap>,
+    // Angle subtended by one pixel, for estimating texture footprints
+    pixel_angle: f64,
+    // Parallel to the scene's lights
+    linked_objects: Vec<LinkedObjects>,
+    layer: Option<LayerObjects>,
+    // Height of the whole image, and which of its rows is the first of the
+    // ones being rendered, when only a band of them is
+    frame_height: u32,
+    first_row: u32,
+    // Whether to split colors into `Layers`, which costs shadow-free light
+    // sums at every first hit
+    layers: bool,
+    // Rays traced, recorded when set
+    paths: Option<Mutex<Vec<RaySegment>>>,
+    // Left by a render whose tiles were queued
+    tile_report: Mutex<Option<TileReport>>,
+}
+
+impl<'a> TraceContext<'a> {
+    fn new(scene: &Scene, settings: &'a RenderSettings, image_height: u32) -> Self {
+        scene.view_lods();
+        let gizmos = if settings.show_lights {
+            scene.lights.iter()
+                .map(|light| Sphere::new(light.center(), LIGHT_GIZMO_RADIUS, Material::emissive(light.color)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let photon_map = settings.caustics
+            .then(|| PhotonMap::build(scene, settings.photons_per_light, settings.photon_radius, settings.seed));
+        let pixel_angle = scene.camera.as_ref()
+            .map_or(0.0, |camera| camera.fov.to_radians() / image_height.max(1) as f64);
+        let linked_objects = scene.lights.iter()
+            .map(|light| LinkedObjects::new(scene, &light.linking))
+            .collect();
+        let layer = settings.layer.map(|layer| LayerObjects::new(scene, layer));
+        TraceContext { settings, gizmos, photon_map, pixel_angle, linked_objects, layer, frame_height: image_height, first_row: 0, layers: false, paths: None, tile_report: Mutex::new(None) }
+    }
+    
+    // Camera ray through (x, y), with y counted from the first row being
+    // rendered
+    fn camera_ray(&self, camera: &Camera, width: u32, x: f64, y: f64) -> Ray {
+        camera_ray(camera, width, self.frame_height, x, y + self.first_row as f64)
+    }
+    
+    // Same, leaving from the point at `lens` of the camera's lens
+    fn lens_ray(&self, camera: &Camera, width: u32, x: f64, y: f64, lens: (f64, f64)) -> Ray {
+        let (u, v) = view_position(width, self.frame_height, x, y + self.first_row as f64);
+        camera.lens_ray(u, v, lens.0, lens.1)
+    }
+}
+
+// A light's linking with the object names resolved to addresses, so
+// shading can check the object it has without looking up its name. Names
+// that match no object are ignored.
+enum LinkedObjects {
+    All,
+    Only(HashSet<usize>),
+    Except(HashSet<usize>),
+}
+
+impl LinkedObjects {
+    fn new(scene: &Scene, linking: &LightLinking) -> Self {
+        let addresses = |names: &[String]| {
+            scene.objects.iter().zip(&scene.object_names)
+                .filter(|(_, name)| names.contains(name))
+                .map(|(object, _)| object_address(object.as_ref()))
+                .collect()
+        };
+        match linking {
+            LightLinking::All => LinkedObjects::All,
+            LightLinking::Only(names) => LinkedObjects::Only(addresses(names)),
+            LightLinking::Except(names) => LinkedObjects::Except(addresses(names)),
+        }
+    }
+    
+    fn includes(&self, object: &dyn Object) -> bool {
+        match self {
+            LinkedObjects::All => true,
+            LinkedObjects::Only(addresses) => addresses.contains(&object_address(object)),
+            LinkedObjects::Except(addresses) => !addresses.contains(&object_address(object)),
+        }
+    }
+}
+
+// A render layer with the objects resolved to addresses, like
+// `LinkedObjects`: whether each scene object shows. Anything else a
+// camera ray can hit goes with the rest.
+struct LayerObjects {
+    shown: HashMap<usize, bool>,
+    rest: bool,
+}
+
+impl LayerObjects {
+    fn new(scene: &Scene, layer: RenderLayer) -> Self {
+        let shown = scene.objects.iter().zip(&scene.object_layers)
+            .map(|(object, id)| {
+                let shows = id.map_or(layer.rest, |id| layer.layers >> id & 1 == 1);
+                (object_address(object.as_ref()), shows)
+            })
+            .collect();
+        LayerObjects { shown, rest: layer.rest }
+    }
+    
+    // Whether a camera ray that hit `object`, or nothing, sees it
+    fn shows(&self, object: Option<&dyn Object>) -> bool {
+        object.and_then(|object| self.shown.get(&object_address(object)).copied()).unwrap_or(self.rest)
+    }
+}
+
+fn object_address(object: &dyn Object) -> usize {
+    object as *const dyn Object as *const () as usize
+}
+
+// Camera ray through image position (x, y) in pixels, with pixel centers
+// on whole numbers and y growing downwards. The first and last pixel
+// centers sit on the edges of the view; along a side one pixel long, the
+// pixel covers the whole view and its center is the view's center.
+fn camera_ray(camera: &Camera, width: u32, height: u32, x: f64, y: f64) -> Ray {
+    let (u, v) = view_position(width, height, x, y);
+    camera.get_ray(u, v)
+}
+
+// The (s, t) that `Camera::get_ray` takes for image position (x, y)
+fn view_position(width: u32, height: u32, x: f64, y: f64) -> (f64, f64) {
+    let u = if width > 1 { x / (width - 1) as f64 } else { x + 0.5 };
+    let v = if height > 1 { ((height - 1) as f64 - y) / (height - 1) as f64 } else { 0.5 - y };
+    (u, v)
+}
+
+// Debug builds name the first pixel and camera ray that produced a NaN or
+// infinite color, which would otherwise just show up as black or garbage
+fn report_non_finite(pixel: (u32, u32), ray: &Ray, color: Color) {
+    static REPORTED: Once = Once::new();
+    REPORTED.call_once(|| {
+        warn!(
+            "non-finite color {:?} at pixel ({}, {}) from ray {:?}; further ones are not reported",
+            color, pixel.0, pixel.1, ray
+        );
+    });
+}
+
+// Offset of the next sample from the pixel center. A lone sample stays on
+// the center so single-sample renders are unchanged.
+fn sample_offset(samples: u32, rng: &mut Rng) -> (f64, f64) {
+    if samples == 1 {
+        return (0.0, 0.0);
+    }
+    (rng.next_f64() - 0.5, rng.next_f64() - 0.5)
+}
+
+// Pixel offsets within a tile in Z-order: the bits of each index are
+// split into x (even bits) and y (odd bits)
+fn morton_tile_order() -> Vec<(u32, u32)> {
+    let compact = |mut bits: u32| {
+        let mut value = 0;
+        for shift in 0..16 {
+            value |= (bits & 1) << shift;
+            bits >>= 2;
+        }
+        value
+    };
+    (0..TILE_SIZE * TILE_SIZE).map(|index| (compact(index), compact(index >> 1))).collect()
+}
+
+// Position of tile (x, y) along a Hilbert curve over a `side` by `side`
+// grid of tiles, `side` being a power of two. Each step of the curve moves
+// to a neighbouring tile, so tiles handed out in a row stay close together
+// in the image.
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut half = side / 2;
+    while half > 0 {
+        let right = (x & half > 0) as u64;
+        let below = (y & half > 0) as u64;
+        index += half as u64 * half as u64 * ((3 * right) ^ below);
+        // Turn the quadrant so its curve joins the next one
+        if below == 0 {
+            if right == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        half /= 2;
+    }
+    index
+}
+
+// What each tile of a `TileOrder::Cost` render cost, in intersection tests,
+// and which image (or band of one) it was
+struct TileCosts {
+    width: u32,
+    height: u32,
+    first_row: u32,
+    costs: Vec<u64>,
+}
+
+// One tile of a queued render, traced but not yet copied into the image
+struct TracedTile {
+    // Position in the tiles' reading order
+    index: usize,
+    corner: (u32, u32),
+    width: u32,
+    colors: Vec<Color>,
+    surfaces: Vec<Surface>,
+    stats: RayStats,
+    truncated: bool,
+    // Since the render started
+    started: Duration,
+    finished: Duration,
+}
+
+fn past_deadline(settings: &RenderSettings) -> bool {
+    settings.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
 
 pub struct Scene {
-    pub objects: Vec<Box<dyn Object>>,
+    objects: Vec<Box<dyn Object>>,
+    // Parallel to `objects`
+    object_names: Vec<String>,
+    // Parallel to `objects`, indices into `layer_names`
+    object_layers: Vec<Option<usize>>,
+    layer_names: Vec<String>,
+    // Objects added so far of each kind, for automatic names
+    kind_counts: HashMap<&'static str, usize>,
+    // Built on the first ray query and dropped whenever objects are added
+    bvh: OnceLock<Bvh>,
     pub lights: Vec<Light>,
+    pub clip_planes: Vec<ClipPlane>,
+    // Openings that occlusion rays are aimed through
+    pub portals: Vec<Portal>,
     pub camera: Option<Camera>,
-    pub background_color: Vec3,
+    // Moves the camera a little in every frame of an animation
+    pub shake: Option<Shake>,
+    // Views composed for other aspect ratios, at most one per ratio
+    pub framings: Vec<Framing>,
+    pub background: Background,
+    // Seen instead of the background by camera rays that miss
+    pub backdrop: Option<Backdrop>,
+    // What each tile cost the last render with `TileOrder::Cost`, to order
+    // the tiles of the next one of the same size
+    tile_costs: Mutex<Option<TileCosts>>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene::new()
+    }
 }
 
 impl Scene {
     pub fn new() -> Self {
         Scene {
             objects: Vec::new(),
+            object_names: Vec::new(),
+            object_layers: Vec::new(),
+            layer_names: Vec::new(),
+            kind_counts: HashMap::new(),
+            bvh: OnceLock::new(),
             lights: Vec::new(),
+            clip_planes: Vec::new(),
+            portals: Vec::new(),
             camera: None,
-            background_color: Vec3::new(0.7, 0.8, 1.0), // Light sky blue
+            shake: None,
+            framings: Vec::new(),
+            background: Background::default(),
+            backdrop: None,
+            tile_costs: Mutex::new(None),
+        }
+    }
+    
+    // Unnamed objects are called after their kind and position among
+    // objects of that kind: sphere1, sphere2, cube1, ...
+    pub fn add_object(&mut self, object: Box<dyn Object>) {
+        let count = self.kind_counts.get(object.kind()).copied().unwrap_or(0);
+        self.add_named_object(&format!("{}{}", object.kind(), count + 1), object);
+    }
+    
+    pub fn add_named_object(&mut self, name: &str, object: Box<dyn Object>) {
+        *self.kind_counts.entry(object.kind()).or_insert(0) += 1;
+        self.objects.push(object);
+        self.object_names.push(name.to_string());
+        self.object_layers.push(None);
+        self.bvh = OnceLock::new();
+    }
+    
+    // Puts an object in a layer for `render_layers`. Layer names end up in
+    // file names, so they are kept to letters, digits, '-' and '_'.
+    pub fn set_object_layer(&mut self, index: usize, layer: &str) -> Result<(), String> {
+        if layer.is_empty() || !layer.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("bad layer name '{}' (use letters, digits, '-' and '_')", layer));
+        }
+        let id = match self.layer_names.iter().position(|name| name == layer) {
+            Some(id) => id,
+            None if self.layer_names.len() < MAX_LAYERS => {
+                self.layer_names.push(layer.to_string());
+                self.layer_names.len() - 1
+            }
+            None => return Err(format!("too many layers (at most {})", MAX_LAYERS)),
+        };
+        self.object_layers[index] = Some(id);
+        Ok(())
+    }
+    
+    pub fn object_layer(&self, index: usize) -> Option<&str> {
+        self.object_layers[index].map(|id| self.layer_names[id].as_str())
+    }
+    
+    // Layer names in the order objects first used them
+    pub fn layer_names(&self) -> &[String] {
+        &self.layer_names
+    }
+    
+    /// Splits the scene into layers for compositing, one `RenderLayer` per
+    /// name in `names`: rendering with it shows that layer's objects and
+    /// holds out the others. The last layer also takes the objects in no
+    /// layer listed, light gizmos, section caps and the background, so the
+    /// layers add up to the full image. Laying each over the next using
+    /// the coverage from `RenderPass::Alpha` comes close too, differing only
+    /// where the edges of different layers share a pixel.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::image::color::Color;
+    /// use rt::scene::{RenderPass, RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+    ///     "background": [0.2, 0.3, 0.5],
+    ///     "lights": [{ "position": [2, 3, 0] }],
+    ///     "objects": [
+    ///         { "type": "sphere", "center": [-0.4, 0, -3], "radius": 0.6, "layer": "fg", "material": { "reflectivity": 0.3 } },
+    ///         { "type": "sphere", "center": [0.6, 0.2, -5], "radius": 1, "layer": "bg" },
+    ///         { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] }
+    ///     ]
+    /// }"#.parse().unwrap();
+    /// let settings = RenderSettings { reflection: true, samples_per_pixel: 4, ..RenderSettings::default() };
+    /// let render = |settings: RenderSettings| {
+    ///     let mut image = Image::new(40, 30);
+    ///     scene.render_into(&mut image, &settings);
+    ///     image.pixels
+    /// };
+    /// let layers = scene.render_layers(&["fg".to_string(), "bg".to_string()]).unwrap();
+    /// let (fg, bg) = (Some(layers[0]), Some(layers[1]));
+    /// let beauty = render(settings);
+    /// let fg_color = render(RenderSettings { layer: fg, ..settings });
+    /// let bg_color = render(RenderSettings { layer: bg, ..settings });
+    /// let fg_alpha = render(RenderSettings { layer: fg, pass: RenderPass::Alpha, ..settings });
+    ///
+    /// // Each sample lands in exactly one layer. The floor and the sky
+    /// // go with the last one, and the foreground sphere holds the
+    /// // background one out where it is in front.
+    /// let apart = |a: Color, b: Color| Vec3::from(a - b).length();
+    /// assert!(beauty.iter().zip(fg_color.iter().zip(&bg_color)).all(|(&all, (&fg, &bg))| apart(fg + bg, all) < 1e-9));
+    /// let center = 15 * 40 + 12;
+    /// assert_eq!((fg_alpha[center], bg_color[center]), (Color::WHITE, Color::BLACK));
+    /// assert_eq!(fg_alpha[0], Color::BLACK);
+    ///
+    /// // Foreground over background
+    /// let over: Vec<Color> = (0..beauty.len()).map(|i| fg_color[i] + bg_color[i] * (1.0 - fg_alpha[i].r)).collect();
+    /// let error = over.iter().zip(&beauty).map(|(&a, &b)| apart(a, b)).sum::<f64>() / beauty.len() as f64;
+    /// assert!(error < 0.002, "{}", error);
+    ///
+    /// assert!(scene.render_layers(&["fg".to_string(), "mid".to_string()]).is_err());
+    /// ```
+    pub fn render_layers(&self, names: &[String]) -> Result<Vec<RenderLayer>, String> {
+        let mut bits = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(format!("layer '{}' is listed twice", name));
+            }
+            let id = self.layer_names.iter().position(|layer| layer == name).ok_or_else(|| {
+                format!("no object is in layer '{}' (scene layers: {})", name, self.layer_names.join(", "))
+            })?;
+            bits.push(1u64 << id);
+        }
+        let listed = bits.iter().fold(0, |all, bit| all | bit);
+        let last = bits.len().saturating_sub(1);
+        Ok(bits.iter().enumerate()
+            .map(|(i, &bit)| match i == last {
+                true => RenderLayer { layers: bit | !listed, rest: true },
+                false => RenderLayer { layers: bit, rest: false },
+            })
+            .collect())
+    }
+    
+    pub fn objects(&self) -> &[Box<dyn Object>] {
+        &self.objects
+    }
+    
+    pub fn object_name(&self, index: usize) -> &str {
+        &self.object_names[index]
+    }
+    
+    pub fn object_names(&self) -> &[String] {
+        &self.object_names
+    }
+    
+    /// Counts, materials, lights, camera and extent of the scene, without
+    /// rendering it.
+    ///
+    /// ```
+    /// use rt::scene::Scene;
+    /// use rt::vector::Vec3;
+    ///
+    /// let scene: Scene = r#"{
+    ///     "materials": { "red": { "color": [1, 0, 0] } },
+    ///     "lights": [{ "position": [0, 5, 0], "intensity": 0.8 }],
+    ///     "objects": [
+    ///         { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] },
+    ///         { "type": "sphere", "center": [0, 0, -5], "radius": 1, "material": "red" },
+    ///         { "type": "cube", "name": "box", "center": [2, 0, -5], "size": 2, "material": "red" }
+    ///     ]
+    /// }"#.parse().unwrap();
+    ///
+    /// let summary = scene.describe();
+    /// assert_eq!(summary.kinds, [("plane", 1), ("sphere", 1), ("cube", 1)]);
+    /// assert_eq!(summary.materials.len(), 2);
+    /// assert_eq!(summary.materials[1].objects, ["sphere1", "box"]);
+    /// assert_eq!(summary.lights[0].kind, "point");
+    /// assert_eq!(summary.unbounded_objects, 1);
+    /// let bounds = summary.bounds.unwrap();
+    /// assert_eq!((bounds.min, bounds.max), (Vec3::new(-1.0, -1.0, -6.0), Vec3::new(3.0, 1.0, -4.0)));
+    ///
+    /// let json = summary.to_json();
+    /// assert_eq!(json.get("objects").and_then(|n| n.as_f64()), Some(3.0));
+    /// assert!(summary.to_string().starts_with("Objects: 3 (plane: 1, sphere: 1, cube: 1)\n"));
+    /// ```
+    pub fn describe(&self) -> SceneSummary {
+        SceneSummary::new(self)
+    }
+    
+    // Pairs of objects, by index, that intersect by more than `tolerance`
+    // or whose bounding boxes do; see `overlap::between`. Bounded objects
+    // are swept along x so only those that share some of it are compared.
+    pub fn detect_overlaps(&self, tolerance: f64) -> Vec<(usize, usize)> {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            match object.bounding_box() {
+                Some(bounds) => bounded.push((index, bounds)),
+                None => unbounded.push(index),
+            }
+        }
+        bounded.sort_by(|a, b| a.1.min.x.total_cmp(&b.1.min.x));
+        
+        let mut pairs = Vec::new();
+        let mut overlapping = |a: usize, b: usize| {
+            if overlap::between(self.objects[a].as_ref(), self.objects[b].as_ref(), tolerance).is_some() {
+                pairs.push((a.min(b), a.max(b)));
+            }
+        };
+        for (i, &(a, bounds)) in bounded.iter().enumerate() {
+            let near = bounded[i + 1..].iter()
+                .take_while(|(_, other)| other.min.x < bounds.max.x - tolerance)
+                .filter(|(_, other)| {
+                    other.min.y < bounds.max.y - tolerance && bounds.min.y < other.max.y - tolerance
+                        && other.min.z < bounds.max.z - tolerance && bounds.min.z < other.max.z - tolerance
+                });
+            for &(b, _) in near {
+                overlapping(a, b);
+            }
+            for &b in &unbounded {
+                overlapping(a, b);
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+    
+    pub fn find_object(&self, name: &str) -> Option<usize> {
+        self.object_names.iter().position(|n| n == name)
+    }
+    
+    /// Changes only the named object's pixels.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{RenderSettings, Scene};
+    ///
+    /// let mut scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 5], "look_at": [0, 0, 0], "fov": 40 },
+    ///     "lights": [{ "position": [0, 5, 5] }],
+    ///     "objects": [
+    ///         { "type": "sphere", "name": "left", "center": [-1.2, 0, 0], "radius": 1 },
+    ///         { "type": "sphere", "name": "right", "center": [1.2, 0, 0], "radius": 1 }
+    ///     ]
+    /// }"#.parse().unwrap();
+    /// let render = |scene: &Scene| {
+    ///     let mut image = Image::new(64, 32);
+    ///     scene.render_into(&mut image, &RenderSettings::default());
+    ///     image
+    /// };
+    /// let before = render(&scene);
+    ///
+    /// scene.apply_material_override(&"right=color:red".parse().unwrap()).unwrap();
+    /// let after = render(&scene);
+    /// for y in 0..32 {
+    ///     for x in 0..32 {
+    ///         assert_eq!(before.get_pixel(x, y), after.get_pixel(x, y));
+    ///     }
+    /// }
+    /// assert_ne!(before.get_pixel(48, 16), after.get_pixel(48, 16));
+    ///
+    /// let err = scene.apply_material_override(&"middle=glass".parse().unwrap()).unwrap_err();
+    /// assert_eq!(err, "unknown object 'middle' (scene objects: left, right)");
+    /// let err = scene.apply_material_override(&"left=glossiness:1".parse().unwrap()).unwrap_err();
+    /// assert_eq!(err, "unknown material field 'glossiness'");
+    /// ```
+    pub fn apply_material_override(&mut self, material_override: &MaterialOverride) -> Result<(), String> {
+        let index = self.find_object(&material_override.object).ok_or_else(|| {
+            format!(
+                "unknown object '{}' (scene objects: {})",
+                material_override.object,
+                self.object_names.join(", ")
+            )
+        })?;
+        for material in self.objects[index].materials_mut() {
+            material_override.apply(material)?;
+        }
+        Ok(())
+    }
+    
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+    
+    // Adds the light and, for area lights, a matching emissive panel so the
+    // light shows up in the image and in reflections
+    pub fn add_light_with_emitter(&mut self, light: Light) {
+        if let LightShape::Quad { edge_u, edge_v } = light.shape {
+            let panel = Quad::new(light.position, edge_u, edge_v, Material::emissive(light.color));
+            self.add_object(Box::new(panel));
+        }
+        self.add_light(light);
+    }
+    
+    /// Adds an area light whose panel shows `texture`, like a lit window
+    /// or a screen, so the pattern appears in the image and in reflections.
+    /// The light shines with the texture's average color rather than its
+    /// pattern, so what it casts matches the panel as a whole. Point lights
+    /// have no panel and are added unchanged.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use rt::camera::Camera;
+    /// use rt::image::color::Color;
+    /// use rt::image::Image;
+    /// use rt::light::Light;
+    /// use rt::material::Material;
+    /// use rt::objects::Plane;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::texture::{ImageTexture, TextureFilter};
+    /// use rt::vector::Vec3;
+    ///
+    /// // A panel overhead, red on one half and blue on the other, over a
+    /// // white floor
+    /// let (red, blue) = (Color::new(1.0, 0.0, 0.0), Color::new(0.0, 0.0, 1.0));
+    /// let stripes = ImageTexture::new(2, 1, vec![red, blue]).with_filter(TextureFilter::Nearest);
+    /// let mut scene = Scene::new();
+    /// let floor = Material { color: Color::WHITE, ..Material::default() };
+    /// scene.add_object(Box::new(Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), floor)));
+    /// let panel = Light::quad(Vec3::new(-1.0, 1.0, -4.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0), Color::WHITE, 0.5);
+    /// scene.add_textured_light(panel, Arc::new(stripes));
+    /// assert_eq!(scene.lights[0].color, Color::new(0.5, 0.0, 0.5));
+    ///
+    /// // Looking up at the panel shows both halves, mirrored from below
+    /// scene.set_camera(Camera::new(Vec3::new(0.0, -0.5, -3.0), Vec3::new(0.0, 1.0, -3.0), Vec3::new(0.0, 0.0, -1.0), 60.0, 1.0).unwrap());
+    /// let mut image = Image::new(16, 16);
+    /// scene.render_into(&mut image, &RenderSettings::default());
+    /// let (left, right) = (image.get_pixel(4, 8), image.get_pixel(11, 8));
+    /// assert_eq!((left, right), (blue, red));
+    ///
+    /// // The floor below is lit purple, the mix of the two
+    /// scene.set_camera(Camera::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, -1.0, -4.0), Vec3::new(0.0, 1.0, 0.0), 20.0, 1.0).unwrap());
+    /// scene.render_into(&mut image, &RenderSettings::default());
+    /// let lit = image.get_pixel(8, 8);
+    /// assert!(lit.r > lit.g + 0.2 && (lit.r - lit.b).abs() < 1e-9);
+    /// ```
+    pub fn add_textured_light(&mut self, light: Light, texture: Arc<ImageTexture>) {
+        let LightShape::Quad { edge_u, edge_v } = light.shape else {
+            self.add_light(light);
+            return;
+        };
+        let panel = Quad::new(light.position, edge_u, edge_v, Material::emissive(light.color))
+            .with_emission_texture(texture.clone());
+        self.add_object(Box::new(panel));
+        self.add_light(Light { color: light.color * texture.average(), ..light });
+    }
+    
+    pub fn add_clip_plane(&mut self, clip_plane: ClipPlane) {
+        self.clip_planes.push(clip_plane);
+    }
+    
+    pub fn add_portal(&mut self, portal: Portal) {
+        self.portals.push(portal);
+    }
+    
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = Some(camera);
+    }
+    
+    // The framing for `aspect`, however its ratio is written
+    pub fn framing(&self, aspect: (u32, u32)) -> Option<&Framing> {
+        self.framings.iter().find(|framing| same_aspect(framing.aspect, aspect))
+    }
+    
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+    
+    pub fn set_backdrop(&mut self, backdrop: Backdrop) {
+        self.backdrop = Some(backdrop);
+    }
+    
+    // Picks the level of every `Lod` from the camera's position, once per
+    // render, so all its rays see the same ones
+    fn view_lods(&self) {
+        if let Some(camera) = &self.camera {
+            lod::view_from_all(&self.objects, camera.position);
+        }
+    }
+    
+    // What a camera ray that hits nothing sees
+    fn camera_miss_color(&self, ray: &Ray) -> Color {
+        match (&self.backdrop, &self.camera) {
+            (Some(backdrop), Some(camera)) => backdrop.color(ray, camera),
+            _ => self.background.color(ray),
+        }
+    }
+    
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            let start = Instant::now();
+            let bvh = Bvh::build(&self.objects);
+            debug!(
+                "Built a BVH over {} objects in {:.1} ms: {} nodes, depth {}, {} unbounded objects tested separately",
+                self.objects.len(),
+                start.elapsed().as_secs_f64() * 1000.0,
+                bvh.node_count(),
+                bvh.depth(),
+                bvh.unbounded_count(),
+            );
+            bvh
+        })
+    }
+    
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(HitRecord, &dyn Object)> {
+        self.counted_hit(ray, t_min, t_max, &mut 0)
+    }
+    
+    // Like `hit`, adding the number of objects tested to `tests`
+    fn counted_hit(&self, ray: &Ray, t_min: f64, t_max: f64, tests: &mut u64) -> Option<(HitRecord, &dyn Object)> {
+        let (hit_record, index, section) = self.closest_hit(ray, t_min, t_max, tests)?;
+        let object = match section {
+            Some(section) => section as &dyn Object,
+            None => self.objects[index].as_ref(),
+        };
+        Some((hit_record, object))
+    }
+    
+    pub fn hit4(&self, packet: &RayPacket4, t_min: [f64; 4], t_max: [f64; 4]) -> [Option<(HitRecord, &dyn Object)>; 4] {
+        if !self.clip_planes.is_empty() {
+            return std::array::from_fn(|lane| self.hit(&packet.rays[lane], t_min[lane], t_max[lane]));
+        }
+        self.bvh().hit4(&self.objects, packet, t_min, t_max)
+    }
+    
+    // Like `counted_hit`, but gives the index of the object that was hit; a
+    // section cap counts as the object it closes off
+    fn hit_index(&self, ray: &Ray, t_min: f64, t_max: f64, tests: &mut u64) -> Option<(HitRecord, usize)> {
+        self.closest_hit(ray, t_min, t_max, tests).map(|(hit_record, index, _)| (hit_record, index))
+    }
+    
+    // Closest hit, with the index of the object hit and the section cap if
+    // that is what was hit
+    fn closest_hit(&self, ray: &Ray, t_min: f64, t_max: f64, tests: &mut u64) -> Option<(HitRecord, usize, Option<&Plane>)> {
+        if self.clip_planes.is_empty() {
+            let (hit_record, index) = self.bvh().closest_hit(ray, t_min, t_max, |index, closest_t| {
+                *tests += 1;
+                self.objects[index].hit(ray, t_min, closest_t)
+            })?;
+            return Some((hit_record, index, None));
+        }
+        self.clipped_hit(ray, t_min, t_max, tests)
+    }
+    
+    // Whether the ray hits one particular object
+    fn hits_object(&self, index: usize, ray: &Ray, t_min: f64, t_max: f64, tests: &mut u64) -> bool {
+        let object = self.objects[index].as_ref();
+        *tests += 1;
+        if self.clip_planes.is_empty() {
+            return object.hit(ray, t_min, t_max).is_some();
+        }
+        clip::kept_range(&self.clip_planes, ray, t_min, t_max)
+            .and_then(|range| self.clipped_object_hit(object, ray, range))
+            .is_some()
+    }
+    
+    // `closest_hit` with the clipping planes applied
+    fn clipped_hit(&self, ray: &Ray, t_min: f64, t_max: f64, tests: &mut u64) -> Option<(HitRecord, usize, Option<&Plane>)> {
+        let range = clip::kept_range(&self.clip_planes, ray, t_min, t_max)?;
+        let mut capped = Vec::new();
+        let (hit_record, index) = self.bvh().closest_hit(ray, range.t_min, range.t_max, |index, closest_t| {
+            *tests += 1;
+            let range = KeptRange { t_max: closest_t, ..range };
+            let (hit_record, is_section) = self.clipped_object_hit(self.objects[index].as_ref(), ray, range)?;
+            if is_section {
+                capped.push(index);
+            }
+            Some(hit_record)
+        })?;
+        let section = range.entry
+            .filter(|_| capped.contains(&index))
+            .and_then(|entry| self.clip_planes[entry].section.as_ref());
+        Some((hit_record, index, section))
+    }
+    
+    // Closest hit on one object within the kept range, and whether it is on
+    // a section cap. The cap is hit where the ray crosses into the kept part
+    // while inside the object.
+    fn clipped_object_hit(&self, object: &dyn Object, ray: &Ray, range: KeptRange) -> Option<(HitRecord, bool)> {
+        if let Some(section) = range.entry.and_then(|entry| self.clip_planes[entry].section.as_ref()) {
+            let inside = object.hit_range(ray, range.t_min, range.t_max)
+                .first()
+                .is_some_and(|&(start, end)| start == range.t_min && end > start);
+            if inside {
+                let t = range.t_min;
+                return Some((HitRecord::new(ray.at(t), section.normal, t, ray), true));
+            }
+        }
+        object.hit(ray, range.t_min, range.t_max).map(|hit_record| (hit_record, false))
+    }
+    
+    /// Renders into `image` without any I/O, returning timing and ray counts.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{RenderSettings, Scene};
+    ///
+    /// let scene: Scene = r#"{ "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] } }"#.parse().unwrap();
+    /// let mut image = Image::new(8, 8);
+    /// let report = scene.render_into(&mut image, &RenderSettings::default());
+    /// assert_eq!(report.stats.primary_rays, 64);
+    /// ```
+    ///
+    /// Images one pixel wide or tall work too, such as strips for
+    /// scanline-style output; along a side of one pixel, that pixel looks
+    /// through the middle of the view. Images with no pixels are left as
+    /// they are.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::image::color::Color;
+    /// use rt::image::Image;
+    /// use rt::light::Light;
+    /// use rt::material::Material;
+    /// use rt::objects::Sphere;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// // The red sphere of the built-in scene1
+    /// let mut scene = Scene::new();
+    /// scene.add_light(Light::new(Vec3::new(2.0, 3.0, 1.0), Color::WHITE, 0.8));
+    /// let red = Material::new(Color::new(0.8, 0.2, 0.2), 0.2, 0.8, 0.3, 100.0, 0.0, 0.0, 1.0);
+    /// scene.add_object(Box::new(Sphere::new(Vec3::new(0.0, 0.0, -5.0), 1.5, red)));
+    ///
+    /// for (width, height) in [(1, 1), (1, 63), (63, 1), (1, 4095), (4095, 1)] {
+    ///     let aspect_ratio = width as f64 / height as f64;
+    ///     scene.set_camera(Camera::new(Vec3::new(0.0, 1.0, 2.0), Vec3::new(0.0, 0.0, -4.0), Vec3::new(0.0, 1.0, 0.0), 60.0, aspect_ratio).unwrap());
+    ///     let mut image = Image::new(width, height);
+    ///     scene.render_into(&mut image, &RenderSettings::default());
+    ///     assert!(image.pixels().iter().all(|pixel| pixel.is_finite()));
+    ///     let middle = image.get_pixel(width as usize / 2, height as usize / 2);
+    ///     assert!(middle.r > 0.2 && middle.r > 2.0 * middle.g, "{}x{}: {:?}", width, height, middle);
+    /// }
+    ///
+    /// let mut empty = Image::new(0, 4);
+    /// assert_eq!(scene.render_into(&mut empty, &RenderSettings::default()).stats.primary_rays, 0);
+    /// ```
+    ///
+    /// Rows are traced straight into the image, and nothing on the way
+    /// from a camera ray to a shaded pixel allocates, so a render makes the
+    /// same few allocations however many pixels it has:
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use std::alloc::{GlobalAlloc, Layout, System};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// // Counts allocations
+    /// struct Counting;
+    /// static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+    /// unsafe impl GlobalAlloc for Counting {
+    ///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ///         ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ///         System.alloc(layout)
+    ///     }
+    ///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    ///         System.dealloc(ptr, layout)
+    ///     }
+    /// }
+    /// #[global_allocator]
+    /// static ALLOCATOR: Counting = Counting;
+    ///
+    /// fn main() {
+    ///     // Glass, mirrors, textures, a group, an area light and portals
+    ///     let scene: Scene = r#"{
+    ///         "camera": { "position": [0, 1, 3], "look_at": [0, 0, 0] },
+    ///         "lights": [
+    ///             { "position": [2, 3, 1] },
+    ///             { "type": "quad", "corner": [-1, 3, -1], "edge_u": [2, 0, 0], "edge_v": [0, 0, 2] }
+    ///         ],
+    ///         "objects": [
+    ///             { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0],
+    ///               "material": { "reflectivity": 0.3, "texture": { "checker": { "size": 64, "squares": 8 } } } },
+    ///             { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": { "preset": "glass" } },
+    ///             { "type": "cube", "center": [1.5, 0, -1], "size": 1, "material": { "preset": "mirror" } },
+    ///             { "type": "group", "transform": { "translate": [-1.5, 0, 0] }, "children": [
+    ///                 { "type": "cylinder", "center": [0, 0, 0], "radius": 0.3, "height": 1 },
+    ///                 { "type": "sphere", "center": [0, 1, 0], "radius": 0.3, "material": { "preset": "glass" } }
+    ///             ] }
+    ///         ],
+    ///         "portals": [
+    ///             { "corner": [-1, 2, -1], "edge_u": [2, 0, 0], "edge_v": [0, 0, 1] },
+    ///             { "corner": [-3, 0, -2], "edge_u": [0, 2, 0], "edge_v": [0, 0, 2] }
+    ///         ]
+    ///     }"#.parse().unwrap();
+    ///     let allocations = |width: u32, height: u32, settings: &RenderSettings| {
+    ///         let mut image = Image::new(width, height);
+    ///         let before = ALLOCATIONS.load(Ordering::Relaxed);
+    ///         scene.render_into(&mut image, settings);
+    ///         ALLOCATIONS.load(Ordering::Relaxed) - before
+    ///     };
+    ///
+    ///     for settings in [
+    ///         RenderSettings::default(),
+    ///         RenderSettings { samples_per_pixel: 2, area_samples: 2, ambient_occlusion: true, ..RenderSettings::default() },
+    ///         RenderSettings { packets: true, ..RenderSettings::default() },
+    ///         RenderSettings { light_samples: Some(1), ..RenderSettings::default() },
+    ///         RenderSettings { ambient_occlusion: true, portal_fraction: 0.5, ..RenderSettings::default() },
+    ///     ] {
+    ///         // The first render large enough to run in parallel also
+    ///         // starts the thread pool
+    ///         allocations(96, 96, &settings);
+    ///         let small = allocations(8, 8, &settings);
+    ///         for (width, height) in [(64, 8), (8, 64), (96, 96)] {
+    ///             let large = allocations(width, height, &settings);
+    ///             assert!(large <= small + 4, "{} allocations for 8x8 but {} for {}x{}", small, large, width, height);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn render_into(&self, image: &mut Image, settings: &RenderSettings) -> RenderReport {
+        self.render_with_progress(image, None, settings, |_| {})
+    }
+    
+    // Command-line flavour of `render_into` that reports progress on stderr
+    pub fn render(&self, image: &mut Image, settings: &RenderSettings) -> RenderReport {
+        self.render_with_aux(image, None, settings)
+    }
+    
+    // Like `render`, also filling `aux` (which must match the image size)
+    // when given
+    pub fn render_with_aux(&self, image: &mut Image, aux: Option<&mut AuxBuffers>, settings: &RenderSettings) -> RenderReport {
+        self.render_with_previews(image, aux, settings, |_, _| {})
+    }
+    
+    /// `progress` is called with the index of each row as it is started.
+    /// Rows are rendered in parallel, so calls arrive out of order and from
+    /// several threads. The deadline in `settings` is checked as each row
+    /// starts, so a render overruns it by at most one row per thread (one
+    /// band of tile rows with Morton order, one tile with a queued tile
+    /// order).
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use std::sync::Mutex;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// // A lit wall filling the view in front of a blue background
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+    ///     "background": [0, 0, 1],
+    ///     "lights": [{ "position": [0, 0, 0] }],
+    ///     "objects": [{ "type": "plane", "point": [0, 0, -2], "normal": [0, 0, 1], "material": { "color": [1, 0, 0] } }]
+    /// }"#.parse().unwrap();
+    /// let (width, height) = (64, 120);
+    /// let background = |image: &Image, j: usize| (0..width).all(|i| {
+    ///     let pixel = image.get_pixel(i, j);
+    ///     pixel.b > 0.5 && pixel.r == 0.0
+    /// });
+    /// let settings = |deadline: Instant| RenderSettings { deadline: Some(deadline), samples_per_pixel: 4, ..RenderSettings::default() };
+    ///
+    /// // Past the deadline already, every row shows the background
+    /// let mut image = Image::new(width as u32, height as u32);
+    /// let report = scene.render_with_progress(&mut image, None, &settings(Instant::now()), |_| {});
+    /// assert!(report.truncated);
+    /// assert!((0..height).all(|j| background(&image, j)));
+    ///
+    /// // With half the time a full render takes, rows started after the
+    /// // deadline are left as background and the rest are traced
+    /// let mut full = Image::new(width as u32, height as u32);
+    /// let full_time = scene.render_into(&mut full, &RenderSettings { samples_per_pixel: 4, ..RenderSettings::default() }).elapsed;
+    /// assert!((0..height).all(|j| !background(&full, j)));
+    /// let row_time = full_time / height as u32;
+    ///
+    /// let started = Mutex::new(Vec::new());
+    /// let start = Instant::now();
+    /// let deadline = start + full_time / 2;
+    /// let report = scene.render_with_progress(&mut image, None, &settings(deadline), |j| {
+    ///     started.lock().unwrap().push((j, Instant::now()));
+    /// });
+    /// let finished = Instant::now();
+    /// assert!(report.truncated);
+    /// let started = started.into_inner().unwrap();
+    /// assert_eq!(started.len(), height);
+    /// for (j, at) in started {
+    ///     if at >= deadline {
+    ///         assert!(background(&image, j), "row {} started after the deadline but was traced", j);
+    ///     } else if !background(&image, j) {
+    ///         assert_eq!(image.get_pixel(0, j), full.get_pixel(0, j));
+    ///     }
+    /// }
+    /// assert!((0..height).any(|j| !background(&image, j)));
+    ///
+    /// // The overrun is the rows already under way, one per thread, with
+    /// // room to spare for a busy machine
+    /// let slack = row_time * rayon::current_num_threads() as u32;
+    /// assert!(finished.saturating_duration_since(deadline) < slack * 4 + Duration::from_millis(50));
+    /// ```
+    pub fn render_with_progress(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        settings: &RenderSettings,
+        progress: impl Fn(usize) + Sync,
+    ) -> RenderReport {
+        self.render_observed(image, aux, settings, progress, &mut |_, _| {})
+    }
+    
+    /// Like `render_with_aux`, calling `preview` with the image and the
+    /// level's pixel spacing after each level of `PixelOrder::Interlaced`
+    /// but the last; other orders have no previews.
+    ///
+    /// ```
+    /// use rt::image::Image;
+    /// use rt::scene::{PixelOrder, RenderSettings, Scene};
+    ///
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+    ///     "lights": [{ "type": "point", "position": [2, 3, 0] }],
+    ///     "objects": [{ "type": "sphere", "center": [0, 0, -3], "radius": 1 }]
+    /// }"#.parse().unwrap();
+    /// let mut scanline = Image::new(30, 21);
+    /// scene.render_into(&mut scanline, &RenderSettings::default());
+    ///
+    /// let settings = RenderSettings { pixel_order: PixelOrder::Interlaced, ..RenderSettings::default() };
+    /// let mut interlaced = Image::new(30, 21);
+    /// let mut previews = Vec::new();
+    /// scene.render_with_previews(&mut interlaced, None, &settings, |image, step| previews.push((step, image.clone())));
+    /// assert_eq!(interlaced.pixels(), scanline.pixels());
+    ///
+    /// // The first preview holds one traced pixel per 8x8 block
+    /// assert_eq!(previews.iter().map(|(step, _)| *step).collect::<Vec<_>>(), vec![8, 4, 2]);
+    /// let (_, coarse) = &previews[0];
+    /// assert_eq!(coarse.get_pixel(8, 8), scanline.get_pixel(8, 8));
+    /// assert_eq!(coarse.get_pixel(15, 15), scanline.get_pixel(8, 8));
+    /// assert_eq!(coarse.get_pixel(29, 20), scanline.get_pixel(24, 16));
+    /// ```
+    pub fn render_with_previews(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        settings: &RenderSettings,
+        mut preview: impl FnMut(&Image, u32),
+    ) -> RenderReport {
+        let height = image.height as usize;
+        let report = self.render_observed(image, aux, settings, |row_idx| {
+            if row_idx % 10 == 0 {
+                info!("Scanlines remaining: {}", height - row_idx - 1);
+            }
+        }, &mut preview);
+        info!("Done.");
+        if report.truncated {
+            match settings.pixel_order {
+                PixelOrder::Interlaced => warn!("time budget exceeded; untraced pixels show the blocks of the last level"),
+                _ if report.tiles.is_some() => warn!("time budget exceeded; unfinished tiles show the background"),
+                _ => warn!("time budget exceeded; unfinished rows show the background"),
+            }
+        }
+        report
+    }
+    
+    fn render_observed(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        settings: &RenderSettings,
+        progress: impl Fn(usize) + Sync,
+        preview: &mut dyn FnMut(&Image, u32),
+    ) -> RenderReport {
+        let start = Instant::now();
+        let mut ctx = TraceContext::new(self, settings, image.height);
+        ctx.layers = aux.as_ref().is_some_and(|aux| aux.layers.is_some());
+        if let Some(aux) = &aux {
+            assert!(
+                aux.width == image.width && aux.height == image.height,
+                "auxiliary buffers are {}x{} but the image is {}x{}",
+                aux.width, aux.height, image.width, image.height
+            );
+        }
+        
+        let (stats, truncated) = self.render_rows(image, aux, &ctx, &progress, preview);
+        
+        let peak_cost = (settings.pass == RenderPass::Cost).then(|| {
+            let peak = image.pixels.iter().fold(0.0, |peak: f64, pixel| peak.max(pixel.r));
+            for pixel in &mut image.pixels {
+                *pixel = viridis_color(if peak > 0.0 { pixel.r / peak } else { 0.0 });
+            }
+            peak
+        });
+        
+        RenderReport {
+            elapsed: start.elapsed(),
+            stats,
+            truncated,
+            peak_cost,
+            tiles: ctx.tile_report.into_inner().unwrap(),
         }
     }
     
-    pub fn add_object(&mut self, object: Box<dyn Object>) {
-        self.objects.push(object);
+    /// Renders a `width` by `height` image `rows` rows at a time, handing
+    /// each band to `sink` top to bottom and dropping it before the next,
+    /// so only one band is ever held. Wide filters render a margin of the
+    /// filter's reach above and below each band, which is thrown away. The
+    /// bands make up the same image `render_into` gives, except for the
+    /// cost pass, which needs the costliest pixel of the whole image and is
+    /// refused. Errors from `sink` stop the render and are passed on.
+    ///
+    /// ```
+    /// use rt::filter::Filter;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::image::Image;
+    /// use rt::vector::Vec3;
+    ///
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+    ///     "lights": [{ "type": "point", "position": [2, 3, 0] }],
+    ///     "objects": [{ "type": "sphere", "center": [0, 0, -3], "radius": 1 }]
+    /// }"#.parse().unwrap();
+    ///
+    /// for filter in [RenderSettings::default().filter, Filter::Gaussian { radius: 1.5 }] {
+    ///     let settings = RenderSettings { filter, ..RenderSettings::default() };
+    ///     let mut whole = Image::new(20, 13);
+    ///     scene.render_into(&mut whole, &settings);
+    ///
+    ///     let mut streamed = Vec::new();
+    ///     let report = scene.render_bands(20, 13, 3, &settings, |band| {
+    ///         assert!(band.width == 20 && band.height <= 3);
+    ///         assert_eq!(band.pixels.len(), 20 * band.height as usize);
+    ///         streamed.extend_from_slice(&band.pixels);
+    ///         Ok(())
+    ///     }).unwrap();
+    ///     // Margin rows are traced by both bands they fall in
+    ///     assert!(report.stats.primary_rays >= 20 * 13);
+    ///     assert_eq!(streamed.len(), whole.pixels.len());
+    ///     for (a, b) in streamed.iter().zip(&whole.pixels) {
+    ///         assert!(Vec3::from(*a - *b).length() < 1e-9);
+    ///     }
+    /// }
+    ///
+    /// let cost = RenderSettings { pass: rt::scene::RenderPass::Cost, ..RenderSettings::default() };
+    /// assert!(scene.render_bands(20, 13, 3, &cost, |_| Ok(())).is_err());
+    /// ```
+    pub fn render_bands(
+        &self,
+        width: u32,
+        height: u32,
+        rows: u32,
+        settings: &RenderSettings,
+        mut sink: impl FnMut(&Image) -> Result<(), String>,
+    ) -> Result<RenderReport, String> {
+        if settings.pass == RenderPass::Cost {
+            return Err("the cost pass needs the whole image at once and cannot be rendered in bands".to_string());
+        }
+        if width == 0 || height == 0 {
+            return Err(format!("cannot render a {}x{} image", width, height));
+        }
+        let start = Instant::now();
+        let mut ctx = TraceContext::new(self, settings, height);
+        let reach = if settings.filter.is_pixel_local() { 0 } else { settings.filter.radius().ceil() as u32 };
+        let mut stats = RayStats::default();
+        let mut truncated = false;
+        let mut tiles: Option<TileReport> = None;
+        
+        for first in (0..height).step_by(rows.max(1) as usize) {
+            let last = (first + rows.max(1)).min(height);
+            let top = first.saturating_sub(reach);
+            let bottom = (last + reach).min(height);
+            ctx.first_row = top;
+            let mut band = Image::new(width, bottom - top);
+            let (band_stats, band_truncated) = self.render_rows(&mut band, None, &ctx, &|_| {}, &mut |_, _| {});
+            
+            band.pixels.truncate((last - top) as usize * width as usize);
+            band.pixels.drain(..(first - top) as usize * width as usize);
+            band.height = last - first;
+            stats += band_stats;
+            truncated |= band_truncated;
+            if let Some(band_tiles) = ctx.tile_report.get_mut().unwrap().take() {
+                tiles = Some(tiles.map_or(band_tiles, |tiles| tiles.combine(band_tiles)));
+            }
+            sink(&band)?;
+        }
+        
+        Ok(RenderReport {
+            elapsed: start.elapsed(),
+            stats,
+            truncated,
+            peak_cost: None,
+            tiles,
+        })
     }
     
-    pub fn add_light(&mut self, light: Light) {
-        self.lights.push(light);
+    // Traces only the given (x, y) pixels of `image`, and their entries of
+    // `aux` when given, leaving everything else as it is. Each comes out
+    // as `render_into` would make it. Filters wider than a pixel mix in
+    // samples from the neighbours, and the cost pass needs every pixel, so
+    // both are refused.
+    pub fn retrace(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        settings: &RenderSettings,
+        pixels: &[(u32, u32)],
+    ) -> Result<RenderReport, String> {
+        if !settings.filter.is_pixel_local() {
+            return Err("only pixel-sized filters can retrace single pixels".to_string());
+        }
+        if settings.pass == RenderPass::Cost {
+            return Err("the cost pass needs the whole image at once and cannot retrace single pixels".to_string());
+        }
+        let start = Instant::now();
+        let ctx = TraceContext::new(self, settings, image.height);
+        let camera = self.camera.as_ref().expect("Camera not set");
+        let width = image.width;
+        
+        let traced: Vec<(Color, Surface, RayStats, bool)> = pixels.par_chunks(64).flat_map_iter(|chunk| {
+            let mut shadow_cache = ShadowCache::default();
+            let past = past_deadline(settings);
+            chunk.iter().map(|&(i, j)| {
+                if past {
+                    let color = self.camera_miss_color(&ctx.camera_ray(camera, width, i as f64, j as f64));
+                    return (color, Surface::miss(), RayStats::default(), true);
+                }
+                let (color, surface, stats) = self.trace_pixel(i, j, camera, width, &ctx, &mut shadow_cache);
+                (color, surface, stats, false)
+            }).collect::<Vec<_>>()
+        }).collect();
+        
+        let mut whole = AuxBuffers::chunks(aux, image.pixels.len(), 1).pop().flatten();
+        let mut stats = RayStats::default();
+        let mut truncated = false;
+        for (&(i, j), (color, surface, pixel_stats, skipped)) in pixels.iter().zip(traced) {
+            let index = (j * width + i) as usize;
+            image.set_pixel(i as usize, j as usize, color);
+            surface.store(&mut whole, index);
+            stats += pixel_stats;
+            truncated |= skipped;
+        }
+        
+        Ok(RenderReport {
+            elapsed: start.elapsed(),
+            stats,
+            truncated,
+            peak_cost: None,
+            tiles: None,
+        })
     }
     
-    pub fn set_camera(&mut self, camera: Camera) {
-        self.camera = Some(camera);
+    /// Color a camera ray along `ray` brings back, shaded just as in a
+    /// render (shadows, reflections and refractions included) but with no
+    /// camera or image needed, so shading can be checked against colors
+    /// worked out by hand.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::image::color::Color;
+    /// use rt::image::Image;
+    /// use rt::ray::Ray;
+    /// use rt::ray_paths::RayKind;
+    /// use rt::scene::{RenderSettings, Scene};
+    /// use rt::vector::Vec3;
+    ///
+    /// let settings = RenderSettings::default();
+    /// let close = |a: Color, b: Color| Vec3::from(a - b).length() < 1e-6;
+    ///
+    /// // Ambient plus diffuse: the sun 60 degrees off the normal at the
+    /// // front of the sphere lights it by cos 60 = 0.5
+    /// let mut scene: Scene = r#"{
+    ///     "lights": [{ "type": "directional", "direction": [-0.8660254037844386, 0, -0.5] }],
+    ///     "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 1,
+    ///         "material": { "color": [1, 0.5, 0.25], "ambient": 0.1, "diffuse": 0.8, "specular": 0 } }]
+    /// }"#.parse().unwrap();
+    /// let lit = Color::new(1.0, 0.5, 0.25) * (0.1 + 0.8 * 0.5);
+    /// let front = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+    /// assert!(close(scene.trace(&front, &settings), lit));
+    /// // and the middle pixel of a render looking straight at it
+    /// scene.set_camera(Camera::orthographic(Vec3::new(0.0, 0.0, 5.0), Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.5, 1.0));
+    /// let mut image = Image::new(5, 5);
+    /// scene.render_into(&mut image, &settings);
+    /// assert!(close(image.pixels[2 * 5 + 2], lit));
+    ///
+    /// // Shadow: with the sun overhead, the floor under the ball gets only
+    /// // its ambient light, and the floor beside it ambient and diffuse
+    /// let scene: Scene = r#"{
+    ///     "lights": [{ "type": "directional", "direction": [0, -1, 0] }],
+    ///     "objects": [
+    ///         { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0],
+    ///           "material": { "color": [0.6, 0.6, 0.6], "ambient": 0.2, "diffuse": 0.7, "specular": 0 } },
+    ///         { "type": "sphere", "center": [0, 2, 0], "radius": 0.5 }
+    ///     ]
+    /// }"#.parse().unwrap();
+    /// let floor = |x: f64| Ray::new(Vec3::new(x + 3.0, 3.0, 0.0), Vec3::new(-1.0, -1.0, 0.0));
+    /// assert!(close(scene.trace(&floor(0.0), &settings), Color::splat(0.6 * 0.2)));
+    /// assert!(close(scene.trace(&floor(2.0), &settings), Color::splat(0.6 * (0.2 + 0.7))));
+    ///
+    /// // Mirror: a perfect mirror floor gives back what its reflected ray,
+    /// // which starts a hair above the floor, sees on its own (reflections
+    /// // are off unless asked for)
+    /// let reflections = RenderSettings { reflection: true, ..RenderSettings::default() };
+    /// let scene: Scene = r#"{
+    ///     "lights": [{ "type": "directional", "direction": [1, -1, -1] }],
+    ///     "objects": [
+    ///         { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0],
+    ///           "material": { "ambient": 0, "diffuse": 0, "specular": 0, "reflectivity": 1 } },
+    ///         { "type": "sphere", "center": [0, 1, -3], "radius": 1, "material": { "preset": "plastic", "color": [0.2, 0.7, 0.3] } }
+    ///     ]
+    /// }"#.parse().unwrap();
+    /// let mirrored = scene.trace(&Ray::new(Vec3::new(0.0, 2.0, 3.0), Vec3::new(0.0, -1.0, -2.0)), &reflections);
+    /// let seen = scene.trace(&Ray::new(Vec3::new(0.0, 0.001, -1.0), Vec3::new(0.0, 1.0, -2.0)), &reflections);
+    /// assert!(close(mirrored, seen) && seen.g > seen.r);
+    ///
+    /// // Refraction: a ray 30 degrees off the normal of a slab of glass
+    /// // (index 1.5) half a unit thick leaves it parallel to where it came
+    /// // in, shifted sideways by d sin a (1 - cos a / sqrt(n^2 - sin^2 a))
+    /// let mut scene: Scene = r#"{
+    ///     "objects": [{ "type": "slab", "center": [0, 0, 0], "edge_u": [4, 0, 0], "edge_v": [0, 4, 0], "thickness": 0.5,
+    ///         "material": { "preset": "glass" } }]
+    /// }"#.parse().unwrap();
+    /// let (sin, cos) = 30f64.to_radians().sin_cos();
+    /// scene.set_camera(Camera::orthographic(Vec3::new(-5.0 * sin, 0.0, 5.0 * cos), Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 0.1, 1.0));
+    /// let segments = scene.trace_paths(1, 1, &settings, &[(0, 0)]).unwrap();
+    /// let primary = segments.iter().find(|s| s.kind == RayKind::Primary).unwrap();
+    /// let refracted: Vec<_> = segments.iter().filter(|s| s.kind == RayKind::Refract).collect();
+    /// let direction = (primary.to - primary.from).normalize();
+    /// let out = refracted[1];
+    /// assert!((out.to - out.from).normalize().dot(&direction) > 1.0 - 1e-9);
+    /// let shift = (out.from - primary.from).cross(&direction).length();
+    /// let expected = 0.5 * sin * (1.0 - cos / (1.5f64.powi(2) - sin * sin).sqrt());
+    /// // The refracted rays start a thousandth of a unit off the surfaces
+    /// assert!((shift - expected).abs() < 1e-3);
+    /// ```
+    pub fn trace(&self, ray: &Ray, settings: &RenderSettings) -> Color {
+        let ctx = TraceContext::new(self, settings, 1);
+        let mut shadow_cache = ShadowCache::default();
+        let mut state = PathState::for_pixel(0, 0, &ctx, &mut shadow_cache);
+        let hit = self.visible_hit(ray, &ctx, &mut state);
+        self.primary_color(ray, hit, &ctx, &mut state)
     }
     
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(HitRecord, &dyn Object)> {
-        let mut closest_hit: Option<(HitRecord, &dyn Object)> = None;
-        let mut closest_t = t_max;
-        
-        for object in &self.objects {
-            if let Some(hit_record) = object.hit(ray, t_min, closest_t) {
-                closest_t = hit_record.t;
-                closest_hit = Some((hit_record, object.as_ref()));
+    /// Every ray traced for the given (x, y) pixels of a `width` by `height`
+    /// render, for seeing in a 3D program where they went: camera rays,
+    /// shadow rays, reflections and refractions, each from its origin to
+    /// what it hit. Rays that hit nothing are cut off at `MISS_LENGTH`.
+    ///
+    /// ```
+    /// use rt::ray_paths::RayKind;
+    /// use rt::scene::{RenderSettings, Scene};
+    ///
+    /// let scene: Scene = r#"{
+    ///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1], "fov": 40 },
+    ///     "lights": [{ "type": "point", "position": [3, 4, 0] }],
+    ///     "objects": [
+    ///         { "type": "sphere", "center": [0, 0, -4], "radius": 1, "material": { "preset": "glass" } },
+    ///         { "type": "plane", "point": [0, 0, -8], "normal": [0, 0, 1] }
+    ///     ]
+    /// }"#.parse().unwrap();
+    /// let settings = RenderSettings::default();
+    /// // A pixel halfway between the sphere's center and its edge
+    /// let segments = scene.trace_paths(41, 41, &settings, &[(26, 20)]).unwrap();
+    ///
+    /// let primary = segments.iter().find(|s| s.kind == RayKind::Primary).unwrap();
+    /// let refracted: Vec<_> = segments.iter().filter(|s| s.kind == RayKind::Refract).collect();
+    /// assert!(primary.hit && refracted.len() >= 2);
+    /// assert!(segments.iter().any(|s| s.kind == RayKind::Shadow));
+    ///
+    /// // The path bends where it enters and where it leaves the glass,
+    /// // each ray starting where the one before it ended
+    /// let direction = |s: &rt::ray_paths::RaySegment| (s.to - s.from).normalize();
+    /// let (inside, outside) = (refracted[0], refracted[1]);
+    /// assert!((inside.from - primary.to).length() < 0.01);
+    /// assert!((outside.from - inside.to).length() < 0.01);
+    /// assert!(direction(primary).dot(&direction(inside)) < 0.99);
+    /// assert!(direction(inside).dot(&direction(outside)) < 0.99);
+    /// assert_eq!((outside.bounce, outside.hit), (2, true));
+    ///
+    /// assert!(scene.trace_paths(41, 41, &settings, &[(41, 0)]).is_err());
+    /// ```
+    pub fn trace_paths(&self, width: u32, height: u32, settings: &RenderSettings, pixels: &[(u32, u32)]) -> Result<Vec<RaySegment>, String> {
+        if let Some((x, y)) = pixels.iter().find(|(x, y)| *x >= width || *y >= height) {
+            return Err(format!("pixel ({}, {}) is outside the {}x{} image", x, y, width, height));
+        }
+        let mut ctx = TraceContext::new(self, settings, height);
+        ctx.paths = Some(Mutex::new(Vec::new()));
+        let camera = self.camera.as_ref().expect("Camera not set");
+        let mut shadow_cache = ShadowCache::default();
+        for &(i, j) in pixels {
+            self.trace_pixel(i, j, camera, width, &ctx, &mut shadow_cache);
+        }
+        Ok(ctx.paths.take().map(|paths| paths.into_inner().unwrap()).unwrap_or_default())
+    }
+    
+    /// Distance along the view direction to the first surface under each
+    /// pixel, row by row from the top, without shading anything; infinite
+    /// where the camera ray hits nothing or only what the clipping range
+    /// cuts away. Measured the same way as `AuxBuffers::depth`.
+    ///
+    /// ```
+    /// use rt::camera::Camera;
+    /// use rt::scene::Scene;
+    /// use rt::vector::Vec3;
+    ///
+    /// let mut scene: Scene = r#"{
+    ///     "objects": [{ "type": "sphere", "center": [0, 0, 0], "radius": 1 }]
+    /// }"#.parse().unwrap();
+    /// // Looking straight down from 5 units above the sphere's center
+    /// scene.set_camera(Camera::orthographic(Vec3::new(0.0, 5.0, 0.0), Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), 4.0, 1.0));
+    /// let depth = scene.depth_map(9, 9);
+    /// assert_eq!(depth.len(), 81);
+    /// assert!((depth[4 * 9 + 4] - 4.0).abs() < 1e-9);
+    /// // Halfway to the edge, a point of the sphere 0.75^0.5 high
+    /// assert!((depth[4 * 9 + 5] - (5.0 - 0.75f64.sqrt())).abs() < 1e-9);
+    /// assert_eq!(depth[0], f64::INFINITY);
+    /// ```
+    pub fn depth_map(&self, width: u32, height: u32) -> Vec<f64> {
+        let camera = self.camera.as_ref().expect("Camera not set");
+        self.view_lods();
+        let mut depth = vec![f64::INFINITY; Image::pixel_count(width, height)];
+        depth.par_chunks_mut(width.max(1) as usize).enumerate().for_each(|(j, row)| {
+            for (i, value) in row.iter_mut().enumerate() {
+                let ray = camera_ray(camera, width, height, i as f64, j as f64);
+                let (near, far) = camera.clip_range(&ray);
+                if let Some(hit) = self.hit(&ray, near, far) {
+                    *value = Surface::of(&ray, Some(&hit), camera).depth;
+                }
             }
+        });
+        depth
+    }
+    
+    fn render_rows(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        ctx: &TraceContext,
+        progress: &(impl Fn(usize) + Sync),
+        preview: &mut dyn FnMut(&Image, u32),
+    ) -> (RayStats, bool) {
+        // An image with no pixels has no rows to split into
+        if image.pixels.is_empty() {
+            return (RayStats::default(), false);
+        }
+        if ctx.settings.filter.is_pixel_local() {
+            self.render_pixels(image, aux, ctx, progress, preview)
+        } else {
+            self.render_splatted(image, aux, ctx, progress)
         }
-        
-        closest_hit
     }
     
-    pub fn render(&self, image: &mut Image, enable_reflection: bool) {
+    // Each pixel is the plain average of its own samples, so rows are
+    // rendered straight into the image buffer; apart from the per-render
+    // context nothing is allocated per pixel or per row
+    fn render_pixels(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        ctx: &TraceContext,
+        progress: &(impl Fn(usize) + Sync),
+        preview: &mut dyn FnMut(&Image, u32),
+    ) -> (RayStats, bool) {
+        match (ctx.settings.pixel_order, ctx.settings.tile_order) {
+            (PixelOrder::Interlaced, _) => return self.render_interlaced(image, aux, ctx, progress, preview),
+            (_, TileOrder::Hilbert | TileOrder::Cost) => return self.render_queued_tiles(image, aux, ctx, progress),
+            (PixelOrder::Morton, TileOrder::Scanline) => return self.render_tiles(image, aux, ctx, progress),
+            (PixelOrder::Scanline, TileOrder::Scanline) => {}
+        }
+        
         let camera = self.camera.as_ref().expect("Camera not set");
         let width = image.width;
         let height = image.height;
+        let settings = ctx.settings;
+        let aux_rows = AuxBuffers::chunks(aux, width as usize, height as usize);
         
-        let pixels: Vec<Vec3> = (0..height).into_par_iter().enumerate().flat_map(|(row_idx, j)| {
-            if row_idx % 10 == 0 {
-                eprintln!("\rScanlines remaining: {}", height as usize - row_idx - 1);
+        let render_row = |row_idx: usize, row: &mut [Color], mut aux_row: Option<AuxChunk<'_>>| {
+            progress(row_idx);
+            let j = row_idx as u32;
+            
+            if past_deadline(settings) {
+                for (i, pixel) in row.iter_mut().enumerate() {
+                    *pixel = self.camera_miss_color(&ctx.camera_ray(camera, width, i as f64, j as f64));
+                }
+                return (RayStats::default(), true);
+            }
+            
+            let mut row_stats = RayStats::default();
+            let mut shadow_cache = ShadowCache::default();
+            if settings.traces_packets(camera) {
+                for start in (0..width).step_by(4) {
+                    let lanes = (width - start).min(4) as usize;
+                    let pixels = [0, 1, 2, 3].map(|lane| (start + lane, j));
+                    let colors = &mut row[start as usize..start as usize + lanes];
+                    let mut surfaces = [Surface::miss(); 4];
+                    row_stats += self.trace_packet(&pixels[..lanes], colors, &mut surfaces, camera, width, ctx, &mut shadow_cache);
+                    for (lane, surface) in surfaces[..lanes].iter().enumerate() {
+                        surface.store(&mut aux_row, start as usize + lane);
+                    }
+                }
+            } else {
+                for (i, pixel) in row.iter_mut().enumerate() {
+                    let (color, surface, stats) = self.trace_pixel(i as u32, j, camera, width, ctx, &mut shadow_cache);
+                    *pixel = color;
+                    surface.store(&mut aux_row, i);
+                    row_stats += stats;
+                }
+            }
+            debug!("Row {} done", j);
+            (row_stats, false)
+        };
+        let merge = |(mut total, truncated): (RayStats, bool), (row_stats, row_truncated): (RayStats, bool)| {
+            total += row_stats;
+            (total, truncated || row_truncated)
+        };
+        
+        if settings.renders_sequentially(width, height) {
+            image.pixels.chunks_mut(width as usize).zip(aux_rows).enumerate()
+                .map(|(row_idx, (row, aux_row))| render_row(row_idx, row, aux_row))
+                .fold((RayStats::default(), false), merge)
+        } else {
+            image.pixels.par_chunks_mut(width as usize).zip(aux_rows).enumerate()
+                .map(|(row_idx, (row, aux_row))| render_row(row_idx, row, aux_row))
+                .reduce(|| (RayStats::default(), false), merge)
+        }
+    }
+    
+    // Same as the scanline path, but each band of TILE_SIZE rows is split
+    // into square tiles whose pixels are traced along a Z-order curve, so
+    // consecutive rays start close together in the image and tend to visit
+    // the same BVH nodes. Pixels are still written to their row-major
+    // positions and seeded by position, so the image does not change.
+    fn render_tiles(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        ctx: &TraceContext,
+        progress: &(impl Fn(usize) + Sync),
+    ) -> (RayStats, bool) {
+        let camera = self.camera.as_ref().expect("Camera not set");
+        let width = image.width;
+        let settings = ctx.settings;
+        let tile_order = morton_tile_order();
+        let band_len = (width * TILE_SIZE) as usize;
+        let aux_bands = AuxBuffers::chunks(aux, band_len, image.pixels.len().div_ceil(band_len));
+        
+        image.pixels.par_chunks_mut(band_len).zip(aux_bands).enumerate().map(|(band_idx, (band, mut aux_band))| {
+            let top = band_idx as u32 * TILE_SIZE;
+            let rows = band.len() as u32 / width;
+            for row in top..top + rows {
+                progress(row as usize);
+            }
+            
+            if past_deadline(settings) {
+                for (index, pixel) in band.iter_mut().enumerate() {
+                    let (i, j) = (index as u32 % width, top + index as u32 / width);
+                    *pixel = self.camera_miss_color(&ctx.camera_ray(camera, width, i as f64, j as f64));
+                }
+                return (RayStats::default(), true);
             }
-            (0..width).into_par_iter().map(move |i| {
-                let u = i as f64 / (width - 1) as f64;
-                let v = (height - 1 - j) as f64 / (height - 1) as f64;
+            
+            let mut band_stats = RayStats::default();
+            let mut shadow_cache = ShadowCache::default();
+            let mut pixels = Vec::with_capacity(tile_order.len());
+            for left in (0..width).step_by(TILE_SIZE as usize) {
+                // Tiles on the right and bottom edges may be cut short
+                pixels.clear();
+                pixels.extend(
+                    tile_order
+                        .iter()
+                        .map(|&(x, y)| (left + x, top + y))
+                        .filter(|&(i, j)| i < width && j < top + rows),
+                );
+                let index = |(i, j): (u32, u32)| ((j - top) * width + i) as usize;
                 
-                let ray = camera.get_ray(u, v);
-                self.ray_color(&ray, 5, enable_reflection) // Max depth of 5
-            })
+                if settings.traces_packets(camera) {
+                    for chunk in pixels.chunks(4) {
+                        let mut colors = [Color::BLACK; 4];
+                        let mut surfaces = [Surface::miss(); 4];
+                        band_stats += self.trace_packet(
+                            chunk, &mut colors[..chunk.len()], &mut surfaces, camera, width, ctx, &mut shadow_cache,
+                        );
+                        for ((&pixel, color), surface) in chunk.iter().zip(colors).zip(surfaces) {
+                            band[index(pixel)] = color;
+                            surface.store(&mut aux_band, index(pixel));
+                        }
+                    }
+                } else {
+                    for &(i, j) in &pixels {
+                        let (color, surface, stats) = self.trace_pixel(i, j, camera, width, ctx, &mut shadow_cache);
+                        band[index((i, j))] = color;
+                        surface.store(&mut aux_band, index((i, j)));
+                        band_stats += stats;
+                    }
+                }
+                debug!("Tile at ({}, {}) done", left, top);
+            }
+            (band_stats, false)
+        }).reduce(|| (RayStats::default(), false), |(mut total, truncated), (band_stats, band_truncated)| {
+            total += band_stats;
+            (total, truncated || band_truncated)
+        })
+    }
+    
+    // Hands out TILE_SIZE tiles one at a time from a shared queue in the
+    // order of `settings.tile_order`, so a thread that runs out of work
+    // takes the next tile rather than idling while another works through a
+    // costly band. Pixels within a tile follow the pixel order. Each tile
+    // is traced into buffers of its own and copied into place once all are
+    // done; pixels are seeded by position, so the image does not change.
+    fn render_queued_tiles(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        ctx: &TraceContext,
+        progress: &(impl Fn(usize) + Sync),
+    ) -> (RayStats, bool) {
+        let camera = self.camera.as_ref().expect("Camera not set");
+        let (width, height) = (image.width, image.height);
+        let settings = ctx.settings;
+        let corners: Vec<(u32, u32)> = (0..height)
+            .step_by(TILE_SIZE as usize)
+            .flat_map(|top| (0..width).step_by(TILE_SIZE as usize).map(move |left| (left, top)))
+            .collect();
+        let queue = self.tile_queue(&corners, width, height, camera, ctx);
+        let within_tile = match settings.pixel_order {
+            PixelOrder::Morton => morton_tile_order(),
+            _ => (0..TILE_SIZE * TILE_SIZE).map(|index| (index % TILE_SIZE, index / TILE_SIZE)).collect(),
+        };
+        // Rows count as started with the first tile taken from them
+        let bands_started: Vec<AtomicBool> = (0..height.div_ceil(TILE_SIZE)).map(|_| AtomicBool::new(false)).collect();
+        let next = AtomicUsize::new(0);
+        let start = Instant::now();
+        
+        let traced: Vec<TracedTile> = (0..rayon::current_num_threads()).into_par_iter().flat_map_iter(|_| {
+            let mut shadow_cache = ShadowCache::default();
+            let mut done = Vec::new();
+            while let Some(&index) = queue.get(next.fetch_add(1, Ordering::Relaxed)) {
+                let (_, top) = corners[index];
+                if !bands_started[(top / TILE_SIZE) as usize].swap(true, Ordering::Relaxed) {
+                    for row in top..(top + TILE_SIZE).min(height) {
+                        progress(row as usize);
+                    }
+                }
+                done.push(self.trace_tile(index, corners[index], &within_tile, (width, height), camera, ctx, &mut shadow_cache, start));
+            }
+            done
         }).collect();
         
-        for (i, pixel) in pixels.into_iter().enumerate() {
-            let x = i % width as usize;
-            let y = i / width as usize;
-            image.set_pixel(x, y, pixel);
+        let mut aux = AuxBuffers::chunks(aux, image.pixels.len(), 1).pop().flatten();
+        let mut stats = RayStats::default();
+        let mut truncated = false;
+        let mut costs = vec![0; corners.len()];
+        for tile in &traced {
+            let (left, top) = tile.corner;
+            for (offset, (&color, &surface)) in tile.colors.iter().zip(&tile.surfaces).enumerate() {
+                let (x, y) = (left + offset as u32 % tile.width, top + offset as u32 / tile.width);
+                let index = (y * width + x) as usize;
+                image.pixels[index] = color;
+                surface.store(&mut aux, index);
+            }
+            stats += tile.stats;
+            truncated |= tile.truncated;
+            costs[tile.index] = tile.stats.intersection_tests;
+        }
+        // Skipped tiles cost nothing, which says nothing about the next
+        // render
+        if settings.tile_order == TileOrder::Cost && !truncated {
+            *self.tile_costs.lock().unwrap() = Some(TileCosts { width, height, first_row: ctx.first_row, costs });
         }
+        *ctx.tile_report.lock().unwrap() = TileReport::of(&traced, ctx.first_row);
+        (stats, truncated)
+    }
+    
+    // One tile of `render_queued_tiles`, timed from `start`; the background
+    // if the deadline has passed
+    #[allow(clippy::too_many_arguments)]
+    fn trace_tile(
+        &self,
+        index: usize,
+        (left, top): (u32, u32),
+        within_tile: &[(u32, u32)],
+        (width, height): (u32, u32),
+        camera: &Camera,
+        ctx: &TraceContext,
+        shadow_cache: &mut ShadowCache,
+        start: Instant,
+    ) -> TracedTile {
+        let started = start.elapsed();
+        // Tiles on the right and bottom edges may be cut short
+        let tile_width = TILE_SIZE.min(width - left);
+        let tile_height = TILE_SIZE.min(height - top);
+        let pixels: Vec<(u32, u32)> = within_tile
+            .iter()
+            .filter(|&&(x, y)| x < tile_width && y < tile_height)
+            .map(|&(x, y)| (left + x, top + y))
+            .collect();
+        let local = |(i, j): (u32, u32)| ((j - top) * tile_width + i - left) as usize;
+        let mut colors = vec![Color::BLACK; pixels.len()];
+        let mut surfaces = vec![Surface::miss(); pixels.len()];
+        let mut stats = RayStats::default();
         
-        eprintln!("\nDone.");
+        let truncated = past_deadline(ctx.settings);
+        if truncated {
+            for &(i, j) in &pixels {
+                colors[local((i, j))] = self.camera_miss_color(&ctx.camera_ray(camera, width, i as f64, j as f64));
+            }
+        } else if ctx.settings.traces_packets(camera) {
+            for chunk in pixels.chunks(4) {
+                let mut chunk_colors = [Color::BLACK; 4];
+                let mut chunk_surfaces = [Surface::miss(); 4];
+                stats += self.trace_packet(
+                    chunk, &mut chunk_colors[..chunk.len()], &mut chunk_surfaces, camera, width, ctx, shadow_cache,
+                );
+                for ((&pixel, color), surface) in chunk.iter().zip(chunk_colors).zip(chunk_surfaces) {
+                    colors[local(pixel)] = color;
+                    surfaces[local(pixel)] = surface;
+                }
+            }
+        } else {
+            for &(i, j) in &pixels {
+                let (color, surface, pixel_stats) = self.trace_pixel(i, j, camera, width, ctx, shadow_cache);
+                colors[local((i, j))] = color;
+                surfaces[local((i, j))] = surface;
+                stats += pixel_stats;
+            }
+        }
+        debug!("Tile at ({}, {}) done", left, top);
+        TracedTile { index, corner: (left, top), width: tile_width, colors, surfaces, stats, truncated, started, finished: start.elapsed() }
     }
     
-    fn ray_color(&self, ray: &Ray, depth: i32, enable_reflection: bool) -> Vec3 {
-        if depth <= 0 {
-            return Vec3::zero();
+    // Indices into `corners` in the order `render_queued_tiles` hands the
+    // tiles out
+    fn tile_queue(&self, corners: &[(u32, u32)], width: u32, height: u32, camera: &Camera, ctx: &TraceContext) -> Vec<usize> {
+        let mut queue: Vec<usize> = (0..corners.len()).collect();
+        match ctx.settings.tile_order {
+            TileOrder::Scanline => {}
+            TileOrder::Hilbert => {
+                let side = width.div_ceil(TILE_SIZE).max(height.div_ceil(TILE_SIZE)).next_power_of_two();
+                queue.sort_by_key(|&tile| hilbert_index(side, corners[tile].0 / TILE_SIZE, corners[tile].1 / TILE_SIZE));
+            }
+            TileOrder::Cost => {
+                let costs = self.predicted_tile_costs(corners, width, height, camera, ctx);
+                // Stable, so tiles of equal cost stay in reading order
+                queue.sort_by_key(|&tile| Reverse(costs[tile]));
+            }
+        }
+        queue
+    }
+    
+    // What each tile cost the last time, when the last `TileOrder::Cost`
+    // render was of the same image, or else what one sample through the
+    // middle of each quarter of the tile costs
+    fn predicted_tile_costs(&self, corners: &[(u32, u32)], width: u32, height: u32, camera: &Camera, ctx: &TraceContext) -> Vec<u64> {
+        if let Some(last) = self.tile_costs.lock().unwrap().as_ref() {
+            if (last.width, last.height, last.first_row) == (width, height, ctx.first_row) {
+                return last.costs.clone();
+            }
         }
+        corners.par_iter().map(|&(left, top)| {
+            let tile_width = TILE_SIZE.min(width - left);
+            let tile_height = TILE_SIZE.min(height - top);
+            [(1, 1), (3, 1), (1, 3), (3, 3)]
+                .iter()
+                .map(|&(x, y)| self.probe_cost(left + tile_width * x / 4, top + tile_height * y / 4, camera, width, ctx))
+                .sum()
+        }).collect()
+    }
+    
+    // Intersection tests of one sample through the middle of the pixel.
+    // Probes are never logged or recorded, not even for the debug pixel,
+    // whose rays are logged when it is traced for real.
+    fn probe_cost(&self, i: u32, j: u32, camera: &Camera, width: u32, ctx: &TraceContext) -> u64 {
+        let mut shadow_cache = ShadowCache::default();
+        let mut state = PathState::for_pixel(i, ctx.first_row + j, ctx, &mut shadow_cache);
+        state.log = false;
+        state.paths = None;
+        let ray = ctx.camera_ray(camera, width, i as f64, j as f64);
+        let hit = self.camera_hit(&ray, camera, ctx, &mut state);
+        self.primary_color(&ray, hit, ctx, &mut state);
+        state.stats.intersection_tests
+    }
+    
+    // Traces the levels of `interlace::STEPS` in turn, each a pass over its
+    // rows in parallel, and fills the blocks between traced pixels before
+    // handing the image to `preview`. Pixels are seeded by position as in
+    // the other orders, so the last level completes the same image. Once
+    // the deadline passes no more levels start, and the untraced pixels
+    // keep the colors of their blocks.
+    fn render_interlaced(
+        &self,
+        image: &mut Image,
+        aux: Option<&mut AuxBuffers>,
+        ctx: &TraceContext,
+        progress: &(impl Fn(usize) + Sync),
+        preview: &mut dyn FnMut(&Image, u32),
+    ) -> (RayStats, bool) {
+        let camera = self.camera.as_ref().expect("Camera not set");
+        let (width, height) = (image.width, image.height);
+        let settings = ctx.settings;
+        let mut aux = AuxBuffers::chunks(aux, image.pixels.len(), 1).pop().flatten();
+        let mut stats = RayStats::default();
+        let mut truncated = false;
         
-        if let Some((hit_record, object)) = self.hit(ray, 0.001, f64::INFINITY) {
-            let material = object.material();
-            let mut color = Vec3::zero();
+        for step in interlace::STEPS {
+            let last = step == 1;
+            let rows: Vec<_> = (0..height).into_par_iter().map(|j| {
+                if last {
+                    progress(j as usize);
+                }
+                let pixels: Vec<(u32, u32)> = interlace::level_pixels(width, j, step).into_iter().map(|i| (i, j)).collect();
+                if pixels.is_empty() {
+                    return (j, Vec::new(), RayStats::default(), false);
+                }
+                if past_deadline(settings) {
+                    // Later levels keep the blocks of the levels before;
+                    // the first has none and shows the background
+                    let missed = match step == interlace::STEPS[0] {
+                        true => pixels.iter()
+                            .map(|&(i, j)| (i, self.camera_miss_color(&ctx.camera_ray(camera, width, i as f64, j as f64)), Surface::miss()))
+                            .collect(),
+                        false => Vec::new(),
+                    };
+                    return (j, missed, RayStats::default(), true);
+                }
+                
+                let mut row_stats = RayStats::default();
+                let mut shadow_cache = ShadowCache::default();
+                let mut traced = Vec::with_capacity(pixels.len());
+                if settings.traces_packets(camera) {
+                    for chunk in pixels.chunks(4) {
+                        let mut colors = [Color::BLACK; 4];
+                        let mut surfaces = [Surface::miss(); 4];
+                        row_stats += self.trace_packet(
+                            chunk, &mut colors[..chunk.len()], &mut surfaces, camera, width, ctx, &mut shadow_cache,
+                        );
+                        traced.extend(chunk.iter().zip(colors).zip(surfaces).map(|((&(i, _), color), surface)| (i, color, surface)));
+                    }
+                } else {
+                    for &(i, j) in &pixels {
+                        let (color, surface, pixel_stats) = self.trace_pixel(i, j, camera, width, ctx, &mut shadow_cache);
+                        traced.push((i, color, surface));
+                        row_stats += pixel_stats;
+                    }
+                }
+                (j, traced, row_stats, false)
+            }).collect();
             
-            // Ambient lighting
-            color = color + material.color * material.ambient;
+            for (j, traced, row_stats, row_truncated) in rows {
+                for (i, color, surface) in traced {
+                    let index = (j * width + i) as usize;
+                    image.pixels[index] = color;
+                    surface.store(&mut aux, index);
+                }
+                stats += row_stats;
+                truncated |= row_truncated;
+            }
+            interlace::fill_blocks(image, step);
+            debug!("Interlaced level of every {} pixels done", step);
+            if truncated {
+                break;
+            }
+            if !last {
+                preview(image, step);
+            }
+        }
+        (stats, truncated)
+    }
+    
+    // Average of the pixel's jittered samples, and what the first of them hit
+    #[allow(clippy::too_many_arguments)]
+    fn trace_pixel(
+        &self,
+        i: u32,
+        j: u32,
+        camera: &Camera,
+        width: u32,
+        ctx: &TraceContext,
+        shadow_cache: &mut ShadowCache,
+    ) -> (Color, Surface, RayStats) {
+        let samples = ctx.settings.samples_per_pixel.max(1);
+        let mut state = PathState::for_pixel(i, ctx.first_row + j, ctx, shadow_cache);
+        let mut color = Color::BLACK;
+        let mut layers = Layers::default();
+        let mut surface = None;
+        for sample in 0..samples {
+            state.begin_sample(ctx.settings.seed, sample);
+            let (dx, dy) = sample_offset(samples, &mut state.rng);
+            let (ray, hit) = self.primary_hit(i as f64 + dx, j as f64 + dy, camera, width, ctx, &mut state);
+            surface.get_or_insert_with(|| Surface::of(&ray, hit.as_ref(), camera));
+            color = color + self.primary_color(&ray, hit, ctx, &mut state);
+            layers = layers + state.layers;
+        }
+        if ctx.settings.pass == RenderPass::Cost {
+            color = Color::splat(state.stats.intersection_tests as f64);
+        }
+        let mut surface = surface.unwrap_or_else(Surface::miss);
+        surface.layers = layers.scaled(1.0 / samples as f64);
+        (color / samples as f64, surface, state.stats)
+    }
+    
+    // Intersects the primary rays of up to four pixels as one packet, then
+    // shades them one at a time into `colors`, noting what each hit in
+    // `surfaces`. Short packets are padded with their last ray.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_packet(
+        &self,
+        pixels: &[(u32, u32)],
+        colors: &mut [Color],
+        surfaces: &mut [Surface; 4],
+        camera: &Camera,
+        width: u32,
+        ctx: &TraceContext,
+        shadow_cache: &mut ShadowCache,
+    ) -> RayStats {
+        let mut stats = RayStats::default();
+        let rays = [0, 1, 2, 3].map(|lane| {
+            let (i, j) = pixels[lane.min(pixels.len() - 1)];
+            ctx.camera_ray(camera, width, i as f64, j as f64)
+        });
+        let packet = RayPacket4::new(rays);
+        let ranges = rays.map(|ray| camera.clip_range(&ray));
+        let hits = self.hit4(&packet, ranges.map(|range| range.0), ranges.map(|range| range.1));
+        
+        for (lane, (&(i, j), color)) in pixels.iter().zip(colors.iter_mut()).enumerate() {
+            let mut state = PathState::for_pixel(i, ctx.first_row + j, ctx, shadow_cache);
+            let (ray, hit) = match self.nearest_gizmo_hit(&rays[lane], hits[lane], ranges[lane], ctx) {
+                None if ctx.settings.seal_pinholes => self.primary_hit(i as f64, j as f64, camera, width, ctx, &mut state),
+                hit => (rays[lane], hit),
+            };
+            surfaces[lane] = Surface::of(&ray, hit.as_ref(), camera);
+            *color = self.primary_color(&ray, hit, ctx, &mut state);
+            surfaces[lane].layers = state.layers;
+            stats += state.stats;
+        }
+        stats
+    }
+    
+    // Filters wider than a pixel weight every sample into all pixels within
+    // the filter radius. Bands of rows splat into their own buffers, which
+    // overlap their neighbours by the filter reach and are summed once all
+    // bands are done; each pixel is then normalized by its total weight, or
+    // takes its own nearest sample if the filter gave it none.
+    fn render_splatted(
+        &self,
+        image: &mut Image,
+        mut aux: Option<&mut AuxBuffers>,
+        ctx: &TraceContext,
+        progress: &(impl Fn(usize) + Sync),
+    ) -> (RayStats, bool) {
+        let camera = self.camera.as_ref().expect("Camera not set");
+        let (width, height) = (image.width, image.height);
+        let (columns, rows) = (width as usize, height as usize);
+        let settings = ctx.settings;
+        let filter = settings.filter;
+        let radius = filter.radius();
+        let reach = radius.ceil() as usize;
+        let samples = settings.samples_per_pixel.max(1);
+        
+        // Layers are splatted like colors, so they are summed over the whole
+        // image below rather than written band by band
+        let mut layers = aux.as_mut().and_then(|aux| aux.layers.take());
+        let row_indices: Vec<usize> = (0..rows).collect();
+        let aux_bands = AuxBuffers::chunks(aux.as_deref_mut(), SPLAT_BAND_ROWS * columns, rows.div_ceil(SPLAT_BAND_ROWS));
+        let bands: Vec<_> = row_indices.par_chunks(SPLAT_BAND_ROWS).zip(aux_bands).map(|(band_rows, mut aux_band)| {
+            let first = band_rows[0].saturating_sub(reach);
+            let last = (band_rows[band_rows.len() - 1] + reach).min(rows - 1);
+            let mut splats = vec![(Color::BLACK, 0.0); (last - first + 1) * columns];
+            let mut layer_splats = if ctx.layers { vec![Layers::default(); splats.len()] } else { Vec::new() };
+            let mut nearest = vec![(Color::BLACK, Layers::default()); band_rows.len() * columns];
+            let mut band_stats = RayStats::default();
+            let mut shadow_cache = ShadowCache::default();
+            let mut truncated = false;
             
-            // Direct lighting from all light sources
-            for light in &self.lights {
-                let light_dir = light.direction_from(hit_record.point);
-                let light_distance = light.distance_from(hit_record.point);
+            for &j in band_rows {
+                progress(j);
+                // Skipped rows still splat one background sample per pixel
+                // so they are never left without weight
+                let skip = past_deadline(settings);
+                truncated |= skip;
                 
-                // Check for shadows
-                let shadow_ray = Ray::new(hit_record.point + hit_record.normal * 0.001, light_dir);
-                let in_shadow = self.hit(&shadow_ray, 0.001, light_distance).is_some();
+                for i in 0..columns {
+                    let mut state = PathState::for_pixel(i as u32, ctx.first_row + j as u32, ctx, &mut shadow_cache);
+                    let mut nearest_distance = f64::INFINITY;
+                    for sample in 0..if skip { 1 } else { samples } {
+                        state.begin_sample(settings.seed, sample);
+                        let (dx, dy) = if skip { (0.0, 0.0) } else { sample_offset(samples, &mut state.rng) };
+                        let (x, y) = (i as f64 + dx, j as f64 + dy);
+                        let color = if skip {
+                            self.camera_miss_color(&ctx.camera_ray(camera, width, x, y))
+                        } else {
+                            let tests_before = state.stats.intersection_tests;
+                            let (ray, hit) = self.primary_hit(x, y, camera, width, ctx, &mut state);
+                            if sample == 0 {
+                                Surface::of(&ray, hit.as_ref(), camera).store(&mut aux_band, (j - band_rows[0]) * columns + i);
+                            }
+                            let color = self.primary_color(&ray, hit, ctx, &mut state);
+                            if settings.pass == RenderPass::Cost {
+                                Color::splat((state.stats.intersection_tests - tests_before) as f64)
+                            } else {
+                                color
+                            }
+                        };
+                        let sample_layers = if skip { Layers { rest: color, ..Layers::default() } } else { state.layers };
+                        if dx * dx + dy * dy < nearest_distance {
+                            nearest_distance = dx * dx + dy * dy;
+                            nearest[(j - band_rows[0]) * columns + i] = (color, sample_layers);
+                        }
+                        
+                        let py_min = ((y - radius).ceil().max(0.0) as usize).max(first);
+                        let py_max = ((y + radius).floor().max(0.0) as usize).min(last);
+                        let px_min = (x - radius).ceil().max(0.0) as usize;
+                        let px_max = ((x + radius).floor().max(0.0) as usize).min(columns - 1);
+                        for py in py_min..=py_max {
+                            for px in px_min..=px_max {
+                                let weight = filter.weight(px as f64 - x, py as f64 - y);
+                                if weight > 0.0 {
+                                    let index = (py - first) * columns + px;
+                                    let splat = &mut splats[index];
+                                    splat.0 = splat.0 + color * weight;
+                                    splat.1 += weight;
+                                    if let Some(layer_splat) = layer_splats.get_mut(index) {
+                                        *layer_splat = *layer_splat + sample_layers.scaled(weight);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    band_stats += state.stats;
+                }
+            }
+            (first, splats, layer_splats, band_rows[0], nearest, band_stats, truncated)
+        }).collect();
+        
+        let mut weights = vec![0.0; image.pixels.len()];
+        image.pixels.fill(Color::BLACK);
+        if let Some(layers) = &mut layers {
+            layers.fill(Layers::default());
+        }
+        let mut stats = RayStats::default();
+        let mut truncated = false;
+        for (first, splats, layer_splats, _, _, band_stats, band_truncated) in &bands {
+            let offset = first * columns;
+            for (k, (color, weight)) in splats.iter().enumerate() {
+                image.pixels[offset + k] = image.pixels[offset + k] + *color;
+                weights[offset + k] += weight;
+            }
+            if let Some(layers) = &mut layers {
+                for (k, layer_splat) in layer_splats.iter().enumerate() {
+                    layers[offset + k] = layers[offset + k] + *layer_splat;
+                }
+            }
+            stats += *band_stats;
+            truncated |= band_truncated;
+        }
+        // A filter narrower than half a pixel can miss a pixel's samples
+        // altogether; rather than leave it black, it takes the one nearest
+        // its center
+        for (_, _, _, first_row, nearest, _, _) in &bands {
+            let offset = first_row * columns;
+            for (k, (color, sample_layers)) in nearest.iter().enumerate() {
+                let weight = weights[offset + k];
+                let pixel = &mut image.pixels[offset + k];
+                *pixel = if weight > 0.0 { *pixel / weight } else { *color };
+                if let Some(layers) = &mut layers {
+                    let layers = &mut layers[offset + k];
+                    *layers = if weight > 0.0 { layers.scaled(1.0 / weight) } else { *sample_layers };
+                }
+            }
+        }
+        if let (Some(aux), Some(layers)) = (aux, layers) {
+            aux.layers = Some(layers);
+        }
+        
+        (stats, truncated)
+    }
+    
+    fn primary_color(
+        &self,
+        ray: &Ray,
+        hit: Option<(HitRecord, &dyn Object)>,
+        ctx: &TraceContext,
+        state: &mut PathState,
+    ) -> Color {
+        state.stats.primary_rays += 1;
+        state.log(0, || format!("camera ray from {:.4} along {:.4}", ray.origin, ray.direction));
+        self.note_hit(ray, hit, RayKind::Primary, 0, state);
+        state.layers = Layers::default();
+        if ctx.layer.as_ref().is_some_and(|layer| !layer.shows(hit.map(|(_, object)| object))) {
+            state.log(0, || "held out of the render layer; black".to_string());
+            return Color::BLACK;
+        }
+        let color = match (ctx.settings.pass, hit) {
+            (RenderPass::Alpha, _) => Color::WHITE,
+            // The cost pass shades as usual for the work it takes; the
+            // caller then swaps in the number of tests made
+            (RenderPass::Beauty | RenderPass::Cost, Some((hit_record, object))) => {
+                self.shade(ray, &hit_record, object, ctx.settings.max_depth, ctx, state)
+            }
+            (RenderPass::Beauty | RenderPass::Cost, None) => self.camera_miss_color(ray),
+            (RenderPass::Irradiance, Some((hit_record, object))) => {
+                heat_color(self.irradiance(&hit_record, object, ctx, state))
+            }
+            (RenderPass::Irradiance, None) => Color::BLACK,
+        };
+        if ctx.layers {
+            // Whatever the split layers do not cover, so they always add
+            // back up to the color
+            state.layers.rest = color - state.layers.direct - state.layers.reflection;
+        }
+        if cfg!(debug_assertions) && !color.is_finite() {
+            report_non_finite(state.pixel, ray, color);
+        }
+        state.log(0, || format!("sample color {:.4}", color));
+        color
+    }
+    
+    // Logs and records what a camera, reflected or refracted ray hit
+    fn note_hit(&self, ray: &Ray, hit: Option<(HitRecord, &dyn Object)>, kind: RayKind, bounce: i32, state: &PathState) {
+        match hit {
+            Some((hit_record, _)) => state.record(kind, bounce, ray.origin, hit_record.point, true),
+            None => state.record(kind, bounce, ray.origin, ray.at(MISS_LENGTH / ray.direction.length()), false),
+        }
+        state.log(bounce, || match hit {
+            Some((hit_record, object)) => format!(
+                "hit {} at t = {:.4}, point {:.4}, normal {:.4} ({} face)",
+                self.object_label(object),
+                hit_record.t,
+                hit_record.point,
+                hit_record.shading_normal,
+                if hit_record.front_face { "front" } else { "back" },
+            ),
+            None if bounce == 0 && self.backdrop.is_some() => format!("missed everything; backdrop {:.4}", self.camera_miss_color(ray)),
+            None => format!("missed everything; background {:.4}", self.background.color(ray)),
+        });
+    }
+    
+    // Name of an object for the debug log; gizmos and section caps are not
+    // part of the scene's objects
+    fn object_label(&self, object: &dyn Object) -> String {
+        let object = object as *const dyn Object;
+        if let Some(index) = self.objects.iter().position(|o| std::ptr::addr_eq(o.as_ref() as *const dyn Object, object)) {
+            return format!("'{}'", self.object_name(index));
+        }
+        let is_section = |clip_plane: &ClipPlane| {
+            clip_plane.section.as_ref().is_some_and(|section| std::ptr::addr_eq(section as *const Plane, object))
+        };
+        match self.clip_planes.iter().position(is_section) {
+            Some(index) => format!("the section cap of clip plane {}", index),
+            None => "a light gizmo".to_string(),
+        }
+    }
+    
+    // Closest hit for rays that can see debug gizmos (camera and reflection
+    // rays). Shadow rays call `hit` directly.
+    fn visible_hit<'s>(&'s self, ray: &Ray, ctx: &'s TraceContext, state: &mut PathState) -> Option<(HitRecord, &'s dyn Object)> {
+        let hit = self.counted_hit(ray, 0.001, f64::INFINITY, &mut state.stats.intersection_tests);
+        self.nearest_gizmo_hit(ray, hit, (0.001, f64::INFINITY), ctx)
+    }
+    
+    // Like `visible_hit`, limited to what lies between the camera's
+    // clipping planes
+    fn camera_hit<'s>(
+        &'s self,
+        ray: &Ray,
+        camera: &Camera,
+        ctx: &'s TraceContext,
+        state: &mut PathState,
+    ) -> Option<(HitRecord, &'s dyn Object)> {
+        let (near, far) = camera.clip_range(ray);
+        let hit = self.counted_hit(ray, near, far, &mut state.stats.intersection_tests);
+        self.nearest_gizmo_hit(ray, hit, (near, far), ctx)
+    }
+    
+    // The camera ray through (x, y) and what it hits. With
+    // `RenderSettings::seal_pinholes`, a ray that misses where the rays a
+    // pixel away on either side, or above and below, hit has likely slipped
+    // through a seam between touching objects, and is traced again a
+    // thousandth of a pixel over.
+    #[allow(clippy::type_complexity)]
+    fn primary_hit<'s>(
+        &'s self,
+        x: f64,
+        y: f64,
+        camera: &Camera,
+        width: u32,
+        ctx: &'s TraceContext,
+        state: &mut PathState,
+    ) -> (Ray, Option<(HitRecord, &'s dyn Object)>) {
+        // Through a lens, the rays traced again below leave from the same
+        // point of it as the first
+        let lens = match camera.lens {
+            Some(_) => (state.rng.next_f64(), state.rng.next_f64()),
+            None => (0.5, 0.5),
+        };
+        let ray = ctx.lens_ray(camera, width, x, y, lens);
+        let hit = self.camera_hit(&ray, camera, ctx, state);
+        if hit.is_some() || !ctx.settings.seal_pinholes {
+            return (ray, hit);
+        }
+        let mut hits = |dx: f64, dy: f64| {
+            let neighbour = ctx.lens_ray(camera, width, x + dx, y + dy, lens);
+            self.camera_hit(&neighbour, camera, ctx, state).is_some()
+        };
+        if !((hits(-1.0, 0.0) && hits(1.0, 0.0)) || (hits(0.0, -1.0) && hits(0.0, 1.0))) {
+            return (ray, hit);
+        }
+        let nudged = ctx.lens_ray(camera, width, x + PINHOLE_NUDGE, y + PINHOLE_NUDGE, lens);
+        match self.camera_hit(&nudged, camera, ctx, state) {
+            Some(hit) => {
+                state.stats.pinholes_sealed += 1;
+                state.log(0, || "camera ray missed between hits; traced again a hair over".to_string());
+                (nudged, Some(hit))
+            }
+            None => (ray, None),
+        }
+    }
+    
+    fn nearest_gizmo_hit<'s>(
+        &'s self,
+        ray: &Ray,
+        scene_hit: Option<(HitRecord, &'s dyn Object)>,
+        (t_min, t_max): (f64, f64),
+        ctx: &'s TraceContext,
+    ) -> Option<(HitRecord, &'s dyn Object)> {
+        let mut closest_hit = scene_hit;
+        let mut closest_t = scene_hit.map_or(t_max, |(hit_record, _)| hit_record.t);
+        
+        for gizmo in &ctx.gizmos {
+            if let Some(hit_record) = gizmo.hit(ray, t_min, closest_t) {
+                closest_t = hit_record.t;
+                closest_hit = Some((hit_record, gizmo as &dyn Object));
+            }
+        }
+        
+        closest_hit
+    }
+    
+    fn ray_color(&self, ray: &Ray, kind: RayKind, depth: i32, ctx: &TraceContext, state: &mut PathState) -> Color {
+        let bounce = ctx.settings.max_depth - depth;
+        if depth <= 0 {
+            state.log(bounce, || "depth limit reached; black".to_string());
+            return Color::BLACK;
+        }
+        
+        state.stats.secondary_rays += 1;
+        let hit = self.visible_hit(ray, ctx, state);
+        self.note_hit(ray, hit, kind, bounce, state);
+        if let Some((hit_record, object)) = hit {
+            self.shade(ray, &hit_record, object, depth, ctx, state)
+        } else {
+            self.background.color(ray)
+        }
+    }
+    
+    fn in_shadow(&self, hit_record: &HitRecord, light_index: usize, light_point: Vec3, bounce: i32, state: &mut PathState) -> bool {
+        state.stats.shadow_rays += 1;
+        let light = &self.lights[light_index];
+        let origin = hit_record.point + hit_record.geometric_normal * 0.001;
+        let (light_dir, max_distance) = match light.shape {
+            LightShape::Point | LightShape::Directional { .. } => (
+                (light_point - hit_record.point).normalize(),
+                (light_point - hit_record.point).length(),
+            ),
+            // Aim from the offset origin and stop just short of the sample so
+            // the light's own emitter panel never counts as an occluder
+            LightShape::Quad { .. } => (
+                (light_point - origin).normalize(),
+                (light_point - origin).length() - AREA_SHADOW_EPSILON,
+            ),
+        };
+        if max_distance <= 0.0 {
+            // The light sits on the surface; there is no direction to test
+            return false;
+        }
+        let shadow_ray = Ray::new(origin, light_dir);
+        
+        if state.paths.is_some() {
+            let blocker = self.hit_index(&shadow_ray, 0.001, max_distance, &mut state.stats.intersection_tests);
+            let end = blocker.as_ref().map_or(shadow_ray.at(max_distance), |(hit_record, _)| hit_record.point);
+            state.record(RayKind::Shadow, bounce, origin, end, blocker.is_some());
+            return blocker.is_some();
+        }
+        let tests = &mut state.stats.intersection_tests;
+        let Some(cache) = state.shadow_cache.as_deref_mut() else {
+            return self.hit_index(&shadow_ray, 0.001, max_distance, tests).is_some();
+        };
+        // Any object in range blocks the light, so a cached occluder that
+        // still blocks this ray settles it without the full search
+        if let Some(object_index) = cache.occluder(light_index) {
+            state.stats.shadow_cache_tests += 1;
+            if self.hits_object(object_index, &shadow_ray, 0.001, max_distance, tests) {
+                state.stats.shadow_cache_hits += 1;
+                return true;
+            }
+        }
+        match self.hit_index(&shadow_ray, 0.001, max_distance, tests) {
+            Some((_, object_index)) => {
+                cache.remember(light_index, object_index);
+                true
+            }
+            None => {
+                cache.forget(light_index);
+                false
+            }
+        }
+    }
+    
+    // Calls `f(light, light_point, weight)` for every unshadowed sample of
+    // every light linked to the object, as seen from the hit point
+    fn for_each_visible_light_sample(
+        &self,
+        hit_record: &HitRecord,
+        object: &dyn Object,
+        bounce: i32,
+        ctx: &TraceContext,
+        state: &mut PathState,
+        mut f: impl FnMut(&Light, Vec3, f64),
+    ) {
+        self.for_each_light_sample(hit_record, object, bounce, ctx, state, |light, light_point, weight, shadowed| {
+            if !shadowed {
+                f(light, light_point, weight);
+            }
+        });
+    }
+    
+    // Calls `f(light, light_point, weight, shadowed)` for every sample of
+    // every light linked to the object, as seen from the hit point. With `light_samples` set, only
+    // that many lights are visited, picked with replacement in proportion to
+    // their estimated importance; each pick is scaled by one over its
+    // probability so the sum stays right on average.
+    fn for_each_light_sample(
+        &self,
+        hit_record: &HitRecord,
+        object: &dyn Object,
+        bounce: i32,
+        ctx: &TraceContext,
+        state: &mut PathState,
+        mut f: impl FnMut(&Light, Vec3, f64, bool),
+    ) {
+        let per_axis = ctx.settings.area_samples.max(1);
+        let mut visit = |light_index: usize, scale: f64, state: &mut PathState| {
+            let light = &self.lights[light_index];
+            if !ctx.linked_objects[light_index].includes(object) {
+                state.log(bounce, || format!("light {} is not linked to this object", light_index));
+                return;
+            }
+            for index in 0..light.sample_count(per_axis) {
+                let (light_point, weight) = light.sample(index, per_axis, &mut state.rng, hit_record.point);
+                // Nothing to shadow past the end of the light's falloff window
+                if !light.reaches((light_point - hit_record.point).length()) {
+                    state.log(bounce, || format!("light {} sample at {:.4}: out of reach", light_index, light_point));
+                    continue;
+                }
+                let shadowed = self.in_shadow(hit_record, light_index, light_point, bounce, state);
+                state.log(bounce, || {
+                    let outcome = if shadowed { "shadowed".to_string() } else { format!("lit, weight {:.4}", weight * scale) };
+                    format!("light {} sample at {:.4}: {}", light_index, light_point, outcome)
+                });
+                f(light, light_point, weight * scale, shadowed);
+            }
+        };
+        
+        match ctx.settings.light_samples {
+            Some(count) if (count as usize) < self.lights.len() => {
+                // Unlinked lights are never picked. Importances are worked
+                // out again for each pick rather than kept, so shading
+                // allocates nothing.
+                let importance = |light_index: usize| {
+                    if ctx.linked_objects[light_index].includes(object) {
+                        self.lights[light_index].importance(hit_record.point)
+                    } else {
+                        0.0
+                    }
+                };
+                let total: f64 = (0..self.lights.len()).map(importance).sum();
+                if total <= 0.0 {
+                    return;
+                }
                 
-                if !in_shadow {
-                    // Diffuse lighting
-                    let diffuse_strength = hit_record.normal.dot(&light_dir).max(0.0);
-                    let diffuse = material.color * light.color * material.diffuse * diffuse_strength * light.intensity;
-                    
-                    // Specular lighting
-                    let view_dir = (-ray.direction).normalize();
-                    let reflect_dir = (-light_dir).reflect(&hit_record.normal);
-                    let spec_strength = view_dir.dot(&reflect_dir).max(0.0).powf(material.shininess);
-                    let specular = light.color * material.specular * spec_strength * light.intensity;
+                for _ in 0..count {
+                    let mut target = state.rng.next_f64() * total;
+                    let mut picked = None;
+                    for light_index in 0..self.lights.len() {
+                        let light_importance = importance(light_index);
+                        if light_importance > 0.0 {
+                            // The last light that can be picked takes any
+                            // rounding left over
+                            picked = Some((light_index, light_importance));
+                        }
+                        target -= light_importance;
+                        if target < 0.0 {
+                            break;
+                        }
+                    }
+                    let (index, light_importance) = picked.expect("some light has importance");
+                    visit(index, 1.0 / (count as f64 * (light_importance / total)), state);
+                }
+            }
+            _ => {
+                for light_index in 0..self.lights.len() {
+                    visit(light_index, 1.0, state);
+                }
+            }
+        }
+    }
+    
+    // Total light intensity reaching a point after attenuation and shadowing,
+    // weighted by the cosine to the surface normal
+    fn irradiance(&self, hit_record: &HitRecord, object: &dyn Object, ctx: &TraceContext, state: &mut PathState) -> f64 {
+        let mut total = 0.0;
+        self.for_each_visible_light_sample(hit_record, object, 0, ctx, state, |light, light_point, weight| {
+            let light_dir = (light_point - hit_record.point).normalize();
+            let light_distance = (light_point - hit_record.point).length();
+            let brightness = (light.color.r + light.color.g + light.color.b) / 3.0;
+            let cos_theta = hit_record.shading_normal.dot(&light_dir).max(0.0);
+            total += brightness * light.intensity * cos_theta * light.attenuation(light_distance) * weight;
+        });
+        total
+    }
+    
+    // Light reaching the hit point through the object from lights behind
+    // it. Each probe starts just under the surface and runs straight toward
+    // a light sample; the light is dimmed exponentially by the length of
+    // object crossed, and the exit point is shadow tested like a surface.
+    // With several samples per pixel the probes start at random points up to
+    // a scatter distance around the hit, which softens the result the way
+    // light spreading inside would; a single sample sends one probe from the
+    // hit point itself.
+    fn subsurface(
+        &self,
+        hit_record: &HitRecord,
+        object: &dyn Object,
+        scatter_distance: f64,
+        bounce: i32,
+        ctx: &TraceContext,
+        state: &mut PathState,
+    ) -> Color {
+        let inward = -hit_record.geometric_normal;
+        let basis = Onb::from_w(inward);
+        let probes = if ctx.settings.samples_per_pixel > 1 { SUBSURFACE_PROBES } else { 1 };
+        let per_axis = ctx.settings.area_samples.max(1);
+        
+        let mut total = Color::BLACK;
+        for (light_index, light) in self.lights.iter().enumerate() {
+            if !ctx.linked_objects[light_index].includes(object) {
+                continue;
+            }
+            for index in 0..light.sample_count(per_axis) {
+                let (light_point, weight) = light.sample(index, per_axis, &mut state.rng, hit_record.point);
+                for _ in 0..probes {
+                    let offset = if probes > 1 {
+                        basis.local(Vec3::random_in_unit_disk(&mut state.rng)) * scatter_distance
+                    } else {
+                        Vec3::zero()
+                    };
+                    let origin = hit_record.point + offset + inward * 0.001;
+                    let to_light = light_point - origin;
+                    // Lights on the near side already reach the surface
+                    // directly
+                    if to_light.dot(&inward) <= 0.0 {
+                        continue;
+                    }
                     
-                    // Apply attenuation
-                    let attenuation = light.attenuation(light_distance);
-                    color = color + (diffuse + specular) * attenuation;
+                    let direction = to_light.normalize();
+                    let probe = Ray::new(origin, direction);
+                    state.stats.intersection_tests += 1;
+                    let Some(&(start, end)) = object.hit_range(&probe, 0.0, to_light.length()).first() else {
+                        continue;
+                    };
+                    let thickness = end - start;
+                    let exit = HitRecord {
+                        point: probe.at(end),
+                        geometric_normal: direction,
+                        shading_normal: direction,
+                        t: end,
+                        front_face: false,
+                        part: hit_record.part,
+                    };
+                    if !light.reaches((light_point - exit.point).length()) {
+                        continue;
+                    }
+                    let shadowed = self.in_shadow(&exit, light_index, light_point, bounce, state);
+                    state.log(bounce, || {
+                        let outcome = if shadowed { "shadowed" } else { "lit" };
+                        format!("light {} probe crosses {:.4} of the object: {}", light_index, thickness, outcome)
+                    });
+                    if !shadowed {
+                        let attenuation = light.attenuation((light_point - exit.point).length());
+                        let falloff = (-thickness / scatter_distance).exp();
+                        total = total + light.color * light.intensity * attenuation * falloff * weight / probes as f64;
+                    }
+                }
+            }
+        }
+        total
+    }
+    
+    // Share of the hemisphere around `normal` left open within the occlusion
+    // distance, from cosine-distributed rays, so openings near the horizon
+    // count for little as they do for diffuse light
+    fn ambient_visibility(&self, hit_record: &HitRecord, normal: Vec3, ctx: &TraceContext, state: &mut PathState) -> f64 {
+        let basis = Onb::from_w(normal);
+        let origin = hit_record.point + hit_record.geometric_normal * 0.001;
+        let samples = ctx.settings.ao_samples.max(1);
+        
+        // Portals with some part in front of the surface, as seen from it.
+        // They are gone through again wherever they are needed rather than
+        // collected, so shading allocates nothing.
+        let seen = || {
+            self.portals.iter()
+                .filter(|portal| portal.corners().iter().any(|corner| (*corner - origin).dot(&normal) > 0.0))
+                .filter_map(|portal| Some((portal, portal.seen_from(origin)?)))
+        };
+        let (mut seen_count, mut total_solid_angle) = (0, 0.0);
+        if ctx.settings.portal_fraction > 0.0 {
+            for (_, rectangle) in seen() {
+                seen_count += 1;
+                total_solid_angle += rectangle.solid_angle;
+            }
+        }
+        let fraction = if seen_count == 0 { 0.0 } else { ctx.settings.portal_fraction };
+        
+        // Each ray counts by the cosine-weighted share of the hemisphere it
+        // stands for over the chance of picking it either way: from the
+        // cosine distribution, or uniformly over the portals' solid angle,
+        // each portal picked in proportion to its solid angle
+        let mut open = 0.0;
+        for _ in 0..samples {
+            let direction = if fraction > 0.0 && state.rng.next_f64() < fraction {
+                // The last portal takes any rounding left over
+                let mut target = state.rng.next_f64() * total_solid_angle;
+                let mut picked = None;
+                for (_, rectangle) in seen() {
+                    target -= rectangle.solid_angle;
+                    picked = Some(rectangle);
+                    if target < 0.0 {
+                        break;
+                    }
                 }
+                picked.expect("some portal is seen").sample(&mut state.rng)
+            } else {
+                basis.local(Vec3::random_cosine_hemisphere(&mut state.rng))
+            };
+            // Bumped shading normals can lean rays into the surface itself
+            if direction.dot(&hit_record.geometric_normal) <= 0.0 {
+                continue;
+            }
+            let cosine = direction.dot(&normal).max(0.0) / std::f64::consts::PI;
+            let ray = Ray::new(origin, direction);
+            let mut pdf = (1.0 - fraction) * cosine;
+            if fraction > 0.0 {
+                let through = seen().filter(|(portal, _)| portal.admits(&ray)).count();
+                pdf += fraction * through as f64 / total_solid_angle;
+            }
+            if cosine <= 0.0 || pdf <= 0.0 {
+                continue;
+            }
+            state.stats.shadow_rays += 1;
+            if self.hit_index(&ray, 0.001, ctx.settings.ao_distance, &mut state.stats.intersection_tests).is_none() {
+                open += cosine / pdf;
+            }
+        }
+        open / samples as f64
+    }
+    
+    // Color of a reflected or refracted ray that will be blended in with
+    // `weight`, or None when that would make up less of the pixel than
+    // `min_contribution`. Leaving the blend out instead changes the pixel by
+    // at most that much, since colors are clamped to 0..1.
+    #[allow(clippy::too_many_arguments)]
+    fn secondary_color(
+        &self,
+        ray: &Ray,
+        kind: RayKind,
+        weight: f64,
+        depth: i32,
+        bounce: i32,
+        ctx: &TraceContext,
+        state: &mut PathState,
+    ) -> Option<Color> {
+        let throughput = state.throughput;
+        if throughput * weight < ctx.settings.min_contribution {
+            state.log(bounce, || format!("contribution {:.6} is too small to trace", throughput * weight));
+            return None;
+        }
+        state.throughput = throughput * weight;
+        let color = self.ray_color(ray, kind, depth - 1, ctx, state);
+        state.throughput = throughput;
+        Some(color)
+    }
+    
+    fn shade(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        object: &dyn Object,
+        depth: i32,
+        ctx: &TraceContext,
+        state: &mut PathState,
+    ) -> Color {
+        let settings = ctx.settings;
+        let bounce = settings.max_depth - depth;
+        let material = object.material_at(hit_record);
+        let normal = hit_record.facing_shading_normal(ray);
+        let mut color = Color::BLACK;
+        state.log(bounce, || format!(
+            "material: color {:.4}, ambient {}, diffuse {}, specular {:.4}, reflectivity {:.4}, transparency {}, emission {:.4}",
+            material.color, material.ambient, material.diffuse, material.specular,
+            material.reflectivity, material.transparency, material.emission,
+        ));
+        
+        // Crude texture footprint: the pixel's width at this distance,
+        // stretched by how obliquely the ray meets the surface. Secondary
+        // rays only count their own segment.
+        let cos_incidence = ray.direction.dot(&hit_record.geometric_normal).abs().max(0.05);
+        let footprint = ctx.pixel_angle * hit_record.t / cos_incidence;
+        let albedo = object.albedo(hit_record, footprint);
+        
+        // Ambient lighting, dimmed in creases and under objects at the
+        // first hit when asked
+        let ambient = if settings.ambient_occlusion && bounce == 0 && material.ambient > 0.0 {
+            let visibility = self.ambient_visibility(hit_record, normal, ctx, state);
+            state.log(bounce, || format!("ambient visibility {:.4}", visibility));
+            material.ambient * visibility
+        } else {
+            material.ambient
+        };
+        color = color + albedo * ambient + object.emission(hit_record, footprint);
+        
+        let coat_reflectance = material.coat_reflectance((-ray.direction).dot(&normal));
+        let film_reflectance = if hit_record.front_face {
+            material.film_reflectance((-ray.direction).dot(&normal), normal.y)
+        } else {
+            Color::BLACK
+        };
+        let sheen_strength = if material.sheen > 0.0 {
+            material.sheen * (1.0 - ray.direction.dot(&normal).abs()).powf(SHEEN_EXPONENT)
+        } else {
+            0.0
+        };
+        
+        // Direct lighting from all light sources. For the split layers of
+        // the first hit, shadowed samples are summed as well.
+        let split = ctx.layers && bounce == 0;
+        let mut direct = Color::BLACK;
+        let mut unshadowed = Color::BLACK;
+        self.for_each_light_sample(hit_record, object, bounce, ctx, state, |light, light_point, weight, shadowed| {
+            if shadowed && !split {
+                return;
             }
+            let light_dir = (light_point - hit_record.point).normalize();
+            let light_distance = (light_point - hit_record.point).length();
+            
+            // Diffuse lighting
+            let diffuse_strength = settings.shading.diffuse(normal.dot(&light_dir).max(0.0));
+            let diffuse = albedo * light.color * material.diffuse * diffuse_strength * light.intensity;
+            
+            // Specular lighting
+            let view_dir = (-ray.direction).normalize();
+            let reflect_dir = (-light_dir).reflect(&normal);
+            let spec_strength = settings.shading.specular(view_dir.dot(&reflect_dir).max(0.0).powf(material.shininess));
+            let specular = light.color * material.specular * spec_strength * light.intensity;
+            
+            // Clear coat and thin film highlights, both as tight as a mirror's
+            let coat_strength = if coat_reflectance > 0.0 || film_reflectance.max_component() > 0.0 {
+                settings.shading.specular(view_dir.dot(&reflect_dir).max(0.0).powf(COAT_SHININESS))
+            } else {
+                0.0
+            };
+            let coat = light.color * (film_reflectance + Color::splat(coat_reflectance)) * coat_strength * light.intensity;
             
-            // Reflection
-            if enable_reflection && material.reflectivity > 0.0 {
-                let reflected_dir = ray.direction.reflect(&hit_record.normal);
-                let reflected_ray = Ray::new(hit_record.point + hit_record.normal * 0.001, reflected_dir);
-                let reflected_color = self.ray_color(&reflected_ray, depth - 1, enable_reflection);
-                color = color * (1.0 - material.reflectivity) + reflected_color * material.reflectivity;
+            // Sheen along the silhouette, wherever the light reaches
+            let sheen = light.color * material.sheen_color * sheen_strength * light.intensity;
+            
+            // Apply attenuation
+            let attenuation = light.attenuation(light_distance);
+            let contribution = (diffuse + specular + coat + sheen) * attenuation * weight;
+            unshadowed = unshadowed + contribution;
+            if !shadowed {
+                direct = direct + contribution;
+                color = color + contribution;
+            }
+        });
+        let mut reflection = Color::BLACK;
+        
+        // Light soaking through thin parts of translucent objects
+        if material.scatter_distance > 0.0 && hit_record.front_face {
+            let transmitted = albedo * material.scatter_tint * material.diffuse
+                * self.subsurface(hit_record, object, material.scatter_distance, bounce, ctx, state);
+            state.log(bounce, || format!("subsurface scattering adds {:.4}", transmitted));
+            color = color + transmitted;
+        }
+        
+        state.log(bounce, || format!("ambient, emission and direct light: {:.4}", color));
+        
+        // Caustics focused onto this point by mirrors and glass
+        if let Some(photon_map) = &ctx.photon_map {
+            let caustic = albedo * material.diffuse * photon_map.irradiance(hit_record.point);
+            state.log(bounce, || format!("caustics add {:.4}", caustic));
+            color = color + caustic;
+        }
+        
+        // Reflection
+        if settings.reflection && settings.shading.reflects() && material.reflectivity.max_component() > 0.0 {
+            let reflected_dir = ray.direction.reflect(&normal);
+            let reflected_ray = Ray::new(hit_record.point + hit_record.geometric_normal * 0.001, reflected_dir);
+            state.log(bounce, || format!("reflecting along {:.4}", reflected_dir));
+            // Weights of the reflection and of the color under it
+            let (reflected_weight, base_weight) = match settings.reflection_blend {
+                ReflectionBlend::Linear => (material.reflectivity, Color::WHITE - material.reflectivity),
+                ReflectionBlend::Fresnel => {
+                    let grazing = (1.0 - (-ray.direction).dot(&normal).clamp(0.0, 1.0)).powi(5);
+                    let rise = (Color::WHITE - material.reflectivity) * grazing;
+                    (material.reflectivity + rise, Color::WHITE - rise)
+                }
+            };
+            let weight = reflected_weight.max_component();
+            if let Some(reflected_color) = self.secondary_color(&reflected_ray, RayKind::Reflect, weight, depth, bounce, ctx, state) {
+                state.log(bounce, || format!("reflection returned {:.4}", reflected_color));
+                color = color * base_weight + reflected_color * reflected_weight;
+                direct = direct * base_weight;
+                unshadowed = unshadowed * base_weight;
+                reflection = reflected_color * reflected_weight;
             }
+        }
+        
+        // Refraction (transparency)
+        if material.transparency > 0.0 {
+            let id = medium::surface(object, hit_record);
+            let (from, to) = state.media.indices(id, material.refractive_index, hit_record.front_face);
+            let refraction_ratio = from / to;
             
-            // Refraction (transparency)
-            if material.transparency > 0.0 {
-                let refraction_ratio = if hit_record.front_face {
-                    1.0 / material.refractive_index
-                } else {
-                    material.refractive_index
-                };
-                
-                if let Some(refracted_dir) = ray.direction.refract(&hit_record.normal, refraction_ratio) {
-                    let refracted_ray = Ray::new(hit_record.point - hit_record.normal * 0.001, refracted_dir);
-                    let refracted_color = self.ray_color(&refracted_ray, depth - 1, enable_reflection);
+            if let Some(refracted_dir) = ray.direction.refract(&normal, refraction_ratio) {
+                let refracted_ray = Ray::new(hit_record.point - hit_record.geometric_normal * 0.001, refracted_dir);
+                state.log(bounce, || format!("refracting along {:.4} (eta {:.4} / {:.4})", refracted_dir, from, to));
+                let weight = material.transparency;
+                let crossing = state.media.cross(id, material.refractive_index, hit_record.front_face);
+                let refracted_color = self.secondary_color(&refracted_ray, RayKind::Refract, weight, depth, bounce, ctx, state);
+                state.media.undo(crossing);
+                if let Some(refracted_color) = refracted_color {
+                    state.log(bounce, || format!("refraction returned {:.4}", refracted_color));
                     color = color * (1.0 - material.transparency) + refracted_color * material.transparency;
+                    direct = direct * (1.0 - material.transparency);
+                    unshadowed = unshadowed * (1.0 - material.transparency);
+                    reflection = reflection * (1.0 - material.transparency);
                 }
+            } else {
+                state.log(bounce, || "total internal reflection; nothing refracted".to_string());
             }
-            
-            color.clamp(0.0, 1.0)
-        } else {
-            self.background_color
         }
+        
+        // Clear coat reflection, over everything the layers below give
+        if settings.reflection && settings.shading.reflects() && coat_reflectance > 0.0 && hit_record.front_face {
+            let reflected_dir = ray.direction.reflect(&normal);
+            let reflected_ray = Ray::new(hit_record.point + hit_record.geometric_normal * 0.001, reflected_dir);
+            state.log(bounce, || format!("clear coat reflects {:.4} along {:.4}", coat_reflectance, reflected_dir));
+            if let Some(reflected_color) = self.secondary_color(&reflected_ray, RayKind::Reflect, coat_reflectance, depth, bounce, ctx, state) {
+                state.log(bounce, || format!("clear coat reflection returned {:.4}", reflected_color));
+                color = color * (1.0 - coat_reflectance) + reflected_color * coat_reflectance;
+                direct = direct * (1.0 - coat_reflectance);
+                unshadowed = unshadowed * (1.0 - coat_reflectance);
+                reflection = reflection * (1.0 - coat_reflectance) + reflected_color * coat_reflectance;
+            }
+        }
+        
+        // Thin film reflection on top, in the colors interference leaves
+        let film_weight = film_reflectance.max_component();
+        if settings.reflection && settings.shading.reflects() && film_weight > 0.0 {
+            let reflected_dir = ray.direction.reflect(&normal);
+            let reflected_ray = Ray::new(hit_record.point + hit_record.geometric_normal * 0.001, reflected_dir);
+            state.log(bounce, || format!("thin film reflects {:.4} along {:.4}", film_reflectance, reflected_dir));
+            if let Some(reflected_color) = self.secondary_color(&reflected_ray, RayKind::Reflect, film_weight, depth, bounce, ctx, state) {
+                state.log(bounce, || format!("thin film reflection returned {:.4}", reflected_color));
+                let under = Color::WHITE - film_reflectance;
+                color = color * under + reflected_color * film_reflectance;
+                direct = direct * under;
+                unshadowed = unshadowed * under;
+                reflection = reflection * under + reflected_color * film_reflectance;
+            }
+        }
+        if split {
+            state.layers = Layers { unshadowed, direct, reflection, rest: Color::BLACK };
+        }
+        
+        let color = color.clamp(0.0, 1.0);
+        state.log(bounce, || format!("shaded color {:.4}", color));
+        color
     }
 }
@@ -0,0 +1,132 @@
+use crate::vector::Vec3;
+use crate::rng::Rng;
+use std::f64::consts::PI;
+
+// Random positions for placing many objects, such as trees on a hill or
+// pebbles on a beach. The same seed always gives the same positions.
+
+// Candidates tried around each point before it is retired, as in Bridson's
+// paper
+const ATTEMPTS: usize = 30;
+
+/// Up to `count` points in the rectangle from (0, 0) to `size`, no two
+/// closer than `min_distance`, by Bridson's Poisson-disk sampling. The
+/// rectangle is filled as densely as the spacing allows, about 0.66 points
+/// per `min_distance` squared, and a random `count` of those are kept, so
+/// fewer come back when the spacing leaves no room for more.
+///
+/// ```
+/// use rt::scene::scatter::poisson_disk;
+///
+/// let points = poisson_disk((20.0, 10.0), 1.0, usize::MAX, 7);
+/// for (i, a) in points.iter().enumerate() {
+///     assert!(a.0 >= 0.0 && a.0 <= 20.0 && a.1 >= 0.0 && a.1 <= 10.0);
+///     for b in &points[i + 1..] {
+///         assert!((a.0 - b.0).hypot(a.1 - b.1) >= 1.0);
+///     }
+/// }
+/// // About two thirds of a point per unit area when filled
+/// let density = points.len() as f64 / 200.0;
+/// assert!(density > 0.6 && density < 0.8, "{} points per unit area", density);
+///
+/// assert_eq!(poisson_disk((20.0, 10.0), 1.0, 50, 7).len(), 50);
+/// assert_eq!(poisson_disk((20.0, 10.0), 1.0, 50, 7), poisson_disk((20.0, 10.0), 1.0, 50, 7));
+/// ```
+pub fn poisson_disk(size: (f64, f64), min_distance: f64, count: usize, seed: u64) -> Vec<(f64, f64)> {
+    let (width, height) = size;
+    if !(min_distance > 0.0 && width > 0.0 && height > 0.0) || count == 0 {
+        return Vec::new();
+    }
+    let mut rng = Rng::new(seed);
+    
+    // Cells small enough that each holds at most one point
+    let cell = min_distance / 2f64.sqrt();
+    let columns = (width / cell).ceil() as usize;
+    let rows = (height / cell).ceil() as usize;
+    let cell_of = |(x, y): (f64, f64)| {
+        ((x / cell) as usize).min(columns - 1) + ((y / cell) as usize).min(rows - 1) * columns
+    };
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    
+    let first = (rng.next_f64() * width, rng.next_f64() * height);
+    let mut points = vec![first];
+    grid[cell_of(first)] = Some(0);
+    let mut active = vec![0];
+    
+    while !active.is_empty() {
+        let slot = (rng.next_f64() * active.len() as f64) as usize;
+        let center = points[active[slot]];
+        let mut placed = false;
+        for _ in 0..ATTEMPTS {
+            // Uniform over the ring between one and two spacings out
+            let angle = rng.next_f64() * 2.0 * PI;
+            let radius = min_distance * (1.0 + 3.0 * rng.next_f64()).sqrt();
+            let candidate = (center.0 + radius * angle.cos(), center.1 + radius * angle.sin());
+            if candidate.0 < 0.0 || candidate.0 > width || candidate.1 < 0.0 || candidate.1 > height {
+                continue;
+            }
+            
+            // Any point too close is within two cells
+            let index = cell_of(candidate);
+            let (column, row) = (index % columns, index / columns);
+            let too_close = (row.saturating_sub(2)..(row + 3).min(rows)).any(|r| {
+                (column.saturating_sub(2)..(column + 3).min(columns)).any(|c| {
+                    grid[c + r * columns].is_some_and(|other| {
+                        let other = points[other];
+                        (candidate.0 - other.0).hypot(candidate.1 - other.1) < min_distance
+                    })
+                })
+            });
+            if !too_close {
+                grid[index] = Some(points.len());
+                active.push(points.len());
+                points.push(candidate);
+                placed = true;
+                break;
+            }
+        }
+        if !placed {
+            active.swap_remove(slot);
+        }
+    }
+    
+    keep_random(points, count, &mut rng)
+}
+
+// `count` points spread uniformly at random over the rectangle from (0, 0)
+// to `size`, with nothing keeping them apart
+pub fn uniform(size: (f64, f64), count: usize, seed: u64) -> Vec<(f64, f64)> {
+    let mut rng = Rng::new(seed);
+    (0..count).map(|_| (rng.next_f64() * size.0, rng.next_f64() * size.1)).collect()
+}
+
+/// `poisson_disk` on the ground: points at the height of `center` in a
+/// rectangle around it, `size.0` along x and `size.1` along z.
+///
+/// ```
+/// use rt::scene::scatter::on_ground;
+/// use rt::vector::Vec3;
+///
+/// let center = Vec3::new(0.0, -1.0, -6.0);
+/// let positions = on_ground(center, (8.0, 4.0), 0.5, 40, 1);
+/// assert_eq!(positions.len(), 40);
+/// assert!(positions.iter().all(|p| p.y == -1.0 && (p.x - center.x).abs() <= 4.0 && (p.z - center.z).abs() <= 2.0));
+/// ```
+pub fn on_ground(center: Vec3, size: (f64, f64), min_distance: f64, count: usize, seed: u64) -> Vec<Vec3> {
+    poisson_disk(size, min_distance, count, seed)
+        .into_iter()
+        .map(|(x, z)| Vec3::new(center.x + x - size.0 / 2.0, center.y, center.z + z - size.1 / 2.0))
+        .collect()
+}
+
+// A random `count` of the points, in random order, or all of them when
+// there are no more
+fn keep_random(mut points: Vec<(f64, f64)>, count: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+    let kept = count.min(points.len());
+    for i in 0..kept {
+        let j = i + (rng.next_f64() * (points.len() - i) as f64) as usize;
+        points.swap(i, j);
+    }
+    points.truncate(kept);
+    points
+}
@@ -0,0 +1,1886 @@
+use crate::vector::Vec3;
+use crate::camera::{Camera, Shake, DEFAULT_NEAR};
+use crate::framing::{parse_aspect, same_aspect, Framing};
+use crate::light::{Attenuation, Light, LightLinking, LightProfile, LightShape};
+use crate::material::Material;
+use crate::objects::{Object, Sphere, Ellipsoid, Capsule, Plane, CheckerPlane, Cube, Cylinder, Quad, Slab};
+use crate::displaced::DisplacedSphere;
+use crate::mesh::{Handedness, Mesh, ObjConvention, Up};
+use crate::group::Group;
+use crate::lod::Lod;
+use crate::transform::Mat4;
+use crate::background::Background;
+use crate::texture::{Gradient, ImageTexture, Texture, TextureFilter, TextureSpace, TextureWrap};
+use crate::scene::Scene;
+use crate::clip::ClipPlane;
+use crate::portal::Portal;
+use crate::sky::SkyModel;
+use crate::image::color::Color;
+use crate::image::resolution;
+use crate::memory::format_size;
+use crate::json::Value;
+use crate::{debug, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use rayon::prelude::*;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+// Scene files are JSON:
+//
+// {
+//   "resolution": "1080p" or [1920, 1080],
+//   "camera": { "position": [0, 1, 2], "look_at": [0, 0, -4], "up": [0, 1, 0], "fov": 60, "near": 0.5,
+//               "shake": { "amplitude": 0.05, "frequency": 2 }, "framings": { "1x1": { "fov": 70 } } },
+//   "background": [0.7, 0.8, 1.0] or { "bottom": [...], "top": [...] },
+//   "materials": { "red_plastic": { "preset": "plastic", "color": [0.8, 0.1, 0.1] } },
+//   "lights": [ { "position": [2, 3, 1], "color": [1, 1, 1], "intensity": 0.8 } ],
+//   "objects": [ { "type": "sphere", "center": [0, 0, -5], "radius": 1, "material": "red_plastic" } ],
+//   "clip": [ { "point": [0, 0, -5], "normal": [0, 0, 1], "section": "red_plastic" } ],
+//   "portals": [ { "corner": [-1, 0.8, -1], "edge_u": [0, 0.8, 0], "edge_v": [0, 0, -1] } ]
+// }
+//
+// Objects give their material either by name from `materials` or inline.
+// Lights may add "affects" or "excludes", a list of object names, to
+// limit which objects they shine on. Every section is optional. The
+// resolution is only a default, which the command line overrides.
+
+pub struct LoadedScene {
+    pub scene: Scene,
+    // Problems that did not stop the scene from loading
+    pub warnings: Vec<String>,
+    // Image size the scene is meant to be rendered at, if it says
+    pub resolution: Option<(u32, u32)>,
+}
+
+// A file as it was when it was loaded
+type AssetKey = (PathBuf, SystemTime);
+
+// Textures and meshes loaded by earlier scenes, kept by path and
+// modification time so a scene that uses the same unchanged file again
+// skips reading and building it. A fresh cache is simply empty.
+#[derive(Default)]
+pub struct AssetCache {
+    textures: RefCell<HashMap<AssetKey, ImageTexture>>,
+    // With the note from a rebuilt stale mesh cache, repeated on every use
+    meshes: RefCell<HashMap<AssetKey, (Mesh, Option<String>)>>,
+}
+
+impl AssetCache {
+    fn texture(&self, path: &Path) -> Result<ImageTexture, String> {
+        let load = || ImageTexture::load_ppm(&path.to_string_lossy());
+        let Some(key) = asset_key(path) else {
+            return load();
+        };
+        if let Some(texture) = self.textures.borrow().get(&key) {
+            return Ok(texture.clone());
+        }
+        let texture = load()?;
+        self.textures.borrow_mut().insert(key, texture.clone());
+        Ok(texture)
+    }
+    
+    fn mesh(&self, path: &Path, material: Material) -> Result<(Mesh, Option<String>), String> {
+        let Some(key) = asset_key(path) else {
+            return load_mesh(path, material);
+        };
+        if let Some((mesh, note)) = self.meshes.borrow().get(&key) {
+            return Ok((mesh.clone().with_material(material), note.clone()));
+        }
+        let (mesh, note) = load_mesh(path, material)?;
+        self.meshes.borrow_mut().insert(key, (mesh.clone(), note.clone()));
+        Ok((mesh, note))
+    }
+    
+    // Loads the mesh files that are not cached yet in parallel, ahead of the
+    // objects that use them, which then find them here. Files that fail to
+    // load are left for those objects to report.
+    fn preload_meshes(&self, paths: Vec<PathBuf>) {
+        let mut keys: Vec<AssetKey> = paths.iter().filter_map(|path| asset_key(path)).collect();
+        keys.sort();
+        keys.dedup();
+        keys.retain(|key| !self.meshes.borrow().contains_key(key));
+        if keys.len() < 2 {
+            return;
+        }
+        let start = Instant::now();
+        let count = keys.len();
+        let loaded: Vec<_> = keys.into_par_iter()
+            .filter_map(|key| load_mesh(&key.0, Material::default()).ok().map(|mesh| (key, mesh)))
+            .collect();
+        debug!("Loaded {} mesh files in parallel in {:.1} ms", count, start.elapsed().as_secs_f64() * 1000.0);
+        self.meshes.borrow_mut().extend(loaded);
+    }
+}
+
+fn load_mesh(path: &Path, material: Material) -> Result<(Mesh, Option<String>), String> {
+    let start = Instant::now();
+    let (mesh, note) = Mesh::load(&path.to_string_lossy(), material)?;
+    debug!(
+        "Loaded {} in {:.1} ms: {} triangles",
+        path.display(),
+        start.elapsed().as_secs_f64() * 1000.0,
+        mesh.triangle_count(),
+    );
+    Ok((mesh, note))
+}
+
+// The files of every mesh entry in `value`, however deeply nested in groups
+// and levels of detail
+fn mesh_files(value: &Value, base_dir: &Path, files: &mut Vec<PathBuf>) {
+    match value {
+        Value::Object(entries) => {
+            if let (Some("mesh"), Some(file)) = (value.get("type").and_then(Value::as_str), value.get("file").and_then(Value::as_str)) {
+                files.push(base_dir.join(file));
+            }
+            for (_, value) in entries {
+                mesh_files(value, base_dir, files);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                mesh_files(item, base_dir, files);
+            }
+        }
+        _ => {}
+    }
+}
+
+// None when the file's modification time cannot be read; such files are
+// loaded every time, and loading reports why they cannot be read
+fn asset_key(path: &Path) -> Option<AssetKey> {
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    Some((path.to_path_buf(), modified))
+}
+
+// Length unit of a scene file. Lengths are converted to meters as they are
+// read, since light falloff and the offsets that keep rays from hitting the
+// surface they leave are tuned for scenes measured in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Units {
+    // Units in a meter; lengths are divided by this, which keeps values
+    // like 300 mm exactly equal to 0.3 m
+    per_meter: f64,
+}
+
+impl Units {
+    pub const METERS: Units = Units { per_meter: 1.0 };
+    
+    // A unit name (mm, cm, m, km, in, ft) or the length of one unit in
+    // meters
+    pub fn parse(s: &str) -> Result<Units, String> {
+        let per_meter = match s.trim() {
+            "mm" => 1000.0,
+            "cm" => 100.0,
+            "m" => 1.0,
+            "km" => 0.001,
+            "in" => 1.0 / 0.0254,
+            "ft" => 1.0 / 0.3048,
+            other => {
+                let meters = other.parse::<f64>()
+                    .map_err(|_| format!("unknown units '{}' (expected mm, cm, m, km, in, ft or meters per unit)", other))?;
+                return Units::from_meters(meters);
+            }
+        };
+        Ok(Units { per_meter })
+    }
+    
+    // Units that are `meters` long
+    pub fn from_meters(meters: f64) -> Result<Units, String> {
+        if !(meters > 0.0 && meters.is_finite()) {
+            return Err(format!("a unit must be a positive length in meters, not {}", meters));
+        }
+        Ok(Units { per_meter: 1.0 / meters })
+    }
+    
+    pub fn meters(&self) -> f64 {
+        1.0 / self.per_meter
+    }
+    
+    fn length(&self, value: f64) -> f64 {
+        value / self.per_meter
+    }
+    
+    fn vector(&self, value: Vec3) -> Vec3 {
+        value / self.per_meter
+    }
+}
+
+/// What to do when a texture or mesh file a scene names does not exist:
+/// fail, or stand in an obvious placeholder and warn. Placeholders are a
+/// magenta and black checkered texture and a unit cube mesh.
+///
+/// ```
+/// use rt::mesh::ObjConvention;
+/// use rt::scene_file::{parse_scene, AssetCache, MissingAssets};
+/// use std::path::Path;
+///
+/// let text = r#"{
+///     "objects": [
+///         { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0],
+///           "material": { "preset": "matte", "texture": { "file": "no_such_texture.ppm" } } },
+///         { "type": "mesh", "file": "no_such_mesh.obj" }
+///     ]
+/// }"#;
+/// let load = |missing| parse_scene(text, Path::new(""), 1.0, &AssetCache::default(), None, ObjConvention::default(), missing, None);
+///
+/// let error = load(MissingAssets::Error).err().unwrap();
+/// assert!(error.starts_with("objects[0].material.texture: cannot read texture"), "{}", error);
+///
+/// let loaded = load(MissingAssets::Placeholder).unwrap();
+/// assert_eq!(loaded.warnings, [
+///     "objects[0].material.texture.file: 'no_such_texture.ppm' does not exist; using a placeholder",
+///     "objects[1].file: 'no_such_mesh.obj' does not exist; using a placeholder",
+/// ]);
+/// let cube = loaded.scene.objects()[1].bounding_box().unwrap();
+/// assert!((cube.min.x + 0.5).abs() < 1e-3 && (cube.max.y - 0.5).abs() < 1e-3);
+///
+/// assert_eq!(MissingAssets::parse("placeholder"), Ok(MissingAssets::Placeholder));
+/// assert!(MissingAssets::parse("skip").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MissingAssets {
+    #[default]
+    Error,
+    Placeholder,
+}
+
+impl MissingAssets {
+    pub fn parse(s: &str) -> Result<MissingAssets, String> {
+        match s {
+            "error" => Ok(MissingAssets::Error),
+            "placeholder" => Ok(MissingAssets::Placeholder),
+            other => Err(format!("unknown missing asset policy '{}' (expected error or placeholder)", other)),
+        }
+    }
+}
+
+// Where a scene's textures, meshes and light profiles come from: files
+// relative to `base_dir`, with textures and meshes going through `assets`.
+// `units` are those the scene's lengths are written in, and `meshes` the
+// coordinate system of mesh files that do not give their own. Textures and
+// meshes that are missing are handled as `missing_assets` says, with the
+// placeholders' warnings collected in `missing`. With a `memory_budget`,
+// what each texture and mesh file will take is added to `planned` before
+// it is loaded, once per file, and loading stops when that goes over.
+struct Files<'a> {
+    base_dir: &'a Path,
+    assets: &'a AssetCache,
+    units: Units,
+    meshes: ObjConvention,
+    missing_assets: MissingAssets,
+    missing: RefCell<Vec<String>>,
+    memory_budget: Option<usize>,
+    planned: Cell<usize>,
+    counted: RefCell<HashSet<PathBuf>>,
+}
+
+impl Files<'_> {
+    // Whether `path` should be stood in for by a placeholder, which is
+    // noted against `context`
+    fn placeholder(&self, path: &Path, context: &str) -> bool {
+        if self.missing_assets == MissingAssets::Error || path.exists() {
+            return false;
+        }
+        self.missing.borrow_mut().push(format!("{}: '{}' does not exist; using a placeholder", context, path.display()));
+        true
+    }
+    
+    // Counts what loading `path` will take, by `estimate`, against the
+    // memory budget. Files that cannot be estimated are left for loading to
+    // report.
+    fn reserve(&self, path: &Path, estimate: impl FnOnce(&str) -> Result<usize, String>) -> Result<(), String> {
+        let Some(budget) = self.memory_budget else {
+            return Ok(());
+        };
+        if !self.counted.borrow_mut().insert(path.to_path_buf()) {
+            return Ok(());
+        }
+        let Ok(bytes) = estimate(&path.to_string_lossy()) else {
+            return Ok(());
+        };
+        let planned = self.planned.get() + bytes;
+        self.planned.set(planned);
+        if planned > budget {
+            return Err(format!(
+                "'{}' needs about {}, which takes the scene's files to about {}, over the memory budget of {}",
+                path.display(), format_size(bytes), format_size(planned), format_size(budget),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `units` overrides the units the scene file declares, if any. `meshes`
+/// is the coordinate system of mesh files whose entries do not give
+/// theirs. A scene whose texture and mesh files would take more than
+/// `memory_budget` bytes fails before those files are read, measured from
+/// image headers, mesh cache headers and a pass over OBJ lines:
+///
+/// ```
+/// use rt::mesh::ObjConvention;
+/// use rt::scene_file::{load_scene, AssetCache, MissingAssets};
+/// use std::alloc::{GlobalAlloc, Layout, System};
+/// use std::fmt::Write;
+/// use std::fs;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// // Counts the bytes allocated and not yet freed, and the most there were
+/// struct Counting;
+/// static LIVE: AtomicUsize = AtomicUsize::new(0);
+/// static PEAK: AtomicUsize = AtomicUsize::new(0);
+/// unsafe impl GlobalAlloc for Counting {
+///     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+///         let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+///         PEAK.fetch_max(live, Ordering::Relaxed);
+///         System.alloc(layout)
+///     }
+///     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+///         LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+///         System.dealloc(ptr, layout)
+///     }
+/// }
+/// #[global_allocator]
+/// static ALLOCATOR: Counting = Counting;
+///
+/// fn main() {
+///     // A 200 x 200 grid of quads, 80,000 triangles taking megabytes
+///     let dir = std::env::temp_dir().join(format!("rt-budget-doctest-{}", std::process::id()));
+///     fs::create_dir_all(&dir).unwrap();
+///     let n = 200;
+///     let mut obj = String::new();
+///     for y in 0..=n {
+///         for x in 0..=n {
+///             writeln!(obj, "v {} {} 0", x, y).unwrap();
+///         }
+///     }
+///     for y in 0..n {
+///         for x in 0..n {
+///             let index = |x: usize, y: usize| y * (n + 1) + x + 1;
+///             writeln!(obj, "f {} {} {} {}", index(x, y), index(x + 1, y), index(x + 1, y + 1), index(x, y + 1)).unwrap();
+///         }
+///     }
+///     fs::write(dir.join("grid.obj"), obj).unwrap();
+///     let path = dir.join("scene.json");
+///     fs::write(&path, r#"{ "objects": [{ "type": "mesh", "file": "grid.obj" }] }"#).unwrap();
+///     let load = |budget| load_scene(path.to_str().unwrap(), 1.0, &AssetCache::default(), None, ObjConvention::default(), MissingAssets::Error, budget);
+///
+///     // Over a 1 MiB budget, the loader stops without building the mesh,
+///     // or even holding its file
+///     let before = LIVE.load(Ordering::Relaxed);
+///     PEAK.store(before, Ordering::Relaxed);
+///     let message = load(Some(1 << 20)).err().unwrap();
+///     assert!(message.contains("grid.obj' needs about") && message.contains("over the memory budget of 1.0 MiB"), "{}", message);
+///     let peak = PEAK.load(Ordering::Relaxed) - before;
+///     assert!(peak < 100_000, "{} bytes allocated", peak);
+///
+///     // Within budget it loads, taking a little less than estimated
+///     let estimate = rt::mesh::Mesh::estimate_file(dir.join("grid.obj").to_str().unwrap()).unwrap();
+///     let loaded = load(Some(estimate * 2)).unwrap();
+///     assert_eq!(loaded.scene.objects().len(), 1);
+///     let memory = rt::memory::MemoryEstimate::new(&loaded.scene).total as f64;
+///     assert!(memory <= estimate as f64 && memory > 0.8 * estimate as f64, "estimated {} before loading, {} after", estimate, memory);
+///     fs::remove_dir_all(&dir).unwrap();
+/// }
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn load_scene(
+    path: &str,
+    aspect_ratio: f64,
+    assets: &AssetCache,
+    units: Option<Units>,
+    meshes: ObjConvention,
+    missing_assets: MissingAssets,
+    memory_budget: Option<usize>,
+) -> Result<LoadedScene, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read scene file '{}': {}", path, e))?;
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    parse_scene(&text, base_dir, aspect_ratio, assets, units, meshes, missing_assets, memory_budget).map_err(|e| format!("{}: {}", path, e))
+}
+
+// The resolution the text of a scene file declares, read without loading
+// the rest, so the image size is known before the camera is set up for it
+pub fn declared_resolution(text: &str) -> Result<Option<(u32, u32)>, String> {
+    Value::parse(text)?.get("resolution").map(parse_resolution).transpose()
+}
+
+/// A scene written out as a string in the scene file format, for tests,
+/// examples and bug reports. Files are looked up relative to the current
+/// directory, the camera is set up for square images, and warnings are
+/// logged. Errors point into the string by line and column, or by the
+/// path to the offending entry.
+///
+/// ```
+/// use rt::background::Background;
+/// use rt::image::color::Color;
+/// use rt::image::Image;
+/// use rt::scene::{RenderSettings, Scene};
+///
+/// let scene: Scene = r#"{
+///     "camera": { "position": [0, 0, 0], "look_at": [0, 0, -1] },
+///     "objects": [{ "type": "sphere", "center": [0, 0, -3], "radius": 1, "material": { "preset": "glow", "color": [1, 1, 1] } }]
+/// }"#.parse().unwrap();
+/// let mut image = Image::new(9, 9);
+/// scene.render_into(&mut image, &RenderSettings::default());
+/// assert!(image.get_pixel(4, 4).r > 0.9);
+///
+/// // Colors are linear [r, g, b] values, sRGB hex strings or names
+/// let pink: Scene = r##"{ "background": "#cc3344", "lights": [{ "position": [0, 1, 0], "color": "gold" }] }"##.parse().unwrap();
+/// assert_eq!(pink.background, Background::Solid(Color::parse("#cc3344").unwrap()));
+/// assert_eq!(pink.lights[0].color.to_srgb8(), [0xff, 0xd7, 0x00]);
+/// let error = r#"{ "background": "cc3344" }"#.parse::<Scene>().err().unwrap();
+/// assert!(error.starts_with("background: unknown color 'cc3344'"), "{}", error);
+///
+/// let error = "{\n  \"objects\": [\n    { \"type\": \"sphere\", }\n  ]\n}".parse::<Scene>().err().unwrap();
+/// assert!(error.starts_with("line 3, column"), "{}", error);
+/// let error = r#"{ "objects": [{ "type": "sphere", "radius": 1 }] }"#.parse::<Scene>().err().unwrap();
+/// assert_eq!(error, "objects[0]: missing 'center'");
+/// ```
+impl FromStr for Scene {
+    type Err = String;
+    
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let loaded = parse_scene(text, Path::new(""), 1.0, &AssetCache::default(), None, ObjConvention::default(), MissingAssets::Error, None)?;
+        for warning in &loaded.warnings {
+            warn!("{}", warning);
+        }
+        Ok(loaded.scene)
+    }
+}
+
+/// Builds a scene from the text of a scene file. Texture and mesh files are
+/// looked up relative to `base_dir`.
+///
+/// A scene written in millimeters renders the same as one in meters:
+///
+/// ```
+/// use rt::image::Image;
+/// use rt::scene::RenderSettings;
+/// use rt::mesh::ObjConvention;
+/// use rt::scene_file::{parse_scene, AssetCache, MissingAssets, Units};
+/// use std::path::Path;
+///
+/// let scene = r#"{
+///     "units": "mm",
+///     "camera": { "position": [0, 500, 3000], "look_at": [0, 300, 0] },
+///     "lights": [{ "position": [2000, 4000, 2000] }],
+///     "objects": [
+///         { "type": "sphere", "center": [0, 300, 0], "radius": 300 },
+///         { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0] }
+///     ]
+/// }"#;
+/// let render = |text: &str, units| {
+///     let loaded = parse_scene(text, Path::new(""), 1.0, &AssetCache::default(), units, ObjConvention::default(), MissingAssets::Error, None).unwrap();
+///     let mut image = Image::new(16, 16);
+///     loaded.scene.render_into(&mut image, &RenderSettings::default());
+///     image.pixels
+/// };
+/// let in_meters = scene
+///     .replace("\"mm\"", "\"m\"")
+///     .replace("[0, 500, 3000]", "[0, 0.5, 3]")
+///     .replace("[0, 300, 0]", "[0, 0.3, 0]")
+///     .replace("[2000, 4000, 2000]", "[2, 4, 2]")
+///     .replace("300 }", "0.3 }");
+/// assert_eq!(render(scene, None), render(&in_meters, None));
+/// // The same numbers read as meters instead, through the override
+/// assert_ne!(render(scene, None), render(scene, Some(Units::METERS)));
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn parse_scene(
+    text: &str,
+    base_dir: &Path,
+    aspect_ratio: f64,
+    assets: &AssetCache,
+    units: Option<Units>,
+    meshes: ObjConvention,
+    missing_assets: MissingAssets,
+    memory_budget: Option<usize>,
+) -> Result<LoadedScene, String> {
+    let root = Value::parse(text)?;
+    check_keys(&root, "scene", &["resolution", "units", "camera", "background", "materials", "lights", "objects", "clip", "portals"])?;
+    let units = match (units, root.get("units")) {
+        (Some(units), _) => units,
+        (None, Some(Value::Number(meters))) => Units::from_meters(*meters).map_err(|e| format!("units: {}", e))?,
+        (None, Some(units)) => Units::parse(string(units, "units")?).map_err(|e| format!("units: {}", e))?,
+        (None, None) => Units::METERS,
+    };
+    if units != Units::METERS {
+        debug!("Scene lengths are in units of {} m, scaled to meters", units.meters());
+    }
+    let files = &Files {
+        base_dir,
+        assets,
+        units,
+        meshes,
+        missing_assets,
+        missing: RefCell::new(Vec::new()),
+        memory_budget,
+        planned: Cell::new(0),
+        counted: RefCell::new(HashSet::new()),
+    };
+    let mut scene = Scene::new();
+    let mut warnings = Vec::new();
+    
+    if let Some(camera) = root.get("camera") {
+        scene.set_camera(parse_camera(camera, aspect_ratio, units)?);
+        if let Some(shake) = camera.get("shake") {
+            scene.shake = Some(parse_shake(shake, units)?);
+        }
+        if let Some(framings) = camera.get("framings") {
+            scene.framings = parse_framings(framings, scene.camera.as_ref().expect("camera just set"), units)?;
+        }
+    }
+    if let Some(background) = root.get("background") {
+        scene.set_background(parse_background(background)?);
+    }
+    
+    // Palette first, so objects can refer to materials in any order
+    let mut palette = Vec::new();
+    if let Some(materials) = root.get("materials") {
+        let entries = materials.as_object().ok_or_else(|| expected("materials", "an object", materials))?;
+        for (name, definition) in entries {
+            palette.push((name.clone(), parse_material(definition, &format!("materials.{}", name), files)?));
+        }
+    }
+    let mut used = HashSet::new();
+    
+    if let Some(lights) = root.get("lights") {
+        for (i, light) in array(lights, "lights")?.iter().enumerate() {
+            parse_light(light, &format!("lights[{}]", i), files, &mut scene)?;
+        }
+    }
+    // After the listed lights, which keep their indices for messages
+    if let Background::Sky(sky) = scene.background {
+        scene.add_light(sky.sun_light());
+    }
+    
+    if let Some(objects) = root.get("objects") {
+        let mut meshes = Vec::new();
+        mesh_files(objects, base_dir, &mut meshes);
+        for path in &meshes {
+            files.reserve(path, Mesh::estimate_file)?;
+        }
+        assets.preload_meshes(meshes);
+        for (i, object) in array(objects, "objects")?.iter().enumerate() {
+            let mut context = format!("objects[{}]", i);
+            if let Some(name) = object.get("name").and_then(Value::as_str) {
+                context = format!("{} ({})", context, name);
+            }
+            parse_object(object, &context, &palette, &mut used, files, &mut scene, &mut warnings)?;
+        }
+    }
+    
+    if let Some(clip) = root.get("clip") {
+        for (i, clip_plane) in array(clip, "clip")?.iter().enumerate() {
+            let clip_plane = parse_clip_plane(clip_plane, &format!("clip[{}]", i), &palette, &mut used, files)?;
+            scene.add_clip_plane(clip_plane);
+        }
+    }
+    
+    if let Some(portals) = root.get("portals") {
+        for (i, portal) in array(portals, "portals")?.iter().enumerate() {
+            scene.add_portal(parse_portal(portal, &format!("portals[{}]", i), files)?);
+        }
+    }
+    
+    // Lights come before objects, so their links are checked once every
+    // object has its name
+    for (i, light) in scene.lights.iter().enumerate() {
+        let (key, names) = match &light.linking {
+            LightLinking::All => continue,
+            LightLinking::Only(names) => ("affects", names),
+            LightLinking::Except(names) => ("excludes", names),
+        };
+        if let Some(name) = names.iter().find(|name| scene.find_object(name).is_none()) {
+            return Err(format!(
+                "lights[{}].{}: unknown object '{}' (scene objects: {})",
+                i, key, name, scene.object_names().join(", ")
+            ));
+        }
+    }
+    
+    warnings.extend(files.missing.take());
+    for (name, _) in &palette {
+        if !used.contains(name.as_str()) {
+            warnings.push(format!("material '{}' is defined but never used", name));
+        }
+    }
+    
+    let resolution = root.get("resolution").map(parse_resolution).transpose()?;
+    Ok(LoadedScene { scene, warnings, resolution })
+}
+
+// A material as written in a scene file; textures ride along with it and
+// are attached to objects that can show them
+#[derive(Clone)]
+struct MaterialDefinition {
+    material: Material,
+    texture: Option<(Arc<Texture>, f64, TextureSpace)>,
+}
+
+fn parse_material(value: &Value, context: &str, files: &Files) -> Result<MaterialDefinition, String> {
+    check_keys(value, context, &[
+        "preset", "color", "emission", "texture", "ambient", "diffuse", "specular",
+        "shininess", "reflectivity", "transparency", "refractive_index", "ior",
+        "scatter_distance", "scatter_tint", "coat", "coat_ior", "sheen", "sheen_color",
+        "film_thickness", "film_gradient", "film_ior", "metalness", "roughness",
+    ])?;
+    
+    let color = match value.get("color") {
+        Some(color) => rgb(color, &format!("{}.color", context))?,
+        None => Material::default().color,
+    };
+    
+    // Metalness and roughness stand in for a preset; either one alone
+    // leaves the other at its default
+    let unit = |key: &str| match optional_number(value, key, context)? {
+        Some(x) if !(0.0..=1.0).contains(&x) => Err(format!("{}.{}: must be between 0 and 1 (got {})", context, key, x)),
+        x => Ok(x),
+    };
+    let (metalness, roughness) = (unit("metalness")?, unit("roughness")?);
+    let mut material = if metalness.is_some() || roughness.is_some() {
+        if value.get("preset").is_some() {
+            return Err(format!("{}: give either a preset or metalness and roughness, not both", context));
+        }
+        Material::from_pbr(color, metalness.unwrap_or(0.0), roughness.unwrap_or(0.5))
+    } else {
+        let preset = match value.get("preset") {
+            Some(preset) => string(preset, &format!("{}.preset", context))?,
+            None => "default",
+        };
+        Material::preset(preset, color).ok_or_else(|| {
+            format!(
+                "{}: unknown material preset '{}' (presets: {})",
+                context,
+                preset,
+                Material::PRESET_NAMES.join(", ")
+            )
+        })?
+    };
+    
+    for (key, field) in value.as_object().unwrap_or(&[]) {
+        if matches!(key.as_str(), "preset" | "color" | "emission" | "texture" | "metalness" | "roughness") {
+            continue;
+        }
+        let field_context = format!("{}.{}", context, key);
+        let result = match field {
+            // Per-channel fields also take [r, g, b] or a hex color
+            Value::Array(_) | Value::String(_) => material.set_channels(key, rgb(field, &field_context)?),
+            // The one length among the fields
+            _ if key == "scatter_distance" => material.set_field(key, files.units.length(number(field, &field_context)?)),
+            _ => material.set_field(key, number(field, &field_context)?),
+        };
+        result.map_err(|e| format!("{}: {}", field_context, e))?;
+    }
+    if let Some(emission) = value.get("emission") {
+        material.emission = rgb(emission, &format!("{}.emission", context))?;
+    }
+    
+    let texture = match value.get("texture") {
+        Some(texture) => {
+            let (texture, scale, space) = parse_texture(texture, &format!("{}.texture", context), files)?;
+            Some((Arc::new(texture), scale, space))
+        }
+        None => None,
+    };
+    
+    Ok(MaterialDefinition { material, texture })
+}
+
+fn parse_texture(value: &Value, context: &str, files: &Files) -> Result<(Texture, f64, TextureSpace), String> {
+    check_keys(value, context, &[
+        "file", "checker", "linear_gradient", "radial_gradient", "ramp", "filter", "wrap", "scale", "space",
+    ])?;
+    
+    let wrap = match value.get("wrap") {
+        Some(wrap) => Some(match string(wrap, &format!("{}.wrap", context))? {
+            "clamp" => TextureWrap::Clamp,
+            "repeat" => TextureWrap::Repeat,
+            "mirror" => TextureWrap::Mirror,
+            other => return Err(format!("{}.wrap: unknown wrap mode '{}' (expected clamp, repeat or mirror)", context, other)),
+        }),
+        None => None,
+    };
+    let kinds = ["file", "checker", "linear_gradient", "radial_gradient", "ramp"];
+    let (kind, block) = match kinds.iter().filter_map(|&kind| Some((kind, value.get(kind)?))).collect::<Vec<_>>()[..] {
+        [found] => found,
+        _ => return Err(format!("{}: expected exactly one of 'file', 'checker', 'linear_gradient', 'radial_gradient' or 'ramp'", context)),
+    };
+    let kind_context = format!("{}.{}", context, kind);
+    
+    let texture = match kind {
+        "file" | "checker" => {
+            let mut image = if kind == "file" {
+                let path = files.base_dir.join(string(block, &kind_context)?);
+                if files.placeholder(&path, &kind_context) {
+                    ImageTexture::placeholder()
+                } else {
+                    files.reserve(&path, ImageTexture::estimate_ppm).map_err(|e| format!("{}: {}", context, e))?;
+                    files.assets.texture(&path).map_err(|e| format!("{}: {}", context, e))?
+                }
+            } else {
+                check_keys(block, &kind_context, &["size", "squares", "colors"])?;
+                let size = optional_number(block, "size", &kind_context)?.unwrap_or(256.0);
+                let squares = optional_number(block, "squares", &kind_context)?.unwrap_or(8.0);
+                let (a, b) = match block.get("colors") {
+                    Some(colors) => {
+                        let colors_context = format!("{}.colors", kind_context);
+                        match array(colors, &colors_context)? {
+                            [a, b] => (rgb(a, &colors_context)?, rgb(b, &colors_context)?),
+                            _ => return Err(format!("{}: expected two colors", colors_context)),
+                        }
+                    }
+                    None => (Color::new(0.9, 0.9, 0.9), Color::new(0.1, 0.1, 0.1)),
+                };
+                if size < 1.0 || squares < 1.0 {
+                    return Err(format!("{}: size and squares must be at least 1", kind_context));
+                }
+                ImageTexture::checker(size as usize, squares as usize, a, b)
+            };
+            if let Some(filter) = value.get("filter") {
+                image.filter = match string(filter, &format!("{}.filter", context))? {
+                    "nearest" => TextureFilter::Nearest,
+                    "bilinear" => TextureFilter::Bilinear,
+                    "mipmap" => TextureFilter::Mipmap,
+                    other => return Err(format!("{}.filter: unknown filter '{}' (expected nearest, bilinear or mipmap)", context, other)),
+                };
+            }
+            if let Some(wrap) = wrap {
+                image.wrap = wrap;
+            }
+            Texture::Image(image)
+        }
+        _ => {
+            if value.get("filter").is_some() {
+                return Err(format!("{}.filter: only image textures are filtered", context));
+            }
+            // Gradients hold their end colors unless asked to repeat
+            parse_gradient_texture(kind, block, &kind_context, wrap.unwrap_or(TextureWrap::Clamp))?
+        }
+    };
+    
+    let scale = optional_number(value, "scale", context)?.unwrap_or(1.0);
+    if scale <= 0.0 {
+        return Err(format!("{}.scale: must be positive", context));
+    }
+    let scale = files.units.length(scale);
+    
+    let space = match value.get("space") {
+        Some(space) => match string(space, &format!("{}.space", context))? {
+            "object" => TextureSpace::Object,
+            "world" => TextureSpace::World,
+            other => return Err(format!("{}.space: unknown space '{}' (expected object or world)", context, other)),
+        },
+        None => TextureSpace::Object,
+    };
+    
+    Ok((texture, scale, space))
+}
+
+// The positions in a gradient block are in texture units, which the
+// texture's scale already turns into world units
+fn parse_gradient_texture(kind: &str, value: &Value, context: &str, wrap: TextureWrap) -> Result<Texture, String> {
+    let extra: &[&str] = match kind {
+        "linear_gradient" => &["axis"],
+        "radial_gradient" => &["center", "radius", "axis"],
+        _ => &["angle"],
+    };
+    check_keys(value, context, &[extra, &["colors", "stops"]].concat())?;
+    
+    let colors_context = format!("{}.colors", context);
+    let colors = array(required(value, "colors", context)?, &colors_context)?
+        .iter()
+        .map(|color| rgb(color, &colors_context))
+        .collect::<Result<Vec<_>, _>>()?;
+    let stops = match value.get("stops") {
+        Some(stops) => {
+            let stops_context = format!("{}.stops", context);
+            Some(array(stops, &stops_context)?.iter().map(|stop| number(stop, &stops_context)).collect::<Result<Vec<_>, _>>()?)
+        }
+        None => None,
+    };
+    let gradient = Gradient::new(colors, stops).map_err(|e| format!("{}: {}", context, e))?.with_wrap(wrap);
+    let axis = match value.get("axis") {
+        Some(axis) => match vec3(axis, &format!("{}.axis", context))? {
+            axis if axis.length_squared() == 0.0 => return Err(format!("{}.axis: must not be zero", context)),
+            axis => Some(axis),
+        },
+        None => None,
+    };
+    
+    Ok(match kind {
+        "linear_gradient" => Texture::LinearGradient { axis: axis.unwrap_or(Vec3::new(0.0, 1.0, 0.0)), gradient },
+        "radial_gradient" => {
+            let center = match value.get("center") {
+                Some(center) => vec3(center, &format!("{}.center", context))?,
+                None => Vec3::zero(),
+            };
+            let radius = optional_number(value, "radius", context)?.unwrap_or(1.0);
+            if radius <= 0.0 {
+                return Err(format!("{}.radius: must be positive", context));
+            }
+            Texture::RadialGradient { center, radius, axis, gradient }
+        }
+        _ => {
+            let angle = optional_number(value, "angle", context)?.unwrap_or(0.0);
+            Texture::Ramp { angle: angle.to_radians(), gradient }
+        }
+    })
+}
+
+fn parse_camera(value: &Value, aspect_ratio: f64, units: Units) -> Result<Camera, String> {
+    check_keys(value, "camera", &["position", "look_at", "up", "fov", "near", "far", "shake", "framings"])?;
+    let up = match value.get("up") {
+        Some(up) => vec3(up, "camera.up")?,
+        None => Vec3::new(0.0, 1.0, 0.0),
+    };
+    let camera = Camera::new(
+        units.vector(vec3(required(value, "position", "camera")?, "camera.position")?),
+        units.vector(vec3(required(value, "look_at", "camera")?, "camera.look_at")?),
+        up,
+        optional_number(value, "fov", "camera")?.unwrap_or(60.0),
+        aspect_ratio,
+    ).map_err(|e| format!("camera: {}", e))?;
+    camera.validate().map_err(|e| format!("camera: {}", e))?;
+    let near = optional_number(value, "near", "camera")?.map_or(camera.near, |near| units.length(near));
+    let far = optional_number(value, "far", "camera")?.map_or(camera.far, |far| units.length(far));
+    camera.with_clip(near, far).map_err(|e| format!("camera: {}", e))
+}
+
+// Framings are keyed by their aspect ratio, such as "16x9", and take any
+// of the camera's position, look_at, up and fov, keeping the camera's
+// own for the rest
+fn parse_framings(value: &Value, camera: &Camera, units: Units) -> Result<Vec<Framing>, String> {
+    let entries = value.as_object().ok_or_else(|| expected("camera.framings", "an object", value))?;
+    let mut framings: Vec<Framing> = Vec::new();
+    for (key, entry) in entries {
+        let context = format!("camera.framings.{}", key);
+        let aspect = parse_aspect(key).map_err(|e| format!("{}: {}", context, e))?;
+        if let Some(other) = framings.iter().find(|framing| same_aspect(framing.aspect, aspect)) {
+            return Err(format!("{}: same aspect ratio as {}x{}", context, other.aspect.0, other.aspect.1));
+        }
+        check_keys(entry, &context, &["position", "look_at", "up", "fov"])?;
+        let field = |key: &str| entry.get(key).map(|v| vec3(v, &format!("{}.{}", context, key))).transpose();
+        let framing = Framing {
+            aspect,
+            position: field("position")?.map_or(camera.position, |position| units.vector(position)),
+            look_at: field("look_at")?.map_or(camera.look_at, |look_at| units.vector(look_at)),
+            up: field("up")?.unwrap_or(camera.up),
+            fov: optional_number(entry, "fov", &context)?.unwrap_or(camera.fov),
+        };
+        Camera::new(framing.position, framing.look_at, framing.up, framing.fov, framing.ratio())
+            .and_then(|camera| camera.validate())
+            .map_err(|e| format!("{}: {}", context, e))?;
+        framings.push(framing);
+    }
+    Ok(framings)
+}
+
+// Amplitude in the scene's units, frequency in cycles per second
+fn parse_shake(value: &Value, units: Units) -> Result<Shake, String> {
+    check_keys(value, "camera.shake", &["amplitude", "frequency"])?;
+    let amplitude = number(required(value, "amplitude", "camera.shake")?, "camera.shake.amplitude")?;
+    let frequency = optional_number(value, "frequency", "camera.shake")?.unwrap_or(1.0);
+    Shake::new(units.length(amplitude), frequency).map_err(|e| format!("camera.shake: {}", e))
+}
+
+fn parse_background(value: &Value) -> Result<Background, String> {
+    if let Some(sky) = value.get("sky") {
+        check_keys(value, "background", &["sky"])?;
+        check_keys(sky, "background.sky", &["sun_elevation", "sun_azimuth", "turbidity"])?;
+        let elevation = number(required(sky, "sun_elevation", "background.sky")?, "background.sky.sun_elevation")?;
+        let azimuth = optional_number(sky, "sun_azimuth", "background.sky")?.unwrap_or(0.0);
+        let turbidity = optional_number(sky, "turbidity", "background.sky")?.unwrap_or(3.0);
+        let sky = SkyModel::new(elevation, azimuth, turbidity).map_err(|e| format!("background.sky: {}", e))?;
+        Ok(Background::Sky(sky))
+    } else if let Value::Object(_) = value {
+        check_keys(value, "background", &["bottom", "top"])?;
+        Ok(Background::Gradient {
+            bottom: rgb(required(value, "bottom", "background")?, "background.bottom")?,
+            top: rgb(required(value, "top", "background")?, "background.top")?,
+        })
+    } else {
+        Ok(Background::Solid(rgb(value, "background")?))
+    }
+}
+
+fn parse_light(value: &Value, context: &str, files: &Files, scene: &mut Scene) -> Result<(), String> {
+    let kind = match value.get("type") {
+        Some(kind) => string(kind, &format!("{}.type", context))?,
+        None => "point",
+    };
+    let color = match value.get("color") {
+        Some(color) => rgb(color, &format!("{}.color", context))?,
+        None => Color::WHITE,
+    };
+    let intensity = optional_number(value, "intensity", context)?.unwrap_or(1.0);
+    // Positions and edges, converted to meters
+    let field = |key: &str| vec3(required(value, key, context)?, &format!("{}.{}", context, key)).map(|v| files.units.vector(v));
+    let linking = parse_light_linking(value, context)?;
+    let falloff = match value.get("attenuation") {
+        Some(falloff) => parse_attenuation(falloff, &format!("{}.attenuation", context), files.units)?,
+        None => Attenuation::default(),
+    };
+    
+    match kind {
+        "point" => {
+            check_keys(value, context, &[
+                "type", "position", "color", "intensity", "attenuation", "falloff_start", "falloff_end", "affects", "excludes",
+                "direction", "profile",
+            ])?;
+            let profile = match value.get("profile") {
+                Some(profile) => parse_light_profile(profile, &format!("{}.profile", context), files.base_dir)?,
+                None => LightProfile::Uniform,
+            };
+            let direction = match value.get("direction") {
+                Some(direction) => vec3(direction, &format!("{}.direction", context))?,
+                None => Vec3::new(0.0, -1.0, 0.0),
+            };
+            if direction.length_squared() == 0.0 {
+                return Err(format!("{}.direction: must not be zero", context));
+            }
+            let light = Light::new(field("position")?, color, intensity)
+                .with_linking(linking)
+                .with_attenuation(falloff)
+                .with_profile(profile, direction);
+            scene.add_light(parse_falloff_window(light, value, context, files.units)?);
+        }
+        "quad" => {
+            check_keys(value, context, &[
+                "type", "corner", "edge_u", "edge_v", "color", "intensity", "attenuation", "falloff_start", "falloff_end",
+                "emitter", "texture", "affects", "excludes",
+            ])?;
+            let light = Light::quad(field("corner")?, field("edge_u")?, field("edge_v")?, color, intensity)
+                .with_linking(linking)
+                .with_attenuation(falloff);
+            let light = parse_falloff_window(light, value, context, files.units)?;
+            // The glowing panel is added as an object unless asked not to
+            let emitter = match value.get("emitter") {
+                Some(emitter) => emitter.as_bool().ok_or_else(|| expected(&format!("{}.emitter", context), "a boolean", emitter))?,
+                None => true,
+            };
+            // A texture is stretched once over the panel, so it takes no
+            // scale or space
+            let texture = match value.get("texture") {
+                Some(texture) => {
+                    let texture_context = format!("{}.texture", context);
+                    if texture.get("scale").is_some() || texture.get("space").is_some() {
+                        return Err(format!("{}: light textures cover the whole panel and take no scale or space", texture_context));
+                    }
+                    match parse_texture(texture, &texture_context, files)?.0 {
+                        Texture::Image(image) => Some(Arc::new(image)),
+                        _ => return Err(format!("{}: light textures must be a file or a checker", texture_context)),
+                    }
+                }
+                None => None,
+            };
+            match (emitter, texture) {
+                (true, Some(texture)) => scene.add_textured_light(light, texture),
+                (false, Some(texture)) => scene.add_light(Light { color: light.color * texture.average(), ..light }),
+                (true, None) => scene.add_light_with_emitter(light),
+                (false, None) => scene.add_light(light),
+            }
+        }
+        "directional" => {
+            check_keys(value, context, &["type", "direction", "angular_diameter", "color", "intensity", "affects", "excludes"])?;
+            let direction = vec3(required(value, "direction", context)?, &format!("{}.direction", context))?;
+            if direction.length_squared() == 0.0 {
+                return Err(format!("{}.direction: must not be zero", context));
+            }
+            let angular_diameter = optional_number(value, "angular_diameter", context)?.unwrap_or(0.0);
+            if !(0.0..180.0).contains(&angular_diameter) {
+                return Err(format!("{}.angular_diameter: must be at least 0 and less than 180 degrees", context));
+            }
+            scene.add_light(Light::directional(direction, angular_diameter, color, intensity).with_linking(linking));
+        }
+        other => return Err(format!("{}: unknown light type '{}' (expected point, quad or directional)", context, other)),
+    }
+    Ok(())
+}
+
+// The section material, given like an object's, is optional; without it
+// the cut is left open
+fn parse_clip_plane(
+    value: &Value,
+    context: &str,
+    palette: &[(String, MaterialDefinition)],
+    used: &mut HashSet<String>,
+    files: &Files,
+) -> Result<ClipPlane, String> {
+    check_keys(value, context, &["point", "normal", "section"])?;
+    let field = |key: &str| vec3(required(value, key, context)?, &format!("{}.{}", context, key));
+    let normal = field("normal")?;
+    if normal.length_squared() == 0.0 {
+        return Err(format!("{}.normal: must not be zero", context));
+    }
+    let mut clip_plane = ClipPlane::new(files.units.vector(field("point")?), normal);
+    if let Some(section) = value.get("section") {
+        let section_context = format!("{}.section", context);
+        let definition = resolve_material(Some(section), &section_context, palette, used, files)?;
+        if definition.texture.is_some() {
+            return Err(format!("{}: section materials cannot have textures", section_context));
+        }
+        clip_plane = clip_plane.with_section(definition.material);
+    }
+    Ok(clip_plane)
+}
+
+// A window or other opening that occlusion rays are aimed through; the
+// edges must be perpendicular
+fn parse_portal(value: &Value, context: &str, files: &Files) -> Result<Portal, String> {
+    check_keys(value, context, &["corner", "edge_u", "edge_v"])?;
+    let field = |key: &str| vec3(required(value, key, context)?, &format!("{}.{}", context, key)).map(|v| files.units.vector(v));
+    Portal::new(field("corner")?, field("edge_u")?, field("edge_v")?).map_err(|e| format!("{}: {}", context, e))
+}
+
+// A built-in curve by name, or { "file": "fixture.txt" } for a table of
+// angles and multipliers
+fn parse_light_profile(value: &Value, context: &str, base_dir: &Path) -> Result<LightProfile, String> {
+    if value.as_object().is_some() {
+        check_keys(value, context, &["file"])?;
+        let path = base_dir.join(string(required(value, "file", context)?, &format!("{}.file", context))?);
+        return LightProfile::load(&path.to_string_lossy()).map_err(|e| format!("{}: {}", context, e));
+    }
+    match string(value, context)? {
+        "uniform" => Ok(LightProfile::Uniform),
+        "cosine" => Ok(LightProfile::Cosine),
+        "beam" => Ok(LightProfile::Beam),
+        other => Err(format!(
+            "{}: unknown profile '{}' (expected uniform, cosine, beam or {{ \"file\": ... }})",
+            context, other
+        )),
+    }
+}
+
+// A preset by name, { "a": ..., "b": ..., "c": ... } for 1 / (a + b d + c d^2),
+// or { "radius": r } for inverse square softened by the source's size.
+// Coefficients and radii are in the scene's units and converted so the
+// falloff is the same once distances are in meters.
+fn parse_attenuation(value: &Value, context: &str, units: Units) -> Result<Attenuation, String> {
+    if value.get("radius").is_some() {
+        check_keys(value, context, &["radius"])?;
+        let radius = number(required(value, "radius", context)?, &format!("{}.radius", context))?;
+        if radius <= 0.0 {
+            return Err(format!("{}.radius: must be positive", context));
+        }
+        return Ok(Attenuation::Soft { radius: units.length(radius) });
+    }
+    if value.as_object().is_some() {
+        check_keys(value, context, &["a", "b", "c"])?;
+        let coefficient = |key: &str| -> Result<f64, String> {
+            let coefficient = optional_number(value, key, context)?.unwrap_or(0.0);
+            if coefficient < 0.0 {
+                return Err(format!("{}.{}: must not be negative", context, key));
+            }
+            Ok(coefficient)
+        };
+        let unit = units.length(1.0);
+        let (a, b, c) = (coefficient("a")?, coefficient("b")? / unit, coefficient("c")? / (unit * unit));
+        if a == 0.0 && b == 0.0 && c == 0.0 {
+            return Err(format!("{}: needs a coefficient above zero", context));
+        }
+        return Ok(Attenuation::Custom { a, b, c });
+    }
+    match string(value, context)? {
+        "standard" => Ok(Attenuation::default()),
+        "none" => Ok(Attenuation::None),
+        "linear" => Ok(Attenuation::Linear),
+        "quadratic" => Ok(Attenuation::Quadratic),
+        other => Err(format!(
+            "{}: unknown attenuation '{}' (expected standard, none, linear, quadratic, {{ \"a\": ..., \"b\": ..., \"c\": ... }} \
+             or {{ \"radius\": ... }})",
+            context, other
+        )),
+    }
+}
+
+// "falloff_start" and "falloff_end", in the scene's units, fade the light
+// out between them; they come together or not at all
+fn parse_falloff_window(light: Light, value: &Value, context: &str, units: Units) -> Result<Light, String> {
+    let start = optional_number(value, "falloff_start", context)?;
+    let end = optional_number(value, "falloff_end", context)?;
+    match (start, end) {
+        (None, None) => Ok(light),
+        (Some(start), Some(end)) => {
+            light.with_falloff_window(units.length(start), units.length(end)).map_err(|e| format!("{}: {}", context, e))
+        }
+        (Some(_), None) => Err(format!("{}: falloff_start needs a falloff_end", context)),
+        (None, Some(_)) => Err(format!("{}: falloff_end needs a falloff_start", context)),
+    }
+}
+
+// "affects" lists the only objects a light shines on, "excludes" the ones
+// it skips
+fn parse_light_linking(value: &Value, context: &str) -> Result<LightLinking, String> {
+    let names = |key: &str| -> Result<Option<Vec<String>>, String> {
+        let Some(list) = value.get(key) else {
+            return Ok(None);
+        };
+        let context = format!("{}.{}", context, key);
+        let names = array(list, &context)?.iter().enumerate()
+            .map(|(i, name)| string(name, &format!("{}[{}]", context, i)).map(str::to_string))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(names))
+    };
+    match (names("affects")?, names("excludes")?) {
+        (Some(_), Some(_)) => Err(format!("{}: give either affects or excludes, not both", context)),
+        (Some(names), None) => Ok(LightLinking::Only(names)),
+        (None, Some(names)) => Ok(LightLinking::Except(names)),
+        (None, None) => Ok(LightLinking::All),
+    }
+}
+
+// A material given either by palette name or inline; missing means the
+// default material
+fn resolve_material(
+    value: Option<&Value>,
+    context: &str,
+    palette: &[(String, MaterialDefinition)],
+    used: &mut HashSet<String>,
+    files: &Files,
+) -> Result<MaterialDefinition, String> {
+    match value {
+        Some(Value::String(name)) => {
+            let (_, definition) = palette.iter().find(|(n, _)| n == name).ok_or_else(|| {
+                let names: Vec<&str> = palette.iter().map(|(n, _)| n.as_str()).collect();
+                format!(
+                    "{}: undefined material '{}' (defined materials: {})",
+                    context,
+                    name,
+                    if names.is_empty() { "none".to_string() } else { names.join(", ") }
+                )
+            })?;
+            used.insert(name.clone());
+            Ok(definition.clone())
+        }
+        Some(inline @ Value::Object(_)) => parse_material(inline, context, files),
+        Some(other) => Err(expected(context, "a material name or an object", other)),
+        None => Ok(MaterialDefinition { material: Material::default(), texture: None }),
+    }
+}
+
+fn parse_object(
+    value: &Value,
+    context: &str,
+    palette: &[(String, MaterialDefinition)],
+    used: &mut HashSet<String>,
+    files: &Files,
+    scene: &mut Scene,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    let object = build_object(value, context, palette, used, files, warnings)?;
+    match value.get("name") {
+        Some(name) => {
+            let name = string(name, &format!("{}.name", context))?;
+            if scene.find_object(name).is_some() {
+                return Err(format!("{}: duplicate object name '{}'", context, name));
+            }
+            scene.add_named_object(name, object);
+        }
+        None => scene.add_object(object),
+    }
+    if let Some(layer) = value.get("layer") {
+        let layer_context = format!("{}.layer", context);
+        let layer = string(layer, &layer_context)?;
+        scene.set_object_layer(scene.objects().len() - 1, layer).map_err(|e| format!("{}: {}", layer_context, e))?;
+    }
+    Ok(())
+}
+
+fn build_object(
+    value: &Value,
+    context: &str,
+    palette: &[(String, MaterialDefinition)],
+    used: &mut HashSet<String>,
+    files: &Files,
+    warnings: &mut Vec<String>,
+) -> Result<Box<dyn Object>, String> {
+    let kind = string(required(value, "type", context)?, &format!("{}.type", context))?;
+    if kind == "group" {
+        return parse_group(value, context, palette, used, files, warnings);
+    }
+    if kind == "lod" {
+        return parse_lod(value, context, palette, used, files, warnings);
+    }
+    let field = |key: &str| vec3(required(value, key, context)?, &format!("{}.{}", context, key));
+    // Positions and sizes, converted to meters
+    let point = |key: &str| field(key).map(|v| files.units.vector(v));
+    let length = |key: &str| {
+        number(required(value, key, context)?, &format!("{}.{}", context, key)).map(|v| files.units.length(v))
+    };
+    
+    let definition = resolve_material(value.get("material"), &format!("{}.material", context), palette, used, files)?;
+    let material = definition.material;
+    
+    let common = ["type", "name", "layer", "material"];
+    let allow = |extra: &[&str]| check_keys(value, context, &[&common[..], extra].concat());
+    let object: Box<dyn Object> = match kind {
+        "sphere" => {
+            allow(&["center", "radius"])?;
+            Box::new(Sphere::new(point("center")?, length("radius")?, material))
+        }
+        "displaced_sphere" => {
+            allow(&["center", "radius", "amplitude", "frequency", "seed"])?;
+            let frequency = number(required(value, "frequency", context)?, &format!("{}.frequency", context))?;
+            let seed = match optional_number(value, "seed", context)? {
+                None => 0,
+                Some(seed) if seed >= 0.0 && seed.fract() == 0.0 && seed <= u32::MAX as f64 => seed as u64,
+                Some(seed) => return Err(format!("{}.seed: {} is not a whole number from 0 to {}", context, seed, u32::MAX)),
+            };
+            let sphere = DisplacedSphere::new(point("center")?, length("radius")?, length("amplitude")?, frequency, seed, material)
+                .map_err(|e| format!("{}: {}", context, e))?;
+            Box::new(sphere)
+        }
+        "ellipsoid" => {
+            allow(&["center", "radii"])?;
+            let radii = point("radii")?;
+            if radii.x <= 0.0 || radii.y <= 0.0 || radii.z <= 0.0 {
+                return Err(format!("{}.radii: all radii must be positive", context));
+            }
+            Box::new(Ellipsoid::new(point("center")?, radii, material))
+        }
+        "capsule" => {
+            allow(&["a", "b", "radius"])?;
+            Box::new(Capsule::new(point("a")?, point("b")?, length("radius")?, material))
+        }
+        "plane" => {
+            allow(&["point", "normal"])?;
+            let mut plane = Plane::new(point("point")?, field("normal")?, material);
+            if let Some((texture, scale, space)) = definition.texture.clone() {
+                plane = plane.with_texture(texture, scale).with_texture_space(space);
+            }
+            Box::new(plane)
+        }
+        "checker_plane" => {
+            // Two materials, one per tile color, instead of `material`
+            check_keys(value, context, &["type", "name", "layer", "point", "normal", "tile_size", "materials"])?;
+            let materials_context = format!("{}.materials", context);
+            let [first, second] = array(required(value, "materials", context)?, &materials_context)? else {
+                return Err(format!("{}: expected two materials", materials_context));
+            };
+            let first = resolve_material(Some(first), &format!("{}[0]", materials_context), palette, used, files)?;
+            let second = resolve_material(Some(second), &format!("{}[1]", materials_context), palette, used, files)?;
+            if first.texture.is_some() || second.texture.is_some() {
+                return Err(format!("{}: checker planes cannot have textures", context));
+            }
+            let tile_size = optional_number(value, "tile_size", context)?.unwrap_or(1.0);
+            if tile_size <= 0.0 {
+                return Err(format!("{}.tile_size: must be positive", context));
+            }
+            let tile_size = files.units.length(tile_size);
+            Box::new(CheckerPlane::new(point("point")?, field("normal")?, first.material, second.material, tile_size))
+        }
+        "cube" => {
+            allow(&["center", "size"])?;
+            Box::new(Cube::new(point("center")?, length("size")?, material))
+        }
+        "cylinder" => {
+            allow(&["center", "radius", "height"])?;
+            Box::new(Cylinder::new(point("center")?, length("radius")?, length("height")?, material))
+        }
+        "quad" => {
+            allow(&["corner", "edge_u", "edge_v"])?;
+            Box::new(Quad::new(point("corner")?, point("edge_u")?, point("edge_v")?, material))
+        }
+        "slab" => {
+            allow(&["center", "edge_u", "edge_v", "thickness"])?;
+            let mut slab = Slab::new(point("center")?, point("edge_u")?, point("edge_v")?, length("thickness")?, material)
+                .map_err(|e| format!("{}: {}", context, e))?;
+            if let Some((texture, scale, space)) = definition.texture.clone() {
+                slab = slab.with_texture(texture, scale).with_texture_space(space);
+            }
+            Box::new(slab)
+        }
+        "mesh" => {
+            // An OBJ file or a cache made from one by `rt bake`
+            allow(&["file", "scale", "up", "handedness"])?;
+            let file = files.base_dir.join(string(required(value, "file", context)?, &format!("{}.file", context))?);
+            let scale = optional_number(value, "scale", context)?.unwrap_or(1.0);
+            if scale <= 0.0 {
+                return Err(format!("{}.scale: must be positive", context));
+            }
+            let mut convention = files.meshes;
+            if let Some(up) = value.get("up") {
+                let context = format!("{}.up", context);
+                convention.up = Up::parse(string(up, &context)?).map_err(|e| format!("{}: {}", context, e))?;
+            }
+            if let Some(handedness) = value.get("handedness") {
+                let context = format!("{}.handedness", context);
+                convention.handedness = Handedness::parse(string(handedness, &context)?).map_err(|e| format!("{}: {}", context, e))?;
+            }
+            let (mut mesh, note) = if files.placeholder(&file, &format!("{}.file", context)) {
+                (Mesh::placeholder(&file.to_string_lossy(), material), None)
+            } else {
+                files.assets.mesh(&file, material).map_err(|e| format!("{}: {}", context, e))?
+            };
+            warnings.extend(note.map(|note| format!("{}: {}", context, note)));
+            if convention != ObjConvention::default() {
+                mesh = mesh.converted(convention);
+            }
+            let scale = files.units.length(scale);
+            if scale != 1.0 {
+                mesh = mesh.scaled(scale);
+            }
+            Box::new(mesh)
+        }
+        other => {
+            return Err(format!(
+                "{}: unknown object type '{}' (expected sphere, displaced_sphere, ellipsoid, capsule, plane, checker_plane, cube, cylinder, quad, slab, mesh, group or lod)",
+                context, other
+            ))
+        }
+    };
+    if definition.texture.is_some() && kind != "plane" && kind != "slab" {
+        return Err(format!("{}: textures are only supported on planes and slabs", context));
+    }
+    Ok(object)
+}
+
+fn parse_group(
+    value: &Value,
+    context: &str,
+    palette: &[(String, MaterialDefinition)],
+    used: &mut HashSet<String>,
+    files: &Files,
+    warnings: &mut Vec<String>,
+) -> Result<Box<dyn Object>, String> {
+    check_keys(value, context, &["type", "name", "layer", "transform", "children"])?;
+    let mut transform = match value.get("transform") {
+        Some(transform) => parse_transform(transform, &format!("{}.transform", context))?,
+        None => Mat4::identity(),
+    };
+    // The children are converted to meters themselves, so only the offset
+    // is
+    for row in &mut transform.rows[..3] {
+        row[3] = files.units.length(row[3]);
+    }
+    
+    let children_context = format!("{}.children", context);
+    let mut children = Vec::new();
+    for (i, child) in array(required(value, "children", context)?, &children_context)?.iter().enumerate() {
+        let child_context = format!("{}[{}]", children_context, i);
+        // Names pick out whole scene objects, for light links and material
+        // overrides, and layers hold whole scene objects too
+        if child.get("name").is_some() {
+            return Err(format!("{}: objects inside a group cannot be named", child_context));
+        }
+        if child.get("layer").is_some() {
+            return Err(format!("{}: objects inside a group take the group's layer", child_context));
+        }
+        children.push(build_object(child, &child_context, palette, used, files, warnings)?);
+    }
+    let group = Group::new(children, transform).map_err(|e| format!("{}: {}", context, e))?;
+    Ok(Box::new(group))
+}
+
+// `levels` of detail, finest first, and the camera's distance at which
+// each one after the first takes over
+fn parse_lod(
+    value: &Value,
+    context: &str,
+    palette: &[(String, MaterialDefinition)],
+    used: &mut HashSet<String>,
+    files: &Files,
+    warnings: &mut Vec<String>,
+) -> Result<Box<dyn Object>, String> {
+    check_keys(value, context, &["type", "name", "layer", "levels", "distances"])?;
+    let levels_context = format!("{}.levels", context);
+    let mut levels = Vec::new();
+    for (i, level) in array(required(value, "levels", context)?, &levels_context)?.iter().enumerate() {
+        let level_context = format!("{}[{}]", levels_context, i);
+        if level.get("name").is_some() {
+            return Err(format!("{}: levels of a lod cannot be named", level_context));
+        }
+        if level.get("layer").is_some() {
+            return Err(format!("{}: levels of a lod take the lod's layer", level_context));
+        }
+        levels.push(build_object(level, &level_context, palette, used, files, warnings)?);
+    }
+    let distances_context = format!("{}.distances", context);
+    let distances = match value.get("distances") {
+        Some(distances) => array(distances, &distances_context)?.iter().enumerate()
+            .map(|(i, distance)| number(distance, &format!("{}[{}]", distances_context, i)).map(|v| files.units.length(v)))
+            .collect::<Result<Vec<f64>, String>>()?,
+        None => Vec::new(),
+    };
+    let lod = Lod::new(levels, distances).map_err(|e| format!("{}: {}", context, e))?;
+    Ok(Box::new(lod))
+}
+
+// Either a `matrix` of three rows of four numbers, or any of `scale` (a
+// number or [x, y, z]), `rotate` (degrees about x, then y, then z) and
+// `translate`, applied in that order
+fn parse_transform(value: &Value, context: &str) -> Result<Mat4, String> {
+    check_keys(value, context, &["matrix", "scale", "rotate", "translate"])?;
+    if let Some(matrix) = value.get("matrix") {
+        if ["scale", "rotate", "translate"].iter().any(|key| value.get(key).is_some()) {
+            return Err(format!("{}: 'matrix' cannot be combined with scale, rotate or translate", context));
+        }
+        let matrix_context = format!("{}.matrix", context);
+        let rows = array(matrix, &matrix_context)?;
+        if rows.len() != 3 {
+            return Err(format!("{}: expected three rows of four numbers", matrix_context));
+        }
+        let mut result = Mat4::identity();
+        for (i, row) in rows.iter().enumerate() {
+            let row_context = format!("{}[{}]", matrix_context, i);
+            let values = array(row, &row_context)?;
+            if values.len() != 4 {
+                return Err(format!("{}: expected four numbers", row_context));
+            }
+            for (j, v) in values.iter().enumerate() {
+                result.rows[i][j] = number(v, &row_context)?;
+            }
+        }
+        return Ok(result);
+    }
+    
+    let scale = match value.get("scale") {
+        Some(scale @ Value::Array(_)) => vec3(scale, &format!("{}.scale", context))?,
+        Some(scale) => Vec3::splat(number(scale, &format!("{}.scale", context))?),
+        None => Vec3::one(),
+    };
+    let rotate = match value.get("rotate") {
+        Some(rotate) => vec3(rotate, &format!("{}.rotate", context))?,
+        None => Vec3::zero(),
+    };
+    let translate = match value.get("translate") {
+        Some(translate) => vec3(translate, &format!("{}.translate", context))?,
+        None => Vec3::zero(),
+    };
+    Ok(Mat4::translation(translate)
+        * Mat4::rotation_z(rotate.z)
+        * Mat4::rotation_y(rotate.y)
+        * Mat4::rotation_x(rotate.x)
+        * Mat4::scaling(scale))
+}
+
+// Writes `scene` back out as a scene file. Identical materials are shared
+// through the `materials` palette. Returns the file text and a warning for
+// anything that could not be written.
+pub fn export_scene(scene: &Scene) -> (String, Vec<String>) {
+    let mut warnings = Vec::new();
+    let mut root = Vec::new();
+    
+    if let Some(camera) = &scene.camera {
+        let mut fields = vec![
+            ("position".to_string(), vec3_value(camera.position)),
+            ("look_at".to_string(), vec3_value(camera.look_at)),
+            ("up".to_string(), vec3_value(camera.up)),
+            ("fov".to_string(), Value::Number(camera.fov)),
+        ];
+        if camera.near != DEFAULT_NEAR {
+            fields.push(("near".to_string(), Value::Number(camera.near)));
+        }
+        if camera.far.is_finite() {
+            fields.push(("far".to_string(), Value::Number(camera.far)));
+        }
+        if let Some(shake) = scene.shake {
+            fields.push(("shake".to_string(), Value::Object(vec![
+                ("amplitude".to_string(), Value::Number(shake.amplitude)),
+                ("frequency".to_string(), Value::Number(shake.frequency)),
+            ])));
+        }
+        if !scene.framings.is_empty() {
+            let framings = scene.framings.iter().map(|framing| {
+                (format!("{}x{}", framing.aspect.0, framing.aspect.1), Value::Object(vec![
+                    ("position".to_string(), vec3_value(framing.position)),
+                    ("look_at".to_string(), vec3_value(framing.look_at)),
+                    ("up".to_string(), vec3_value(framing.up)),
+                    ("fov".to_string(), Value::Number(framing.fov)),
+                ]))
+            });
+            fields.push(("framings".to_string(), Value::Object(framings.collect())));
+        }
+        root.push(("camera".to_string(), Value::Object(fields)));
+    }
+    
+    let background = match scene.background {
+        Background::Solid(color) => color_value(color),
+        Background::Gradient { bottom, top } => Value::Object(vec![
+            ("bottom".to_string(), color_value(bottom)),
+            ("top".to_string(), color_value(top)),
+        ]),
+        Background::Sky(sky) => Value::Object(vec![("sky".to_string(), Value::Object(vec![
+            ("sun_elevation".to_string(), Value::Number(sky.sun_elevation)),
+            ("sun_azimuth".to_string(), Value::Number(sky.sun_azimuth)),
+            ("turbidity".to_string(), Value::Number(sky.turbidity)),
+        ]))]),
+    };
+    root.push(("background".to_string(), background));
+    
+    let mut palette: Vec<Material> = Vec::new();
+    let mut objects = Vec::new();
+    for (index, object) in scene.objects().iter().enumerate() {
+        let name = scene.object_name(index);
+        let Some(mut entries) = object_entries(object.as_ref(), name, &mut palette, &mut warnings) else {
+            warnings.push(format!("object '{}' ({}) cannot be exported and was skipped", name, object.kind()));
+            continue;
+        };
+        entries.insert(1, ("name".to_string(), Value::String(name.to_string())));
+        if let Some(layer) = scene.object_layer(index) {
+            entries.insert(2, ("layer".to_string(), Value::String(layer.to_string())));
+        }
+        objects.push(Value::Object(entries));
+    }
+    
+    let materials = palette.iter().enumerate()
+        .map(|(i, material)| (format!("material{}", i + 1), material_value(material)))
+        .collect();
+    root.push(("materials".to_string(), Value::Object(materials)));
+    
+    // A sky brings its sun light back when it is read in
+    let sun = match scene.background {
+        Background::Sky(sky) => Some(sky.sun_light()),
+        _ => None,
+    };
+    let lights = scene.lights.iter().filter(|&light| Some(light) != sun.as_ref()).map(|light| {
+        let mut entries = match light.shape {
+            LightShape::Point => {
+                let mut entries = vec![("position".to_string(), vec3_value(light.position))];
+                let profile = match &light.profile {
+                    LightProfile::Uniform => None,
+                    LightProfile::Cosine => Some(Value::String("cosine".to_string())),
+                    LightProfile::Beam => Some(Value::String("beam".to_string())),
+                    LightProfile::Table { path, .. } => Some(Value::Object(vec![
+                        ("file".to_string(), Value::String(path.clone())),
+                    ])),
+                };
+                if let Some(profile) = profile {
+                    entries.push(("direction".to_string(), vec3_value(light.direction)));
+                    entries.push(("profile".to_string(), profile));
+                }
+                entries
+            }
+            LightShape::Directional { angular_radius } => vec![
+                ("type".to_string(), Value::String("directional".to_string())),
+                ("direction".to_string(), vec3_value(light.direction)),
+                ("angular_diameter".to_string(), Value::Number((angular_radius * 2.0).to_degrees())),
+            ],
+            LightShape::Quad { edge_u, edge_v } => vec![
+                ("type".to_string(), Value::String("quad".to_string())),
+                ("corner".to_string(), vec3_value(light.position)),
+                ("edge_u".to_string(), vec3_value(edge_u)),
+                ("edge_v".to_string(), vec3_value(edge_v)),
+                // The emitter panel, if any, is exported with the objects
+                ("emitter".to_string(), Value::Bool(false)),
+            ],
+        };
+        entries.push(("color".to_string(), color_value(light.color)));
+        entries.push(("intensity".to_string(), Value::Number(light.intensity)));
+        let falloff = match light.falloff {
+            _ if light.falloff == Attenuation::default() => None,
+            // Directional lights never dim and take no attenuation
+            _ if matches!(light.shape, LightShape::Directional { .. }) => None,
+            Attenuation::None => Some(Value::String("none".to_string())),
+            Attenuation::Linear => Some(Value::String("linear".to_string())),
+            Attenuation::Quadratic => Some(Value::String("quadratic".to_string())),
+            Attenuation::Custom { a, b, c } => Some(Value::Object(vec![
+                ("a".to_string(), Value::Number(a)),
+                ("b".to_string(), Value::Number(b)),
+                ("c".to_string(), Value::Number(c)),
+            ])),
+            Attenuation::Soft { radius } => Some(Value::Object(vec![("radius".to_string(), Value::Number(radius))])),
+        };
+        if let Some(falloff) = falloff {
+            entries.push(("attenuation".to_string(), falloff));
+        }
+        if let Some((start, end)) = light.falloff_window {
+            entries.push(("falloff_start".to_string(), Value::Number(start)));
+            entries.push(("falloff_end".to_string(), Value::Number(end)));
+        }
+        let names_value = |names: &[String]| Value::Array(names.iter().map(|name| Value::String(name.clone())).collect());
+        match &light.linking {
+            LightLinking::All => {}
+            LightLinking::Only(names) => entries.push(("affects".to_string(), names_value(names))),
+            LightLinking::Except(names) => entries.push(("excludes".to_string(), names_value(names))),
+        }
+        Value::Object(entries)
+    }).collect();
+    root.push(("lights".to_string(), Value::Array(lights)));
+    root.push(("objects".to_string(), Value::Array(objects)));
+    
+    if !scene.clip_planes.is_empty() {
+        let clip = scene.clip_planes.iter().map(|clip_plane| {
+            let mut entries = vec![
+                ("point".to_string(), vec3_value(clip_plane.point)),
+                ("normal".to_string(), vec3_value(clip_plane.normal)),
+            ];
+            if let Some(section) = &clip_plane.section {
+                entries.push(("section".to_string(), material_value(&section.material)));
+            }
+            Value::Object(entries)
+        }).collect();
+        root.push(("clip".to_string(), Value::Array(clip)));
+    }
+    
+    if !scene.portals.is_empty() {
+        let portals = scene.portals.iter().map(|portal| Value::Object(vec![
+            ("corner".to_string(), vec3_value(portal.corner)),
+            ("edge_u".to_string(), vec3_value(portal.edge_u)),
+            ("edge_v".to_string(), vec3_value(portal.edge_v)),
+        ])).collect();
+        root.push(("portals".to_string(), Value::Array(portals)));
+    }
+    
+    (Value::Object(root).to_pretty_string(), warnings)
+}
+
+// Everything but the name of an object scene files know about, with its
+// materials added to `palette`. `name` is the scene object it belongs to,
+// for warnings.
+fn object_entries(
+    object: &dyn Object,
+    name: &str,
+    palette: &mut Vec<Material>,
+    warnings: &mut Vec<String>,
+) -> Option<Vec<(String, Value)>> {
+    if let Some(group) = object.as_any().downcast_ref::<Group>() {
+        let children = group.children().iter()
+            .filter_map(|child| {
+                let entries = object_entries(child.as_ref(), name, palette, warnings);
+                if entries.is_none() {
+                    warnings.push(format!("a {} inside '{}' cannot be exported and was skipped", child.kind(), name));
+                }
+                entries.map(Value::Object)
+            })
+            .collect();
+        let rows = group.transform().rows[..3].iter()
+            .map(|row| Value::Array(row.iter().map(|&v| Value::Number(v)).collect()))
+            .collect();
+        return Some(vec![
+            ("type".to_string(), "group".into()),
+            ("transform".to_string(), Value::Object(vec![("matrix".to_string(), Value::Array(rows))])),
+            ("children".to_string(), Value::Array(children)),
+        ]);
+    }
+    if let Some(lod) = object.as_any().downcast_ref::<Lod>() {
+        // Levels cannot be skipped without shifting the distances, so a lod
+        // exports whole or not at all
+        let levels = lod.levels().iter()
+            .map(|level| object_entries(level.as_ref(), name, palette, warnings).map(Value::Object))
+            .collect::<Option<Vec<Value>>>()?;
+        let distances = lod.distances().iter().map(|&distance| Value::Number(distance)).collect();
+        return Some(vec![
+            ("type".to_string(), "lod".into()),
+            ("levels".to_string(), Value::Array(levels)),
+            ("distances".to_string(), Value::Array(distances)),
+        ]);
+    }
+    
+    let mut entries = object_fields(object)?;
+    if let Some(plane) = object.as_any().downcast_ref::<Plane>() {
+        if plane.texture.is_some() {
+            warnings.push(format!("the texture on '{}' cannot be exported and was dropped", name));
+        }
+    }
+    if let Some(slab) = object.as_any().downcast_ref::<Slab>() {
+        if slab.texture.is_some() {
+            warnings.push(format!("the texture on '{}' cannot be exported and was dropped", name));
+        }
+    }
+    if let Some(quad) = object.as_any().downcast_ref::<Quad>() {
+        if quad.emission_texture.is_some() {
+            warnings.push(format!("the emission texture on '{}' cannot be exported and was dropped", name));
+        }
+    }
+    
+    let mut palette_name = |material: Material| {
+        let index = palette.iter().position(|m| *m == material).unwrap_or_else(|| {
+            palette.push(material);
+            palette.len() - 1
+        });
+        Value::String(format!("material{}", index + 1))
+    };
+    if let Some(checker) = object.as_any().downcast_ref::<CheckerPlane>() {
+        let materials = checker.materials.map(&mut palette_name).to_vec();
+        entries.push(("materials".to_string(), Value::Array(materials)));
+    } else {
+        entries.push(("material".to_string(), palette_name(*object.material())));
+    }
+    Some(entries)
+}
+
+// Type and shape fields of the simple objects scene files know about
+fn object_fields(object: &dyn Object) -> Option<Vec<(String, Value)>> {
+    let any = object.as_any();
+    let fields: Vec<(&str, Value)> = if let Some(sphere) = any.downcast_ref::<Sphere>() {
+        vec![("type", "sphere".into()), ("center", vec3_value(sphere.center)), ("radius", sphere.radius.into())]
+    } else if let Some(sphere) = any.downcast_ref::<DisplacedSphere>() {
+        vec![
+            ("type", "displaced_sphere".into()),
+            ("center", vec3_value(sphere.center)),
+            ("radius", sphere.radius.into()),
+            ("amplitude", sphere.amplitude.into()),
+            ("frequency", sphere.frequency.into()),
+            ("seed", (sphere.seed as f64).into()),
+        ]
+    } else if let Some(ellipsoid) = any.downcast_ref::<Ellipsoid>() {
+        vec![("type", "ellipsoid".into()), ("center", vec3_value(ellipsoid.center)), ("radii", vec3_value(ellipsoid.radii))]
+    } else if let Some(capsule) = any.downcast_ref::<Capsule>() {
+        vec![
+            ("type", "capsule".into()),
+            ("a", vec3_value(capsule.a)),
+            ("b", vec3_value(capsule.b)),
+            ("radius", capsule.radius.into()),
+        ]
+    } else if let Some(plane) = any.downcast_ref::<Plane>() {
+        vec![("type", "plane".into()), ("point", vec3_value(plane.point)), ("normal", vec3_value(plane.normal))]
+    } else if let Some(checker) = any.downcast_ref::<CheckerPlane>() {
+        vec![
+            ("type", "checker_plane".into()),
+            ("point", vec3_value(checker.point)),
+            ("normal", vec3_value(checker.normal)),
+            ("tile_size", checker.tile_size.into()),
+        ]
+    } else if let Some(cube) = any.downcast_ref::<Cube>() {
+        vec![("type", "cube".into()), ("center", vec3_value(cube.center)), ("size", cube.size.into())]
+    } else if let Some(cylinder) = any.downcast_ref::<Cylinder>() {
+        vec![
+            ("type", "cylinder".into()),
+            ("center", vec3_value(cylinder.center)),
+            ("radius", cylinder.radius.into()),
+            ("height", cylinder.height.into()),
+        ]
+    } else if let Some(quad) = any.downcast_ref::<Quad>() {
+        vec![
+            ("type", "quad".into()),
+            ("corner", vec3_value(quad.corner)),
+            ("edge_u", vec3_value(quad.edge_u)),
+            ("edge_v", vec3_value(quad.edge_v)),
+        ]
+    } else if let Some(slab) = any.downcast_ref::<Slab>() {
+        vec![
+            ("type", "slab".into()),
+            ("center", vec3_value(slab.center)),
+            ("edge_u", vec3_value(slab.edge_u)),
+            ("edge_v", vec3_value(slab.edge_v)),
+            ("thickness", slab.thickness.into()),
+        ]
+    } else if let Some(mesh) = any.downcast_ref::<Mesh>() {
+        let mut fields = vec![("type", "mesh".into()), ("file", mesh.path.as_str().into())];
+        if mesh.scale != 1.0 {
+            fields.push(("scale", mesh.scale.into()));
+        }
+        // Written even when they are the defaults the scene was loaded
+        // with, which may not be the defaults it is read back with
+        fields.push(("up", mesh.convention.up.name().into()));
+        fields.push(("handedness", mesh.convention.handedness.name().into()));
+        fields
+    } else {
+        return None;
+    };
+    Some(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn material_value(material: &Material) -> Value {
+    Value::Object(vec![
+        ("color".to_string(), color_value(material.color)),
+        ("ambient".to_string(), material.ambient.into()),
+        ("diffuse".to_string(), material.diffuse.into()),
+        ("specular".to_string(), channels_value(material.specular)),
+        ("shininess".to_string(), material.shininess.into()),
+        ("reflectivity".to_string(), channels_value(material.reflectivity)),
+        ("transparency".to_string(), material.transparency.into()),
+        ("refractive_index".to_string(), material.refractive_index.into()),
+        ("emission".to_string(), color_value(material.emission)),
+        ("scatter_distance".to_string(), material.scatter_distance.into()),
+        ("scatter_tint".to_string(), channels_value(material.scatter_tint)),
+        ("coat".to_string(), material.coat.into()),
+        ("coat_ior".to_string(), material.coat_ior.into()),
+        ("sheen".to_string(), material.sheen.into()),
+        ("sheen_color".to_string(), channels_value(material.sheen_color)),
+        ("film_thickness".to_string(), material.film_thickness.into()),
+        ("film_gradient".to_string(), material.film_gradient.into()),
+        ("film_ior".to_string(), material.film_ior.into()),
+    ])
+}
+
+// A per-channel material field, written as one number when the channels
+// agree
+fn channels_value(c: Color) -> Value {
+    if c.r == c.g && c.g == c.b {
+        Value::Number(c.r)
+    } else {
+        color_value(c)
+    }
+}
+
+fn vec3_value(v: Vec3) -> Value {
+    Value::Array(vec![Value::Number(v.x), Value::Number(v.y), Value::Number(v.z)])
+}
+
+fn color_value(c: Color) -> Value {
+    Value::Array(vec![Value::Number(c.r), Value::Number(c.g), Value::Number(c.b)])
+}
+
+fn parse_resolution(value: &Value) -> Result<(u32, u32), String> {
+    let side = |value: &Value| match value.as_f64() {
+        Some(pixels) if pixels >= 1.0 && pixels.fract() == 0.0 && pixels <= u32::MAX as f64 => Ok(pixels as u32),
+        Some(pixels) => Err(format!("resolution: {} is not a positive whole number of pixels", pixels)),
+        None => Err(expected("resolution", "a number", value)),
+    };
+    match value {
+        Value::String(text) => resolution::parse(text).map_err(|e| format!("resolution: {}", e)),
+        Value::Array(sides) if sides.len() == 2 => Ok((side(&sides[0])?, side(&sides[1])?)),
+        _ => Err(expected("resolution", "a name such as \"1080p\" or a [width, height] array", value)),
+    }
+}
+
+fn expected(context: &str, what: &str, found: &Value) -> String {
+    format!("{}: expected {} but found {}", context, what, found.type_name())
+}
+
+// Rejects keys outside `allowed`, which are almost always typos
+fn check_keys(value: &Value, context: &str, allowed: &[&str]) -> Result<(), String> {
+    let entries = value.as_object().ok_or_else(|| expected(context, "an object", value))?;
+    for (key, _) in entries {
+        if !allowed.contains(&key.as_str()) {
+            return Err(format!("{}: unknown key '{}' (expected one of: {})", context, key, allowed.join(", ")));
+        }
+    }
+    Ok(())
+}
+
+fn required<'v>(value: &'v Value, key: &str, context: &str) -> Result<&'v Value, String> {
+    value.get(key).ok_or_else(|| format!("{}: missing '{}'", context, key))
+}
+
+fn array<'v>(value: &'v Value, context: &str) -> Result<&'v [Value], String> {
+    value.as_array().ok_or_else(|| expected(context, "an array", value))
+}
+
+fn string<'v>(value: &'v Value, context: &str) -> Result<&'v str, String> {
+    value.as_str().ok_or_else(|| expected(context, "a string", value))
+}
+
+fn number(value: &Value, context: &str) -> Result<f64, String> {
+    value.as_f64().ok_or_else(|| expected(context, "a number", value))
+}
+
+fn optional_number(value: &Value, key: &str, context: &str) -> Result<Option<f64>, String> {
+    value.get(key).map(|v| number(v, &format!("{}.{}", context, key))).transpose()
+}
+
+fn vec3(value: &Value, context: &str) -> Result<Vec3, String> {
+    match value.as_array() {
+        Some([x, y, z]) => Ok(Vec3::new(number(x, context)?, number(y, context)?, number(z, context)?)),
+        _ => Err(expected(context, "an [x, y, z] array", value)),
+    }
+}
+
+// An [r, g, b] array of linear values, or an sRGB hex color like "#cc3344"
+// or a color name
+fn rgb(value: &Value, context: &str) -> Result<Color, String> {
+    match value {
+        Value::String(text) => Color::parse(text).map_err(|e| format!("{}: {}", context, e)),
+        Value::Array(channels) => match channels.as_slice() {
+            [r, g, b] => Ok(Color::new(number(r, context)?, number(g, context)?, number(b, context)?)),
+            _ => Err(expected(context, "an [r, g, b] array", value)),
+        },
+        _ => Err(expected(context, "an [r, g, b] array or a color string", value)),
+    }
+}
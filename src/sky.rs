@@ -0,0 +1,161 @@
+use crate::vector::Vec3;
+use crate::image::color::Color;
+use crate::light::Light;
+
+// Optical depth of the air straight up at the red, green and blue
+// wavelengths. Molecules scatter blue far more than red (Rayleigh's inverse
+// fourth power), while haze scatters every color alike and grows with the
+// turbidity, 1 being perfectly clean air.
+const RAYLEIGH_DEPTH: Color = Color { r: 0.05, g: 0.11, b: 0.24 };
+const HAZE_DEPTH_PER_TURBIDITY: f64 = 0.02;
+
+// Ozone scatters nothing but absorbs orange and green light, which keeps
+// the sky overhead blue at twilight, when sunlight crosses a lot of it
+const OZONE_DEPTH: Color = Color { r: 0.028, g: 0.023, b: 0.0024 };
+
+// How strongly haze scatters light forward, making the glow around the sun
+const HAZE_ASYMMETRY: f64 = 0.7;
+
+// Brightness of the sky relative to the sun light, and of the sun's disk
+const SKY_BRIGHTNESS: f64 = 1.0;
+const SUN_DISK_BRIGHTNESS: f64 = 50.0;
+
+// Light scattered more than once brightens the sky away from the horizon,
+// counted roughly by thickening the air each view looks through
+const MULTIPLE_SCATTERING: f64 = 3.0;
+
+// Angular radius of the sun's disk in radians
+const SUN_RADIUS: f64 = 0.0047;
+
+// Below the horizon the sky is replaced by ground reflecting this much of
+// the horizon's light
+const GROUND_ALBEDO: f64 = 0.3;
+
+// An analytic daylight sky for a sun at a given elevation and azimuth:
+// sunlight scattered once by the air along each view direction, blue
+// overhead, paler towards the horizon and glowing around the sun. The
+// lower the sun, the longer its light's path through the air and the more
+// of its blue is lost, so sunsets turn the sun and the horizon red.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyModel {
+    // Degrees above the horizon, and clockwise from -z (straight ahead of
+    // an unturned camera) towards +x
+    pub sun_elevation: f64,
+    pub sun_azimuth: f64,
+    pub turbidity: f64,
+    sun: Vec3,
+    // Optical depth of the haze, and of everything together
+    haze: f64,
+    depth: Color,
+}
+
+impl SkyModel {
+    /// Elevation is 0 to 90 degrees and turbidity at least 1.
+    ///
+    /// ```
+    /// use rt::image::color::Color;
+    /// use rt::light::LightShape;
+    /// use rt::sky::SkyModel;
+    /// use rt::vector::Vec3;
+    ///
+    /// let noon = SkyModel::new(60.0, 120.0, 3.0).unwrap();
+    /// let sunset = SkyModel::new(3.0, 120.0, 3.0).unwrap();
+    ///
+    /// // Blue overhead at midday
+    /// let zenith = noon.radiance(Vec3::UP);
+    /// assert!(zenith.b > zenith.g && zenith.g > zenith.r);
+    ///
+    /// // At sunset the sun and the horizon below it turn red
+    /// let toward_sun = |sky: &SkyModel| {
+    ///     let sun = sky.sun_direction();
+    ///     Vec3::new(sun.x, 0.02, sun.z).normalize()
+    /// };
+    /// let redness = |color: Color| color.r / color.b;
+    /// assert!(redness(sunset.radiance(toward_sun(&sunset))) > 2.0 * redness(noon.radiance(toward_sun(&noon))));
+    /// assert!(redness(sunset.sun_light().color) > 2.0 * redness(noon.sun_light().color));
+    ///
+    /// // The sun light shines from where the sun is drawn, and azimuth 90
+    /// // puts it to the right
+    /// let light = noon.sun_light();
+    /// assert!((light.direction + noon.sun_direction()).length() < 1e-12);
+    /// assert!(matches!(light.shape, LightShape::Directional { angular_radius } if (angular_radius - 0.0047).abs() < 1e-12));
+    /// assert!(noon.radiance(noon.sun_direction()).r > 10.0 * zenith.r);
+    /// let east = SkyModel::new(0.0, 90.0, 3.0).unwrap().sun_direction();
+    /// assert!((east - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+    ///
+    /// assert!(SkyModel::new(-5.0, 0.0, 3.0).is_err());
+    /// assert!(SkyModel::new(30.0, 0.0, 0.5).is_err());
+    /// ```
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64) -> Result<Self, String> {
+        if !(0.0..=90.0).contains(&sun_elevation) {
+            return Err(format!("sun elevation must be between 0 and 90 degrees (got {})", sun_elevation));
+        }
+        if !(turbidity >= 1.0 && turbidity.is_finite()) {
+            return Err(format!("turbidity must be at least 1 (got {})", turbidity));
+        }
+        let (elevation, azimuth) = (sun_elevation.to_radians(), sun_azimuth.to_radians());
+        let sun = Vec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos());
+        let haze = HAZE_DEPTH_PER_TURBIDITY * (turbidity - 1.0);
+        let depth = RAYLEIGH_DEPTH + OZONE_DEPTH + Color::splat(haze);
+        Ok(SkyModel { sun_elevation, sun_azimuth, turbidity, sun, haze, depth })
+    }
+    
+    // Unit vector towards the sun
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun
+    }
+    
+    // Share of each color that gets through `layer` of the air (1 for all
+    // of it) along a direction `up` (its y) above the horizon
+    fn transmittance(&self, up: f64, layer: f64) -> Color {
+        let mass = air_mass(up) * layer;
+        Color::new((-self.depth.r * mass).exp(), (-self.depth.g * mass).exp(), (-self.depth.b * mass).exp())
+    }
+    
+    // What the sky gives off towards the viewer along `direction`
+    pub fn radiance(&self, direction: Vec3) -> Color {
+        let direction = direction.normalize();
+        if direction.y < 0.0 {
+            return self.radiance(Vec3::new(direction.x, 0.0, direction.z)) * GROUND_ALBEDO;
+        }
+        
+        // Looking up, most of the light is scattered high in the air and
+        // reaches it through only part of the air the sun shines through;
+        // looking at the horizon it is scattered near the ground
+        let sunlight = self.transmittance(self.sun.y, 1.0 - 0.5 * direction.y);
+        
+        // Light scattered towards the viewer along the whole view path,
+        // split between molecules and haze by their share of the depth;
+        // the ozone's share is absorbed
+        let cosine = direction.dot(&self.sun);
+        let rayleigh_phase = 0.75 * (1.0 + cosine * cosine);
+        let g = HAZE_ASYMMETRY;
+        let haze_phase = (1.0 - g * g) / (1.0 + g * g - 2.0 * g * cosine).powf(1.5);
+        let share = |rayleigh: f64, depth: f64| (rayleigh * rayleigh_phase + self.haze * haze_phase) / depth;
+        let scattered = Color::new(
+            share(RAYLEIGH_DEPTH.r, self.depth.r),
+            share(RAYLEIGH_DEPTH.g, self.depth.g),
+            share(RAYLEIGH_DEPTH.b, self.depth.b),
+        );
+        let mut color = sunlight * scattered * (Color::WHITE - self.transmittance(direction.y, MULTIPLE_SCATTERING)) * SKY_BRIGHTNESS;
+        
+        if cosine > SUN_RADIUS.cos() {
+            color = color + sunlight * SUN_DISK_BRIGHTNESS;
+        }
+        color
+    }
+    
+    // A directional light standing in for the sun, as wide as its disk so
+    // shadows soften with distance, and colored by the air its light
+    // crosses so shading matches the sky
+    pub fn sun_light(&self) -> Light {
+        Light::directional(-self.sun, (2.0 * SUN_RADIUS).to_degrees(), self.transmittance(self.sun.y, 1.0), 1.0)
+    }
+}
+
+// Thickness of air crossed looking `up` (the sine of the elevation),
+// relative to looking straight up (Kasten and Young's formula)
+fn air_mass(up: f64) -> f64 {
+    let elevation = up.clamp(0.0, 1.0).asin().to_degrees();
+    1.0 / ((90.0 - elevation).to_radians().cos() + 0.50572 * (elevation + 6.07995).powf(-1.6364))
+}
@@ -0,0 +1,308 @@
+use crate::vector::Vec3;
+use crate::image::color::Color;
+use crate::scene::Scene;
+use crate::objects::Object;
+use crate::material::Material;
+use crate::light::LightShape;
+use crate::bvh::{Aabb, Bvh};
+use crate::group::Group;
+use crate::lod::Lod;
+use crate::mesh::Mesh;
+use crate::json::Value;
+use crate::memory::{format_size, MemoryEstimate};
+use std::fmt;
+
+// What a scene holds, for `rt describe`: printed with `Display`, or as JSON
+// through `to_json`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneSummary {
+    // Objects added to the scene, not counting what is inside groups
+    pub objects: usize,
+    // Objects of each kind in the order they first appear, counting groups
+    // and everything inside them
+    pub kinds: Vec<(&'static str, usize)>,
+    pub triangles: usize,
+    pub materials: Vec<MaterialUse>,
+    pub lights: Vec<LightSummary>,
+    pub camera: Option<CameraSummary>,
+    // Box around every bounded object; planes have no bounds and are only
+    // counted
+    pub bounds: Option<Aabb>,
+    pub unbounded_objects: usize,
+    pub bvh_depth: usize,
+    // Estimated bytes for the objects; see `MemoryEstimate`
+    pub memory: usize,
+    pub clip_planes: usize,
+    pub portals: usize,
+}
+
+// A distinct material and the objects that use it; parts of a group are
+// listed under the group's name
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialUse {
+    pub material: Material,
+    pub objects: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightSummary {
+    // "point" or "quad"
+    pub kind: &'static str,
+    pub position: Vec3,
+    pub color: Color,
+    pub intensity: f64,
+    // Edge lengths of a quad light
+    pub size: Option<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraSummary {
+    pub position: Vec3,
+    pub look_at: Vec3,
+    pub fov: f64,
+    pub aspect_ratio: f64,
+    pub near: f64,
+    pub far: f64,
+}
+
+impl SceneSummary {
+    pub fn new(scene: &Scene) -> Self {
+        let mut summary = SceneSummary {
+            objects: scene.objects().len(),
+            kinds: Vec::new(),
+            triangles: 0,
+            materials: Vec::new(),
+            lights: Vec::new(),
+            camera: None,
+            bounds: None,
+            unbounded_objects: 0,
+            bvh_depth: 0,
+            memory: MemoryEstimate::new(scene).total,
+            clip_planes: scene.clip_planes.len(),
+            portals: scene.portals.len(),
+        };
+        for (index, object) in scene.objects().iter().enumerate() {
+            summary.add_object(object.as_ref(), scene.object_name(index));
+            match object.bounding_box() {
+                Some(bounds) => summary.bounds = Some(summary.bounds.map_or(bounds, |total| total.union(&bounds))),
+                None => summary.unbounded_objects += 1,
+            }
+        }
+        summary.bvh_depth = Bvh::build(scene.objects()).depth();
+        
+        summary.lights = scene.lights.iter().map(|light| {
+            let (kind, size) = match light.shape {
+                LightShape::Point => ("point", None),
+                LightShape::Directional { .. } => ("directional", None),
+                LightShape::Quad { edge_u, edge_v } => ("quad", Some((edge_u.length(), edge_v.length()))),
+            };
+            LightSummary { kind, position: light.position, color: light.color, intensity: light.intensity, size }
+        }).collect();
+        
+        summary.camera = scene.camera.as_ref().map(|camera| CameraSummary {
+            position: camera.position,
+            look_at: camera.look_at,
+            fov: camera.fov,
+            aspect_ratio: camera.aspect_ratio * camera.pixel_aspect,
+            near: camera.near,
+            far: camera.far,
+        });
+        summary
+    }
+    
+    // Counts the object and its parts, and notes their materials under `name`
+    fn add_object(&mut self, object: &dyn Object, name: &str) {
+        match self.kinds.iter_mut().find(|(kind, _)| *kind == object.kind()) {
+            Some((_, count)) => *count += 1,
+            None => self.kinds.push((object.kind(), 1)),
+        }
+        if let Some(group) = object.as_any().downcast_ref::<Group>() {
+            for child in group.children() {
+                self.add_object(child.as_ref(), name);
+            }
+            return;
+        }
+        if let Some(lod) = object.as_any().downcast_ref::<Lod>() {
+            for level in lod.levels() {
+                self.add_object(level.as_ref(), name);
+            }
+            return;
+        }
+        if let Some(mesh) = object.as_any().downcast_ref::<Mesh>() {
+            self.triangles += mesh.triangle_count();
+        }
+        
+        let material = *object.material();
+        match self.materials.iter_mut().find(|used| used.material == material) {
+            Some(used) if used.objects.last().map(String::as_str) == Some(name) => {}
+            Some(used) => used.objects.push(name.to_string()),
+            None => self.materials.push(MaterialUse { material, objects: vec![name.to_string()] }),
+        }
+    }
+    
+    pub fn to_json(&self) -> Value {
+        let vector = |v: Vec3| Value::Array(vec![v.x.into(), v.y.into(), v.z.into()]);
+        let rgb = |c: Color| Value::Array(vec![c.r.into(), c.g.into(), c.b.into()]);
+        let count = |n: usize| Value::from(n as f64);
+        let mut root = vec![
+            ("objects".to_string(), count(self.objects)),
+            ("kinds".to_string(), Value::Object(self.kinds.iter().map(|&(kind, n)| (kind.to_string(), count(n))).collect())),
+            ("triangles".to_string(), count(self.triangles)),
+        ];
+        
+        let materials = self.materials.iter().map(|used| {
+            let m = &used.material;
+            Value::Object(vec![
+                ("objects".to_string(), Value::Array(used.objects.iter().map(|name| name.as_str().into()).collect())),
+                ("color".to_string(), rgb(m.color)),
+                ("ambient".to_string(), m.ambient.into()),
+                ("diffuse".to_string(), m.diffuse.into()),
+                ("specular".to_string(), rgb(m.specular)),
+                ("shininess".to_string(), m.shininess.into()),
+                ("reflectivity".to_string(), rgb(m.reflectivity)),
+                ("transparency".to_string(), m.transparency.into()),
+                ("refractive_index".to_string(), m.refractive_index.into()),
+                ("emission".to_string(), rgb(m.emission)),
+                ("scatter_distance".to_string(), m.scatter_distance.into()),
+                ("coat".to_string(), m.coat.into()),
+                ("sheen".to_string(), m.sheen.into()),
+                ("film_thickness".to_string(), m.film_thickness.into()),
+            ])
+        }).collect();
+        root.push(("materials".to_string(), Value::Array(materials)));
+        
+        let lights = self.lights.iter().map(|light| {
+            let mut entries = vec![
+                ("type".to_string(), light.kind.into()),
+                ("position".to_string(), vector(light.position)),
+                ("color".to_string(), rgb(light.color)),
+                ("intensity".to_string(), light.intensity.into()),
+            ];
+            if let Some((width, height)) = light.size {
+                entries.push(("size".to_string(), Value::Array(vec![width.into(), height.into()])));
+            }
+            Value::Object(entries)
+        }).collect();
+        root.push(("lights".to_string(), Value::Array(lights)));
+        
+        if let Some(camera) = &self.camera {
+            root.push(("camera".to_string(), Value::Object(vec![
+                ("position".to_string(), vector(camera.position)),
+                ("look_at".to_string(), vector(camera.look_at)),
+                ("fov".to_string(), camera.fov.into()),
+                ("aspect_ratio".to_string(), camera.aspect_ratio.into()),
+                ("near".to_string(), camera.near.into()),
+                // An unlimited far plane comes out as null
+                ("far".to_string(), camera.far.into()),
+            ])));
+        }
+        
+        let bounds = match &self.bounds {
+            Some(bounds) => Value::Object(vec![
+                ("min".to_string(), vector(bounds.min)),
+                ("max".to_string(), vector(bounds.max)),
+                ("size".to_string(), vector(bounds.max - bounds.min)),
+            ]),
+            None => Value::Null,
+        };
+        root.push(("bounds".to_string(), bounds));
+        root.push(("unbounded_objects".to_string(), count(self.unbounded_objects)));
+        root.push(("bvh_depth".to_string(), count(self.bvh_depth)));
+        root.push(("memory_bytes".to_string(), count(self.memory)));
+        root.push(("clip_planes".to_string(), count(self.clip_planes)));
+        root.push(("portals".to_string(), count(self.portals)));
+        Value::Object(root)
+    }
+}
+
+impl fmt::Display for SceneSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kinds: Vec<String> = self.kinds.iter().map(|(kind, n)| format!("{}: {}", kind, n)).collect();
+        writeln!(f, "Objects: {} ({})", self.objects, kinds.join(", "))?;
+        if self.triangles > 0 {
+            writeln!(f, "Triangles: {}", self.triangles)?;
+        }
+        match &self.bounds {
+            Some(bounds) => {
+                let size = bounds.max - bounds.min;
+                write!(f, "Bounds: {:.2} to {:.2}, {:.2} x {:.2} x {:.2}", bounds.min, bounds.max, size.x, size.y, size.z)?;
+            }
+            None => write!(f, "Bounds: none")?,
+        }
+        match self.unbounded_objects {
+            0 => writeln!(f)?,
+            1 => writeln!(f, ", plus 1 unbounded object")?,
+            n => writeln!(f, ", plus {} unbounded objects", n)?,
+        }
+        writeln!(f, "BVH depth: {}", self.bvh_depth)?;
+        writeln!(f, "Memory: about {}", format_size(self.memory))?;
+        if self.clip_planes > 0 {
+            writeln!(f, "Clipping planes: {}", self.clip_planes)?;
+        }
+        if self.portals > 0 {
+            writeln!(f, "Portals: {}", self.portals)?;
+        }
+        
+        match &self.camera {
+            Some(camera) => {
+                write!(
+                    f,
+                    "Camera: at {} looking at {}, fov {}, aspect {:.3}, near {}",
+                    camera.position, camera.look_at, camera.fov, camera.aspect_ratio, camera.near,
+                )?;
+                if camera.far.is_finite() {
+                    write!(f, ", far {}", camera.far)?;
+                }
+                writeln!(f)?;
+            }
+            None => writeln!(f, "Camera: none")?,
+        }
+        
+        writeln!(f, "Lights: {}", self.lights.len())?;
+        for light in &self.lights {
+            write!(f, "  {} at {}, color {}, intensity {}", light.kind, light.position, light.color, light.intensity)?;
+            if let Some((width, height)) = light.size {
+                write!(f, ", {} x {}", width, height)?;
+            }
+            writeln!(f)?;
+        }
+        
+        writeln!(f, "Materials: {}", self.materials.len())?;
+        for used in &self.materials {
+            // Long lists, such as the cubes of a sponge, are cut short
+            let mut users = used.objects.iter().take(5).cloned().collect::<Vec<_>>().join(", ");
+            if used.objects.len() > 5 {
+                users += &format!(" and {} more", used.objects.len() - 5);
+            }
+            writeln!(f, "  {}: {}", users, material_properties(&used.material))?;
+        }
+        Ok(())
+    }
+}
+
+// The color and whatever sets the material apart from plain plastic
+fn material_properties(material: &Material) -> String {
+    let mut properties = vec![format!("color {}", material.color), format!("shininess {}", material.shininess)];
+    if material.reflectivity.max_component() > 0.0 {
+        properties.push(format!("reflectivity {}", material.reflectivity));
+    }
+    if material.transparency > 0.0 {
+        properties.push(format!("transparency {} (index {})", material.transparency, material.refractive_index));
+    }
+    if material.emission.max_component() > 0.0 {
+        properties.push(format!("emission {}", material.emission));
+    }
+    if material.scatter_distance > 0.0 {
+        properties.push(format!("subsurface {}", material.scatter_distance));
+    }
+    if material.coat > 0.0 {
+        properties.push(format!("clear coat {}", material.coat));
+    }
+    if material.sheen > 0.0 {
+        properties.push(format!("sheen {}", material.sheen));
+    }
+    if material.film_thickness > 0.0 {
+        properties.push(format!("thin film {} nm", material.film_thickness));
+    }
+    properties.join(", ")
+}
@@ -0,0 +1,416 @@
+use crate::image::{parse_ppm, read_ppm_size};
+use crate::image::color::{srgb_to_linear, Color};
+use crate::vector::Vec3;
+use std::fs;
+use std::mem;
+use std::sync::Arc;
+
+// How texels are looked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFilter {
+    Nearest,
+    Bilinear,
+    // Trilinear lookup in a pyramid of prefiltered, halved copies
+    Mipmap,
+}
+
+// Where texture coordinates are measured from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSpace {
+    // The object's own frame, so the pattern moves with the object
+    Object,
+    // The world origin, so the pattern stays put while the object moves
+    // through it
+    World,
+}
+
+// What happens to texture coordinates outside 0..1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureWrap {
+    Clamp,
+    Repeat,
+    // Repeats with every other copy flipped, so there are no seams
+    Mirror,
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: i64, y: i64, wrap: TextureWrap) -> Color {
+        let (x, y) = match wrap {
+            TextureWrap::Clamp => (
+                x.clamp(0, self.width as i64 - 1),
+                y.clamp(0, self.height as i64 - 1),
+            ),
+            TextureWrap::Repeat => (
+                x.rem_euclid(self.width as i64),
+                y.rem_euclid(self.height as i64),
+            ),
+            TextureWrap::Mirror => {
+                let mirror = |i: i64, size: i64| {
+                    let i = i.rem_euclid(2 * size);
+                    if i < size { i } else { 2 * size - 1 - i }
+                };
+                (mirror(x, self.width as i64), mirror(y, self.height as i64))
+            }
+        };
+        self.texels[y as usize * self.width + x as usize]
+    }
+    
+    // Each texel of the next level averages the block of texels it covers
+    // here. Odd sizes are rounded down, so some blocks are 3 texels wide.
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+        
+        for y in 0..height {
+            let y0 = y * self.height / height;
+            let y1 = ((y + 1) * self.height / height).max(y0 + 1);
+            for x in 0..width {
+                let x0 = x * self.width / width;
+                let x1 = ((x + 1) * self.width / width).max(x0 + 1);
+                
+                let mut sum = Color::BLACK;
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        sum = sum + self.texels[sy * self.width + sx];
+                    }
+                }
+                texels.push(sum / ((x1 - x0) * (y1 - y0)) as f64);
+            }
+        }
+        
+        MipLevel { width, height, texels }
+    }
+    
+    fn nearest(&self, u: f64, v: f64, wrap: TextureWrap) -> Color {
+        let x = (u * self.width as f64).floor() as i64;
+        let y = ((1.0 - v) * self.height as f64).floor() as i64;
+        self.texel(x, y, wrap)
+    }
+    
+    fn bilinear(&self, u: f64, v: f64, wrap: TextureWrap) -> Color {
+        // Texel centers sit at half-integer positions
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        
+        let top = self.texel(x0, y0, wrap).lerp(&self.texel(x0 + 1, y0, wrap), fx);
+        let bottom = self.texel(x0, y0 + 1, wrap).lerp(&self.texel(x0 + 1, y0 + 1, wrap), fx);
+        top.lerp(&bottom, fy)
+    }
+}
+
+// An RGB image mapped onto a surface by (u, v) coordinates, with v = 0 at
+// the bottom row. The mip pyramid is built up front whatever the filter so
+// the filter can be changed later. Clones share the texels.
+#[derive(Clone)]
+pub struct ImageTexture {
+    levels: Arc<[MipLevel]>,
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+}
+
+impl ImageTexture {
+    // `texels` are row-major, top row first
+    pub fn new(width: usize, height: usize, texels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0 && texels.len() == width * height, "texture size does not match its texels");
+        
+        let mut levels = vec![MipLevel { width, height, texels }];
+        while let Some(last) = levels.last().filter(|level| level.width > 1 || level.height > 1) {
+            let next = last.downsample();
+            levels.push(next);
+        }
+        
+        ImageTexture {
+            levels: levels.into(),
+            filter: TextureFilter::Bilinear,
+            wrap: TextureWrap::Repeat,
+        }
+    }
+    
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+    
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+    
+    // Alternating `a` and `b` squares, `squares` to a side
+    pub fn checker(size: usize, squares: usize, a: Color, b: Color) -> Self {
+        let square = (size / squares.max(1)).max(1);
+        let texels = (0..size * size)
+            .map(|i| {
+                let (x, y) = (i % size / square, i / size / square);
+                if (x + y) % 2 == 0 { a } else { b }
+            })
+            .collect();
+        ImageTexture::new(size, size, texels)
+    }
+    
+    // Magenta and black squares, standing in for a texture file that is
+    // missing so the gap is hard to miss
+    pub fn placeholder() -> Self {
+        ImageTexture::checker(64, 8, Color::new(1.0, 0.0, 1.0), Color::BLACK)
+    }
+    
+    // Reads a binary (P6) or plain (P3) PPM file
+    pub fn load_ppm(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("cannot read texture '{}': {}", path, e))?;
+        let image = parse_ppm(&data).map_err(|e| format!("bad texture '{}': {}", path, e))?;
+        // Image files hold sRGB-encoded colors; shading needs them linear
+        let texels = image.pixels().iter()
+            .map(|p| Color::new(srgb_to_linear(p.r), srgb_to_linear(p.g), srgb_to_linear(p.b)))
+            .collect();
+        Ok(ImageTexture::new(image.width as usize, image.height as usize, texels))
+    }
+    
+    // Bytes a texture `width` by `height` texels takes with its mip levels,
+    // as `memory_estimate` counts them, before any of it is built
+    pub fn memory_for(width: usize, height: usize) -> usize {
+        let (mut width, mut height) = (width.max(1), height.max(1));
+        let mut bytes = mem::size_of::<Self>();
+        loop {
+            bytes += mem::size_of::<MipLevel>() + width * height * mem::size_of::<Color>();
+            if width == 1 && height == 1 {
+                return bytes;
+            }
+            (width, height) = ((width / 2).max(1), (height / 2).max(1));
+        }
+    }
+    
+    // What loading the PPM file at `path` would take, from its header
+    pub fn estimate_ppm(path: &str) -> Result<usize, String> {
+        let (width, height) = read_ppm_size(path)?;
+        Ok(ImageTexture::memory_for(width, height))
+    }
+    
+    // Bytes held by the texture and its mip levels, which clones share
+    pub fn memory_estimate(&self) -> usize {
+        let texels: usize = self.levels.iter().map(|level| level.texels.capacity() * mem::size_of::<Color>()).sum();
+        mem::size_of::<Self>() + mem::size_of_val(&*self.levels) + texels
+    }
+    
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+    
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+    
+    // Average color over the whole texture: the one texel at the top of the
+    // pyramid, exact when the sides are powers of two
+    pub fn average(&self) -> Color {
+        self.levels[self.levels.len() - 1].texels[0]
+    }
+    
+    // `footprint` is roughly how much of the texture one pixel covers, in
+    // texture-coordinate units; only the mipmap filter looks at it
+    pub fn sample(&self, u: f64, v: f64, footprint: f64) -> Color {
+        match self.filter {
+            TextureFilter::Nearest => self.levels[0].nearest(u, v, self.wrap),
+            TextureFilter::Bilinear => self.levels[0].bilinear(u, v, self.wrap),
+            TextureFilter::Mipmap => {
+                let texels_per_pixel = footprint * self.width().max(self.height()) as f64;
+                let level = texels_per_pixel.max(1.0).log2().min((self.levels.len() - 1) as f64);
+                let lower = level.floor() as usize;
+                let upper = (lower + 1).min(self.levels.len() - 1);
+                
+                let a = self.levels[lower].bilinear(u, v, self.wrap);
+                let b = self.levels[upper].bilinear(u, v, self.wrap);
+                a.lerp(&b, level - lower as f64)
+            }
+        }
+    }
+}
+
+/// Colors at positions along a line, blended linearly between neighbouring
+/// stops. The stops are sorted by position; colors given at the same
+/// position keep their order and make a hard edge, the later one taking
+/// over at the position itself. Before the first stop and after the last
+/// the end colors hold. `wrap` decides what happens to positions outside
+/// 0..1: left alone, repeated or mirrored.
+///
+/// ```
+/// use rt::image::color::Color;
+/// use rt::texture::{Gradient, TextureWrap};
+///
+/// let (black, red, white) = (Color::BLACK, Color::new(1.0, 0.0, 0.0), Color::WHITE);
+///
+/// // Without stops the colors are spread evenly over 0..1
+/// let even = Gradient::new(vec![black, white], None).unwrap();
+/// assert_eq!(even.color_at(0.25), Color::new(0.25, 0.25, 0.25));
+///
+/// // Stops may come in any order; each stays with its color
+/// let unsorted = Gradient::new(vec![white, black, red], Some(vec![1.0, 0.0, 0.5])).unwrap();
+/// assert_eq!(unsorted.color_at(0.25), Color::new(0.5, 0.0, 0.0));
+/// assert_eq!(unsorted.color_at(0.75), Color::new(1.0, 0.5, 0.5));
+///
+/// // Two colors at one position switch sharply
+/// let step = Gradient::new(vec![black, black, red, red], Some(vec![0.0, 0.5, 0.5, 1.0])).unwrap();
+/// assert_eq!(step.color_at(0.499), black);
+/// assert_eq!(step.color_at(0.5), red);
+///
+/// // Outside 0..1 the ends hold, or the gradient repeats or mirrors
+/// assert_eq!(even.color_at(-3.0), black);
+/// assert_eq!(even.color_at(7.5), white);
+/// let repeat = even.clone().with_wrap(TextureWrap::Repeat);
+/// assert_eq!(repeat.color_at(1.25), even.color_at(0.25));
+/// assert_eq!(repeat.color_at(-0.25), even.color_at(0.75));
+/// let mirror = even.clone().with_wrap(TextureWrap::Mirror);
+/// assert_eq!(mirror.color_at(1.25), even.color_at(0.75));
+/// assert_eq!(mirror.color_at(-0.25), even.color_at(0.25));
+///
+/// assert!(Gradient::new(vec![], None).is_err());
+/// assert!(Gradient::new(vec![black, white], Some(vec![0.0])).is_err());
+/// assert!(Gradient::new(vec![black, white], Some(vec![0.0, f64::NAN])).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    stops: Vec<(f64, Color)>,
+    pub wrap: TextureWrap,
+}
+
+impl Gradient {
+    pub fn new(colors: Vec<Color>, stops: Option<Vec<f64>>) -> Result<Self, String> {
+        if colors.is_empty() {
+            return Err("a gradient needs at least one color".to_string());
+        }
+        let positions = match stops {
+            Some(stops) if stops.len() != colors.len() => {
+                return Err(format!("expected one stop per color ({}), got {}", colors.len(), stops.len()));
+            }
+            Some(stops) if stops.iter().any(|stop| !stop.is_finite()) => {
+                return Err("stops must be finite numbers".to_string());
+            }
+            Some(stops) => stops,
+            None => {
+                let last = (colors.len() - 1).max(1) as f64;
+                (0..colors.len()).map(|i| i as f64 / last).collect()
+            }
+        };
+        let mut stops: Vec<(f64, Color)> = positions.into_iter().zip(colors).collect();
+        // A stable sort, so colors at the same position stay in order
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Gradient { stops, wrap: TextureWrap::Clamp })
+    }
+    
+    pub fn with_wrap(mut self, wrap: TextureWrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+    
+    pub fn color_at(&self, t: f64) -> Color {
+        let t = match self.wrap {
+            TextureWrap::Clamp => t,
+            TextureWrap::Repeat => t.rem_euclid(1.0),
+            TextureWrap::Mirror => 1.0 - (t.rem_euclid(2.0) - 1.0).abs(),
+        };
+        // The first stop past t; the one before it is at or before t, so
+        // the two are never at the same position
+        let next = self.stops.partition_point(|&(position, _)| position <= t);
+        if next == 0 {
+            return self.stops[0].1;
+        }
+        let Some(&(end, to)) = self.stops.get(next) else {
+            return self.stops[self.stops.len() - 1].1;
+        };
+        let (start, from) = self.stops[next - 1];
+        from.lerp(&to, (t - start) / (end - start))
+    }
+}
+
+/// What a plane or slab shows in place of its material color: an image, or
+/// a gradient worked out from where the ray hit. Gradients look at the hit
+/// relative to the texture origin, or at its (u, v) coordinates on the
+/// surface, both divided by the texture scale.
+///
+/// ```
+/// use rt::image::color::Color;
+/// use rt::texture::{Gradient, Texture};
+/// use rt::vector::Vec3;
+///
+/// let gradient = Gradient::new(vec![Color::WHITE, Color::BLACK], None).unwrap();
+/// let at = |texture: &Texture, point: Vec3| texture.sample(point, point.x, point.y, 0.0).r;
+///
+/// // Halfway along the axis, whatever the other coordinates
+/// let linear = Texture::LinearGradient { axis: Vec3::new(0.0, 4.0, 0.0), gradient: gradient.clone() };
+/// assert_eq!(at(&linear, Vec3::new(9.0, 2.0, -3.0)), 0.5);
+///
+/// // Spherical and cylindrical: distance from the center or its axis
+/// let center = Vec3::new(1.0, 0.0, 0.0);
+/// let sphere = Texture::RadialGradient { center, radius: 2.0, axis: None, gradient: gradient.clone() };
+/// let cylinder = Texture::RadialGradient { center, radius: 2.0, axis: Some(Vec3::new(0.0, 0.0, 5.0)), gradient: gradient.clone() };
+/// assert_eq!(at(&sphere, Vec3::new(1.0, 0.0, 1.0)), 0.5);
+/// assert_eq!(at(&sphere, Vec3::new(1.0, 1.0, 10.0)), 0.0);
+/// assert_eq!(at(&cylinder, Vec3::new(1.0, 1.0, 10.0)), 0.5);
+///
+/// // A ramp across (u, v), here along v
+/// let ramp = Texture::Ramp { angle: 90f64.to_radians(), gradient };
+/// assert!((at(&ramp, Vec3::new(0.7, 0.25, 0.0)) - 0.75).abs() < 1e-12);
+/// ```
+#[derive(Clone)]
+pub enum Texture {
+    Image(ImageTexture),
+    // t is 0 at the texture origin and 1 one `axis` length along it
+    LinearGradient { axis: Vec3, gradient: Gradient },
+    // t is the distance from `center` over `radius`: spherical, or
+    // cylindrical around the line through `center` along `axis` if given
+    RadialGradient { center: Vec3, radius: f64, axis: Option<Vec3>, gradient: Gradient },
+    // t runs across the surface in the direction `angle` radians from
+    // the u axis towards v
+    Ramp { angle: f64, gradient: Gradient },
+}
+
+impl Texture {
+    // `footprint` only matters to images; see `ImageTexture::sample`
+    pub fn sample(&self, point: Vec3, u: f64, v: f64, footprint: f64) -> Color {
+        match self {
+            Texture::Image(image) => image.sample(u, v, footprint),
+            Texture::LinearGradient { axis, gradient } => gradient.color_at(point.dot(axis) / axis.length_squared()),
+            Texture::RadialGradient { center, radius, axis, gradient } => {
+                let offset = point - *center;
+                let offset = match axis {
+                    Some(axis) => {
+                        let axis = axis.normalize();
+                        offset - axis * offset.dot(&axis)
+                    }
+                    None => offset,
+                };
+                gradient.color_at(offset.length() / radius)
+            }
+            Texture::Ramp { angle, gradient } => gradient.color_at(u * angle.cos() + v * angle.sin()),
+        }
+    }
+    
+    pub fn memory_estimate(&self) -> usize {
+        match self {
+            Texture::Image(image) => mem::size_of::<Self>() - mem::size_of::<ImageTexture>() + image.memory_estimate(),
+            Texture::LinearGradient { gradient, .. } | Texture::RadialGradient { gradient, .. } | Texture::Ramp { gradient, .. } => {
+                mem::size_of::<Self>() + gradient.stops.capacity() * mem::size_of::<(f64, Color)>()
+            }
+        }
+    }
+    
+    // The texture's estimate with an address that is the same for every
+    // copy sharing its data: the texels of an image loaded once and used by
+    // several materials, or the texture itself otherwise
+    pub fn shared_memory(&self) -> (usize, usize) {
+        let address = match self {
+            Texture::Image(image) => Arc::as_ptr(&image.levels) as *const u8 as usize,
+            _ => self as *const Texture as usize,
+        };
+        (address, self.memory_estimate())
+    }
+}
@@ -0,0 +1,143 @@
+use crate::vector::Vec3;
+use std::ops::Mul;
+
+// Affine transform as a 4x4 matrix acting on column vectors, so in `a * b`
+// the transform `b` is applied first
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub rows: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Self {
+        Mat4::from_rows([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    
+    pub fn from_rows(rows: [[f64; 4]; 4]) -> Self {
+        Mat4 { rows }
+    }
+    
+    pub fn translation(offset: Vec3) -> Self {
+        let mut matrix = Mat4::identity();
+        matrix.rows[0][3] = offset.x;
+        matrix.rows[1][3] = offset.y;
+        matrix.rows[2][3] = offset.z;
+        matrix
+    }
+    
+    pub fn scaling(factors: Vec3) -> Self {
+        let mut matrix = Mat4::identity();
+        matrix.rows[0][0] = factors.x;
+        matrix.rows[1][1] = factors.y;
+        matrix.rows[2][2] = factors.z;
+        matrix
+    }
+    
+    // Rotations by an angle in degrees, counter-clockwise when looking down
+    // the axis toward the origin
+    pub fn rotation_x(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Mat4::from_rows([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, -sin, 0.0],
+            [0.0, sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    
+    pub fn rotation_y(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Mat4::from_rows([
+            [cos, 0.0, sin, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sin, 0.0, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    
+    pub fn rotation_z(degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Mat4::from_rows([
+            [cos, -sin, 0.0, 0.0],
+            [sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform_vector(p) + Vec3::new(self.rows[0][3], self.rows[1][3], self.rows[2][3])
+    }
+    
+    // Ignores the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let r = &self.rows;
+        Vec3::new(
+            r[0][0] * v.x + r[0][1] * v.y + r[0][2] * v.z,
+            r[1][0] * v.x + r[1][1] * v.y + r[1][2] * v.z,
+            r[2][0] * v.x + r[2][1] * v.y + r[2][2] * v.z,
+        )
+    }
+    
+    // Carries a normal of a surface through the transform; called on the
+    // inverse matrix, this multiplies by its transpose
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let r = &self.rows;
+        Vec3::new(
+            r[0][0] * n.x + r[1][0] * n.y + r[2][0] * n.z,
+            r[0][1] * n.x + r[1][1] * n.y + r[2][1] * n.z,
+            r[0][2] * n.x + r[1][2] * n.y + r[2][2] * n.z,
+        )
+    }
+    
+    // Inverse of the affine part, or None when it flattens space
+    pub fn inverse(&self) -> Option<Mat4> {
+        let r = &self.rows;
+        let cofactor = |row: usize, col: usize| {
+            let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+            let (c0, c1) = ((col + 1) % 3, (col + 2) % 3);
+            r[r0][c0] * r[r1][c1] - r[r0][c1] * r[r1][c0]
+        };
+        let determinant = r[0][0] * cofactor(0, 0) + r[0][1] * cofactor(0, 1) + r[0][2] * cofactor(0, 2);
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        
+        let mut inverse = Mat4::identity();
+        for row in 0..3 {
+            for col in 0..3 {
+                inverse.rows[row][col] = cofactor(col, row) / determinant;
+            }
+        }
+        let offset = inverse.transform_vector(Vec3::new(r[0][3], r[1][3], r[2][3]));
+        inverse.rows[0][3] = -offset.x;
+        inverse.rows[1][3] = -offset.y;
+        inverse.rows[2][3] = -offset.z;
+        Some(inverse)
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::identity()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * other.rows[k][j]).sum();
+            }
+        }
+        Mat4 { rows }
+    }
+}
@@ -1,6 +1,9 @@
+use crate::rng::Rng;
+use std::f64::consts::PI;
+use std::fmt;
 use std::ops::{Add, Sub, Mul, Div, Neg};
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -8,6 +11,13 @@ pub struct Vec3 {
 }
 
 impl Vec3 {
+    // Scene coordinates are right-handed with Y up: x points right, y up
+    // and z towards the viewer, so a camera that is not turned looks down
+    // -z. Files written in other conventions are converted as they load.
+    pub const RIGHT: Vec3 = Vec3 { x: 1.0, y: 0.0, z: 0.0 };
+    pub const UP: Vec3 = Vec3 { x: 0.0, y: 1.0, z: 0.0 };
+    pub const FORWARD: Vec3 = Vec3 { x: 0.0, y: 0.0, z: -1.0 };
+    
     pub fn new(x: f64, y: f64, z: f64) -> Self {
         Vec3 { x, y, z }
     }
@@ -20,6 +30,33 @@ impl Vec3 {
         Vec3::new(1.0, 1.0, 1.0)
     }
     
+    /// A point or direction given right-handed with Z up, as in Blender and
+    /// most CAD tools, in the scene's Y-up coordinates. Their y points away
+    /// from the viewer, which here is -z.
+    ///
+    /// ```
+    /// use rt::vector::Vec3;
+    ///
+    /// assert_eq!(Vec3::from_z_up(Vec3::new(0.0, 0.0, 1.0)), Vec3::UP);
+    /// assert_eq!(Vec3::from_z_up(Vec3::new(0.0, 1.0, 0.0)), Vec3::FORWARD);
+    /// assert_eq!(Vec3::from_z_up(Vec3::new(1.0, 0.0, 0.0)), Vec3::RIGHT);
+    /// // A rotation, so handedness is kept
+    /// let (a, b) = (Vec3::new(1.0, 2.0, 3.0), Vec3::new(-2.0, 0.5, 1.0));
+    /// assert_eq!(Vec3::from_z_up(a.cross(&b)), Vec3::from_z_up(a).cross(&Vec3::from_z_up(b)));
+    /// ```
+    pub fn from_z_up(v: Vec3) -> Vec3 {
+        Vec3::new(v.x, v.z, -v.y)
+    }
+    
+    // All three components set to `value`
+    pub fn splat(value: f64) -> Self {
+        Vec3::new(value, value, value)
+    }
+    
+    pub fn max_component(&self) -> f64 {
+        self.x.max(self.y).max(self.z)
+    }
+    
     pub fn dot(&self, other: &Vec3) -> f64 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
@@ -40,6 +77,10 @@ impl Vec3 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
     
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+    
     pub fn normalize(&self) -> Vec3 {
         let len = self.length();
         if len > 0.0 {
@@ -76,6 +117,103 @@ impl Vec3 {
             self.z.clamp(min, max),
         )
     }
+    
+    // Uniform over the sphere
+    pub fn random_unit_vector(rng: &mut Rng) -> Vec3 {
+        let z = 1.0 - 2.0 * rng.next_f64();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.next_f64();
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+    
+    /// Uniform over the disk of radius 1 in the xy plane, so the squared
+    /// distance from the center is uniform in 0..1.
+    ///
+    /// ```
+    /// use rt::rng::Rng;
+    /// use rt::vector::Vec3;
+    ///
+    /// let mut rng = Rng::new(7);
+    /// let squares: Vec<f64> = (0..100_000).map(|_| Vec3::random_in_unit_disk(&mut rng).length_squared()).collect();
+    /// assert!(squares.iter().all(|&r2| r2 < 1.0));
+    /// for limit in [0.1, 0.25, 0.5, 0.9] {
+    ///     let share = squares.iter().filter(|&&r2| r2 < limit).count() as f64 / squares.len() as f64;
+    ///     assert!((share - limit).abs() < 0.01, "{} of the samples fall within r^2 < {}", share, limit);
+    /// }
+    /// ```
+    pub fn random_in_unit_disk(rng: &mut Rng) -> Vec3 {
+        let radius = rng.next_f64().sqrt();
+        let angle = rng.next_f64() * 2.0 * PI;
+        Vec3::new(radius * angle.cos(), radius * angle.sin(), 0.0)
+    }
+    
+    /// Over the hemisphere around +z with density proportional to the
+    /// cosine of the angle from z, the way light leaves a diffuse surface.
+    /// Carry it to a surface with `Onb::local`. The mean direction is 2/3
+    /// of the way along the axis.
+    ///
+    /// ```
+    /// use rt::rng::Rng;
+    /// use rt::vector::{Onb, Vec3};
+    ///
+    /// let normal = Vec3::new(1.0, 2.0, -2.0).normalize();
+    /// let basis = Onb::from_w(normal);
+    /// let mut rng = Rng::new(11);
+    /// let count = 100_000;
+    /// let mut sum = Vec3::zero();
+    /// for _ in 0..count {
+    ///     let direction = basis.local(Vec3::random_cosine_hemisphere(&mut rng));
+    ///     assert!(direction.dot(&normal) >= 0.0);
+    ///     assert!((direction.length() - 1.0).abs() < 1e-9);
+    ///     sum = sum + direction;
+    /// }
+    /// let mean = sum / count as f64;
+    /// assert!((mean - normal * (2.0 / 3.0)).length() < 0.01);
+    /// ```
+    pub fn random_cosine_hemisphere(rng: &mut Rng) -> Vec3 {
+        let disk = Vec3::random_in_unit_disk(rng);
+        Vec3::new(disk.x, disk.y, (1.0 - disk.length_squared()).max(0.0).sqrt())
+    }
+}
+
+// Orthonormal basis with `w` along a given direction, for carrying vectors
+// sampled around +z over to it. Built without branching on the axis the
+// direction is near (Duff et al., "Building an Orthonormal Basis,
+// Revisited", 2017).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    // `w` must be of unit length
+    pub fn from_w(w: Vec3) -> Self {
+        let sign = 1.0_f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        Onb {
+            u: Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+    
+    // The vector with coordinates `a` in this basis
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+}
+
+// "(x, y, z)", with any precision applied to each component
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match f.precision() {
+            Some(p) => write!(f, "({:.*}, {:.*}, {:.*})", p, self.x, p, self.y, p, self.z),
+            None => write!(f, "({}, {}, {})", self.x, self.y, self.z),
+        }
+    }
 }
 
 impl Add for Vec3 {
@@ -0,0 +1,147 @@
+use rt::json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn rt(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rt")).args(args).output().expect("cannot run rt")
+}
+
+// A file in the temp directory only this test uses
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rt-exit-codes-{}-{}", std::process::id(), name))
+}
+
+fn status(output: &Output) -> i32 {
+    output.status.code().expect("rt was killed by a signal")
+}
+
+// The one JSON object --error-format json writes on stderr
+fn json_report(output: &Output) -> Value {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let line = stderr.lines().last().expect("nothing on stderr");
+    Value::parse(line).unwrap_or_else(|err| panic!("not JSON: {}: {}", line, err))
+}
+
+#[test]
+fn success() {
+    let path = temp_path("ok.ppm");
+    let output = rt(&["--scene", "scene1", "--width", "8", "--height", "6", "-q", "-o", path.to_str().unwrap()]);
+    let written = fs::metadata(&path).map(|metadata| metadata.len());
+    let _ = fs::remove_file(&path);
+    assert_eq!(status(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(written.unwrap() > 0);
+    
+    // A budget that fits a Duration but runs past the clock's range is no
+    // deadline at all
+    let output = rt(&["--scene", "scene1", "--width", "8", "--height", "6", "-q", "--time-budget", "1e19", "-o", path.to_str().unwrap()]);
+    let _ = fs::remove_file(&path);
+    assert_eq!(status(&output), 0, "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn usage_errors() {
+    // Rejected by the argument parser
+    assert_eq!(status(&rt(&["--no-such-option"])), 2);
+    assert_eq!(status(&rt(&["--width", "0"])), 2);
+    
+    // Option values out of range, whether the parser or the scene setup
+    // catches them
+    for args in [
+        &["--near", "0"][..],
+        &["--far", "nan"],
+        &["--near", "5", "--far", "2"],
+        &["--pixel-aspect=-1"],
+        &["--aperture=-1"],
+        &["--sky", "sun", "--sun-elevation", "200"],
+        &["--scene", "menger", "--level", "9"],
+        &["--framing", "4x5"],
+        &["--filter", "gaussian", "--filter-radius", "0.2"],
+        &["--filter", "tent", "--filter-radius", "0"],
+        &["--time-budget", "1e300"],
+        &["--time-budget=-1"],
+    ] {
+        let output = rt(&[&["--width", "8", "--height", "6", "-q", "-o", "unused.ppm"][..], args].concat());
+        assert_eq!(status(&output), 2, "{:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    }
+    
+    // Numeric options that take no nan or inf
+    for option in [
+        "--filter-radius",
+        "--time-budget",
+        "--softbox-size",
+        "--photon-radius",
+        "--ao-distance",
+        "--outline-depth",
+        "--outline-angle",
+        "--band-smoothing",
+        "--overlap-tolerance",
+    ] {
+        for value in ["nan", "inf"] {
+            let output = rt(&["--width", "8", "--height", "6", "-q", "-o", "unused.ppm", option, value]);
+            assert_eq!(status(&output), 2, "{} {}: {}", option, value, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+    
+    // Options that cannot be combined
+    let output = rt(&["--scene", "scene1", "--stream-rows", "4", "--pass", "cost", "-q", "-o", "unused.ppm"]);
+    assert_eq!(status(&output), 2);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--stream-rows cannot be combined with --pass cost"));
+    
+    let report = json_report(&rt(&["--no-such-option", "--error-format", "json"]));
+    assert_eq!(report.get("code").and_then(Value::as_f64), Some(2.0));
+    assert!(report.get("message").and_then(Value::as_str).unwrap().contains("--no-such-option"));
+}
+
+#[test]
+fn scene_errors() {
+    let missing = temp_path("missing.json");
+    assert_eq!(status(&rt(&["--scene-file", missing.to_str().unwrap(), "-q"])), 3);
+    assert_eq!(status(&rt(&["--scene-inline", "{ \"objects\": 1 }", "-q"])), 3);
+    
+    let malformed = temp_path("malformed.json");
+    fs::write(&malformed, "{\n  \"objects\": [ } ]\n}\n").unwrap();
+    let path = malformed.to_str().unwrap();
+    let output = rt(&["--scene-file", path, "-q", "--error-format", "json"]);
+    let _ = fs::remove_file(&malformed);
+    assert_eq!(status(&output), 3);
+    
+    let report = json_report(&output);
+    assert_eq!(report.get("code").and_then(Value::as_f64), Some(3.0));
+    assert!(report.get("message").and_then(Value::as_str).unwrap().contains("unexpected character"));
+    assert_eq!(report.get("file").and_then(Value::as_str), Some(path));
+    assert_eq!(report.get("line").and_then(Value::as_f64), Some(2.0));
+    assert_eq!(report.get("column").and_then(Value::as_f64), Some(16.0));
+    let keys: Vec<&str> = report.as_object().unwrap().iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["code", "message", "file", "line", "column"]);
+}
+
+#[test]
+fn render_errors() {
+    // Far more memory than any machine has for the frame
+    let path = temp_path("huge.ppm");
+    let output = rt(&["--scene", "scene1", "--width", "4000000", "--height", "4000000", "-q", "-o", path.to_str().unwrap()]);
+    assert_eq!(status(&output), 4, "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!path.exists());
+    
+    let report = json_report(&rt(&["--scene", "scene1", "--width", "4000000", "--height", "4000000", "-q", "--error-format", "json"]));
+    assert_eq!(report.get("code").and_then(Value::as_f64), Some(4.0));
+    assert!(report.get("line").is_none() && report.get("file").is_none());
+}
+
+#[test]
+fn output_errors() {
+    let inside = temp_path("no-such-directory").join("out.ppm");
+    assert_eq!(status(&rt(&["--scene", "scene1", "--width", "8", "--height", "6", "-q", "-o", inside.to_str().unwrap()])), 5);
+    
+    // A device that is always full, where there is one
+    if !std::path::Path::new("/dev/full").exists() {
+        return;
+    }
+    let output = rt(&["--scene", "scene1", "--width", "8", "--height", "6", "-q", "-o", "/dev/full"]);
+    assert_eq!(status(&output), 5);
+    
+    let report = json_report(&rt(&["--scene", "scene1", "--width", "8", "--height", "6", "-q", "-o", "/dev/full", "--error-format", "json"]));
+    assert_eq!(report.get("code").and_then(Value::as_f64), Some(5.0));
+    assert!(report.get("message").and_then(Value::as_str).unwrap().contains("/dev/full"));
+}
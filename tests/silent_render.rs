@@ -0,0 +1,66 @@
+// Library renders must leave stdout and stderr alone: stdout may be
+// carrying image data, and embedders report progress their own way. The
+// test runs itself again as a child that only renders, with both streams
+// captured, so anything written to the real file descriptors is caught,
+// not just what goes through the log macros.
+
+use rt::image::Image;
+use rt::log::{self, Level};
+use rt::scene::{AuxBuffers, PixelOrder, RenderSettings, Scene, TileOrder};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+const CHILD: &str = "RT_SILENT_RENDER_CHILD";
+
+fn render_everything() {
+    let scene: Scene = r#"{
+        "camera": { "position": [0, 1, 3], "look_at": [0, 0, 0] },
+        "lights": [{ "position": [2, 3, 1] }],
+        "objects": [
+            { "type": "plane", "point": [0, -1, 0], "normal": [0, 1, 0] },
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": { "preset": "glass" } }
+        ]
+    }"#.parse().unwrap();
+    let (width, height) = (48, 32);
+    
+    let mut image = Image::new(width, height);
+    scene.render_into(&mut image, &RenderSettings::default());
+    scene.render_with_progress(&mut image, None, &RenderSettings::default(), |_| {});
+    let mut aux = AuxBuffers::new(width, height);
+    scene.render_with_progress(&mut image, Some(&mut aux), &RenderSettings { samples_per_pixel: 2, ..RenderSettings::default() }, |_| {});
+    scene.render_into(&mut image, &RenderSettings { pixel_order: PixelOrder::Interlaced, ..RenderSettings::default() });
+    scene.render_into(&mut image, &RenderSettings { tile_order: TileOrder::Hilbert, ..RenderSettings::default() });
+    // A render cut short by its deadline
+    let report = scene.render_into(&mut image, &RenderSettings { deadline: Some(Instant::now()), ..RenderSettings::default() });
+    assert!(report.truncated);
+    scene.render_bands(width, height, 8, &RenderSettings::default(), |_| Ok(())).unwrap();
+    scene.trace_paths(width, height, &RenderSettings::default(), &[(24, 16)]).unwrap();
+}
+
+fn main() {
+    if std::env::var_os(CHILD).is_some() {
+        // At the default level nothing is reported at all
+        render_everything();
+        
+        // With everything enabled, messages only reach an installed sink
+        let lines = Arc::new(Mutex::new(0));
+        let counted = Arc::clone(&lines);
+        log::set_max_level(Level::Trace);
+        log::set_sink(move |_, _| *counted.lock().unwrap() += 1);
+        render_everything();
+        log::reset_sink();
+        assert!(*lines.lock().unwrap() > 0, "nothing was logged at the trace level");
+        return;
+    }
+    
+    let output = Command::new(std::env::current_exe().unwrap())
+        .env(CHILD, "1")
+        .output()
+        .expect("cannot run the test again as a child");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "the child failed: {}", stderr);
+    assert!(output.stdout.is_empty(), "library renders wrote to stdout: {}", String::from_utf8_lossy(&output.stdout));
+    assert!(stderr.is_empty(), "library renders wrote to stderr: {}", stderr);
+    println!("test silent_render ... ok");
+}